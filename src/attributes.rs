@@ -0,0 +1,122 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+/// What the files placed in the library keep of their source and who owns them, in `[attributes]`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Attributes {
+    /// Copies keep the modification time, the permissions and the extended attributes of their source
+    #[serde(default)]
+    pub preserve: bool,
+
+    /// The user owning the files and directories placed in the library, by name or id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+
+    /// The group owning the files and directories placed in the library, by name or id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// The permission bits cleared on the files and directories placed in the library, e.g. `0o002`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub umask: Option<u32>,
+}
+
+impl Attributes {
+    /// The ids of the owner and group, none for the ones not configured
+    pub fn ownership(&self) -> Result<(Option<u32>, Option<u32>), io::Error> {
+        let owner = self.owner.as_deref().map(user_id).transpose()?;
+        let group = self.group.as_deref().map(group_id).transpose()?;
+        Ok((owner, group))
+    }
+}
+
+/// The id of the user, given by name or as a number
+fn user_id(user: &str) -> Result<u32, io::Error> {
+    if let Ok(id) = user.parse() {
+        return Ok(id);
+    }
+    #[cfg(unix)]
+    {
+        lookup(user, "user", |name, buffer| {
+            let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+            let mut found = std::ptr::null_mut();
+            // SAFETY: every pointer is valid for the duration of the call, the buffer with its length
+            let status = unsafe {
+                libc::getpwnam_r(name, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut found)
+            };
+            (status, (!found.is_null()).then_some(entry.pw_uid))
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("users like {} only exist on unix", user)))
+    }
+}
+
+/// The id of the group, given by name or as a number
+fn group_id(group: &str) -> Result<u32, io::Error> {
+    if let Ok(id) = group.parse() {
+        return Ok(id);
+    }
+    #[cfg(unix)]
+    {
+        lookup(group, "group", |name, buffer| {
+            let mut entry: libc::group = unsafe { std::mem::zeroed() };
+            let mut found = std::ptr::null_mut();
+            // SAFETY: every pointer is valid for the duration of the call, the buffer with its length
+            let status = unsafe {
+                libc::getgrnam_r(name, &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut found)
+            };
+            (status, (!found.is_null()).then_some(entry.gr_gid))
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("groups like {} only exist on unix", group)))
+    }
+}
+
+/// Looks the name up in the user or group database, with a buffer grown until the entry fits
+#[cfg(unix)]
+fn lookup(
+    name: &str,
+    kind: &str,
+    get: impl Fn(*const libc::c_char, &mut [libc::c_char]) -> (libc::c_int, Option<u32>),
+) -> Result<u32, io::Error> {
+    let c_name = std::ffi::CString::new(name).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    let mut buffer = vec![0; 1024];
+    loop {
+        match get(c_name.as_ptr(), &mut buffer) {
+            (0, Some(id)) => return Ok(id),
+            (0, None) => {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("there is no {} named {}", kind, name)))
+            }
+            (libc::ERANGE, _) if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            (status, _) => return Err(io::Error::from_raw_os_error(status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owners_are_given_by_id_or_name() {
+        let attributes = |owner: &str, group: &str| Attributes {
+            owner: Some(owner.to_string()),
+            group: Some(group.to_string()),
+            ..Attributes::default()
+        };
+        assert_eq!(attributes("1000", "100").ownership().unwrap(), (Some(1000), Some(100)));
+        assert_eq!(Attributes::default().ownership().unwrap(), (None, None));
+        if cfg!(unix) {
+            assert_eq!(attributes("root", "0").ownership().unwrap(), (Some(0), Some(0)));
+            assert_eq!(
+                attributes("no-such-user-here", "0").ownership().unwrap_err().kind(),
+                io::ErrorKind::NotFound
+            );
+        }
+    }
+}
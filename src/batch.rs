@@ -0,0 +1,121 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+/// The progress of a run through its files, written to disk after every placed file so that a
+/// run stopped by a crash or a reboot resumes where it stopped. The files placed before are not
+/// searched, copied or linked again
+pub struct Batch {
+    path: PathBuf,
+    state: Mutex<BatchState>,
+    /// How many files the interrupted run had placed
+    resumed: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+struct BatchState {
+    input: PathBuf,
+    action: String,
+    outputs: Vec<PathBuf>,
+    /// The files placed with their sidecars
+    done: BTreeSet<PathBuf>,
+}
+
+impl Batch {
+    /// Opens the batch at `path`, resuming the one of an interrupted run of the same input, action
+    /// and output directories. A batch of other arguments is started over
+    pub fn open(path: &Path, input: &Path, action: &str, outputs: &[PathBuf]) -> Result<Self, io::Error> {
+        let mut state = BatchState {
+            input: input.to_path_buf(),
+            action: action.to_string(),
+            outputs: outputs.to_vec(),
+            done: BTreeSet::new(),
+        };
+        if let Some(previous) = load(path)? {
+            if (&previous.input, &previous.action, &previous.outputs) == (&state.input, &state.action, &state.outputs) {
+                state.done = previous.done;
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            resumed: state.done.len(),
+            state: Mutex::new(state),
+        })
+    }
+
+    pub fn resumed(&self) -> usize {
+        self.resumed
+    }
+
+    /// Whether the file was placed by the interrupted run
+    pub fn is_done(&self, path: &Path) -> bool {
+        self.state.lock().unwrap().done.contains(path)
+    }
+
+    /// Marks the file as placed. The batch is replaced on disk at once, so a crash while writing
+    /// it leaves the previous one
+    pub fn record(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        state.done.insert(path.to_path_buf());
+
+        let json = serde_json::to_string_pretty(&*state).expect("Could not serialize the batch");
+        let mut partial = self.path.as_os_str().to_owned();
+        partial.push(".partial");
+        if let Err(error) = fs::write(&partial, json).and_then(|()| fs::rename(&partial, &self.path)) {
+            error!("Could not write the batch {}: {}", self.path.display(), error);
+        }
+    }
+
+    /// Removes the batch once every file of the run was gone through
+    pub fn finish(&self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            if error.kind() != io::ErrorKind::NotFound {
+                error!("Could not remove the batch {}: {}", self.path.display(), error);
+            }
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Option<BatchState>, io::Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_batch_of_the_same_run_is_resumed() {
+        let path = std::env::temp_dir().join(format!("media-renamer-batch-{}.json", std::process::id()));
+        let outputs = vec![PathBuf::from("library")];
+
+        let batch = Batch::open(&path, Path::new("downloads"), "copy", &outputs).unwrap();
+        batch.record(Path::new("downloads/Show.S01E01.mkv"));
+        drop(batch);
+
+        let resumed = Batch::open(&path, Path::new("downloads"), "copy", &outputs).unwrap();
+        assert_eq!(resumed.resumed(), 1);
+        assert!(resumed.is_done(Path::new("downloads/Show.S01E01.mkv")));
+        assert!(!resumed.is_done(Path::new("downloads/Show.S01E02.mkv")));
+
+        let other = Batch::open(&path, Path::new("downloads"), "move", &outputs).unwrap();
+        assert_eq!(other.resumed(), 0);
+
+        resumed.finish();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+
+/// A response kept in the cache with the time it was received at
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+struct CachedResponse {
+    /// Seconds since the epoch
+    fetched_at: u64,
+    body: String,
+}
+
+/// The successful API responses of the previous runs keyed by request, so that rerunning over a
+/// library does not ask the providers the same things again. Responses older than the time to
+/// live are dropped. The cache is written back to disk when it is dropped
+pub struct ResponseCache {
+    path: PathBuf,
+    ttl: Duration,
+    responses: Mutex<BTreeMap<String, CachedResponse>>,
+    changed: AtomicBool,
+    /// Whether the responses are kept for the run only, never written back
+    read_only: bool,
+}
+
+impl ResponseCache {
+    /// Opens the cache at `path`, starting an empty one if there is none yet
+    pub fn open(path: &Path, ttl: Duration) -> Result<Self, io::Error> {
+        let mut responses: BTreeMap<String, CachedResponse> = if path.exists() {
+            let contents = fs::read_to_string(path)?;
+            serde_json::from_str(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?
+        } else {
+            BTreeMap::new()
+        };
+
+        let count = responses.len();
+        let now = now();
+        responses.retain(|_, response| !expired(response, now, ttl));
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            ttl,
+            changed: AtomicBool::new(responses.len() != count),
+            responses: Mutex::new(responses),
+            read_only: false,
+        })
+    }
+
+    /// Opens the cache at `path` like [`ResponseCache::open`], starting it over when its file
+    /// cannot be parsed rather than going without a cache on every run
+    pub fn open_or_reset(path: &Path, ttl: Duration) -> Result<Self, io::Error> {
+        match Self::open(path, ttl) {
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                warn!("The cache {} cannot be read, starting it over: {}", path.display(), error);
+                fs::remove_file(path)?;
+                Self::open(path, ttl)
+            }
+            result => result,
+        }
+    }
+
+    /// Keeps the responses added by the run in memory only, leaving the cache on disk as it is
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let responses = self.responses.lock().unwrap();
+        let response = responses
+            .get(key)
+            .filter(|response| !expired(response, now(), self.ttl))?;
+        debug!("Using the cached response of {}", key);
+        Some(response.body.clone())
+    }
+
+    pub fn insert(&self, key: String, body: String) {
+        let response = CachedResponse {
+            fetched_at: now(),
+            body,
+        };
+        self.responses.lock().unwrap().insert(key, response);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Writes the cache next to its file and then over it, so that a run cut short leaves the
+    /// previous cache whole
+    fn save(&self) -> Result<(), io::Error> {
+        let json = serde_json::to_string(&*self.responses.lock().unwrap()).map_err(io::Error::other)?;
+        let mut partial = self.path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, json)?;
+        fs::rename(&partial, &self.path)
+    }
+}
+
+impl Drop for ResponseCache {
+    fn drop(&mut self) {
+        if self.read_only || !self.changed.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(error) = self.save() {
+            error!("Could not write the cache {}: {}", self.path.display(), error);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn expired(response: &CachedResponse, now: u64, ttl: Duration) -> bool {
+    now.saturating_sub(response.fetched_at) >= ttl.as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_survive_until_they_expire() {
+        let path = std::env::temp_dir().join(format!("media-renamer-cache-{}.json", std::process::id()));
+        let week = Duration::from_secs(7 * 24 * 60 * 60);
+
+        let cache = ResponseCache::open(&path, week).unwrap();
+        cache.insert("/search?q=Severance".to_string(), "Severance".to_string());
+        cache.responses.lock().unwrap().insert(
+            "/search?q=Dune".to_string(),
+            CachedResponse {
+                fetched_at: now() - week.as_secs(),
+                body: "Dune".to_string(),
+            },
+        );
+        assert_eq!(cache.get("/search?q=Dune"), None);
+        drop(cache);
+
+        let cache = ResponseCache::open(&path, week).unwrap();
+        assert_eq!(cache.get("/search?q=Severance"), Some("Severance".to_string()));
+        assert_eq!(cache.responses.lock().unwrap().len(), 1);
+        drop(cache);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_cache_that_cannot_be_read_is_started_over() {
+        let path = std::env::temp_dir().join(format!("media-renamer-torn-cache-{}.json", std::process::id()));
+        let week = Duration::from_secs(7 * 24 * 60 * 60);
+        fs::write(&path, r#"{"/search?q=Severance": {"fetched_at": 1, "bo"#).unwrap();
+
+        assert!(ResponseCache::open(&path, week).is_err());
+        let cache = ResponseCache::open_or_reset(&path, week).unwrap();
+        cache.insert("/search?q=Severance".to_string(), "Severance".to_string());
+        drop(cache);
+
+        let cache = ResponseCache::open(&path, week).unwrap();
+        assert_eq!(cache.get("/search?q=Severance"), Some("Severance".to_string()));
+        drop(cache);
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        assert!(!Path::new(&partial).exists());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+
+use crate::{
+    filesystem::Fs,
+    path_utils::{get_extension, get_filename},
+};
+
+/// The files releases come with that are of no use once their videos are moved
+pub fn default_leftover_extensions() -> Vec<String> {
+    ["nfo", "txt", "url", "sfv", "md5", "nzb", "exe", "jpg", "jpeg", "png"].map(str::to_string).to_vec()
+}
+
+/// What is left of the source directories once their videos are moved, and may go with them
+pub struct Leftovers<'a> {
+    pub fs: &'a dyn Fs,
+    /// The extensions of the leftover files, compared case-insensitively
+    pub extensions: &'a [String],
+    /// The names of the directories that go whatever they hold, like sample folders
+    pub dirs: &'a [String],
+    /// The files that go whatever their extension, like the `.plexmatch` written by an earlier run
+    pub files: &'a BTreeSet<PathBuf>,
+}
+
+impl Leftovers<'_> {
+    /// Whether the path holds only leftovers: a leftover file, an ignored directory, or a
+    /// directory of nothing else
+    pub fn contains_only_leftovers(&self, path: &Path) -> bool {
+        if !self.fs.is_dir(path) {
+            return self.files.contains(path)
+                || get_extension(path).is_some_and(|extension| {
+                    self.extensions.iter().any(|leftover| leftover.eq_ignore_ascii_case(&extension))
+                });
+        }
+        if get_filename(path).is_some_and(|name| self.dirs.contains(&name)) {
+            return true;
+        }
+        self.fs
+            .read_dir(path)
+            .is_ok_and(|entries| entries.iter().all(|entry| self.contains_only_leftovers(entry)))
+    }
+
+    /// Removes the path and everything in it
+    pub fn remove(&self, path: &Path) -> Result<(), io::Error> {
+        if !self.fs.is_dir(path) {
+            return self.fs.remove_file(path);
+        }
+        for entry in self.fs.read_dir(path)? {
+            self.remove(&entry)?;
+        }
+        self.fs.remove_dir(path)
+    }
+
+    /// Deletes the directories the files were in below `top`, once they hold only leftovers, and
+    /// then their parents left the same way. `top` itself is kept
+    pub fn clean<'p>(&self, files: impl IntoIterator<Item = &'p Path>, top: &Path) {
+        let mut dirs = BTreeSet::new();
+        for file in files {
+            let mut dir = file.parent();
+            while let Some(parent) = dir.filter(|dir| dir.starts_with(top) && *dir != top) {
+                dirs.insert(parent.to_path_buf());
+                dir = parent.parent();
+            }
+        }
+        // the deepest first, so that a parent is only looked at once its children are gone
+        for dir in dirs.iter().rev() {
+            if !self.fs.is_dir(dir) || !self.contains_only_leftovers(dir) {
+                continue;
+            }
+            match self.remove(dir) {
+                Ok(()) => info!("Deleted {}", dir.display()),
+                Err(error) => warn!("Could not delete {}: {}", dir.display(), error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+
+    #[test]
+    fn only_directories_of_leftovers_are_leftovers() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Show.S01E01-GRP/Show.S01E01-GRP.NFO", b"");
+        fs.add_file("downloads/Show.S01E01-GRP/Sample/sample.mkv", b"");
+        fs.add_file("downloads/Show.S01E02-GRP/Show.S01E02-GRP.mkv", b"");
+        fs.add_file("downloads/Show.S01E02-GRP/info.txt", b"");
+        let extensions = default_leftover_extensions();
+        let dirs = vec!["Sample".to_string()];
+        let leftovers = Leftovers {
+            fs: &fs,
+            extensions: &extensions,
+            dirs: &dirs,
+            files: &BTreeSet::new(),
+        };
+
+        assert!(leftovers.contains_only_leftovers(Path::new("downloads/Show.S01E01-GRP")));
+        assert!(!leftovers.contains_only_leftovers(Path::new("downloads/Show.S01E02-GRP")));
+        leftovers.remove(Path::new("downloads/Show.S01E01-GRP")).unwrap();
+        assert!(!fs.exists(Path::new("downloads/Show.S01E01-GRP")));
+    }
+}
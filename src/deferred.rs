@@ -0,0 +1,108 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+/// The moves put off until the torrents of the sources are done seeding, kept on disk between runs. Until then
+/// the library has a link to the source, and a later move run replaces it with the source itself
+pub struct Deferred {
+    path: PathBuf,
+    moves: Mutex<Vec<DeferredMove>>,
+}
+
+/// A file linked into the library that is to be moved there once its torrent is done seeding
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DeferredMove {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// Whether the library has a hard link to the source, a symlink otherwise
+    pub hardlink: bool,
+}
+
+impl Deferred {
+    /// Opens the deferred moves at `path`, none when the file does not exist
+    pub fn open(path: &Path) -> Result<Self, io::Error> {
+        let moves = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            moves: Mutex::new(moves),
+        })
+    }
+
+    /// Defers the move of a file just linked into the library
+    pub fn add(&self, deferred_move: DeferredMove) {
+        let mut moves = self.moves.lock().unwrap();
+        moves.push(deferred_move);
+        self.save(&moves);
+    }
+
+    /// Takes the moves that are done out of the deferred ones, the others are left for later runs
+    pub fn take_done(&self, is_done: impl Fn(&DeferredMove) -> bool) -> Vec<DeferredMove> {
+        let mut moves = self.moves.lock().unwrap();
+        let (done, pending) = moves.drain(..).partition(|deferred_move| is_done(deferred_move));
+        *moves = pending;
+        self.save(&moves);
+        done
+    }
+
+    /// Replaces the file on disk at once, so that a crash while writing it leaves the previous
+    /// one. It is removed once no move is deferred
+    fn save(&self, moves: &[DeferredMove]) {
+        let result = if moves.is_empty() {
+            match fs::remove_file(&self.path) {
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        } else {
+            let json = serde_json::to_string_pretty(moves).expect("Could not serialize the deferred moves");
+            let mut partial = self.path.as_os_str().to_owned();
+            partial.push(".partial");
+            fs::write(&partial, json).and_then(|()| fs::rename(&partial, &self.path))
+        };
+        if let Err(error) = result {
+            error!("Could not write the deferred moves {}: {}", self.path.display(), error);
+        }
+    }
+}
+
+/// The time as seconds since the epoch, times before it are the epoch
+pub fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_done_moves_are_taken() {
+        let path = std::env::temp_dir().join(format!("media-renamer-deferred-{}.json", std::process::id()));
+        let deferred_move = |name: &str| DeferredMove {
+            source: PathBuf::from("downloads").join(name),
+            destination: PathBuf::from("library").join(name),
+            hardlink: true,
+        };
+
+        let deferred = Deferred::open(&path).unwrap();
+        deferred.add(deferred_move("a.mkv"));
+        deferred.add(deferred_move("b.mkv"));
+        drop(deferred);
+
+        let deferred = Deferred::open(&path).unwrap();
+        let is_a = |deferred_move: &DeferredMove| deferred_move.source.ends_with("a.mkv");
+        assert_eq!(deferred.take_done(is_a), vec![deferred_move("a.mkv")]);
+        assert_eq!(deferred.take_done(is_a), Vec::new());
+        assert_eq!(deferred.take_done(|_| true), vec![deferred_move("b.mkv")]);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+};
+
+use clap::Parser;
+use log::{error, info, warn};
+
+use crate::{
+    filesystem::{Fs, RealFs},
+    placement::OutputRoots,
+    process_input,
+    summary::Summary,
+    transfer::CancellationToken,
+    Args, Config, Context,
+};
+
+/// The fake releases the demo starts from, as they typically look in a downloads folder
+const DEMO_FILES: [&str; 9] = [
+    "Severance.S01E01.1080p.WEB.H264-GRP/Severance.S01E01.1080p.WEB.H264-GRP.mkv",
+    "Severance.S01E01.1080p.WEB.H264-GRP/Sample/severance.s01e01.sample.mkv",
+    "Severance.S01E02.1080p.WEB.H264-GRP.mkv",
+    "Paradise.2025.S01E04.480p.x264-RUBiK.mkv",
+    "Show Complete S01-S02/S02/E05.mkv",
+    "Conclave.2024.2160p.UHD.BluRay.x265-SURCODE.mkv",
+    "Blade.Runner.2049.2017.2160p.MA.WEB-DL.TrueHD.Atmos.7.1.DV.HDR.H.265-FLUX.mkv",
+    "RARBG.com.mkv",
+    "Conclave.2024.2160p.UHD.BluRay.x265-SURCODE.nfo",
+];
+
+/// Creates a sandbox with fake releases, moves them into a library in offline mode and prints
+/// the resulting tree. The sandbox is removed afterwards unless `keep` is set
+pub fn demo(keep: bool) {
+    let sandbox = env::temp_dir().join(format!("media-renamer-demo-{}", process::id()));
+    let downloads = sandbox.join("downloads");
+    let library = sandbox.join("library");
+
+    if let Err(error) = create_sandbox(&RealFs, &downloads, &library) {
+        error!("Could not create the demo sandbox in {}: {}", sandbox.display(), error);
+        return;
+    }
+
+    println!("Downloads before:");
+    print_tree(&RealFs, &downloads);
+
+    let args = Args::parse_from([
+        "media-renamer",
+        "--input",
+        &downloads.to_string_lossy(),
+        "--output",
+        &library.to_string_lossy(),
+        "--action",
+        "move",
+    ]);
+    // the demo shows the defaults, whatever the configuration of the user
+    let config = Config::default();
+    let context = Context {
+        args: &args,
+        config: &config,
+        outputs: Mutex::new(OutputRoots::new(&RealFs, vec![library.clone()], args.placement)),
+        providers: Vec::new(),
+        fs: &RealFs,
+        cancellation: CancellationToken::new(),
+        claimed: Mutex::new(HashMap::new()),
+        summary: Mutex::new(Summary::new()),
+        journal: None,
+        batch: None,
+        prompt: None,
+        conflict_resolution: Mutex::new(None),
+        placed: Mutex::new(Vec::new()),
+        want_list: None,
+        kids_outputs: None,
+        routes: Vec::new(),
+        originals: HashMap::new(),
+        deferred: None,
+        seeding: None,
+    };
+    process_input(&downloads, &context);
+    context.summary.lock().unwrap().log(false);
+
+    println!();
+    println!("Library after:");
+    print_tree(&RealFs, &library);
+    println!();
+    println!("Downloads after:");
+    print_tree(&RealFs, &downloads);
+
+    if keep {
+        info!("The demo sandbox is kept in {}", sandbox.display());
+    } else if let Err(error) = std::fs::remove_dir_all(&sandbox) {
+        warn!("Could not remove the demo sandbox {}: {}", sandbox.display(), error);
+    }
+}
+
+fn create_sandbox(fs: &dyn Fs, downloads: &Path, library: &Path) -> Result<(), std::io::Error> {
+    fs.create_dir_all(library)?;
+    for file in DEMO_FILES {
+        let path = downloads.join(file);
+        if let Some(parent) = path.parent() {
+            fs.create_dir_all(parent)?;
+        }
+        fs.open_write(&path, false)?.write_all(b"demo")?;
+    }
+    Ok(())
+}
+
+/// Prints the directory as an indented tree, entries in alphabetical order
+fn print_tree(fs: &dyn Fs, dir: &Path) {
+    for line in tree_lines(fs, dir, 1) {
+        println!("{}", line);
+    }
+}
+
+fn tree_lines(fs: &dyn Fs, dir: &Path, depth: usize) -> Vec<String> {
+    let mut entries: Vec<PathBuf> = fs.read_dir(dir).unwrap_or_default();
+    entries.sort();
+
+    let mut lines = Vec::new();
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if fs.is_dir(&entry) {
+            lines.push(format!("{}{}/", "  ".repeat(depth), name));
+            lines.extend(tree_lines(fs, &entry, depth + 1));
+        } else {
+            lines.push(format!("{}{}", "  ".repeat(depth), name));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filesystem::MemoryFs;
+
+    use super::*;
+
+    #[test]
+    fn tree_is_indented_and_sorted() {
+        let fs = MemoryFs::new();
+        fs.add_file("library/TV/Severance/Season 1/Severance - s01e02.mkv", b"");
+        fs.add_file("library/Movies/Conclave (2024)/Conclave (2024).mkv", b"");
+
+        assert_eq!(
+            tree_lines(&fs, Path::new("library"), 1),
+            vec![
+                "  Movies/",
+                "    Conclave (2024)/",
+                "      Conclave (2024).mkv",
+                "  TV/",
+                "    Severance/",
+                "      Season 1/",
+                "        Severance - s01e02.mkv",
+            ]
+        );
+    }
+}
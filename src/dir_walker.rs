@@ -1,79 +1,366 @@
 use std::{
-    collections::VecDeque,
-    fs::{self, DirEntry, ReadDir},
+    collections::{HashSet, VecDeque},
     io::Error,
-    path::Path,
+    path::{Path, PathBuf},
+    time::SystemTime,
+    vec,
 };
 
-use log::debug;
+use log::{debug, warn};
 
-use crate::path_utils::get_filename;
+use crate::{filesystem::Fs, path_utils::get_filename};
 
-/// An iterator that iterates over a directory
-pub struct DirWalker {
-    iterator_queue: VecDeque<Result<ReadDir, Error>>,
+/// An entry of the walked directory, with what the walk already read of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// 1 for the entries of the walked directory, 2 for the ones of its subdirectories and so on
+    pub depth: usize,
+    pub is_dir: bool,
+    /// Whether it is a regular file, or a symlink to one
+    pub is_file: bool,
+    /// The size in bytes, 0 for directories and when it cannot be read
+    pub size: u64,
+    /// The last modification time, none when it cannot be read
+    pub modified: Option<SystemTime>,
+}
+
+/// An iterator over the entries of a directory and of its subdirectories, breadth first. The walk
+/// is configured with the `with_` methods before it starts
+pub struct DirWalker<'a> {
+    fs: &'a dyn Fs,
+    /// The entries left of the directories being walked, with their depth
+    iterator_queue: VecDeque<(vec::IntoIter<PathBuf>, usize)>,
+    started: bool,
     max_depth: Option<usize>,
     ignored_dirs: Vec<String>,
+    follow_symlinks: bool,
+    sort: bool,
+    /// The directories that were not entered because of their name
+    ignored: Vec<PathBuf>,
+    /// The directories that could not be read, with why
+    unreadable: Vec<(PathBuf, Error)>,
+    root: PathBuf,
+    /// How many entries are walked at most, none for no limit
+    max_entries: Option<usize>,
+    walked: usize,
+    /// The device and inode of the directories entered, so that none is entered twice through a
+    /// symlink or a bind mount looping back to it
+    entered: HashSet<(u64, u64)>,
 }
 
-impl DirWalker {
-    pub fn new(path: &Path, max_depth: Option<usize>, ignored_dirs: Vec<String>) -> Self {
-        let mut iterator_queue = VecDeque::new();
-        if !path.is_dir() {
-            let error = Err(Error::new(
-                std::io::ErrorKind::NotADirectory,
-                format!("The file at {} is not a directory", path.display()),
-            ));
-            iterator_queue.push_back(error);
+impl<'a> DirWalker<'a> {
+    /// Walks the whole tree under the path, following symlinks, in the order of the filesystem
+    pub fn new(fs: &'a dyn Fs, path: &Path) -> Self {
+        Self {
+            fs,
+            iterator_queue: VecDeque::new(),
+            started: false,
+            max_depth: None,
+            ignored_dirs: Vec::new(),
+            follow_symlinks: true,
+            sort: false,
+            ignored: Vec::new(),
+            unreadable: Vec::new(),
+            root: path.to_path_buf(),
+            max_entries: None,
+            walked: 0,
+            entered: fs.file_id(path).into_iter().collect(),
+        }
+    }
+
+    /// Walks only the given number of levels, 1 for the entries of the directory alone. None
+    /// walks the whole tree
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Does not enter the directories with one of the names, they are still yielded
+    pub fn with_ignored_dirs(mut self, ignored_dirs: Vec<String>) -> Self {
+        self.ignored_dirs = ignored_dirs;
+        self
+    }
+
+    /// Whether the directories reached through a symlink are entered, they are by default
+    pub fn with_follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Yields the entries of every directory in the order of their names, rather than in the one
+    /// of the filesystem
+    pub fn with_sorting(mut self) -> Self {
+        self.sort = true;
+        self
+    }
+
+    /// Stops the walk after the given number of entries, so that a path set by mistake to a huge
+    /// tree cannot walk forever
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Whether the directory was not entered yet, its inode is recorded as entered. Directories
+    /// are always entered on the platforms without inodes
+    fn first_entry(&mut self, dir: &Path) -> bool {
+        self.fs.file_id(dir).map_or(true, |id| self.entered.insert(id))
+    }
+
+    /// The directories skipped so far because their name is ignored
+    pub fn ignored(&self) -> &[PathBuf] {
+        &self.ignored
+    }
+
+    /// Takes the directories below the walked one that could not be read so far, with why. The
+    /// walk goes on with the rest of the tree without them
+    pub fn take_unreadable(&mut self) -> Vec<(PathBuf, Error)> {
+        std::mem::take(&mut self.unreadable)
+    }
+
+    fn read_dir(&self, dir: &Path) -> Result<vec::IntoIter<PathBuf>, Error> {
+        let mut entries = self.fs.read_dir(dir)?;
+        if self.sort {
+            entries.sort();
+        }
+        Ok(entries.into_iter())
+    }
+
+    /// Queues the entries of the directory found at the depth, unless it is not to be entered
+    fn enter(&mut self, dir: &Path, depth: usize) {
+        if get_filename(dir).is_some_and(|name| self.ignored_dirs.contains(&name)) {
+            debug!("Ignoring directory {} because excluded", dir.display());
+            self.ignored.push(dir.to_path_buf());
+        } else if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            debug!("Not entering {}, it is deeper than the max depth", dir.display());
+        } else if !self.follow_symlinks && self.fs.is_symlink(dir) {
+            debug!("Not entering {}, it is a symlink", dir.display());
+        } else if !self.first_entry(dir) {
+            warn!("Not entering {}, it loops back to a directory already walked", dir.display());
         } else {
-            let iter = fs::read_dir(path);
-            iterator_queue.push_back(iter);
+            match self.read_dir(dir) {
+                Ok(entries) => {
+                    debug!("Adding directory to iteration queue {}", dir.display());
+                    self.iterator_queue.push_back((entries, depth + 1));
+                }
+                Err(error) => {
+                    warn!("Could not read the directory {}, leaving it out: {}", dir.display(), error);
+                    self.unreadable.push((dir.to_path_buf(), error));
+                }
+            }
         }
+    }
 
-        Self {
-            iterator_queue,
-            max_depth,
-            ignored_dirs,
+    fn entry(&self, path: PathBuf, depth: usize, is_dir: bool) -> Entry {
+        let is_file = !is_dir && self.fs.is_file(&path);
+        Entry {
+            size: if is_file { self.fs.file_size(&path).unwrap_or(0) } else { 0 },
+            modified: self.fs.modified(&path).ok(),
+            path,
+            depth,
+            is_dir,
+            is_file,
         }
     }
 }
 
-impl Iterator for DirWalker {
-    type Item = Result<DirEntry, Error>;
+impl Iterator for DirWalker<'_> {
+    type Item = Result<Entry, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(iter_res) = self.iterator_queue.pop_front() {
-            if self.max_depth.is_some_and(|val| val <= 0) {
-                break
+        if !self.started {
+            self.started = true;
+            if !self.fs.is_dir(&self.root) {
+                return Some(Err(Error::new(
+                    std::io::ErrorKind::NotADirectory,
+                    format!("The file at {} is not a directory", self.root.display()),
+                )));
+            }
+            match self.read_dir(&self.root) {
+                Ok(entries) => self.iterator_queue.push_back((entries, 1)),
+                Err(error) => return Some(Err(error)),
             }
+        }
 
-            match iter_res {
-                Ok(mut iter) => {
-                    if let Some(item) = iter.next() {
-                        if let Ok(entry) = &item {
-                            if entry.path().is_dir() {
-                                if get_filename(&entry.path()).is_some_and(|name| self.ignored_dirs.contains(&name)) {
-                                    debug!("Ignoring directory {} because excluded", entry.path().display());
-                                } else {
-                                    debug!("Adding directory to iteration queue {}", entry.path().display());
-                                    self.iterator_queue.push_back(fs::read_dir(entry.path()));
-                                }
-                            }
-                        }
-                        // put back the iterator in front of the queue, it may be not exhausted yet
-                        self.iterator_queue.push_front(Ok(iter));
-                        return Some(item);
-                    } 
-                    // else {
-                    // the iterator is exausted, try with the next in the queue
-                    self.max_depth = self.max_depth.map(|val| val - 1);
-                    // continue;
-                    // }
-                }
-                Err(e) => return Some(Err(e)),
+        while let Some((mut entries, depth)) = self.iterator_queue.pop_front() {
+            if self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+                continue;
             }
+            // the directory is exhausted, go on with the next in the queue
+            let Some(path) = entries.next() else {
+                continue;
+            };
+            // put back the iterator in front of the queue, it may be not exhausted yet
+            self.iterator_queue.push_front((entries, depth));
+            // fifos and devices could block or never end when read
+            if self.fs.is_special(&path) {
+                debug!("Skipping {}, it is not a regular file", path.display());
+                continue;
+            }
+            self.walked += 1;
+            if let Some(max_entries) = self.max_entries.filter(|max_entries| self.walked > *max_entries) {
+                warn!(
+                    "Stopped walking {} after {} entries, the rest of it is left as it is",
+                    self.root.display(),
+                    max_entries
+                );
+                self.iterator_queue.clear();
+                return None;
+            }
+            let is_dir = self.fs.is_dir(&path);
+            if is_dir {
+                self.enter(&path, depth);
+            }
+            return Some(Ok(self.entry(path, depth, is_dir)));
         }
         // all iterators are exhausted, done
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::filesystem::MemoryFs;
+
+    use super::*;
+
+    #[test]
+    fn walks_in_memory_tree_skipping_ignored_dirs() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Show.S01E01.mkv", b"");
+        fs.add_file("downloads/Release/Show.S01E02.mkv", b"");
+        fs.add_file("downloads/Release/Sample/sample.mkv", b"");
+
+        let paths: Vec<PathBuf> = DirWalker::new(&fs, Path::new("downloads"))
+            .with_ignored_dirs(vec!["Sample".to_string()])
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.is_file)
+            .map(|entry| entry.path)
+            .collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("downloads/Show.S01E01.mkv"),
+                PathBuf::from("downloads/Release/Show.S01E02.mkv"),
+            ]
+        );
+    }
+
+    #[test]
+    fn loops_special_files_and_huge_trees_are_not_walked() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Release/Show.S01E01.mkv", b"");
+        fs.add_file("downloads/Release/again/Show.S01E02.mkv", b"");
+        fs.bind("downloads/Release/again", "downloads");
+        fs.add_special("downloads/Release/pipe.mkv");
+
+        let paths: Vec<PathBuf> = DirWalker::new(&fs, Path::new("downloads"))
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("downloads/Release"),
+                PathBuf::from("downloads/Release/Show.S01E01.mkv"),
+                PathBuf::from("downloads/Release/again"),
+            ]
+        );
+
+        let walked = DirWalker::new(&fs, Path::new("downloads"))
+            .with_max_entries(2)
+            .count();
+        assert_eq!(walked, 2);
+    }
+
+    #[test]
+    fn unreadable_directories_are_reported_and_their_siblings_walked() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/A/Show.S01E01.mkv", b"");
+        fs.add_file("downloads/B/Show.S01E02.mkv", b"");
+        fs.add_file("downloads/C/Show.S01E03.mkv", b"");
+        fs.set_unreadable("downloads/B");
+
+        let mut walker = DirWalker::new(&fs, Path::new("downloads"));
+        let files: Vec<PathBuf> = walker
+            .by_ref()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.is_file)
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("downloads/A/Show.S01E01.mkv"),
+                PathBuf::from("downloads/C/Show.S01E03.mkv"),
+            ]
+        );
+        let unreadable = walker.take_unreadable();
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0].0, PathBuf::from("downloads/B"));
+        assert_eq!(unreadable[0].1.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn entries_carry_their_depth_size_and_time_in_order() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/b.mkv", b"episode");
+        fs.add_file("downloads/a/Show.S01E01.mkv", b"");
+        fs.add_file("downloads/a/deeper/Show.S01E02.mkv", b"");
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(60);
+        fs.set_modified("downloads/b.mkv", modified);
+
+        let entries: Vec<Entry> = DirWalker::new(&fs, Path::new("downloads"))
+            .with_max_depth(Some(2))
+            .with_sorting()
+            .map(|entry| entry.unwrap())
+            .collect();
+        let walked: Vec<(&Path, usize)> = entries.iter().map(|entry| (entry.path.as_path(), entry.depth)).collect();
+        assert_eq!(
+            walked,
+            vec![
+                (Path::new("downloads/a"), 1),
+                (Path::new("downloads/b.mkv"), 1),
+                (Path::new("downloads/a/Show.S01E01.mkv"), 2),
+                (Path::new("downloads/a/deeper"), 2),
+            ]
+        );
+        assert_eq!(
+            entries[1],
+            Entry {
+                path: PathBuf::from("downloads/b.mkv"),
+                depth: 1,
+                is_dir: false,
+                is_file: true,
+                size: 7,
+                modified: Some(modified),
+            }
+        );
+        assert!(entries[0].is_dir && !entries[0].is_file && entries[0].size == 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_are_entered_only_when_followed() {
+        use crate::filesystem::RealFs;
+
+        let dir = std::env::temp_dir().join(format!("media-renamer-walker-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("elsewhere")).unwrap();
+        std::fs::create_dir_all(dir.join("downloads")).unwrap();
+        std::fs::write(dir.join("elsewhere").join("Show.S01E01.mkv"), b"").unwrap();
+        std::os::unix::fs::symlink(dir.join("elsewhere"), dir.join("downloads").join("linked")).unwrap();
+
+        let files = |follow_symlinks| {
+            DirWalker::new(&RealFs, &dir.join("downloads"))
+                .with_follow_symlinks(follow_symlinks)
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.is_file)
+                .count()
+        };
+        let (followed, not_followed) = (files(true), files(false));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((followed, not_followed), (1, 0));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use log::debug;
+
+use crate::{filesystem::Fs, path_utils::get_extension};
+
+/// How much of the start and of the end of a file is searched for its title. MKV files keep it
+/// near the start, MP4 files at either end depending on how they were written
+const SCAN_BYTES: u64 = 1 << 20;
+
+/// The id of the Title element of the segment information of MKV files
+const MKV_TITLE: [u8; 2] = [0x7B, 0xA9];
+
+/// The atom of the title of MP4 files, `©nam`
+const MP4_TITLE: [u8; 4] = [0xA9, b'n', b'a', b'm'];
+
+/// The longest title taken, longer ones are more likely bytes of the video than a title
+const MAX_TITLE_LENGTH: usize = 255;
+
+/// The title stored in the tags of an MKV or MP4 file, like `Movie.2020.1080p.BluRay-GROUP`,
+/// none when the file has no title or is of another container
+pub fn title(fs: &dyn Fs, path: &Path) -> Option<String> {
+    let find: fn(&[u8]) -> Option<String> = match get_extension(path)?.to_lowercase().as_str() {
+        "mkv" | "mk3d" | "webm" => mkv_title,
+        "mp4" | "m4v" | "mov" => mp4_title,
+        _ => return None,
+    };
+
+    let size = fs.file_size(path).ok()?;
+    let mut file = fs.open_read(path).ok()?;
+    let mut start = Vec::new();
+    file.by_ref().take(SCAN_BYTES).read_to_end(&mut start).ok()?;
+    let title = find(&start).or_else(|| {
+        if size <= SCAN_BYTES * 2 {
+            return None;
+        }
+        file.seek(SeekFrom::End(-(SCAN_BYTES as i64))).ok()?;
+        let mut end = Vec::new();
+        file.read_to_end(&mut end).ok()?;
+        find(&end)
+    })?;
+    debug!("Found the title {} in {}", title, path.display());
+    Some(title)
+}
+
+/// The first MKV Title element of the bytes, its size is a variable-length integer of one or two bytes
+fn mkv_title(bytes: &[u8]) -> Option<String> {
+    positions(bytes, &MKV_TITLE).find_map(|position| {
+        let rest = &bytes[position + MKV_TITLE.len()..];
+        let (length, rest) = match *rest.first()? {
+            first if first & 0x80 != 0 => (usize::from(first & 0x7F), &rest[1..]),
+            first if first & 0x40 != 0 => (usize::from(first & 0x3F) << 8 | usize::from(*rest.get(1)?), rest.get(2..)?),
+            _ => return None,
+        };
+        text(rest.get(..length)?)
+    })
+}
+
+/// The first MP4 title of the bytes: the `data` atom in the `©nam` atom holds the type and the
+/// locale of the value before the text
+fn mp4_title(bytes: &[u8]) -> Option<String> {
+    positions(bytes, &MP4_TITLE).find_map(|position| {
+        let data = bytes.get(position + MP4_TITLE.len()..)?;
+        let size = usize::try_from(u32::from_be_bytes(data.get(..4)?.try_into().ok()?)).ok()?;
+        if data.get(4..8)? != b"data" || size < 16 {
+            return None;
+        }
+        text(data.get(16..size)?)
+    })
+}
+
+/// Where the pattern is in the bytes
+fn positions<'a>(bytes: &'a [u8], pattern: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    bytes
+        .windows(pattern.len())
+        .enumerate()
+        .filter(move |(_, window)| *window == pattern)
+        .map(|(position, _)| position)
+}
+
+/// The bytes as a title, when they are printable UTF-8 of a sensible length
+fn text(bytes: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(bytes).ok()?.trim();
+    (!text.is_empty() && text.len() <= MAX_TITLE_LENGTH && !text.chars().any(char::is_control)).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+
+    #[test]
+    fn titles_are_read_from_mkv_and_mp4_tags() {
+        let fs = MemoryFs::new();
+        let name = b"Movie.2020.1080p.BluRay-GROUP";
+
+        let mut mkv = vec![0x1A, 0x45, 0xDF, 0xA3, 0x00, 0x15, 0x49, 0xA9, 0x66];
+        mkv.extend_from_slice(&MKV_TITLE);
+        mkv.push(0x80 | name.len() as u8);
+        mkv.extend_from_slice(name);
+        mkv.extend_from_slice(&[0x1F, 0x43, 0xB6, 0x75]);
+        fs.add_file("downloads/abc123.mkv", &mkv);
+
+        let mut mp4 = b"\x00\x00\x00\x18ftypisom".to_vec();
+        mp4.extend_from_slice(&MP4_TITLE);
+        mp4.extend_from_slice(&(16 + name.len() as u32).to_be_bytes());
+        mp4.extend_from_slice(b"data\x00\x00\x00\x01\x00\x00\x00\x00");
+        mp4.extend_from_slice(name);
+        fs.add_file("downloads/abc123.mp4", &mp4);
+        fs.add_file("downloads/abc123.avi", &mkv);
+
+        let read = |path: &str| title(&fs, Path::new(path));
+        assert_eq!(read("downloads/abc123.mkv").as_deref(), Some("Movie.2020.1080p.BluRay-GROUP"));
+        assert_eq!(read("downloads/abc123.mp4").as_deref(), Some("Movie.2020.1080p.BluRay-GROUP"));
+        assert_eq!(read("downloads/abc123.avi"), None);
+    }
+}
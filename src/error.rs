@@ -0,0 +1,188 @@
+use std::{fmt::Display, io, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::provider::{ProviderError, ProviderKind};
+
+/// The stage of the pipeline an error happened in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Config,
+    ReadDir,
+    Search,
+    CreateDir,
+    Move,
+    Copy,
+    Symlink,
+    Hardlink,
+    Replace,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stage = match self {
+            Stage::Config => "config",
+            Stage::ReadDir => "read directory",
+            Stage::Search => "search",
+            Stage::CreateDir => "create directory",
+            Stage::Move => "move",
+            Stage::Copy => "copy",
+            Stage::Symlink => "symlink",
+            Stage::Hardlink => "hardlink",
+            Stage::Replace => "replace",
+        };
+        write!(f, "{}", stage)
+    }
+}
+
+/// Why a file was skipped, as a stable code for the JSON report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    ExtensionFiltered,
+    NonUtf8,
+    IgnoredDir,
+    Orphan,
+    MainSkipped,
+    TooSmall,
+    InProgress,
+    Junk,
+    Unparsed,
+    NotWanted,
+    NotSelected,
+    IgnoredTitle,
+    NotFound,
+    Exists,
+    Conflict,
+    Declined,
+}
+
+/// The errors of media-renamer, each carrying the file and stage it happened at
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{} does not have one of the configured extensions", path.display())]
+    ExtensionFiltered { path: PathBuf },
+
+    #[error("{} skipped: non-UTF-8 name", path.display())]
+    NonUtf8 { path: PathBuf },
+
+    #[error("{} is an ignored directory", path.display())]
+    IgnoredDir { path: PathBuf },
+
+    #[error("{} has no file placed in this run to go with", path.display())]
+    Orphan { path: PathBuf },
+
+    #[error("{} goes with {}, which was not placed", path.display(), main.display())]
+    MainSkipped { path: PathBuf, main: PathBuf },
+
+    #[error("{} is smaller than {min_size} bytes", path.display())]
+    TooSmall { path: PathBuf, min_size: u64 },
+
+    #[error("{} was modified in the last {settle_time} seconds and may still be downloading", path.display())]
+    InProgress { path: PathBuf, settle_time: u64 },
+
+    #[error("Could not parse filename {}", path.display())]
+    Parse { path: PathBuf },
+
+    #[error("{} is not a media title ({reason})", path.display())]
+    Junk { path: PathBuf, reason: &'static str },
+
+    #[error("{} is {title}, which is not on the want-list", path.display())]
+    NotWanted { path: PathBuf, title: String },
+
+    #[error("{} is not selected by --only, --only-tvdb-id, --season or --episodes", path.display())]
+    NotSelected { path: PathBuf },
+
+    #[error("{} is {title}, ignored by the ignored_titles entry {entry}", path.display())]
+    IgnoredTitle { path: PathBuf, title: String, entry: String },
+
+    #[error("Could not find {name} on {providers} (from {})", path.display())]
+    NotFound {
+        path: PathBuf,
+        name: String,
+        /// The providers that were asked, e.g. `TVDB, TMDB`
+        providers: String,
+    },
+
+    #[error("{provider} error at {stage} stage for {}: {source}", path.display())]
+    Provider {
+        path: PathBuf,
+        stage: Stage,
+        provider: ProviderKind,
+        source: ProviderError,
+    },
+
+    #[error("Filesystem error at {stage} stage for {}: {source}", path.display())]
+    Filesystem {
+        path: PathBuf,
+        stage: Stage,
+        source: io::Error,
+    },
+
+    #[error("File {} already exists", path.display())]
+    AlreadyExists { path: PathBuf },
+
+    #[error(
+        "{} would also be placed at {}, already claimed in this run by {}",
+        path.display(),
+        destination.display(),
+        claimed_by.display()
+    )]
+    Conflict {
+        path: PathBuf,
+        destination: PathBuf,
+        claimed_by: PathBuf,
+    },
+
+    #[error("{} would be placed at {}, which {problem}", path.display(), destination.display())]
+    InvalidName {
+        path: PathBuf,
+        destination: PathBuf,
+        problem: String,
+    },
+
+    #[error("{} was skipped by the user", path.display())]
+    Declined { path: PathBuf },
+
+    #[error("Config error in {}: {message}", path.display())]
+    Config { path: PathBuf, message: String },
+
+    #[error("Output directory {} {message}", path.display())]
+    Preflight { path: PathBuf, message: String },
+}
+
+impl Error {
+    /// Whether the error only means the file was skipped rather than something going wrong
+    pub fn is_skip(&self) -> bool {
+        self.skip_reason().is_some()
+    }
+
+    /// Why the file was skipped, none when something went wrong
+    pub fn skip_reason(&self) -> Option<SkipReason> {
+        match self {
+            Error::ExtensionFiltered { .. } => Some(SkipReason::ExtensionFiltered),
+            Error::IgnoredDir { .. } => Some(SkipReason::IgnoredDir),
+            Error::NonUtf8 { .. } => Some(SkipReason::NonUtf8),
+            Error::Orphan { .. } => Some(SkipReason::Orphan),
+            Error::MainSkipped { .. } => Some(SkipReason::MainSkipped),
+            Error::TooSmall { .. } => Some(SkipReason::TooSmall),
+            Error::InProgress { .. } => Some(SkipReason::InProgress),
+            Error::Junk { .. } => Some(SkipReason::Junk),
+            Error::Parse { .. } => Some(SkipReason::Unparsed),
+            Error::NotWanted { .. } => Some(SkipReason::NotWanted),
+            Error::NotSelected { .. } => Some(SkipReason::NotSelected),
+            Error::IgnoredTitle { .. } => Some(SkipReason::IgnoredTitle),
+            Error::NotFound { .. } => Some(SkipReason::NotFound),
+            Error::AlreadyExists { .. } => Some(SkipReason::Exists),
+            Error::Conflict { .. } => Some(SkipReason::Conflict),
+            Error::Declined { .. } => Some(SkipReason::Declined),
+            Error::Provider { .. }
+            | Error::InvalidName { .. }
+            | Error::Filesystem { .. }
+            | Error::Config { .. }
+            | Error::Preflight { .. } => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
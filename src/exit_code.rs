@@ -0,0 +1,29 @@
+use std::process::ExitCode;
+
+use serde::Serialize;
+
+/// How a run ended, the exit code of the process for the scripts run after it. The argument
+/// errors of clap exit with 2, which is why no outcome uses it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// Every file was imported or deliberately skipped
+    Success = 0,
+    /// The run could not start or stopped on an error of its own, like missing arguments or an
+    /// output directory that cannot be written
+    Error = 1,
+    /// Some files could not be parsed or found at the metadata providers
+    Unmatched = 3,
+    /// Moving, copying or linking some files failed, or their destination was invalid
+    Failed = 4,
+    /// The configuration could not be read
+    Config = 5,
+    /// A metadata provider refused to log in, usually for a wrong API key
+    ProviderAuth = 6,
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        ExitCode::from(outcome as u8)
+    }
+}
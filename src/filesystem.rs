@@ -0,0 +1,980 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Cursor, Read, Seek, Write},
+    os,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::trash;
+
+/// A readable and seekable file
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// The filesystem operations used by media-renamer, so that the decision logic
+/// can run against the real filesystem or an in-memory one
+pub trait Fs: Send + Sync {
+    fn exists(&self, path: &Path) -> bool;
+
+    fn is_file(&self, path: &Path) -> bool;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    fn file_size(&self, path: &Path) -> Result<u64, io::Error>;
+
+    /// The last modification time of the file
+    fn modified(&self, path: &Path) -> Result<SystemTime, io::Error>;
+
+    /// The space available to the user on the filesystem containing the path
+    fn available_space(&self, path: &Path) -> Result<u64, io::Error>;
+
+    /// The id of the filesystem containing the path, files are only renamed within one
+    fn device(&self, path: &Path) -> Result<u64, io::Error>;
+
+    /// The device and inode of the path, the same for every path reaching the same file or
+    /// directory through symlinks or bind mounts
+    fn file_id(&self, path: &Path) -> Result<(u64, u64), io::Error>;
+
+    /// Whether the path is a fifo, a socket or a device node, that are not read like files
+    fn is_special(&self, path: &Path) -> bool;
+
+    /// Whether the path itself is a symlink, whatever it points to
+    fn is_symlink(&self, path: &Path) -> bool;
+
+    /// The paths of the entries of the directory
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>, io::Error>;
+
+    fn create_dir_all(&self, path: &Path) -> Result<(), io::Error>;
+
+    /// Removes an empty directory
+    fn remove_dir(&self, path: &Path) -> Result<(), io::Error>;
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error>;
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<(), io::Error>;
+
+    /// Creates a hard link, both paths must be on the same filesystem
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<(), io::Error>;
+
+    /// Creates `copy` sharing the data of `original` until either is written to, without copying
+    /// it. Only copy-on-write filesystems like btrfs, XFS and APFS can, within one filesystem
+    fn reflink(&self, original: &Path, copy: &Path) -> Result<(), io::Error>;
+
+    /// Gives `to` the modification time, the permissions and the extended attributes of `from`
+    fn copy_attributes(&self, from: &Path, to: &Path) -> Result<(), io::Error>;
+
+    /// Changes the owner and the group of the path, the ones given as none are kept
+    fn set_owner(&self, path: &Path, owner: Option<u32>, group: Option<u32>) -> Result<(), io::Error>;
+
+    /// Sets the unix permission bits of the path, like `0o664`
+    fn set_mode(&self, path: &Path, mode: u32) -> Result<(), io::Error>;
+
+    /// Removes a file or a symlink
+    fn remove_file(&self, path: &Path) -> Result<(), io::Error>;
+
+    /// Sends the file to the trash of the OS, from where the user can restore it. Returns where
+    /// the file is in the trash when the OS tells it
+    fn trash(&self, path: &Path) -> Result<Option<PathBuf>, io::Error>;
+
+    /// The absolute path with every symlink resolved
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, io::Error>;
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn ReadSeek>, io::Error>;
+
+    /// Opens the file for writing, truncating it unless `append` is set
+    fn open_write(&self, path: &Path, append: bool) -> Result<Box<dyn Write>, io::Error>;
+
+    fn read_to_string(&self, path: &Path) -> Result<String, io::Error> {
+        let mut contents = String::new();
+        self.open_read(path)?.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+}
+
+/// The real filesystem
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn file_size(&self, path: &Path) -> Result<u64, io::Error> {
+        Ok(path.metadata()?.len())
+    }
+
+    fn modified(&self, path: &Path) -> Result<SystemTime, io::Error> {
+        path.metadata()?.modified()
+    }
+
+    fn available_space(&self, path: &Path) -> Result<u64, io::Error> {
+        fs4::available_space(path)
+    }
+
+    #[cfg(unix)]
+    fn device(&self, path: &Path) -> Result<u64, io::Error> {
+        use std::os::unix::fs::MetadataExt;
+        Ok(path.metadata()?.dev())
+    }
+
+    #[cfg(not(unix))]
+    fn device(&self, path: &Path) -> Result<u64, io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("the filesystem of {} cannot be told on this platform", path.display()),
+        ))
+    }
+
+    #[cfg(unix)]
+    fn file_id(&self, path: &Path) -> Result<(u64, u64), io::Error> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = path.metadata()?;
+        Ok((metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn file_id(&self, path: &Path) -> Result<(u64, u64), io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("the inode of {} cannot be told on this platform", path.display()),
+        ))
+    }
+
+    #[cfg(unix)]
+    fn is_special(&self, path: &Path) -> bool {
+        use std::os::unix::fs::FileTypeExt;
+        path.metadata().is_ok_and(|metadata| {
+            let file_type = metadata.file_type();
+            file_type.is_fifo() || file_type.is_socket() || file_type.is_block_device() || file_type.is_char_device()
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn is_special(&self, _path: &Path) -> bool {
+        false
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        path.is_symlink()
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<(), io::Error> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), io::Error> {
+        fs::remove_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        fs::rename(from, to)
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<(), io::Error> {
+        let original_absolute = original.canonicalize()?;
+        #[cfg(windows)]
+        {
+            os::windows::fs::symlink_file(original_absolute, link)
+        }
+        // every unix, so that macOS and the BSDs of NAS boxes get symlinks as Linux does
+        #[cfg(unix)]
+        {
+            os::unix::fs::symlink(original_absolute, link)
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = (original_absolute, link);
+            Err(io::Error::new(io::ErrorKind::Unsupported, "symlinks are not supported on this system"))
+        }
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<(), io::Error> {
+        fs::hard_link(original, link)
+    }
+
+    fn reflink(&self, original: &Path, copy: &Path) -> Result<(), io::Error> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+
+            let source = File::open(original)?;
+            let destination = File::create(copy)?;
+            // SAFETY: both descriptors stay open for the duration of the call
+            if unsafe { libc::ioctl(destination.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == 0 {
+                return Ok(());
+            }
+            let error = io::Error::last_os_error();
+            drop(destination);
+            let _ = fs::remove_file(copy);
+            Err(error)
+        }
+        #[cfg(target_os = "macos")]
+        {
+            use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+            let c_path = |path: &Path| {
+                CString::new(path.as_os_str().as_bytes()).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))
+            };
+            let (original, copy) = (c_path(original)?, c_path(copy)?);
+            // SAFETY: both paths are NUL-terminated and outlive the call
+            if unsafe { libc::clonefile(original.as_ptr(), copy.as_ptr(), 0) } == 0 {
+                return Ok(());
+            }
+            Err(io::Error::last_os_error())
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        {
+            let _ = (original, copy);
+            Err(io::Error::new(io::ErrorKind::Unsupported, "reflinks are not supported on this system"))
+        }
+    }
+
+    fn copy_attributes(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        let metadata = fs::metadata(from)?;
+        OpenOptions::new().write(true).open(to)?.set_modified(metadata.modified()?)?;
+        #[cfg(target_os = "linux")]
+        copy_xattrs(from, to)?;
+        // last, a read-only source would make the copy unwritable before the rest is set
+        fs::set_permissions(to, metadata.permissions())
+    }
+
+    fn set_owner(&self, path: &Path, owner: Option<u32>, group: Option<u32>) -> Result<(), io::Error> {
+        #[cfg(unix)]
+        {
+            os::unix::fs::chown(path, owner, group)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (path, owner, group);
+            Err(io::Error::new(io::ErrorKind::Unsupported, "owners can only be set on unix"))
+        }
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> Result<(), io::Error> {
+        #[cfg(unix)]
+        {
+            use os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (path, mode);
+            Err(io::Error::new(io::ErrorKind::Unsupported, "permission bits can only be set on unix"))
+        }
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), io::Error> {
+        fs::remove_file(path)
+    }
+
+    fn trash(&self, path: &Path) -> Result<Option<PathBuf>, io::Error> {
+        trash::trash(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, io::Error> {
+        path.canonicalize()
+    }
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn ReadSeek>, io::Error> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn open_write(&self, path: &Path, append: bool) -> Result<Box<dyn Write>, io::Error> {
+        let file = if append {
+            OpenOptions::new().append(true).open(path)?
+        } else {
+            File::create(path)?
+        };
+        Ok(Box::new(file))
+    }
+}
+
+/// Copies the extended attributes of `from` to `to`. The ones the destination filesystem does not
+/// support or the user may not set, like those of the `security` namespace, are left out
+#[cfg(target_os = "linux")]
+fn copy_xattrs(from: &Path, to: &Path) -> Result<(), io::Error> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path = |path: &Path| CString::new(path.as_os_str().as_bytes()).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error));
+    let (from, to) = (c_path(from)?, c_path(to)?);
+    // a size of 0 asks for the size the list or the value needs
+    let size = |result: isize| usize::try_from(result).map_err(|_| io::Error::last_os_error());
+
+    // SAFETY: the paths are NUL-terminated and the buffers are given with their lengths
+    let mut names = vec![0u8; size(unsafe { libc::listxattr(from.as_ptr(), std::ptr::null_mut(), 0) })?];
+    let length = size(unsafe { libc::listxattr(from.as_ptr(), names.as_mut_ptr().cast(), names.len()) })?;
+    names.truncate(length);
+    for name in names.split(|byte| *byte == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        let mut value = vec![0u8; size(unsafe { libc::getxattr(from.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) })?];
+        let length = size(unsafe { libc::getxattr(from.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast(), value.len()) })?;
+        let set = unsafe { libc::setxattr(to.as_ptr(), name.as_ptr(), value.as_ptr().cast(), length, 0) };
+        if set != 0 {
+            let error = io::Error::last_os_error();
+            if !matches!(error.raw_os_error(), Some(libc::ENOTSUP | libc::EPERM)) {
+                return Err(error);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryNode {
+    File(Vec<u8>),
+    Dir,
+    Symlink(PathBuf),
+    /// A fifo, a socket or a device node
+    Special,
+}
+
+/// The filesystem of test runs: it reads through to the wrapped one and refuses every change, so
+/// that a dry run cannot touch the disk even when a code path forgets to check the action
+pub struct ReadOnlyFs<'a>(pub &'a dyn Fs);
+
+impl ReadOnlyFs<'_> {
+    fn refuse(path: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("dry run, {} is not changed", path.display()),
+        )
+    }
+}
+
+impl Fs for ReadOnlyFs<'_> {
+    fn exists(&self, path: &Path) -> bool {
+        self.0.exists(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.0.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.0.is_dir(path)
+    }
+
+    fn file_size(&self, path: &Path) -> Result<u64, io::Error> {
+        self.0.file_size(path)
+    }
+
+    fn modified(&self, path: &Path) -> Result<SystemTime, io::Error> {
+        self.0.modified(path)
+    }
+
+    fn available_space(&self, path: &Path) -> Result<u64, io::Error> {
+        self.0.available_space(path)
+    }
+
+    fn device(&self, path: &Path) -> Result<u64, io::Error> {
+        self.0.device(path)
+    }
+
+    fn file_id(&self, path: &Path) -> Result<(u64, u64), io::Error> {
+        self.0.file_id(path)
+    }
+
+    fn is_special(&self, path: &Path) -> bool {
+        self.0.is_special(path)
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        self.0.is_symlink(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+        self.0.read_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<(), io::Error> {
+        Err(Self::refuse(path))
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), io::Error> {
+        Err(Self::refuse(path))
+    }
+
+    fn rename(&self, from: &Path, _to: &Path) -> Result<(), io::Error> {
+        Err(Self::refuse(from))
+    }
+
+    fn symlink(&self, _original: &Path, link: &Path) -> Result<(), io::Error> {
+        Err(Self::refuse(link))
+    }
+
+    fn hard_link(&self, _original: &Path, link: &Path) -> Result<(), io::Error> {
+        Err(Self::refuse(link))
+    }
+
+    fn reflink(&self, _original: &Path, copy: &Path) -> Result<(), io::Error> {
+        Err(Self::refuse(copy))
+    }
+
+    fn copy_attributes(&self, _from: &Path, to: &Path) -> Result<(), io::Error> {
+        Err(Self::refuse(to))
+    }
+
+    fn set_owner(&self, path: &Path, _owner: Option<u32>, _group: Option<u32>) -> Result<(), io::Error> {
+        Err(Self::refuse(path))
+    }
+
+    fn set_mode(&self, path: &Path, _mode: u32) -> Result<(), io::Error> {
+        Err(Self::refuse(path))
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), io::Error> {
+        Err(Self::refuse(path))
+    }
+
+    fn trash(&self, path: &Path) -> Result<Option<PathBuf>, io::Error> {
+        Err(Self::refuse(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, io::Error> {
+        self.0.canonicalize(path)
+    }
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn ReadSeek>, io::Error> {
+        self.0.open_read(path)
+    }
+
+    fn open_write(&self, path: &Path, _append: bool) -> Result<Box<dyn Write>, io::Error> {
+        Err(Self::refuse(path))
+    }
+}
+
+/// The owner and the group of a path, none for the ones never set
+type Ownership = (Option<u32>, Option<u32>);
+
+/// An in-memory filesystem, nothing it does is visible outside of it
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFs {
+    nodes: Arc<Mutex<BTreeMap<PathBuf, MemoryNode>>>,
+    modified: Arc<Mutex<BTreeMap<PathBuf, SystemTime>>>,
+    available_space: Arc<Mutex<BTreeMap<PathBuf, u64>>>,
+    devices: Arc<Mutex<BTreeMap<PathBuf, u32>>>,
+    bound: Arc<Mutex<BTreeMap<PathBuf, PathBuf>>>,
+    unreadable: Arc<Mutex<Vec<PathBuf>>>,
+    reflinks: Arc<Mutex<Vec<PathBuf>>>,
+    modes: Arc<Mutex<BTreeMap<PathBuf, u32>>>,
+    owners: Arc<Mutex<BTreeMap<PathBuf, Ownership>>>,
+}
+
+#[allow(dead_code)]
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file with the given contents, creating its parent directories
+    pub fn add_file<P: AsRef<Path>>(&self, path: P, contents: &[u8]) {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)
+                .expect("Could not create the parent directories");
+        }
+        self.nodes
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), MemoryNode::File(contents.to_vec()));
+    }
+
+    /// Sets the modification time of a path, paths without one were modified at the epoch
+    pub fn set_modified<P: AsRef<Path>>(&self, path: P, modified: SystemTime) {
+        self.modified
+            .lock()
+            .unwrap()
+            .insert(path.as_ref().to_path_buf(), modified);
+    }
+
+    /// Sets the available space for everything under the path, by default the space is unlimited
+    pub fn set_available_space<P: AsRef<Path>>(&self, path: P, space: u64) {
+        self.available_space
+            .lock()
+            .unwrap()
+            .insert(path.as_ref().to_path_buf(), space);
+    }
+
+    /// Puts everything under the path on another device, files cannot be renamed or hard linked
+    /// across devices. By default everything is on device 0
+    pub fn set_device<P: AsRef<Path>>(&self, path: P, device: u32) {
+        self.devices
+            .lock()
+            .unwrap()
+            .insert(path.as_ref().to_path_buf(), device);
+    }
+
+    /// Adds a fifo, a socket or a device node, creating its parent directories
+    pub fn add_special<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)
+                .expect("Could not create the parent directories");
+        }
+        self.nodes.lock().unwrap().insert(path.to_path_buf(), MemoryNode::Special);
+    }
+
+    /// Makes the path the same directory as the target, as a bind mount of it would be: both
+    /// have the same inode. By default every path has an inode of its own
+    pub fn bind<P: AsRef<Path>, Q: AsRef<Path>>(&self, path: P, target: Q) {
+        self.bound
+            .lock()
+            .unwrap()
+            .insert(path.as_ref().to_path_buf(), target.as_ref().to_path_buf());
+    }
+
+    /// Makes listing the directory fail as if its permissions did not allow it
+    pub fn set_unreadable<P: AsRef<Path>>(&self, path: P) {
+        self.unreadable.lock().unwrap().push(path.as_ref().to_path_buf());
+    }
+
+    /// Makes the filesystem under the path copy-on-write, files in it can be reflinked. By default
+    /// reflinks are not supported
+    pub fn set_reflinks<P: AsRef<Path>>(&self, path: P) {
+        self.reflinks.lock().unwrap().push(path.as_ref().to_path_buf());
+    }
+
+    /// The permission bits set on the path, none when they were never set
+    pub fn mode<P: AsRef<Path>>(&self, path: P) -> Option<u32> {
+        self.modes.lock().unwrap().get(path.as_ref()).copied()
+    }
+
+    /// The owner and group set on the path, none when they were never set
+    pub fn owner<P: AsRef<Path>>(&self, path: P) -> Option<Ownership> {
+        self.owners.lock().unwrap().get(path.as_ref()).copied()
+    }
+
+    pub fn node<P: AsRef<Path>>(&self, path: P) -> Option<MemoryNode> {
+        self.nodes.lock().unwrap().get(path.as_ref()).cloned()
+    }
+
+    /// All the paths in the filesystem, in order
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.nodes.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn not_found(path: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found", path.display()),
+        )
+    }
+
+    fn device_of(&self, path: &Path) -> u32 {
+        let devices = self.devices.lock().unwrap();
+        path.ancestors().find_map(|ancestor| devices.get(ancestor).copied()).unwrap_or(0)
+    }
+
+    fn check_device(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        if self.device_of(from) != self.device_of(to) {
+            return Err(io::Error::new(
+                io::ErrorKind::CrossesDevices,
+                format!("{} and {} are on different devices", from.display(), to.display()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_parent(&self, path: &Path) -> Result<(), io::Error> {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() && !self.is_dir(parent) => {
+                Err(Self::not_found(parent))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Fs for MemoryFs {
+    fn exists(&self, path: &Path) -> bool {
+        self.nodes.lock().unwrap().contains_key(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        matches!(self.node(path), Some(MemoryNode::File(_)))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        matches!(self.node(path), Some(MemoryNode::Dir))
+    }
+
+    fn file_size(&self, path: &Path) -> Result<u64, io::Error> {
+        match self.node(path) {
+            Some(MemoryNode::File(contents)) => Ok(contents.len() as u64),
+            Some(_) => Ok(0),
+            None => Err(Self::not_found(path)),
+        }
+    }
+
+    fn modified(&self, path: &Path) -> Result<SystemTime, io::Error> {
+        if !self.exists(path) {
+            return Err(Self::not_found(path));
+        }
+        Ok(self
+            .modified
+            .lock()
+            .unwrap()
+            .get(path)
+            .copied()
+            .unwrap_or(SystemTime::UNIX_EPOCH))
+    }
+
+    fn available_space(&self, path: &Path) -> Result<u64, io::Error> {
+        let available_space = self.available_space.lock().unwrap();
+        Ok(path
+            .ancestors()
+            .find_map(|ancestor| available_space.get(ancestor).copied())
+            .unwrap_or(u64::MAX))
+    }
+
+    fn device(&self, path: &Path) -> Result<u64, io::Error> {
+        Ok(u64::from(self.device_of(path)))
+    }
+
+    fn file_id(&self, path: &Path) -> Result<(u64, u64), io::Error> {
+        let target = self.bound.lock().unwrap().get(path).cloned();
+        if let Some(target) = target {
+            return self.file_id(&target);
+        }
+        let nodes = self.nodes.lock().unwrap();
+        let inode = nodes.keys().position(|node| node == path).ok_or_else(|| Self::not_found(path))?;
+        Ok((u64::from(self.device_of(path)), inode as u64 + 1))
+    }
+
+    fn is_special(&self, path: &Path) -> bool {
+        matches!(self.node(path), Some(MemoryNode::Special))
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        matches!(self.node(path), Some(MemoryNode::Symlink(_)))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+        if !self.is_dir(path) {
+            return Err(Self::not_found(path));
+        }
+        if self.unreadable.lock().unwrap().iter().any(|dir| dir == path) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} cannot be read", path.display()),
+            ));
+        }
+        Ok(self
+            .nodes
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|entry| entry.parent() == Some(path))
+            .cloned()
+            .collect())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<(), io::Error> {
+        let mut nodes = self.nodes.lock().unwrap();
+        for ancestor in path.ancestors() {
+            if ancestor.as_os_str().is_empty() {
+                continue;
+            }
+            match nodes.get(ancestor) {
+                Some(MemoryNode::Dir) => {}
+                Some(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} is not a directory", ancestor.display()),
+                    ))
+                }
+                None => {
+                    nodes.insert(ancestor.to_path_buf(), MemoryNode::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), io::Error> {
+        if !self.read_dir(path)?.is_empty() {
+            return Err(io::Error::other(format!("{} is not empty", path.display())));
+        }
+        self.nodes.lock().unwrap().remove(path);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        self.check_parent(to)?;
+        self.check_device(from, to)?;
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.remove(from).ok_or_else(|| Self::not_found(from))?;
+        // the contents of a directory move along with it
+        let children: Vec<PathBuf> = nodes.keys().filter(|path| path.starts_with(from)).cloned().collect();
+        for child in children {
+            let moved = to.join(child.strip_prefix(from).unwrap());
+            let child_node = nodes.remove(&child).unwrap();
+            nodes.insert(moved, child_node);
+        }
+        nodes.insert(to.to_path_buf(), node);
+        Ok(())
+    }
+
+    fn symlink(&self, original: &Path, link: &Path) -> Result<(), io::Error> {
+        if !self.exists(original) {
+            return Err(Self::not_found(original));
+        }
+        self.check_parent(link)?;
+        self.nodes
+            .lock()
+            .unwrap()
+            .insert(link.to_path_buf(), MemoryNode::Symlink(original.to_path_buf()));
+        Ok(())
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> Result<(), io::Error> {
+        // the contents are not shared in memory, a copy behaves the same until either is written to
+        let Some(MemoryNode::File(contents)) = self.node(original) else {
+            return Err(Self::not_found(original));
+        };
+        self.check_parent(link)?;
+        self.check_device(original, link)?;
+        let mut nodes = self.nodes.lock().unwrap();
+        if nodes.contains_key(link) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", link.display()),
+            ));
+        }
+        nodes.insert(link.to_path_buf(), MemoryNode::File(contents));
+        Ok(())
+    }
+
+    fn reflink(&self, original: &Path, copy: &Path) -> Result<(), io::Error> {
+        let supported = |path: &Path| self.reflinks.lock().unwrap().iter().any(|dir| path.starts_with(dir));
+        if !supported(original) || !supported(copy) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "reflinks are not supported here"));
+        }
+        self.check_device(original, copy)?;
+        let Some(MemoryNode::File(contents)) = self.node(original) else {
+            return Err(Self::not_found(original));
+        };
+        self.check_parent(copy)?;
+        self.nodes.lock().unwrap().insert(copy.to_path_buf(), MemoryNode::File(contents));
+        Ok(())
+    }
+
+    fn copy_attributes(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        let modified = self.modified(from)?;
+        if !self.exists(to) {
+            return Err(Self::not_found(to));
+        }
+        self.set_modified(to, modified);
+        if let Some(mode) = self.mode(from) {
+            self.modes.lock().unwrap().insert(to.to_path_buf(), mode);
+        }
+        Ok(())
+    }
+
+    fn set_owner(&self, path: &Path, owner: Option<u32>, group: Option<u32>) -> Result<(), io::Error> {
+        if !self.exists(path) {
+            return Err(Self::not_found(path));
+        }
+        let mut owners = self.owners.lock().unwrap();
+        let (current_owner, current_group) = owners.get(path).copied().unwrap_or_default();
+        owners.insert(path.to_path_buf(), (owner.or(current_owner), group.or(current_group)));
+        Ok(())
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> Result<(), io::Error> {
+        if !self.exists(path) {
+            return Err(Self::not_found(path));
+        }
+        self.modes.lock().unwrap().insert(path.to_path_buf(), mode);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), io::Error> {
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(path) {
+            Some(MemoryNode::File(_)) | Some(MemoryNode::Symlink(_)) | Some(MemoryNode::Special) => {
+                nodes.remove(path);
+                Ok(())
+            }
+            Some(MemoryNode::Dir) => Err(io::Error::other(format!(
+                "{} is a directory",
+                path.display()
+            ))),
+            None => Err(Self::not_found(path)),
+        }
+    }
+
+    /// Moves the file into the `trash` directory at the root
+    fn trash(&self, path: &Path) -> Result<Option<PathBuf>, io::Error> {
+        let trash = Path::new("trash");
+        self.create_dir_all(trash)?;
+        let trashed = trash.join(path.file_name().unwrap_or_default());
+        self.rename(path, &trashed)?;
+        Ok(Some(trashed))
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, io::Error> {
+        // there are no symlinked directories in memory, existing paths are already canonical
+        if !self.exists(path) {
+            return Err(Self::not_found(path));
+        }
+        Ok(path.to_path_buf())
+    }
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn ReadSeek>, io::Error> {
+        match self.node(path) {
+            Some(MemoryNode::File(contents)) => Ok(Box::new(Cursor::new(contents))),
+            _ => Err(Self::not_found(path)),
+        }
+    }
+
+    fn open_write(&self, path: &Path, append: bool) -> Result<Box<dyn Write>, io::Error> {
+        self.check_parent(path)?;
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(path) {
+            Some(MemoryNode::File(_)) if append => {}
+            None if append => return Err(Self::not_found(path)),
+            _ => {
+                nodes.insert(path.to_path_buf(), MemoryNode::File(Vec::new()));
+            }
+        }
+        Ok(Box::new(MemoryWriter {
+            fs: self.clone(),
+            path: path.to_path_buf(),
+        }))
+    }
+}
+
+/// Appends what is written to a file of a `MemoryFs`
+struct MemoryWriter {
+    fs: MemoryFs,
+    path: PathBuf,
+}
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.fs.nodes.lock().unwrap().get_mut(&self.path) {
+            Some(MemoryNode::File(contents)) => {
+                contents.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            _ => Err(MemoryFs::not_found(&self.path)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn read_only_filesystems_refuse_every_change() {
+        let memory = MemoryFs::new();
+        memory.add_file("downloads/Show.S01E01.mkv", b"episode");
+        let fs = ReadOnlyFs(&memory);
+        let file = Path::new("downloads/Show.S01E01.mkv");
+        let other = Path::new("library/Show.S01E01.mkv");
+
+        assert!(fs.is_file(file));
+        assert_eq!(fs.read_to_string(file).unwrap(), "episode");
+        let refused = [
+            fs.create_dir_all(Path::new("library")),
+            fs.rename(file, other),
+            fs.symlink(file, other),
+            fs.hard_link(file, other),
+            fs.reflink(file, other),
+            fs.copy_attributes(file, other),
+            fs.set_owner(file, Some(0), None),
+            fs.set_mode(file, 0o644),
+            fs.remove_file(file),
+            fs.remove_dir(Path::new("downloads")),
+            fs.trash(file).map(drop),
+            fs.open_write(other, false).map(drop),
+        ];
+        assert!(refused.iter().all(|result| {
+            result.as_ref().is_err_and(|error| error.kind() == io::ErrorKind::PermissionDenied)
+        }));
+        assert_eq!(memory.paths(), vec![PathBuf::from("downloads"), file.to_path_buf()]);
+    }
+
+    #[test]
+    fn copies_get_the_modification_time_and_permissions_of_the_original() {
+        let dir = env::temp_dir().join(format!("media-renamer-attributes-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (original, copy) = (dir.join("original.mkv"), dir.join("copy.mkv"));
+        fs::write(&original, b"episode").unwrap();
+        fs::write(&copy, b"episode").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        File::options().write(true).open(&original).unwrap().set_modified(modified).unwrap();
+        let mut permissions = fs::metadata(&original).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&original, permissions).unwrap();
+
+        RealFs.copy_attributes(&original, &copy).unwrap();
+
+        let metadata = fs::metadata(&copy).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert!(metadata.permissions().readonly());
+        for path in [&original, &copy] {
+            let mut permissions = fs::metadata(path).unwrap().permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            fs::set_permissions(path, permissions).unwrap();
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn symlinks_point_to_the_absolute_original() {
+        let dir = env::temp_dir().join(format!("media-renamer-symlink-{}", process::id()));
+        fs::create_dir_all(dir.join("library")).unwrap();
+        let original = dir.join("Show.S01E01.mkv");
+        fs::write(&original, b"episode").unwrap();
+        let link = dir.join("library").join("Show - s01e01.mkv");
+
+        let result = RealFs.symlink(&original, &link);
+        // creating symlinks on Windows needs developer mode or administrator rights
+        if cfg!(windows) && result.is_err() {
+            fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+        result.unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), original.canonicalize().unwrap());
+        assert_eq!(fs::read(&link).unwrap(), b"episode");
+        assert_eq!(
+            RealFs.symlink(&dir.join("missing.mkv"), &dir.join("library").join("missing.mkv")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    path_utils::get_filestem,
+    policy::{policy_for, ExtensionPolicy},
+    Config,
+};
+
+/// The files of a run grouped into items: each main file with the subtitles, NFOs and artwork
+/// that go with it, so that they are named, placed or skipped together
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Groups {
+    /// The sidecars named after a main file, by main file. They are renamed along with it
+    pub named: HashMap<PathBuf, Vec<PathBuf>>,
+    /// The sidecars not named after any main file, by directory. They go to the folder of the
+    /// main files of their directory as they are, when those all went to the same folder
+    pub shared: BTreeMap<PathBuf, Vec<PathBuf>>,
+    /// The sidecars with no main file to go with
+    pub orphans: Vec<PathBuf>,
+}
+
+impl Groups {
+    /// Groups the sidecars with the main files of their directory. A sidecar named after more than
+    /// one main file, like `Show.S01E01.Extended.en.srt` next to `Show.S01E01.mkv` and
+    /// `Show.S01E01.Extended.mkv`, goes with the longest name
+    pub fn new(mains: &[PathBuf], sidecars: &[PathBuf], config: &Config) -> Self {
+        let mut by_dir: HashMap<Option<&Path>, Vec<(&PathBuf, String)>> = HashMap::new();
+        for main in mains {
+            if let Some(stem) = get_filestem(main) {
+                by_dir.entry(main.parent()).or_default().push((main, stem));
+            }
+        }
+
+        let mut groups = Self::default();
+        for sidecar in sidecars {
+            let siblings = by_dir.get(&sidecar.parent());
+            let named = get_filestem(sidecar).and_then(|stem| {
+                siblings?
+                    .iter()
+                    .filter(|(_, main_stem)| stem.starts_with(main_stem.as_str()))
+                    .max_by_key(|(_, main_stem)| main_stem.len())
+            });
+
+            match (named, siblings) {
+                (Some((main, _)), _) => groups.named.entry((*main).clone()).or_default().push(sidecar.clone()),
+                (None, Some(_)) if policy_for(sidecar, config) == ExtensionPolicy::Follow => groups
+                    .shared
+                    .entry(sidecar.parent().map(Path::to_path_buf).unwrap_or_default())
+                    .or_default()
+                    .push(sidecar.clone()),
+                (None, _) => groups.orphans.push(sidecar.clone()),
+            }
+        }
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_go_with_the_main_file_they_are_named_after() {
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+        let mains = paths(&[
+            "downloads/Show.S01/Show.S01E01.mkv",
+            "downloads/Show.S01/Show.S01E01.Extended.mkv",
+        ]);
+        let sidecars = paths(&[
+            "downloads/Show.S01/Show.S01E01.en.srt",
+            "downloads/Show.S01/Show.S01E01.Extended.en.srt",
+            "downloads/Show.S01/poster.jpg",
+            "downloads/Show.S01/commentary.srt",
+            "downloads/Other.en.srt",
+        ]);
+
+        let groups = Groups::new(&mains, &sidecars, &Config::default());
+
+        assert_eq!(groups.named[&mains[0]], paths(&["downloads/Show.S01/Show.S01E01.en.srt"]));
+        assert_eq!(groups.named[&mains[1]], paths(&["downloads/Show.S01/Show.S01E01.Extended.en.srt"]));
+        assert_eq!(groups.shared[Path::new("downloads/Show.S01")], paths(&["downloads/Show.S01/poster.jpg"]));
+        assert_eq!(
+            groups.orphans,
+            paths(&["downloads/Show.S01/commentary.srt", "downloads/Other.en.srt"])
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use crate::filesystem::Fs;
+
+/// The algorithm files are hashed with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// Fast and cryptographic, the default
+    #[default]
+    Blake3,
+    /// The fastest, fine to tell files apart but not against tampering
+    Xxh3,
+    /// The slowest, for checksums compared with other tools
+    Sha256,
+}
+
+/// How files are hashed for one use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HashSettings {
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+
+    /// Only hash this many MiB at the start and at the end of the file, along with its size, 0 to
+    /// hash whole files. Reading a few MiB of a multi-gigabyte file tells copies apart almost as well
+    #[serde(default)]
+    pub partial_mib: u64,
+}
+
+/// How files are hashed for each use, in `[hashing]`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Hashing {
+    /// Comparing copies with their source after --verify
+    #[serde(default)]
+    pub verify: HashSettings,
+
+    /// Telling whether an existing destination is the same file as the new one, which is then not
+    /// asked about with --interactive
+    #[serde(default = "default_dedupe")]
+    pub dedupe: HashSettings,
+}
+
+/// Duplicates are looked for often and on whole libraries, a partial hash is enough to find them
+fn default_dedupe() -> HashSettings {
+    HashSettings {
+        algorithm: HashAlgorithm::Xxh3,
+        partial_mib: 16,
+    }
+}
+
+impl Default for Hashing {
+    fn default() -> Self {
+        Self {
+            verify: HashSettings::default(),
+            dedupe: default_dedupe(),
+        }
+    }
+}
+
+/// The running state of one of the algorithms
+enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::new(xxhash_rust::xxh3::Xxh3::new())),
+            HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+            Hasher::Xxh3(hasher) => hasher.update(bytes),
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Xxh3(hasher) => format!("{:032x}", hasher.digest128()),
+            Hasher::Sha256(hasher) => hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+}
+
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// The hash of the file as a hex string. A partial hash covers the size and the first and last
+/// MiBs of the file, which is all of it when the file is small enough
+pub fn hash_file(fs: &dyn Fs, path: &Path, settings: HashSettings) -> Result<String, io::Error> {
+    let size = fs.file_size(path)?;
+    let mut file = fs.open_read(path)?;
+    let mut hasher = Hasher::new(settings.algorithm);
+    let part = settings.partial_mib.saturating_mul(1 << 20);
+
+    if part == 0 || size <= part.saturating_mul(2) {
+        hash_reader(&mut hasher, &mut file, u64::MAX)?;
+    } else {
+        hasher.update(&size.to_le_bytes());
+        hash_reader(&mut hasher, &mut file, part)?;
+        file.seek(SeekFrom::End(-(part as i64)))?;
+        hash_reader(&mut hasher, &mut file, part)?;
+    }
+    Ok(hasher.finish())
+}
+
+/// Hashes up to `limit` bytes of the reader, from where it is
+fn hash_reader(hasher: &mut Hasher, reader: &mut dyn Read, limit: u64) -> Result<(), io::Error> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut left = limit;
+    while left > 0 {
+        let wanted = buffer.len().min(usize::try_from(left).unwrap_or(usize::MAX));
+        let read = reader.read(&mut buffer[..wanted])?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        left -= read as u64;
+    }
+    Ok(())
+}
+
+/// Whether the files have the same size and hash
+pub fn same_contents(fs: &dyn Fs, first: &Path, second: &Path, settings: HashSettings) -> Result<bool, io::Error> {
+    if fs.file_size(first)? != fs.file_size(second)? {
+        return Ok(false);
+    }
+    Ok(hash_file(fs, first, settings)? == hash_file(fs, second, settings)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+
+    #[test]
+    fn algorithms_give_known_digests() {
+        let fs = MemoryFs::new();
+        fs.add_file("abc", b"abc");
+        let hash = |algorithm| {
+            hash_file(
+                &fs,
+                Path::new("abc"),
+                HashSettings {
+                    algorithm,
+                    partial_mib: 0,
+                },
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            hash(HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Blake3),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(hash(HashAlgorithm::Xxh3).len(), 32);
+    }
+
+    #[test]
+    fn partial_hashes_only_see_the_ends_of_big_files() {
+        let fs = MemoryFs::new();
+        let mut contents = vec![0u8; 3 << 20];
+        fs.add_file("original.mkv", &contents);
+        contents[3 << 19] = 1;
+        fs.add_file("middle.mkv", &contents);
+        contents[0] = 1;
+        fs.add_file("start.mkv", &contents);
+
+        let partial = HashSettings {
+            algorithm: HashAlgorithm::Xxh3,
+            partial_mib: 1,
+        };
+        let full = HashSettings {
+            partial_mib: 0,
+            ..partial
+        };
+        let same = |first: &str, second: &str, settings| {
+            same_contents(&fs, Path::new(first), Path::new(second), settings).unwrap()
+        };
+        assert!(same("original.mkv", "middle.mkv", partial));
+        assert!(!same("original.mkv", "middle.mkv", full));
+        assert!(!same("original.mkv", "start.mkv", partial));
+    }
+}
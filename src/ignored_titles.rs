@@ -0,0 +1,85 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::want_list::compact_title;
+
+/// An entry ending in a season like `Survivor S45` ignores only that season of the series
+static SEASON_SUFFIX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)^(.+?)\s+s([0-9]{1,3})$").unwrap());
+
+/// The entry of `ignored_titles` the series or movie matches, if any. Entries are titles compared
+/// ignoring case, punctuation and spacing, or globs with `*` and `?` like `Love Island*`, and may
+/// end with a season like `Survivor S45` to ignore only that season
+pub fn find<'e>(entries: &'e [String], title: &str, season: Option<u32>) -> Option<&'e str> {
+    let title = compact_title(title);
+    entries
+        .iter()
+        .find(|entry| {
+            let (pattern, entry_season) = match SEASON_SUFFIX.captures(entry) {
+                Some(captures) => (captures.get(1).unwrap().as_str(), captures[2].parse().ok()),
+                None => (entry.as_str(), None),
+            };
+            (entry_season.is_none() || entry_season == season) && glob_matches(&compact_pattern(pattern), &title)
+        })
+        .map(String::as_str)
+}
+
+/// The pattern compacted like the titles it is matched with, keeping its wildcards
+fn compact_pattern(pattern: &str) -> String {
+    let mut compacted = String::new();
+    let mut literal = String::new();
+    for c in pattern.chars() {
+        if c == '*' || c == '?' {
+            compacted.push_str(&compact_title(&literal));
+            compacted.push(c);
+            literal.clear();
+        } else {
+            literal.push(c);
+        }
+    }
+    compacted.push_str(&compact_title(&literal));
+    compacted
+}
+
+/// Whether the text matches the glob, where `*` stands for any characters and `?` for one
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // the last `*` met and the position of the text it was tried at, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_globs_and_seasons_are_ignored() {
+        let entries = ["The Bachelor".to_string(), "Love Island*".to_string(), "Survivor S45".to_string()];
+
+        assert_eq!(find(&entries, "the.bachelor", Some(28)), Some("The Bachelor"));
+        assert_eq!(find(&entries, "The Bachelorette", Some(1)), None);
+        assert_eq!(find(&entries, "Love Island (US)", Some(5)), Some("Love Island*"));
+        assert_eq!(find(&entries, "Love Island", None), Some("Love Island*"));
+        assert_eq!(find(&entries, "Survivor", Some(45)), Some("Survivor S45"));
+        assert_eq!(find(&entries, "Survivor", Some(46)), None);
+        assert_eq!(find(&entries, "Survivor", None), None);
+        assert_eq!(find(&entries, "Shameless", Some(1)), None);
+    }
+}
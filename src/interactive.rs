@@ -0,0 +1,307 @@
+use std::{
+    cmp::Ordering,
+    io::{self, BufRead, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{provider::SearchResult, quality::Quality};
+
+/// What the user decided about the planned destination of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Accept,
+    Skip,
+    /// Place the file under another series or movie name
+    Rename(String),
+    /// Stop the run, leaving this and the remaining files alone
+    Abort,
+}
+
+/// What is done with a file whose destination already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the existing file and skip the new one
+    Keep,
+    /// Replace the existing file with the new one
+    Replace,
+    /// Place the new file next to the existing one under a numbered name
+    KeepBoth,
+}
+
+/// How the existing destination of a file is resolved when the run is not interactive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Keep the existing file and skip the new one
+    #[default]
+    Skip,
+    /// Replace the existing file with the new one
+    Overwrite,
+    /// Place the new file next to the existing one under a numbered name
+    RenameWithSuffix,
+    /// Replace the existing file when the new one is larger, like an upgrade from 720p to 1080p
+    KeepLarger,
+    /// Replace the existing file when the new one is of a better quality by the resolution and
+    /// source tags of their names, or when larger if the tags do not tell
+    Upgrade,
+}
+
+impl ConflictPolicy {
+    /// The resolution of the conflict between the new file and the existing one
+    pub fn resolve(self, new: &FileInfo, existing: &FileInfo) -> Resolution {
+        let larger = match (new.size, existing.size) {
+            (Some(new_size), Some(existing_size)) if new_size > existing_size => Resolution::Replace,
+            _ => Resolution::Keep,
+        };
+        match self {
+            ConflictPolicy::Skip => Resolution::Keep,
+            ConflictPolicy::Overwrite => Resolution::Replace,
+            ConflictPolicy::RenameWithSuffix => Resolution::KeepBoth,
+            ConflictPolicy::KeepLarger => larger,
+            ConflictPolicy::Upgrade => match new.quality.compare(&existing.quality) {
+                Some(Ordering::Greater) => Resolution::Replace,
+                Some(Ordering::Less | Ordering::Equal) => Resolution::Keep,
+                None => larger,
+            },
+        }
+    }
+}
+
+/// Whether replacing the existing file with the new one looks like a mistake, like a 700 MB file
+/// replacing a 40 GB remux: the new file is more than `max_size_ratio` times smaller or larger
+/// without being of a better resolution. A ratio of 0 allows any size
+pub fn is_suspicious_replacement(new: &FileInfo, existing: &FileInfo, max_size_ratio: f64) -> bool {
+    let (Some(new_size), Some(existing_size)) = (new.size, existing.size) else {
+        return false;
+    };
+    let better_resolution = matches!(
+        (new.quality.resolution, existing.quality.resolution),
+        (Some(new), Some(existing)) if new > existing
+    );
+    let ratio = new_size.max(existing_size) as f64 / new_size.min(existing_size).max(1) as f64;
+    max_size_ratio > 0.0 && ratio > max_size_ratio && !better_resolution
+}
+
+/// The answer to a conflict, and whether it applies to the other conflicts of the run too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConflictAnswer {
+    pub resolution: Resolution,
+    pub apply_to_all: bool,
+}
+
+/// What is shown of each file of a conflict
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileInfo {
+    pub size: Option<u64>,
+    pub modified: Option<SystemTime>,
+    pub quality: Quality,
+}
+
+impl FileInfo {
+    fn describe(&self) -> String {
+        let size = match self.size {
+            Some(size) => format!("{:.1} MiB", size as f64 / (1 << 20) as f64),
+            None => "unknown size".to_string(),
+        };
+        let modified = match self.modified.and_then(|modified| modified.elapsed().ok()) {
+            Some(age) => format!("modified {} days ago", age.as_secs() / (24 * 60 * 60)),
+            None => "unknown modification time".to_string(),
+        };
+        let quality = if self.quality.is_known() {
+            self.quality.to_string()
+        } else {
+            "quality not in the name".to_string()
+        };
+        format!("{}, {}, {}", size, modified, quality)
+    }
+}
+
+/// Asks the user to confirm the destination of every file
+pub trait Prompt: Send + Sync {
+    fn confirm(&self, source: &Path, destination: &Path) -> Decision;
+
+    /// Asks what to do with a file whose destination already exists
+    fn resolve_conflict(
+        &self,
+        source: &Path,
+        source_info: &FileInfo,
+        destination: &Path,
+        destination_info: &FileInfo,
+    ) -> ConflictAnswer;
+
+    /// Asks which of the search results, the best first, the searched name is. None skips the file
+    fn pick(&self, searched: &str, results: &[&SearchResult]) -> Option<usize>;
+}
+
+/// Asks on the terminal
+pub struct TerminalPrompt;
+
+impl Prompt for TerminalPrompt {
+    fn confirm(&self, source: &Path, destination: &Path) -> Decision {
+        println!("{}\n  -> {}", source.display(), destination.display());
+        loop {
+            let Some(answer) = ask("[a]ccept, [s]kip, [e]dit name, [q]uit? [a] ") else {
+                return Decision::Abort;
+            };
+            match parse_answer(&answer) {
+                Some(Decision::Rename(_)) => match ask("New name: ") {
+                    Some(name) if !name.trim().is_empty() => {
+                        return Decision::Rename(name.trim().to_string())
+                    }
+                    Some(_) => continue,
+                    None => return Decision::Abort,
+                },
+                Some(decision) => return decision,
+                None => println!("Please answer a, s, e or q"),
+            }
+        }
+    }
+
+    fn resolve_conflict(
+        &self,
+        source: &Path,
+        source_info: &FileInfo,
+        destination: &Path,
+        destination_info: &FileInfo,
+    ) -> ConflictAnswer {
+        println!("{} already exists", destination.display());
+        println!("  existing: {}", destination_info.describe());
+        println!("  new:      {} ({})", source_info.describe(), source.display());
+        loop {
+            // a closed input keeps the library as it is
+            let Some(answer) = ask("[k]eep existing, [r]eplace, keep [b]oth, upper case for all conflicts? [k] ") else {
+                return ConflictAnswer {
+                    resolution: Resolution::Keep,
+                    apply_to_all: true,
+                };
+            };
+            match parse_conflict_answer(&answer) {
+                Some(answer) => return answer,
+                None => println!("Please answer k, r or b, or K, R or B for all conflicts"),
+            }
+        }
+    }
+
+    fn pick(&self, searched: &str, results: &[&SearchResult]) -> Option<usize> {
+        println!("{} matches several titles:", searched);
+        for (index, result) in results.iter().enumerate() {
+            println!("  {}. {}", index + 1, result);
+        }
+        loop {
+            // a closed input, like a run from a script, takes the best result
+            let Some(answer) = ask(&format!("[1-{}], [s]kip? [1] ", results.len())) else {
+                return Some(0);
+            };
+            match parse_pick(&answer, results.len()) {
+                Some(pick) => return pick,
+                None => println!("Please answer a number from 1 to {} or s", results.len()),
+            }
+        }
+    }
+}
+
+/// Prints the question and reads a line, none when the input is closed
+fn ask(question: &str) -> Option<String> {
+    print!("{}", question);
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
+
+/// The decision of an answer, an empty answer accepts. Editing asks for the name separately
+pub fn parse_answer(answer: &str) -> Option<Decision> {
+    match answer.trim().to_lowercase().as_str() {
+        "" | "a" | "accept" | "y" | "yes" => Some(Decision::Accept),
+        "s" | "skip" | "n" | "no" => Some(Decision::Skip),
+        "e" | "edit" => Some(Decision::Rename(String::new())),
+        "q" | "quit" | "abort" => Some(Decision::Abort),
+        _ => None,
+    }
+}
+
+/// The answer to a conflict, an empty answer keeps the existing file. Upper case applies to all
+pub fn parse_conflict_answer(answer: &str) -> Option<ConflictAnswer> {
+    let answer = answer.trim();
+    let apply_to_all = answer.len() == 1 && answer.chars().all(|c| c.is_ascii_uppercase());
+    let resolution = match answer.to_lowercase().as_str() {
+        "" | "k" | "keep" => Resolution::Keep,
+        "r" | "replace" => Resolution::Replace,
+        "b" | "both" => Resolution::KeepBoth,
+        _ => return None,
+    };
+    Some(ConflictAnswer {
+        resolution,
+        apply_to_all,
+    })
+}
+
+/// The index of the result picked by an answer counting from 1, none to skip. An empty answer
+/// picks the first result
+pub fn parse_pick(answer: &str, count: usize) -> Option<Option<usize>> {
+    match answer.trim().to_lowercase().as_str() {
+        "" => Some(Some(0)),
+        "s" | "skip" => Some(None),
+        number => match number.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => Some(Some(number - 1)),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_are_parsed() {
+        assert_eq!(parse_answer("\n"), Some(Decision::Accept));
+        assert_eq!(parse_answer("S\n"), Some(Decision::Skip));
+        assert_eq!(parse_answer("e"), Some(Decision::Rename(String::new())));
+        assert_eq!(parse_answer("quit"), Some(Decision::Abort));
+        assert_eq!(parse_answer("maybe"), None);
+    }
+
+    #[test]
+    fn conflict_answers_are_parsed() {
+        let answer = |resolution, apply_to_all| {
+            Some(ConflictAnswer {
+                resolution,
+                apply_to_all,
+            })
+        };
+        assert_eq!(parse_conflict_answer("\n"), answer(Resolution::Keep, false));
+        assert_eq!(parse_conflict_answer("r\n"), answer(Resolution::Replace, false));
+        assert_eq!(parse_conflict_answer("B"), answer(Resolution::KeepBoth, true));
+        assert_eq!(parse_conflict_answer("maybe"), None);
+    }
+
+    #[test]
+    fn replacements_of_a_very_different_size_are_suspicious() {
+        let file = |size: u64, name: &str| FileInfo {
+            size: Some(size),
+            quality: Quality::parse(name),
+            ..FileInfo::default()
+        };
+        let remux = file(40_000, "Movie.2021.1080p.Remux");
+        assert!(is_suspicious_replacement(&file(700, "Movie.2021.1080p.WEBRip"), &remux, 4.0));
+        assert!(!is_suspicious_replacement(&file(700, "Movie.2021.1080p.WEBRip"), &remux, 0.0));
+        assert!(!is_suspicious_replacement(&file(700, "Movie.2021.2160p.WEBRip"), &remux, 4.0));
+        assert!(!is_suspicious_replacement(&file(20_000, "Movie.2021.1080p.BluRay"), &remux, 4.0));
+        assert!(is_suspicious_replacement(&file(200_000, "Movie.2021.1080p.BluRay"), &remux, 4.0));
+    }
+
+    #[test]
+    fn picks_are_parsed() {
+        assert_eq!(parse_pick("\n", 3), Some(Some(0)));
+        assert_eq!(parse_pick("3\n", 3), Some(Some(2)));
+        assert_eq!(parse_pick("s", 3), Some(None));
+        assert_eq!(parse_pick("4", 3), None);
+        assert_eq!(parse_pick("0", 3), None);
+    }
+}
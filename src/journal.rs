@@ -0,0 +1,295 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{filesystem::Fs, run_id, transfer::Transfer, trash};
+
+/// The extension of journals that were undone, so that they are not undone twice
+const UNDONE_EXTENSION: &str = "undone";
+
+/// A change made to the filesystem by a run
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "operation", rename_all = "kebab-case")]
+pub enum Operation {
+    CreateDir { path: PathBuf },
+    Move { source: PathBuf, destination: PathBuf },
+    Copy { source: PathBuf, destination: PathBuf },
+    Symlink { source: PathBuf, destination: PathBuf },
+    Hardlink { source: PathBuf, destination: PathBuf },
+    /// A file written by media-renamer itself, like a `.plexmatch`
+    Write { path: PathBuf },
+    /// A source sent to the trash of the OS once it was copied, with `trashed` where it is in the
+    /// trash when the OS tells it
+    Trash {
+        original: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trashed: Option<PathBuf>,
+    },
+    /// A file placed with its parsed name because the metadata providers could not be reached, or
+    /// matched to one of several results too close to tell apart, to be looked up again with
+    /// `refresh-metadata`. Nothing to undo
+    PendingMetadata { path: PathBuf },
+    /// A file pending metadata that was looked up again and found where it already was. Nothing to undo
+    Refreshed { path: PathBuf },
+}
+
+/// The changes made by a run, written to disk after every change so that it survives an interrupted run
+pub struct Journal {
+    path: PathBuf,
+    operations: Mutex<Vec<Operation>>,
+}
+
+impl Journal {
+    /// Starts the journal of this run in the history directory, named after the run id
+    pub fn create(history_dir: &Path) -> Result<Self, io::Error> {
+        fs::create_dir_all(history_dir)?;
+        Ok(Self {
+            path: history_dir.join(format!("{}.json", run_id::current())),
+            operations: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, operation: Operation) {
+        let mut operations = self.operations.lock().unwrap();
+        operations.push(operation);
+
+        let json = serde_json::to_string_pretty(&*operations).expect("Could not serialize the journal");
+        if let Err(error) = fs::write(&self.path, json) {
+            error!("Could not write journal {}: {}", self.path.display(), error);
+        }
+    }
+}
+
+/// The journal of the last run that was not undone yet
+pub fn last_journal(history_dir: &Path) -> Option<PathBuf> {
+    journals(history_dir).pop()
+}
+
+/// The journals of the runs that were not undone, oldest first
+pub fn journals(history_dir: &Path) -> Vec<PathBuf> {
+    history(history_dir)
+        .into_iter()
+        .filter(|(_, undone)| !undone)
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// The journals of every run, oldest first, with whether the run was undone
+pub fn history(history_dir: &Path) -> Vec<(PathBuf, bool)> {
+    let Ok(entries) = fs::read_dir(history_dir) else {
+        return Vec::new();
+    };
+    let mut journals: Vec<(PathBuf, bool)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| match path.extension()?.to_str()? {
+            "json" => Some((path, false)),
+            UNDONE_EXTENSION => Some((path, true)),
+            _ => None,
+        })
+        .collect();
+    journals.sort_by_key(|(path, _)| journal_run_id(path).and_then(|run_id| run_id.parse::<u128>().ok()).unwrap_or(0));
+    journals
+}
+
+/// The id of the run of the journal, which it is named after
+pub fn journal_run_id(path: &Path) -> Option<&str> {
+    path.file_name()?.to_str()?.split('.').next()
+}
+
+pub fn load(path: &Path) -> Result<Vec<Operation>, io::Error> {
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Reverts the journal of a run and marks it as undone once every operation is reverted. The
+/// operations that could not be are kept in the journal, for another undo once what stopped them
+/// is fixed. Returns whether every operation was reverted
+pub fn undo_journal(fs: &dyn Fs, path: &Path) -> Result<bool, io::Error> {
+    let operations = load(path)?;
+    info!("Undoing {} operations of {}", operations.len(), path.display());
+
+    let left = undo(fs, &operations);
+    if !left.is_empty() {
+        warn!("Keeping the {} operations that could not be undone in {}", left.len(), path.display());
+        let json = serde_json::to_string_pretty(&left).map_err(io::Error::other)?;
+        fs::write(path, json)?;
+        return Ok(false);
+    }
+    let mut undone = path.as_os_str().to_owned();
+    undone.push(format!(".{}", UNDONE_EXTENSION));
+    fs::rename(path, undone)?;
+    Ok(true)
+}
+
+/// Reverts the operations, last first. Files whose reversal would lose data are left alone.
+/// Returns the operations that could not be reverted, in the order of the journal
+pub fn undo(fs: &dyn Fs, operations: &[Operation]) -> Vec<Operation> {
+    let mut left = Vec::new();
+    for operation in operations.iter().rev() {
+        if let Err(error) = undo_operation(fs, operation) {
+            warn!("Could not undo {:?}: {}", operation, error);
+            left.push(operation.clone());
+        }
+    }
+    left.reverse();
+    left
+}
+
+fn undo_operation(fs: &dyn Fs, operation: &Operation) -> Result<(), io::Error> {
+    match operation {
+        Operation::CreateDir { path } => {
+            if fs.read_dir(path)?.is_empty() {
+                fs.remove_dir(path)?;
+            } else {
+                info!("Keeping {}, it is not empty", path.display());
+            }
+        }
+        Operation::Move { source, destination } => {
+            if fs.exists(source) {
+                return Err(already_exists(source));
+            }
+            if let Some(parent) = source.parent() {
+                fs.create_dir_all(parent)?;
+            }
+            match fs.rename(destination, source) {
+                // a move across filesystems was a copy, it is copied back
+                Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+                    Transfer::new().copy(fs, destination, source)?;
+                    fs.remove_file(destination)?;
+                }
+                result => result?,
+            }
+            info!("Moved {} back to {}", destination.display(), source.display());
+        }
+        Operation::Copy { source, destination } | Operation::Hardlink { source, destination } => {
+            // without the source the library file is the only one left
+            if !fs.is_file(source) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is gone, keeping {}", source.display(), destination.display()),
+                ));
+            }
+            fs.remove_file(destination)?;
+            info!("Removed {}", destination.display());
+        }
+        Operation::Symlink { destination, .. } => {
+            fs.remove_file(destination)?;
+            info!("Removed {}", destination.display());
+        }
+        Operation::Write { path } => {
+            fs.remove_file(path)?;
+            info!("Removed {}", path.display());
+        }
+        // a source put back by hand, before an undo that could not find it, is left as it is
+        Operation::Trash { original, trashed: None } if fs.exists(original) => {}
+        Operation::Trash { original, trashed: None } => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is in the trash of the OS, restore it from there", original.display()),
+            ));
+        }
+        Operation::Trash {
+            original,
+            trashed: Some(trashed),
+        } => {
+            if fs.exists(original) {
+                return Err(already_exists(original));
+            }
+            if let Some(parent) = original.parent() {
+                fs.create_dir_all(parent)?;
+            }
+            fs.rename(trashed, original)?;
+            if let Some(info) = trash::info_path(trashed).filter(|info| fs.is_file(info)) {
+                fs.remove_file(&info)?;
+            }
+            info!("Put {} back from the trash", original.display());
+        }
+        Operation::PendingMetadata { .. } | Operation::Refreshed { .. } => {}
+    }
+    Ok(())
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filesystem::{MemoryFs, MemoryNode};
+
+    use super::*;
+
+    #[test]
+    fn undo_reverts_last_operation_first() {
+        let fs = MemoryFs::new();
+        fs.add_file("library/.keep", b"");
+        fs.add_file("library/TV/Severance/Season 2/Severance - s02e01.mkv", b"moved");
+        fs.add_file("library/TV/Severance/Season 2/Severance - s02e02.mkv", b"copied");
+        fs.add_file("downloads/Severance.S02E02.mkv", b"copied");
+        let operations = vec![
+            Operation::CreateDir { path: PathBuf::from("library/TV") },
+            Operation::CreateDir { path: PathBuf::from("library/TV/Severance") },
+            Operation::CreateDir { path: PathBuf::from("library/TV/Severance/Season 2") },
+            Operation::Move {
+                source: PathBuf::from("downloads/Severance.S02E01.mkv"),
+                destination: PathBuf::from("library/TV/Severance/Season 2/Severance - s02e01.mkv"),
+            },
+            Operation::Copy {
+                source: PathBuf::from("downloads/Severance.S02E02.mkv"),
+                destination: PathBuf::from("library/TV/Severance/Season 2/Severance - s02e02.mkv"),
+            },
+        ];
+
+        assert!(undo(&fs, &operations).is_empty());
+        assert_eq!(fs.node("downloads/Severance.S02E01.mkv"), Some(MemoryNode::File(b"moved".to_vec())));
+        assert!(!fs.exists(Path::new("library/TV")));
+        assert!(fs.exists(Path::new("downloads/Severance.S02E02.mkv")));
+    }
+
+    #[test]
+    fn operations_that_cannot_be_undone_are_kept_in_the_journal() {
+        let fs = MemoryFs::new();
+        fs.add_file("library/Severance - s01e01.mkv", b"1");
+        let dir = std::env::temp_dir().join(format!("media-renamer-undo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("1.json");
+        // the recycle bin does not tell where the source went
+        let operations = vec![
+            Operation::Copy {
+                source: PathBuf::from("downloads/Severance.S01E01.mkv"),
+                destination: PathBuf::from("library/Severance - s01e01.mkv"),
+            },
+            Operation::Trash {
+                original: PathBuf::from("downloads/Severance.S01E01.mkv"),
+                trashed: None,
+            },
+        ];
+        fs::write(&path, serde_json::to_string(&operations).unwrap()).unwrap();
+
+        let first = undo_journal(&fs, &path).unwrap();
+        let kept = load(&path).unwrap();
+        // the source is put back by hand
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        let second = undo_journal(&fs, &path).unwrap();
+        let history = history(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!first);
+        assert_eq!(kept, operations);
+        assert!(second);
+        assert!(!fs.exists(Path::new("library/Severance - s01e01.mkv")));
+        assert_eq!(history, vec![(dir.join("1.json.undone"), true)]);
+    }
+}
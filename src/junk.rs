@@ -0,0 +1,95 @@
+/// The names that are not media titles by default, mostly release sites and leftovers of releases
+pub fn default_junk_names() -> Vec<String> {
+    ["rarbg", "com", "www", "sample", "proof", "etrg", "eztv", "ettv", "yts", "torrent"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Hex strings at least this long are taken for hashes
+const MIN_HASH_LENGTH: usize = 16;
+/// Single words at least this long with a few digits among the letters are checked for randomness
+const MIN_RANDOM_LENGTH: usize = 20;
+const MIN_RANDOM_DIGITS: usize = 3;
+/// Bits per character above which a word is taken for random, even long English words stay below
+const MAX_ENTROPY: f64 = 4.0;
+
+/// Why the filename stem is clearly not a media title, if it is not. The stem is junk when all of its
+/// words are in the denylist, it has no letters, or it is a single hash-like or random-looking word
+pub fn junk_reason(stem: &str, denylist: &[String]) -> Option<&'static str> {
+    let words: Vec<String> = stem
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+
+    if words.is_empty() {
+        return Some("empty");
+    }
+    if words
+        .iter()
+        .all(|word| denylist.iter().any(|junk| junk.eq_ignore_ascii_case(word)))
+    {
+        return Some("denylisted");
+    }
+    if !words.iter().any(|word| word.chars().any(char::is_alphabetic)) {
+        return Some("numeric only");
+    }
+
+    let [word] = words.as_slice() else {
+        return None;
+    };
+    if word.len() >= MIN_HASH_LENGTH && word.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Some("looks like a hash");
+    }
+    // random names are generated from ASCII letters and digits, while in scripts like Japanese
+    // nearly every character of a title is different
+    if word.is_ascii()
+        && word.len() >= MIN_RANDOM_LENGTH
+        && word.chars().filter(|c| c.is_ascii_digit()).count() >= MIN_RANDOM_DIGITS
+        && entropy(word) > MAX_ENTROPY
+    {
+        return Some("looks random");
+    }
+    None
+}
+
+/// The Shannon entropy of the characters of the word, in bits per character
+fn entropy(word: &str) -> f64 {
+    let mut counts = [0usize; 256];
+    let mut total = 0;
+    for byte in word.bytes() {
+        counts[byte as usize] += 1;
+        total += 1;
+    }
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_junk_but_not_titles() {
+        let denylist = default_junk_names();
+        assert_eq!(junk_reason("RARBG com", &denylist), Some("denylisted"));
+        assert_eq!(junk_reason("sample", &denylist), Some("denylisted"));
+        assert_eq!(junk_reason("20240312 1080", &denylist), Some("numeric only"));
+        assert_eq!(junk_reason("3f786850e387550fdab836ed7e6dc881de23001b", &denylist), Some("looks like a hash"));
+        assert_eq!(junk_reason("x8Kq2vN7pL4mZ9tR1wY6bC3", &denylist), Some("looks random"));
+
+        assert_eq!(junk_reason("Severance", &denylist), None);
+        assert_eq!(junk_reason("1917 2019 1080p", &denylist), None);
+        assert_eq!(junk_reason("Blade Runner 2049", &denylist), None);
+        assert_eq!(junk_reason("Supercalifragilisticexpialidocious2", &denylist), None);
+        assert_eq!(junk_reason("東京喰種トーキョーグール2014年版第123話", &denylist), None);
+        assert_eq!(junk_reason("Бригада", &denylist), None);
+    }
+}
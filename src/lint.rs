@@ -0,0 +1,164 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::{
+    media::MediaData,
+    name_parser::{apply_replacements, RegexKind},
+    Config,
+};
+
+/// Names of releases as they are commonly found, that the regexes are tried on along with the
+/// names given to the linter
+const SAMPLE_NAMES: &[&str] = &[
+    "Show.Name.S01E02.1080p.WEB-DL.x264-GRP",
+    "Show.Name.2019.S01E02.720p.HDTV.x264-GRP",
+    "show.name.s01e02.episode.title.1080p.web.h264-grp",
+    "Show Name - S01E02 - Episode Title",
+    "Show.Name.S01E02E03.1080p.WEB-DL-GRP",
+    "Show.Name.2024.03.15.1080p.WEB.h264-GRP",
+    "[Group] Show Name - 12 [1080p]",
+    "[Group] Show Name - 1024v2 (1080p) [ABCD1234]",
+    "Movie.Name.2021.1080p.BluRay.x264-GRP",
+    "Movie.Name.1984.2160p.UHD.BluRay.x265-GRP",
+    "Movie Name (2021) 1080p",
+];
+
+/// An episode named by season and episode, whatever the regexes make of it
+static EPISODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bs[0-9]{1,3}e[0-9]{1,4}").unwrap());
+
+/// A regex of the configuration, where it is in its list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    kind: RegexKind,
+    index: usize,
+}
+
+/// Looks for the mistakes in the order of the regexes of the configuration: regexes that never
+/// parse a name because an earlier one always parses it first, and names of episodes parsed as
+/// something else, like a movie. Names of the user's releases can be given to be tried along with
+/// the samples. Returns the problems found, with the names showing them
+pub fn lint_regexes(config: &Config, names: &[String]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut regexes = Vec::new();
+    for kind in RegexKind::ALL {
+        for (index, re_string) in kind.regexes(config).iter().enumerate() {
+            match Regex::new(re_string) {
+                Ok(re) => regexes.push((Position { kind, index }, re)),
+                Err(error) => problems.push(format!("{}[{}] is not a valid regex: {}", kind.field(), index, error)),
+            }
+        }
+    }
+    let describe = |position: Position| {
+        format!(
+            "{}[{}] `{}`",
+            position.kind.field(),
+            position.index,
+            position.kind.regexes(config)[position.index]
+        )
+    };
+
+    let names: Vec<&str> = SAMPLE_NAMES
+        .iter()
+        .copied()
+        .chain(names.iter().map(String::as_str))
+        .collect();
+    // the regexes parsing every name, in the order they are tried
+    let parsed: Vec<(&str, Vec<(Position, MediaData)>)> = names
+        .iter()
+        .map(|name| {
+            let stem = apply_replacements(name.to_string(), config);
+            let parsing = regexes
+                .iter()
+                .filter_map(|(position, re)| {
+                    let (title, media_data) = position.kind.parse(re, &stem)?;
+                    (!title.trim().is_empty()).then_some((*position, media_data))
+                })
+                .collect();
+            (*name, parsing)
+        })
+        .collect();
+
+    for (position, _) in &regexes {
+        let mut shadowed_by = None;
+        for (name, parsing) in &parsed {
+            if !parsing.iter().any(|(parser, _)| parser == position) {
+                continue;
+            }
+            // a movie regex is only ever shadowed by another, the series and movie readings of a
+            // name are arbitrated
+            let first = parsing
+                .iter()
+                .map(|(parser, _)| *parser)
+                .find(|parser| (parser.kind == RegexKind::Movie) == (position.kind == RegexKind::Movie))
+                .unwrap_or(*position);
+            if first == *position {
+                shadowed_by = None;
+                break;
+            }
+            shadowed_by = shadowed_by.or(Some((name, first)));
+        }
+        if let Some((name, first)) = shadowed_by {
+            problems.push(format!(
+                "{} never parses a name: an earlier regex always parses them first, like {} for `{}`",
+                describe(*position),
+                describe(first),
+                name
+            ));
+        }
+    }
+
+    for (name, parsing) in &parsed {
+        let Some((first, media_data)) = parsing.first() else {
+            continue;
+        };
+        if EPISODE.is_match(name) && !matches!(media_data, MediaData::TvSeries { .. }) {
+            problems.push(format!(
+                "{} parses the episode `{}` as {}",
+                describe(*first),
+                name,
+                match media_data {
+                    MediaData::Movie { .. } => "a movie",
+                    MediaData::Daily { .. } => "an episode of a daily show",
+                    _ => "an episode by absolute number",
+                }
+            ));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadowed_regexes_and_swallowed_episodes_are_found() {
+        assert_eq!(lint_regexes(&Config::default(), &[]), Vec::<String>::new());
+
+        let config = Config {
+            tv_regex: vec![
+                "(?<name>.*) S(?<season>[0-9]+)E(?<episode>[0-9]+)".to_string(),
+                "(?<name>.*) S(?<season>[0-9]+)E(?<episode>[0-9]+) ".to_string(),
+            ],
+            movie_regex: vec![
+                "(?<name>.*) (?<year>[0-9]{4}) ".to_string(),
+                "(?<name>.*) [".to_string(),
+            ],
+            ..Config::default()
+        };
+        let problems = lint_regexes(&config, &["show.name.2019.s01e02.1080p".to_string()]);
+        assert_eq!(problems.len(), 3, "{:#?}", problems);
+        assert!(problems[0].starts_with("movie_regex[1] is not a valid regex"));
+        assert_eq!(
+            problems[1],
+            "tv_regex[1] `(?<name>.*) S(?<season>[0-9]+)E(?<episode>[0-9]+) ` never parses a name: an earlier regex \
+             always parses them first, like tv_regex[0] `(?<name>.*) S(?<season>[0-9]+)E(?<episode>[0-9]+)` \
+             for `Show.Name.S01E02.1080p.WEB-DL.x264-GRP`"
+        );
+        assert_eq!(
+            problems[2],
+            "movie_regex[0] `(?<name>.*) (?<year>[0-9]{4}) ` parses the episode `show.name.2019.s01e02.1080p` as a movie"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use log::debug;
+use regex::Regex;
+
+use crate::{
+    filesystem::Fs,
+    path_utils::{get_extension, get_filestem},
+    plexmatch::PLEXMATCH_FILENAME,
+};
+
+/// How many directories above the file are searched, enough to reach the series folder
+/// from `Series/Season 1/episode.mkv`
+const MAX_LEVELS: usize = 2;
+
+static PLEXMATCH_TVDB_ID: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?mi)^\s*tvdbid\s*:\s*([0-9]+)\s*$").unwrap());
+static IMDB_ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)imdb\.com/title/tt([0-9]+)|<uniqueid[^>]*type="imdb"[^>]*>\s*tt([0-9]+)|<imdbid>\s*tt([0-9]+)"#).unwrap()
+});
+static NFO_TVDB_ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<uniqueid[^>]*type="tvdb"[^>]*>\s*([0-9]+)\s*</uniqueid>|<tvdbid>\s*([0-9]+)\s*</tvdbid>"#)
+        .unwrap()
+});
+
+/// The ids of the series or movie of a file found next to it, used before searching its name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KnownIds {
+    pub tvdb_id: Option<u64>,
+    pub imdb_id: Option<u64>,
+}
+
+/// Finds the IMDb id of the release of the file in an `.nfo` next to it or in one of its parent
+/// folders, like the `https://www.imdb.com/title/tt0111161/` link of scene releases. Returns the
+/// number of the id with the file it was read from
+pub fn find_imdb_id(fs: &dyn Fs, path: &Path) -> Option<(u64, PathBuf)> {
+    for dir in path.ancestors().skip(1).take(MAX_LEVELS) {
+        let Ok(entries) = fs.read_dir(dir) else {
+            continue;
+        };
+        let nfos = entries.into_iter().filter(|entry| {
+            get_extension(entry).is_some_and(|extension| extension.eq_ignore_ascii_case("nfo")) && fs.is_file(entry)
+        });
+        for nfo in nfos {
+            // scene NFOs are often not UTF-8, their links are ASCII anyway
+            let mut contents = Vec::new();
+            if fs.open_read(&nfo).and_then(|mut file| file.read_to_end(&mut contents)).is_err() {
+                continue;
+            }
+            if let Some(id) = parse_imdb_id(&String::from_utf8_lossy(&contents)) {
+                debug!("Found IMDb id {} in {}", id, nfo.display());
+                return Some((id, nfo));
+            }
+        }
+    }
+    None
+}
+
+/// Reads the number of the IMDb id of an `.nfo`, from a link to IMDb or a Kodi id
+pub fn parse_imdb_id(contents: &str) -> Option<u64> {
+    let captures = IMDB_ID.captures(contents)?;
+    captures.iter().skip(1).flatten().next()?.as_str().parse().ok()
+}
+
+/// Finds the TVDB id already established for the file by a `.plexmatch` or `.nfo` next to it
+/// or in one of its parent folders, returning it with the file it was read from
+pub fn find_tvdb_id(fs: &dyn Fs, path: &Path) -> Option<(u64, PathBuf)> {
+    for dir in path.ancestors().skip(1).take(MAX_LEVELS) {
+        let mut candidates = vec![dir.join(PLEXMATCH_FILENAME)];
+        if let Some(stem) = get_filestem(path) {
+            candidates.push(dir.join(format!("{}.nfo", stem)));
+        }
+        candidates.push(dir.join("tvshow.nfo"));
+        candidates.push(dir.join("movie.nfo"));
+
+        for candidate in candidates {
+            if !fs.is_file(&candidate) {
+                continue;
+            }
+            let Ok(contents) = fs.read_to_string(&candidate) else {
+                continue;
+            };
+            if let Some(id) = parse_tvdb_id(&contents) {
+                debug!("Found TVDB id {} in {}", id, candidate.display());
+                return Some((id, candidate));
+            }
+        }
+    }
+    None
+}
+
+/// Reads the TVDB id from the contents of a `.plexmatch` or an `.nfo`
+pub fn parse_tvdb_id(contents: &str) -> Option<u64> {
+    if let Some(captures) = PLEXMATCH_TVDB_ID.captures(contents) {
+        return captures[1].parse().ok();
+    }
+    let captures = NFO_TVDB_ID.captures(contents)?;
+    captures.get(1).or(captures.get(2))?.as_str().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filesystem::MemoryFs;
+
+    use super::*;
+
+    #[test]
+    fn reads_ids_from_plexmatch_and_nfo() {
+        assert_eq!(parse_tvdb_id("title: Severance\nyear: 2022\ntvdbid: 371980\n"), Some(371980));
+        assert_eq!(
+            parse_tvdb_id("<tvshow><uniqueid type=\"tvdb\" default=\"true\">371980</uniqueid></tvshow>"),
+            Some(371980)
+        );
+        assert_eq!(parse_tvdb_id("<movie><tvdbid>123</tvdbid></movie>"), Some(123));
+        assert_eq!(parse_tvdb_id("<movie><uniqueid type=\"imdb\">tt1</uniqueid></movie>"), None);
+    }
+
+    #[test]
+    fn finds_imdb_links_of_release_nfos() {
+        assert_eq!(parse_imdb_id("Link ....: https://www.imdb.com/title/tt1160419/"), Some(1160419));
+        assert_eq!(parse_imdb_id("<movie><uniqueid type=\"imdb\">tt0111161</uniqueid></movie>"), Some(111161));
+        assert_eq!(parse_imdb_id("<movie><tvdbid>123</tvdbid></movie>"), None);
+
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Dune.2021.1080p/dune-group.NFO", b"\xb0\xb1 imdb.com/title/tt1160419 \xb2");
+        fs.add_file("downloads/Dune.2021.1080p/Dune.2021.1080p.mkv", b"");
+        assert_eq!(
+            find_imdb_id(&fs, Path::new("downloads/Dune.2021.1080p/Dune.2021.1080p.mkv")),
+            Some((1160419, PathBuf::from("downloads/Dune.2021.1080p/dune-group.NFO")))
+        );
+    }
+
+    #[test]
+    fn finds_plexmatch_of_series_folder() {
+        let fs = MemoryFs::new();
+        fs.add_file("TV/Severance/.plexmatch", b"title: Severance\ntvdbid: 371980\n");
+        fs.add_file("TV/Severance/Season 1/Severance - s01e01.mkv", b"");
+
+        assert_eq!(
+            find_tvdb_id(&fs, Path::new("TV/Severance/Season 1/Severance - s01e01.mkv")),
+            Some((371980, PathBuf::from("TV/Severance/.plexmatch")))
+        );
+        assert_eq!(find_tvdb_id(&fs, Path::new("downloads/Severance.S01E01.mkv")), None);
+    }
+}
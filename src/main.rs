@@ -1,24 +1,122 @@
 use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
     env,
+    fmt::Display,
     fs::{self, OpenOptions},
-    io, os,
+    io::{self, Read},
+    ops::RangeInclusive,
     path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::Duration,
     vec,
 };
 
-use clap::{builder::PossibleValue, Parser, ValueEnum};
+use attributes::Attributes;
+use batch::Batch;
+use cache::ResponseCache;
+use cleanup::Leftovers;
+use deferred::{Deferred, DeferredMove};
+use clap::{builder::PossibleValue, Parser, Subcommand, ValueEnum};
 use dir_walker::DirWalker;
+use error::{Error, Stage};
+use exit_code::Outcome;
+use filesystem::{Fs, ReadOnlyFs, RealFs};
+use grouping::Groups;
+use hashing::Hashing;
+use interactive::{ConflictPolicy, Decision, FileInfo, Prompt, Resolution, TerminalPrompt};
+use journal::{Journal, Operation};
+use local_ids::KnownIds;
 use log::{debug, error, info, warn};
-use name_parser::parse_filepath;
-use path_utils::get_extension;
+use media::{Matching, MediaData, MediaFile, MediaType, Metadata, NameMatch};
+use name_parser::{parse_file, parse_filepath, MediaPreference, ParseFallback};
+use parsed::ParsedMedia;
+use naming::{Scheme, Template};
+use omdb::OmdbClient;
+use overrides::{EpisodeOrder, Override};
+use path_utils::{get_extension, get_filename, get_filestem, has_utf8_name};
+use placement::{OutputRoots, Placement};
+use quality::Quality;
+use plan::{Plan, PlanEntry};
+use policy::ExtensionPolicy;
+use preflight::LowSpace;
+use provider::{MetadataProvider, ProviderKind};
+use recent::RecentImport;
+use rpc::{Jobs, RpcError};
+use recording::Recording;
+use routing::Route;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use summary::{ReportEntry, Summary};
+use transfer::{CancellationToken, Transfer};
+use torrent_client::{Seeding, TorrentClient};
+use tmdb::TmdbClient;
 use tvdb::TvdbClient;
+use tvmaze::TvmazeClient;
+use validation::Validation;
+use want_list::WantList;
 
+mod attributes;
+mod batch;
+mod cache;
+mod cleanup;
+mod deferred;
+mod demo;
 mod dir_walker;
+mod embedded;
+mod error;
+mod exit_code;
+mod filesystem;
+mod grouping;
+mod hashing;
+mod ignored_titles;
+mod interactive;
+mod journal;
+mod junk;
+mod lint;
+mod local_ids;
 mod media;
 mod name_parser;
+mod naming;
+mod omdb;
+mod overrides;
+mod parsed;
 mod path_utils;
+mod placement;
+mod plan;
+mod policy;
+mod provider;
+mod nfo;
+mod plexmatch;
+mod preflight;
+mod progress;
+mod quality;
+mod query;
+mod reapply;
+mod recent;
+mod recording;
+mod routing;
+mod rpc;
+mod run_id;
+mod simulate;
+mod staging;
+mod subtitles;
+mod summary;
+mod trace;
+mod transfer;
+mod trash;
+mod torrent_client;
+mod tmdb;
 mod tvdb;
+mod tvmaze;
+mod validation;
+mod want_list;
+mod workers;
 
 #[derive(Debug, Clone, Copy)]
 enum Action {
@@ -26,11 +124,18 @@ enum Action {
     Move,
     Copy,
     Symlink,
+    Hardlink,
 }
 
 impl ValueEnum for Action {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Action::Test, Action::Move, Action::Copy, Action::Symlink]
+        &[
+            Action::Test,
+            Action::Move,
+            Action::Copy,
+            Action::Symlink,
+            Action::Hardlink,
+        ]
     }
 
     fn to_possible_value(&self) -> Option<PossibleValue> {
@@ -45,22 +150,74 @@ impl From<Action> for &str {
             Action::Move => "move",
             Action::Copy => "copy",
             Action::Symlink => "symlink",
+            Action::Hardlink => "hardlink",
         }
     }
 }
 
-impl ToString for Action {
-    fn to_string(&self) -> String {
-        Into::<&str>::into(*self).into()
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
     }
 }
 
-#[derive(Parser, Debug)]
+/// The order in which the files of a run are processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Priority {
+    Oldest,
+    Newest,
+    Smallest,
+    Largest,
+    TvFirst,
+    MoviesFirst,
+}
+
+impl ValueEnum for Priority {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Priority::Oldest,
+            Priority::Newest,
+            Priority::Smallest,
+            Priority::Largest,
+            Priority::TvFirst,
+            Priority::MoviesFirst,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(Into::<&str>::into(*self)))
+    }
+}
+
+impl From<Priority> for &str {
+    fn from(value: Priority) -> Self {
+        match value {
+            Priority::Oldest => "oldest",
+            Priority::Newest => "newest",
+            Priority::Smallest => "smallest",
+            Priority::Largest => "largest",
+            Priority::TvFirst => "tv-first",
+            Priority::MoviesFirst => "movies-first",
+        }
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
 #[command(version, about = "Rename downloaded media and create the Plex directory structure", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The input file or folder
-    #[arg(short, long)]
-    input: String,
+    #[arg(short, long, required = true)]
+    input: Option<String>,
 
     /// The max depth to traverse directories, if none recurse indefinitely
     #[arg(short, long)]
@@ -70,17 +227,321 @@ struct Args {
     #[arg(short, long, default_value_t = Action::Test)]
     action: Action,
 
-    /// The output directory for the files
-    #[arg(short, long)]
-    output: String,
+    /// Run with the test action and write nothing at all: no configuration directory, default configuration, log file, cache or TVDB token
+    #[arg(long, default_value_t = false)]
+    pure_dry_run: bool,
 
-    /// The path of the configuration file
+    /// The output directory for the files, can be given multiple times to spread the library across disks
+    #[arg(short, long, required = true)]
+    output: Vec<String>,
+
+    /// The output directory for the series and movies with a kids content rating, placed in --output when none
     #[arg(long)]
+    kids_output: Option<String>,
+
+    /// How series and movies are placed when there are multiple output directories
+    #[arg(long, default_value_t = Placement::SeriesAffinity)]
+    placement: Placement,
+
+    /// The path of the configuration file
+    #[arg(long, global = true)]
     config: Option<String>,
 
     /// Should print verbose output (useful for debugging config for example)
-    #[arg(long, default_value_t = false)]
+    #[arg(long, global = true, default_value_t = false)]
     verbose: bool,
+
+    /// Record the raw TVDB responses into this file
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// Serve the TVDB responses from a file created with --record instead of querying the API
+    #[arg(long)]
+    replay: Option<String>,
+
+    /// Limit the copy speed, in KiB per second
+    #[arg(long)]
+    rate_limit: Option<u64>,
+
+    /// Compare copied files with their source after copying, by the hash of [hashing.verify] in the config
+    #[arg(long, default_value_t = false)]
+    verify: bool,
+
+    /// Continue the copies left unfinished by an interrupted run instead of removing them when the run starts
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+
+    /// Import at most this many files, in priority order, leaving the rest for the next run
+    #[arg(long)]
+    max_files: Option<usize>,
+
+    /// Import at most this many bytes (suffixes K, M, G and T are accepted), in priority order, leaving the rest for the next run
+    #[arg(long, value_parser = parse_size)]
+    max_bytes: Option<u64>,
+
+    /// The order in which files are processed, by default alphabetical or oldest first when a limit is set
+    #[arg(long)]
+    priority: Option<Priority>,
+
+    /// Write a .plexmatch file with the title, year and TVDB id into each series and movie folder
+    #[arg(long, default_value_t = false)]
+    plexmatch: bool,
+
+    /// Write a tvshow.nfo or movie.nfo file, read by Kodi, with the title, year and ids into each series and movie folder
+    #[arg(long, default_value_t = false)]
+    nfo: bool,
+
+    /// Name the files with the layout of a media center instead of the templates of the configuration
+    #[arg(long)]
+    scheme: Option<Scheme>,
+
+    /// Resolve every file and validate the whole plan before moving, copying or linking any of them
+    #[arg(long, default_value_t = false)]
+    two_pass: bool,
+
+    /// How many files are looked up and placed at the same time, 0 for one per CPU. Every file is then resolved before any is placed, as with --two-pass
+    #[arg(long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Confirm the destination of every file, with the choice to skip it, change its name or stop
+    #[arg(long, default_value_t = false)]
+    interactive: bool,
+
+    /// Let the conflict policy replace existing files with files of a very different size, which are otherwise
+    /// kept as suspicious, see max_size_ratio in the config
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Place the files in a hidden staging directory in the output directory and move them into the library at the end of the run,
+    /// so that media servers never see a half-imported season
+    #[arg(long, default_value_t = false)]
+    staging: bool,
+
+    /// Do not draw progress bars, which are otherwise drawn while scanning, processing and copying when the output is a terminal
+    #[arg(long, default_value_t = false)]
+    no_progress: bool,
+
+    /// Write the outcome of every file, with a reason code for the skipped ones, to this JSON file
+    #[arg(long)]
+    report: Option<String>,
+
+    /// Only import the series and movies of this list: a text file with one title per line, or a JSON export of a Trakt list or of Sonarr or Radarr
+    #[arg(long)]
+    want_list: Option<String>,
+
+    /// Only import the files of this series or movie, compared with the name in the filename. Can be given multiple times
+    #[arg(long)]
+    only: Vec<String>,
+
+    /// Only import the files of the series or movie with this TVDB id. Can be given multiple times
+    #[arg(long)]
+    only_tvdb_id: Vec<u64>,
+
+    /// Only import the episodes of this season. Can be given multiple times
+    #[arg(long)]
+    season: Vec<u32>,
+
+    /// Only import the episodes in this range, e.g. 1-10, of every season or of the ones given with --season
+    #[arg(long, value_parser = parse_episode_range)]
+    episodes: Option<RangeInclusive<u32>>,
+
+    #[command(flatten)]
+    forced: Forced,
+}
+
+/// What the single file given as --input is, in place of what its name parses to. The forced name
+/// is still searched, and used as-is when the metadata providers do not know it
+#[derive(clap::Args, Debug, Clone, Default)]
+struct Forced {
+    /// The name of the series or movie of the file
+    #[arg(long)]
+    force_name: Option<String>,
+
+    /// The season of the episode, making the file an episode
+    #[arg(long)]
+    force_season: Option<u32>,
+
+    /// The number of the episode in its season, making the file an episode
+    #[arg(long)]
+    force_episode: Option<u32>,
+
+    /// The year of the movie
+    #[arg(long)]
+    force_year: Option<u32>,
+
+    /// Whether the file is an episode of a series or a movie
+    #[arg(long = "force-type", value_parser = parse_media_type)]
+    force_media_type: Option<MediaType>,
+}
+
+impl Forced {
+    fn is_empty(&self) -> bool {
+        self.force_name.is_none()
+            && self.force_season.is_none()
+            && self.force_episode.is_none()
+            && self.force_year.is_none()
+            && self.force_media_type.is_none()
+    }
+
+    /// The file with the forced parts in place of the parsed ones, none when together they do not
+    /// tell the name and the episode or the year of the movie
+    fn apply(&self, path: &Path, parsed: Option<MediaFile>) -> Option<MediaFile> {
+        if self.is_empty() {
+            return parsed;
+        }
+        let name = self.force_name.clone().or_else(|| Some(parsed.as_ref()?.name().to_string()))?;
+        let tags = parsed.as_ref().map(|parsed| parsed.parsed().clone());
+        let parsed = parsed.map(|parsed| *parsed.media());
+        let numbered = self.force_season.is_some() || self.force_episode.is_some();
+        let media_type = match (self.force_media_type, &parsed) {
+            (Some(media_type), _) => media_type,
+            _ if numbered => MediaType::Series,
+            (None, Some(MediaData::Movie { .. }) | None) => MediaType::Movie,
+            (None, Some(_)) => MediaType::Series,
+        };
+
+        let media_data = match (media_type, parsed) {
+            (MediaType::Series, Some(MediaData::TvSeries { season, episode })) => MediaData::TvSeries {
+                season: self.force_season.unwrap_or(season),
+                episode: self.force_episode.unwrap_or(episode),
+            },
+            // daily and absolute numbers are kept when no other numbers are forced
+            (MediaType::Series, Some(parsed @ (MediaData::Daily { .. } | MediaData::Absolute { .. }))) if !numbered => parsed,
+            (MediaType::Series, _) => MediaData::TvSeries {
+                season: self.force_season?,
+                episode: self.force_episode?,
+            },
+            (MediaType::Movie, Some(MediaData::Movie { year })) => MediaData::Movie {
+                year: self.force_year.unwrap_or(year),
+            },
+            (MediaType::Movie, _) => MediaData::Movie { year: self.force_year? },
+        };
+
+        // the tags of the release are kept, and its episodes unless the numbering is forced
+        let forced = ParsedMedia::new(name, media_data);
+        let release = match tags {
+            Some(tags) if tags.media == media_data => ParsedMedia {
+                title: forced.title,
+                ..tags
+            },
+            Some(tags) => ParsedMedia {
+                title: forced.title,
+                media: forced.media,
+                episodes: forced.episodes,
+                ..tags
+            },
+            None => forced,
+        };
+        Some(MediaFile::from_parsed(release, get_extension(path)?))
+    }
+}
+
+/// Parses the type of a media file, series (or tv) or movie
+fn parse_media_type(media_type: &str) -> Result<MediaType, String> {
+    match media_type.to_lowercase().as_str() {
+        "series" | "tv" => Ok(MediaType::Series),
+        "movie" => Ok(MediaType::Movie),
+        _ => Err(format!("{} is not series or movie", media_type)),
+    }
+}
+
+/// Parses a size in bytes with an optional binary suffix, e.g. 500M or 2G
+fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&size[..size.len() - 1], 1u64 << 10),
+        Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') => (&size[..size.len() - 1], 1 << 30),
+        Some('T') => (&size[..size.len() - 1], 1 << 40),
+        _ => (size, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .map_err(|error| format!("invalid size {}: {}", size, error))?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size {} is too big", size))
+}
+
+/// Parses a range of episode numbers like 1-10, or a single episode like 5
+fn parse_episode_range(range: &str) -> Result<RangeInclusive<u32>, String> {
+    let number = |number: &str| {
+        number
+            .trim()
+            .parse::<u32>()
+            .map_err(|error| format!("invalid episode {}: {}", number, error))
+    };
+    let (first, last) = match range.split_once('-') {
+        Some((first, last)) => (number(first)?, number(last)?),
+        None => (number(range)?, number(range)?),
+    };
+    if first > last {
+        return Err(format!("the episode range {} ends before it starts", range));
+    }
+    Ok(first..=last)
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Run hypothetical filenames through the parse and naming pipeline without touching files or the API
+    Simulate {
+        /// A file with one filename per line, empty lines and lines starting with # are skipped
+        #[arg(long)]
+        fixture: String,
+    },
+    /// Move a few fake releases into a library in a temporary sandbox, offline, and show the result
+    Demo {
+        /// Keep the sandbox instead of removing it at the end
+        #[arg(long, default_value_t = false)]
+        keep: bool,
+    },
+    /// Reverse the moves, copies and links of the last run
+    Undo {
+        /// The journal of the run to undo, by default the last one in ~/.media-renamer/history
+        #[arg(long)]
+        journal: Option<String>,
+    },
+    /// Write the destinations of the files to a JSON plan, to review or edit, without touching them
+    Plan {
+        /// The file the plan is written to
+        #[arg(long)]
+        file: String,
+    },
+    /// Move, copy or link the files of a plan written by the plan command to its destinations
+    Apply {
+        /// The plan to apply
+        #[arg(long)]
+        file: String,
+    },
+    /// Rename the files imported by earlier runs into the output directories to match the current templates
+    ReapplyTemplates,
+    /// Look up the files imported without metadata or with an uncertain match again, and rename them
+    RefreshMetadata,
+    /// Print what the runs did with a source or destination file, from the log and the journals of the history
+    Trace {
+        /// The file, as it was in the downloads or as it is in the library
+        path: String,
+    },
+    /// Import the files and folders submitted over a Unix socket with JSON-RPC 2.0, with the other arguments of the command
+    Serve {
+        /// The Unix socket the calls are answered on, only the current user can connect to it
+        socket: String,
+    },
+    /// Check the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigCommand {
+    /// Look for regexes that never parse a name because an earlier one always does first, and episodes parsed as movies
+    Validate {
+        /// A file of the user's filenames the regexes are tried on along with common release names, one per line like the fixture of simulate
+        #[arg(long)]
+        fixture: Option<String>,
+    },
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -88,33 +549,293 @@ struct Config {
     /// The API key for TVDB
     tvdb_api_key: String,
 
+    /// Where series and movies are matched, tvdb or tmdb
+    #[serde(default)]
+    provider: ProviderKind,
+
+    /// The providers asked in order when the provider does not find a series or movie
+    #[serde(default)]
+    fallback_providers: Vec<ProviderKind>,
+
+    /// The API read access token of the TMDB account, used when the provider is tmdb
+    #[serde(default)]
+    tmdb_token: String,
+
+    /// The API key of OMDb, used when the provider is omdb
+    #[serde(default)]
+    omdb_api_key: String,
+
+    /// How many hours the API responses are kept in the cache, 0 disables the cache
+    #[serde(default = "default_cache_ttl_hours")]
+    cache_ttl_hours: u64,
+
+    /// When a provider cannot be reached to log in, carry on without it instead of stopping, naming
+    /// the files with their parsed names when it was the only one
+    #[serde(default)]
+    offline_fallback: bool,
+
     /// The extensions of the files that should be processed
     extensions: Vec<String>,
 
+    /// What is done with the files of other extensions: subtitle, follow, ignore, delete or process
+    #[serde(default = "policy::default_extension_policies")]
+    extension_policies: BTreeMap<String, ExtensionPolicy>,
+
     /// The regular expressions to parse tv series filenames
     tv_regex: Vec<String>,
 
+    /// The regular expressions to parse filenames of daily shows named by air date, tried after `tv_regex`
+    #[serde(default = "default_daily_regex")]
+    daily_regex: Vec<String>,
+
+    /// The regular expressions to parse filenames numbered by absolute episode, like anime releases,
+    /// tried after `daily_regex`
+    #[serde(default = "default_absolute_regex")]
+    absolute_regex: Vec<String>,
+
     /// The regular expressions to parse movie filenames
     movie_regex: Vec<String>,
 
+    /// Which reading wins when a filename is parsed by both a series regex and a movie regex:
+    /// specific (the reading with the more numbers, like an episode over a year), series or movie
+    #[serde(default)]
+    media_preference: MediaPreference,
+
+    /// Searches the other reading of a filename parsed both as a series and as a movie when the
+    /// providers do not find the one preferred
+    #[serde(default)]
+    confirm_media_type: bool,
+
+    /// What is tried in order when a filename matches none of the regexes: parent_dir,
+    /// embedded_title, heuristics and quarantine
+    #[serde(default = "name_parser::default_parse_fallbacks")]
+    parse_fallbacks: Vec<ParseFallback>,
+
+    /// Where the quarantine fallback places the files it could not parse, relative to the output directory
+    #[serde(default = "default_quarantine_dir")]
+    quarantine_dir: PathBuf,
+
+    /// The path of episodes in the library, relative to the output directory
+    #[serde(default = "naming::default_tv_template")]
+    tv_template: Template,
+
+    /// The path of episodes of daily shows in the library when their season and number are unknown
+    #[serde(default = "naming::default_daily_template")]
+    daily_template: Template,
+
+    /// The path of movies in the library, relative to the output directory
+    #[serde(default = "naming::default_movie_template")]
+    movie_template: Template,
+
     /// Replacements that will be applied before matching with regex
     replacements: Vec<(String, String)>,
 
     /// Directories with these names are ignored
     ignored_dirs: Vec<String>,
+
+    /// How many files and directories are walked at most in a directory, like an input directory set
+    /// by mistake to `/`, the rest of it is left as it is
+    #[serde(default = "default_max_walk_entries")]
+    max_walk_entries: usize,
+
+    /// Directories reached through a symlink are not entered when walking an input directory
+    #[serde(default)]
+    skip_symlinked_dirs: bool,
+
+    /// Filenames made only of these words are not media titles and are skipped without querying TVDB
+    #[serde(default = "junk::default_junk_names")]
+    junk_names: Vec<String>,
+
+    /// Series and movies skipped without being searched or reported as failures, as titles or globs
+    /// like "Love Island*", ending with a season like "Survivor S45" to skip only that season
+    #[serde(default)]
+    ignored_titles: Vec<String>,
+
+    /// How many TVDB results of every search are logged with --verbose
+    #[serde(default = "default_candidates")]
+    candidates: usize,
+
+    /// The confidence from 0 to 1 the best search result needs to be matched to, 0 to take any result
+    #[serde(default = "default_min_confidence")]
+    min_confidence: f64,
+
+    /// Search results within this much confidence of the best one are too close to tell apart:
+    /// with --interactive the user chooses between them, otherwise the best one is taken with a warning
+    #[serde(default = "default_ambiguity_margin")]
+    ambiguity_margin: f64,
+
+    /// Files smaller than this many bytes are skipped, 0 to import files of any size
+    #[serde(default)]
+    min_size: u64,
+
+    /// Files modified less than this many seconds ago are skipped as still downloading, 0 to never skip them
+    #[serde(default)]
+    settle_time: u64,
+
+    /// With the move action, copy the files and send the originals to the trash of the OS instead of moving them
+    #[serde(default)]
+    trash_sources: bool,
+
+    /// With the move action, the source directories left with only leftovers once their files are
+    /// moved are deleted along with them, up to the input directory
+    #[serde(default)]
+    clean_sources: bool,
+
+    /// The extensions of the files that are leftovers of a release once its files are moved, like
+    /// `nfo` and `txt`. The ignored directories, like sample folders, are leftovers too
+    #[serde(default = "cleanup::default_leftover_extensions")]
+    leftover_extensions: Vec<String>,
+
+    /// What is done with a file whose destination exists when not running with --interactive: skip,
+    /// overwrite, rename-with-suffix or keep-larger
+    #[serde(default)]
+    on_conflict: ConflictPolicy,
+
+    /// How many times smaller or larger than the existing file a file replacing it by the conflict policy
+    /// can be, unless of a better resolution or with --force, 0 to allow any size
+    #[serde(default = "default_max_size_ratio")]
+    max_size_ratio: f64,
+
+    /// The bytes kept free in the output directories: before placing any file, a run copying more than
+    /// the free space less this margin does what on_low_space says
+    #[serde(default = "default_free_space_margin")]
+    free_space_margin: u64,
+
+    /// What is done when an output directory has too little free space for the files of the run: abort or warn
+    #[serde(default)]
+    on_low_space: LowSpace,
+
+    /// The TVDB language code searches are restricted to, e.g. "fra"
+    #[serde(default)]
+    language: Option<String>,
+
+    /// The TVDB country code searches are restricted to, e.g. "fra"
+    #[serde(default)]
+    country: Option<String>,
+
+    /// The content ratings of the titles placed in the --kids-output library
+    #[serde(default = "default_kids_ratings")]
+    kids_ratings: Vec<String>,
+
+    /// The TVDB country code of the content ratings compared with kids_ratings
+    #[serde(default = "default_rating_country")]
+    rating_country: String,
+
+    /// How many of the latest imports are kept in recent.json for dashboards, 0 to not write it
+    #[serde(default = "default_recent_imports")]
+    recent_imports: usize,
+
+    /// The checks the destinations of the files have to pass
+    #[serde(default)]
+    validation: Validation,
+
+    /// How files are hashed to verify copies and to find duplicates
+    #[serde(default)]
+    hashing: Hashing,
+
+    /// What the files placed in the library keep of their source, and who owns them
+    #[serde(default)]
+    attributes: Attributes,
+
+    /// The torrent client asked which downloads are still seeding, so that the move action links
+    /// them into the library until they are done
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    torrent_client: Option<TorrentClient>,
+
+    /// Settings of single series or movies under their name, like `[overrides."Firefly"]`
+    #[serde(default)]
+    overrides: BTreeMap<String, Override>,
+
+    /// The libraries the series and movies matching a rule are placed in instead of the output
+    /// directory, like `[[routes]]`. The first route matching a file is taken
+    #[serde(default)]
+    routes: Vec<Route>,
+}
+
+fn default_daily_regex() -> Vec<String> {
+    vec![
+        "(?<name>.*) (?<year>[0-9]{4}) (?<month>[0-9]{2}) (?<day>[0-9]{2})\\b".to_string(), // Show Name 2024 03 12
+    ]
+}
+
+fn default_absolute_regex() -> Vec<String> {
+    vec![
+        "^\\[[^\\]]+\\] (?<name>.+?) - (?<episode>[0-9]{1,4})(?:v[0-9])?\\b".to_string(), // [Group] Show Name - 28
+    ]
+}
+
+fn default_max_walk_entries() -> usize {
+    1_000_000
+}
+
+fn default_quarantine_dir() -> PathBuf {
+    PathBuf::from("Unparsed")
+}
+
+fn default_candidates() -> usize {
+    5
+}
+
+fn default_min_confidence() -> f64 {
+    0.6
+}
+
+fn default_ambiguity_margin() -> f64 {
+    0.05
+}
+
+fn default_recent_imports() -> usize {
+    50
+}
+
+fn default_max_size_ratio() -> f64 {
+    4.0
+}
+
+fn default_free_space_margin() -> u64 {
+    1 << 30
+}
+
+fn default_kids_ratings() -> Vec<String> {
+    ["G", "PG", "TV-Y", "TV-Y7", "TV-G"].map(str::to_string).to_vec()
+}
+
+fn default_rating_country() -> String {
+    "usa".to_string()
+}
+
+/// A week, long enough for a rerun over the same library and short enough to see new episodes
+fn default_cache_ttl_hours() -> u64 {
+    7 * 24
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             tvdb_api_key: "<ENTER HERE THE TVDB API KEY>".to_string(),
-            extensions: vec!["mkv".to_string(), "srr".to_string()],
+            provider: ProviderKind::Tvdb,
+            fallback_providers: Vec::new(),
+            tmdb_token: String::new(),
+            omdb_api_key: String::new(),
+            cache_ttl_hours: default_cache_ttl_hours(),
+            offline_fallback: false,
+            extensions: policy::default_extensions(),
+            extension_policies: policy::default_extension_policies(),
             tv_regex: vec![
                 "(?<name>.*) [Ss](?<season>[0-9]+)[Ee](?<episode>[0-9]+)".to_string(), // Series Name S01E01
             ],
+            daily_regex: default_daily_regex(),
+            absolute_regex: default_absolute_regex(),
             movie_regex: vec![
                 "(?<name>.*) (?<year>[0-9]{4}) ".to_string(), // Movie Name 2025
             ],
+            media_preference: MediaPreference::default(),
+            confirm_media_type: false,
+            parse_fallbacks: name_parser::default_parse_fallbacks(),
+            quarantine_dir: default_quarantine_dir(),
+            tv_template: naming::default_tv_template(),
+            daily_template: naming::default_daily_template(),
+            movie_template: naming::default_movie_template(),
             replacements: vec![(".".to_string(), " ".to_string())],
             ignored_dirs: vec![
                 "Sample".to_string(),
@@ -122,6 +843,33 @@ impl Default for Config {
                 "Samples".to_string(),
                 "samples".to_string(),
             ],
+            max_walk_entries: default_max_walk_entries(),
+            skip_symlinked_dirs: false,
+            junk_names: junk::default_junk_names(),
+            ignored_titles: Vec::new(),
+            candidates: default_candidates(),
+            min_confidence: default_min_confidence(),
+            ambiguity_margin: default_ambiguity_margin(),
+            min_size: 0,
+            settle_time: 0,
+            trash_sources: false,
+            clean_sources: false,
+            leftover_extensions: cleanup::default_leftover_extensions(),
+            on_conflict: ConflictPolicy::default(),
+            max_size_ratio: default_max_size_ratio(),
+            free_space_margin: default_free_space_margin(),
+            on_low_space: LowSpace::default(),
+            language: None,
+            country: None,
+            kids_ratings: default_kids_ratings(),
+            rating_country: default_rating_country(),
+            recent_imports: default_recent_imports(),
+            validation: Validation::default(),
+            hashing: Hashing::default(),
+            attributes: Attributes::default(),
+            torrent_client: None,
+            overrides: BTreeMap::new(),
+            routes: Vec::new(),
         }
     }
 }
@@ -142,24 +890,6 @@ fn get_filepath_in_conf_dir(filename: &str) -> Option<PathBuf> {
     Some(path)
 }
 
-fn extension_matches(path: &Path, extensions: &[String]) -> bool {
-    let Some(ext) = get_extension(path) else { return false; };
-    extensions.contains(&ext)
-}
-
-fn symlink(original: &Path, link: &Path) -> Result<(), io::Error> {
-    let original_absolute = original.canonicalize()?;
-    #[cfg(target_os = "windows")]
-    {
-        os::windows::fs::symlink_file(original_absolute, link)?;
-    }
-    #[cfg(target_os = "linux")]
-    {
-        os::unix::fs::symlink(original_absolute, link)?;
-    }
-    Ok(())
-}
-
 fn ensure_conf_dir_exists() {
     let conf_dir = get_conf_dir().expect("Could not get home directory");
     if !conf_dir.exists() {
@@ -171,13 +901,32 @@ fn ensure_conf_dir_exists() {
                     conf_dir.display(),
                     error
                 );
-                return;
             }
         }
     }
 }
 
 fn init_logger(args: &Args) -> bool {
+    let level = if args.verbose {
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Info
+    };
+    // the progress bars are hidden while a line is written under them
+    let terminal = progress::ProgressLogger::new(simplelog::TermLogger::new(
+        level,
+        simplelog::Config::default(),
+        simplelog::TerminalMode::Mixed,
+        simplelog::ColorChoice::Auto,
+    ));
+    if args.pure_dry_run {
+        if let Err(error) = simplelog::CombinedLogger::init(vec![terminal]) {
+            println!("Could not initialize logger: {}", error);
+            return false;
+        }
+        return true;
+    }
+
     let Some(log_filepath) = get_filepath_in_conf_dir("log.txt") else {
         return false;
     };
@@ -198,20 +947,10 @@ fn init_logger(args: &Args) -> bool {
         }
     };
 
-    let level = if args.verbose {
-        log::LevelFilter::Debug
-    } else {
-        log::LevelFilter::Info
-    };
-
     if let Err(error) = simplelog::CombinedLogger::init(vec![
-        simplelog::TermLogger::new(
-            level,
-            simplelog::Config::default(),
-            simplelog::TerminalMode::Mixed,
-            simplelog::ColorChoice::Auto,
-        ),
-        simplelog::WriteLogger::new(level, simplelog::Config::default(), file),
+        terminal,
+        // several runs append to the same file, their lines are told apart by the run id
+        run_id::RunLogger::new(simplelog::WriteLogger::new(level, simplelog::Config::default(), file)),
     ]) {
         println!("Could not initialize logger: {}", error);
         return false;
@@ -220,195 +959,4103 @@ fn init_logger(args: &Args) -> bool {
     true
 }
 
+/// The configuration of the run, none when it cannot be read or is invalid, which stops the run.
+/// A missing configuration is written with the defaults and the defaults are used
 fn read_config(args: &Args) -> Option<Config> {
     let config_path = match &args.config {
         Some(path) => Some(PathBuf::from(path)),
         None => get_filepath_in_conf_dir("config.toml"),
     }?;
 
+    if !config_path.exists() && args.pure_dry_run {
+        info!("There is no configuration at {}, using the defaults", config_path.display());
+        return Some(Config::default());
+    }
     if !config_path.exists() {
-        if let Some(parent) = config_path.parent() {
-            if let Err(error) = fs::create_dir_all(parent) {
-                error!(
-                    "Could not create directories to {}: {}",
-                    parent.display(),
-                    error
-                );
-            }
-        }
-        let default_config = Config::default();
-
-        if let Err(error) = fs::write(
-            &config_path,
-            toml::to_string(&default_config).expect("Could not serialize the default config"),
-        ) {
-            error!(
-                "Could not write default configuration to {}: {}",
-                config_path.display(),
-                error
-            );
+        if let Err(error) = write_default_config(&config_path) {
+            error!("{}", error);
             warn!("Continuing with defaults");
+            return Some(Config::default());
         }
     }
 
     info!("Reading configuration from {}", config_path.display());
-    let config = match fs::read_to_string(&config_path) {
-        Ok(config_string) => match toml::from_str(&config_string) {
-            Ok(conf) => conf,
-            Err(error) => {
-                error!(
-                    "Could not parse config {}: {}",
-                    config_path.display(),
-                    error
-                );
-                warn!("Continuing with defaults");
-                Config::default()
-            }
-        },
+    match load_config(&config_path) {
+        Ok(config) => Some(config),
         Err(error) => {
-            error!("Could not read config {}: {}", config_path.display(), error);
-            warn!("Continuing with defaults");
-            Config::default()
+            error!("{}", error);
+            None
         }
-    };
-
-    Some(config)
+    }
 }
 
-fn process_file(path: &Path, args: &Args, config: &Config, tvdb: &TvdbClient) {
-    info!("Processing file {}", path.display());
-
-    let Some(mut media_file) = parse_filepath(path, &config) else {
-        warn!("Could not parse filename {}", path.display());
-        return;
-    };
+/// The configuration of the run with the templates of `--scheme`, the config outcome when it
+/// cannot be read
+fn configure(args: &Args) -> Result<Config, Outcome> {
+    let mut config = read_config(args).ok_or(Outcome::Config)?;
+    if let Some(scheme) = args.scheme {
+        info!("Naming the files with the {} scheme instead of the templates of the configuration", scheme);
+        [config.tv_template, config.daily_template, config.movie_template] = scheme.templates();
+    }
+    Ok(config)
+}
 
-    match media_file.request_name(&tvdb) {
-        Ok(true) => {}
-        Ok(false) => {
-            warn!("Could not find {} on TVDB. Ignoring", media_file.name());
-            return;
-        }
-        Err(error) => {
-            error!(
-                "TVDB error while searching for {}: {}",
-                media_file.name(),
-                error
-            );
-        }
+fn write_default_config(config_path: &Path) -> error::Result<()> {
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent).map_err(|source| Error::Filesystem {
+            path: parent.to_path_buf(),
+            stage: Stage::CreateDir,
+            source,
+        })?;
     }
 
-    debug!("{:#?}", media_file);
+    let default_config = Config::default();
+    fs::write(
+        config_path,
+        toml::to_string(&default_config).expect("Could not serialize the default config"),
+    )
+    .map_err(|source| Error::Filesystem {
+        path: config_path.to_path_buf(),
+        stage: Stage::Config,
+        source,
+    })
+}
 
-    let mut final_path = PathBuf::from(&args.output);
-    final_path.push(media_file.get_path());
+fn load_config(config_path: &Path) -> error::Result<Config> {
+    let config_string = fs::read_to_string(config_path).map_err(|source| Error::Filesystem {
+        path: config_path.to_path_buf(),
+        stage: Stage::Config,
+        source,
+    })?;
 
-    info!("Final path: {}", final_path.display());
+    // editors on Windows like Notepad start UTF-8 files with a byte order mark
+    let config_string = config_string.strip_prefix('\u{feff}').unwrap_or(&config_string);
+    let config: Config = toml::from_str(config_string).map_err(|error| Error::Config {
+        path: config_path.to_path_buf(),
+        message: error.to_string(),
+    })?;
+    overrides::check(&config.overrides)
+        .and_then(|()| routing::check(&config.routes))
+        .map_err(|message| Error::Config {
+            path: config_path.to_path_buf(),
+            message,
+        })?;
+    Ok(config)
+}
 
-    if final_path.exists() {
-        warn!("File {} already exists: ignoring", final_path.display());
-        return;
+/// Parses the filename and searches the parsed name with the metadata providers, or looks up the
+/// id already established for the file when there is one.
+/// When no provider is given the parsed name is used as-is (offline mode). The prompt, when
+/// running interactively, chooses between search results too close to tell apart. The file is
+/// read for its embedded title only when the filesystem is given
+fn resolve_media(
+    path: &Path,
+    config: &Config,
+    fs: Option<&dyn Fs>,
+    providers: &[&dyn MetadataProvider],
+    mut known: KnownIds,
+    prompt: Option<&dyn Prompt>,
+    forced: &Forced,
+) -> error::Result<MediaFile> {
+    // leftovers like hashes and site names would only be noise to parse and search
+    let junk = get_filestem(path).and_then(|stem| junk::junk_reason(&stem, &config.junk_names));
+    if let (Some(reason), None) = (junk, &forced.force_name) {
+        return Err(Error::Junk {
+            path: path.to_path_buf(),
+            reason,
+        });
     }
 
-    match args.action {
-        Action::Test => {}
-        _ => match final_path.parent() {
-            Some(parent_final_path) => {
-                if let Err(error) = fs::create_dir_all(parent_final_path) {
-                    error!(
-                        "Could not create directory {}: {}",
-                        parent_final_path.display(),
-                        error
-                    );
-                    return;
-                }
+    let mut media_file = forced
+        .apply(path, parse_file(path, config, fs))
+        .ok_or_else(|| Error::Parse {
+            path: path.to_path_buf(),
+        })?;
+
+    let parsed_name = media_file.name().to_string();
+    // series with regional variants are pinned to the right one in the configuration
+    if let Some(pinned) = overrides::find(&config.overrides, &[&parsed_name]) {
+        if let Some(name) = &pinned.name {
+            debug!("Searching {} instead of {} as configured", name, parsed_name);
+            media_file.set_name(name.clone());
+        }
+        match (pinned.tvdb_id, providers.first()) {
+            (Some(tvdb_id), Some(primary)) if primary.kind() == ProviderKind::Tvdb => {
+                info!("Using TVDB id {} of the override of {}", tvdb_id, parsed_name);
+                known.tvdb_id = Some(tvdb_id);
             }
-            None => {}
-        },
+            (Some(_), Some(primary)) => warn!(
+                "The TVDB id of the override of {} is not used, the provider is {}",
+                parsed_name,
+                primary.kind()
+            ),
+            _ => {}
+        }
     }
 
-    match args.action {
-        Action::Test => {
+    if providers.is_empty() {
+        return Ok(media_file);
+    }
+
+    match resolve_name(&mut media_file, path, config, providers, known, prompt) {
+        Err(Error::NotFound { name, providers, .. }) if forced.force_name.is_some() => {
+            warn!("Could not find {} on {}, using the forced name as it is", name, providers);
+            return Ok(media_file);
+        }
+        Err(error @ Error::NotFound { .. }) if config.confirm_media_type && forced.is_empty() => {
+            // a name parsed both as a series and as a movie is whichever the providers know
+            let Some(alternative) = media_file.parsed().alternative.clone() else {
+                return Err(error);
+            };
+            let mut other = MediaFile::from_parsed(*alternative, media_file.extension().to_string());
+            match resolve_name(&mut other, path, config, providers, known, prompt) {
+                Err(Error::NotFound { .. }) => return Err(error),
+                result => result?,
+            }
             info!(
-                "TEST: would move from {} to {}",
+                "{} is not found, taking {} for {} instead",
+                media_file.name(),
                 path.display(),
-                final_path.display()
+                other.title()
             );
+            media_file = other;
         }
-        Action::Move => {
-            if let Err(error) = fs::rename(path, &final_path) {
-                error!(
-                    "Could not move {} to {}: {}",
-                    path.display(),
-                    final_path.display(),
-                    error
-                );
+        result => result?,
+    }
+
+    // episodes not found are still placed, without a title and by air date for daily shows
+    if let Some(provider) = matched_provider(providers, &media_file) {
+        let order = overrides::find(&config.overrides, &[&parsed_name, media_file.name()])
+            .map_or(EpisodeOrder::default(), |found| found.episode_order);
+        if let Err(error) = media_file.request_episode(provider, order) {
+            warn!("Could not get the episode of {}: {}", path.display(), error);
+        }
+    }
+
+    Ok(media_file)
+}
+
+/// Replaces the parsed name with the one of the series or movie at the first metadata provider
+/// that finds it, the primary one first and then the fallbacks in order. Known ids are looked up
+/// before the name is searched
+fn resolve_name(
+    media_file: &mut MediaFile,
+    path: &Path,
+    config: &Config,
+    providers: &[&dyn MetadataProvider],
+    known: KnownIds,
+    prompt: Option<&dyn Prompt>,
+) -> error::Result<()> {
+    if let (Some(id), Some(primary)) = (known.tvdb_id, providers.first()) {
+        match media_file.request_by_id(*primary, id) {
+            Ok(()) => return Ok(()),
+            Err(source) => error!(
+                "{}",
+                Error::Provider {
+                    path: path.to_path_buf(),
+                    stage: Stage::Search,
+                    provider: primary.kind(),
+                    source,
+                }
+            ),
+        }
+    }
+
+    if let Some(imdb_id) = known.imdb_id {
+        for provider in providers {
+            match media_file.request_by_imdb_id(*provider, imdb_id) {
+                Ok(true) => return Ok(()),
+                Ok(false) => debug!("{} has nothing with IMDb id {}", provider.kind(), provider::imdb_id(imdb_id)),
+                Err(source) => error!(
+                    "{}",
+                    Error::Provider {
+                        path: path.to_path_buf(),
+                        stage: Stage::Search,
+                        provider: provider.kind(),
+                        source,
+                    }
+                ),
             }
         }
-        Action::Copy => {
-            if let Err(error) = fs::copy(path, &final_path) {
+    }
+
+    let matching = Matching {
+        candidates: config.candidates,
+        min_confidence: config.min_confidence,
+        ambiguity_margin: config.ambiguity_margin,
+        prompt,
+    };
+    let mut failed = false;
+    for (index, provider) in providers.iter().enumerate() {
+        match media_file.request_name(*provider, &matching) {
+            Ok(NameMatch::Found) => return Ok(()),
+            Ok(NameMatch::NotFound) => {}
+            Ok(NameMatch::Declined) => {
+                return Err(Error::Declined {
+                    path: path.to_path_buf(),
+                })
+            }
+            Err(source) => {
+                failed = true;
                 error!(
-                    "Could not copy {} to {}: {}",
-                    path.display(),
-                    final_path.display(),
-                    error
+                    "{}",
+                    Error::Provider {
+                        path: path.to_path_buf(),
+                        stage: Stage::Search,
+                        provider: provider.kind(),
+                        source,
+                    }
                 );
             }
         }
-        Action::Symlink => {
-            if let Err(error) = symlink(path, &final_path) {
-                error!(
-                    "Could not copy {} to {}: {}",
-                    path.display(),
-                    final_path.display(),
-                    error
-                );
+        if let Some(next) = providers.get(index + 1) {
+            info!("{} did not find {}, trying {}", provider.kind(), media_file.name(), next.kind());
+        }
+    }
+
+    // the parsed name is still usable when a provider could not be asked, carry on with it
+    if failed {
+        return Ok(());
+    }
+    Err(Error::NotFound {
+        path: path.to_path_buf(),
+        name: media_file.name().to_string(),
+        providers: providers
+            .iter()
+            .map(|provider| provider.kind().to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    })
+}
+
+/// The provider the series or movie was matched by, the one to ask about its episodes
+fn matched_provider<'p>(
+    providers: &[&'p dyn MetadataProvider],
+    media_file: &MediaFile,
+) -> Option<&'p dyn MetadataProvider> {
+    providers
+        .iter()
+        .find(|provider| provider.kind().id(media_file.metadata()).is_some())
+        .copied()
+}
+
+/// Everything needed to process the files of a run
+struct Context<'a> {
+    args: &'a Args,
+    config: &'a Config,
+    outputs: Mutex<OutputRoots>,
+    /// The metadata providers in the order they are asked, none to use the parsed names as-is
+    providers: Vec<&'a dyn MetadataProvider>,
+    fs: &'a dyn Fs,
+    cancellation: CancellationToken,
+    /// The destinations claimed by a file in this run, with the file that claimed them
+    claimed: Mutex<HashMap<PathBuf, PathBuf>>,
+    summary: Mutex<Summary>,
+    /// Where the changes to the filesystem are recorded so that the run can be undone
+    journal: Option<Journal>,
+    /// The files of the run placed so far, so that an interrupted run resumes where it stopped
+    batch: Option<Batch>,
+    /// Asks the user to confirm every destination, none to place the files without asking
+    prompt: Option<&'a dyn Prompt>,
+    /// How the user chose to resolve every conflict with an existing file, once they did
+    conflict_resolution: Mutex<Option<Resolution>>,
+    /// The files placed in this run, that subtitles and other companion files are placed next to
+    placed: Mutex<Vec<Placed>>,
+    /// The series and movies to import, none to import everything
+    want_list: Option<WantList>,
+    /// The library the titles rated for kids are placed in, none to place them with the others
+    kids_outputs: Option<Mutex<OutputRoots>>,
+    /// The libraries of the routes of the configuration, in their order. Empty when the files are
+    /// not routed
+    routes: Vec<Mutex<OutputRoots>>,
+    /// The names the files placed by earlier runs had before, by where they are now, read from the
+    /// history when the upgrade policy needs the quality of existing files
+    originals: HashMap<PathBuf, PathBuf>,
+    /// The moves put off until the sources are done seeding, none when moves are not deferred
+    deferred: Option<Deferred>,
+    /// What the torrent client seeds, none when no client is asked
+    seeding: Option<Seeding>,
+}
+
+/// A file placed in the library by this run
+struct Placed {
+    source: PathBuf,
+    destination: PathBuf,
+    title: String,
+    media_type: MediaType,
+    /// The ids of the series or movie, none for companions and names resolved offline
+    metadata: Metadata,
+    /// Whether the file is a subtitle or other companion of another placed file
+    companion: bool,
+    /// The season and episodes of an episode file, to tell the episodes missing from a season pack
+    episodes: Option<PlacedEpisodes>,
+}
+
+/// The episodes of a season in one placed file
+struct PlacedEpisodes {
+    season: u32,
+    episodes: Vec<u32>,
+}
+
+impl PlacedEpisodes {
+    fn of(media_file: &MediaFile) -> Option<Self> {
+        let MediaData::TvSeries { season, episode } = *media_file.media() else {
+            return None;
+        };
+        // the episodes of a multi-episode name, unless matching renumbered it
+        let parsed = media_file.parsed();
+        let episodes = if parsed.media == *media_file.media() {
+            parsed.episodes.clone()
+        } else {
+            vec![episode]
+        };
+        Some(Self { season, episodes })
+    }
+}
+
+impl Context<'_> {
+    fn record(&self, operation: Operation) {
+        if let Some(journal) = &self.journal {
+            journal.record(operation);
+        }
+    }
+}
+
+fn process_file(path: &Path, context: &Context) -> error::Result<()> {
+    let mut planned = plan_file(path, context).inspect_err(|error| quarantine(path, error, context))?;
+    let result = confirm(&mut planned, context).and_then(|()| apply(&planned, context));
+    record_result(&planned, &result, context);
+    result
+}
+
+/// A file resolved to its place in the library, ready to be acted on
+struct Planned {
+    path: PathBuf,
+    media_file: MediaFile,
+    /// The folder of the series or movie in the library
+    folder: PathBuf,
+    destination: PathBuf,
+    /// Whether the existing file at the destination is replaced
+    replace: bool,
+}
+
+/// A file of the input to place, with its destination when planned and its sidecars
+type Placing = (PathBuf, Option<Planned>, Option<Vec<PathBuf>>);
+
+/// Resolves the file and chooses its destination without touching the filesystem.
+/// Failures are recorded in the summary, the ones in the destination under the resolved title
+fn plan_file(path: &Path, context: &Context) -> error::Result<Planned> {
+    resolve_file(path, context).and_then(|media_file| place_file(path, media_file, context))
+}
+
+/// Resolves the file with the metadata providers, the part of planning it that can run on many
+/// files at the same time
+fn resolve_file(path: &Path, context: &Context) -> error::Result<MediaFile> {
+    info!("Processing file {}", path.display());
+
+    // files of other titles are left out before anything is searched when the names tell
+    let selected_by_name = selected_by_name(path, context);
+    if selected_by_name == Some(false) && context.args.only_tvdb_id.is_empty() {
+        return Err(not_selected(path, context));
+    }
+    if let Some(parsed) = context.args.forced.apply(path, parse_filepath(path, context.config)) {
+        if selected_episode(parsed.media(), context.args) == Some(false) {
+            return Err(not_selected(path, context));
+        }
+        ignore_title(path, parsed.name(), parsed.media(), context)?;
+    }
+
+    let known = find_known_ids(path, context);
+    let mut media_file = resolve_media(
+        path,
+        context.config,
+        Some(context.fs),
+        &context.providers,
+        known,
+        context.prompt,
+        &context.args.forced,
+    )
+        .inspect_err(|error| context.summary.lock().unwrap().record_unmatched(path, error))?;
+
+    debug!("{:#?}", media_file);
+
+    let selected_by_id = media_file
+        .metadata()
+        .tvdb_id
+        .is_some_and(|tvdb_id| context.args.only_tvdb_id.contains(&tvdb_id));
+    if selected_by_name == Some(false) && !selected_by_id {
+        return Err(not_selected(path, context));
+    }
+    // episodes numbered by air date or from the start of the series are only known once resolved
+    let episode_filtered = !context.args.season.is_empty() || context.args.episodes.is_some();
+    if episode_filtered && selected_episode(media_file.media(), context.args) != Some(true) {
+        return Err(not_selected(path, context));
+    }
+
+    ignore_title(path, media_file.name(), media_file.media(), context)?;
+
+    if let Some(want_list) = &context.want_list {
+        if !want_list.wants(&media_file) {
+            let error = Error::NotWanted {
+                path: path.to_path_buf(),
+                title: media_file.title(),
+            };
+            context.summary.lock().unwrap().record_filtered(path, &error);
+            return Err(error);
+        }
+    }
+
+    if let (Some(provider), Some(_)) = (matched_provider(&context.providers, &media_file), &context.kids_outputs) {
+        if let Err(error) = media_file.request_content_rating(provider, &context.config.rating_country) {
+            warn!("Could not get the content rating of {}: {}", media_file.title(), error);
+        }
+    }
+    if let Some(provider) = matched_provider(&context.providers, &media_file) {
+        if !context.routes.is_empty() && routing::needs_genres(&context.config.routes) {
+            if let Err(error) = media_file.request_genres(provider) {
+                warn!("Could not get the genres of {}: {}", media_file.title(), error);
             }
         }
     }
+    Ok(media_file)
 }
 
-fn main() {
-    let args = Args::parse();
+/// Chooses the destination of the resolved file and claims it. Files are placed in the order of
+/// the run, so that placement and conflicts do not depend on which lookup finished first
+fn place_file(path: &Path, media_file: MediaFile, context: &Context) -> error::Result<Planned> {
+    let folder = choose_folder(path, &media_file, context);
+    let destination = folder.join(media_file.path_in_folder(context.config));
+    let mut planned = Planned {
+        path: path.to_path_buf(),
+        media_file,
+        folder,
+        destination,
+        replace: false,
+    };
 
-    ensure_conf_dir_exists();
+    info!("Final path: {}", planned.destination.display());
 
-    if !init_logger(&args) {
-        return;
+    let claimed = claim_destination(&mut planned, context);
+    if claimed.is_err() {
+        record_result(&planned, &claimed, context);
     }
+    claimed.map(|()| planned)
+}
 
-    debug!("{:#?}", args);
+/// Whether the name parsed from the file is one of the --only names, none when the run is not
+/// restricted to some titles
+fn selected_by_name(path: &Path, context: &Context) -> Option<bool> {
+    let args = context.args;
+    if args.only.is_empty() && args.only_tvdb_id.is_empty() {
+        return None;
+    }
+    let parsed = args.forced.apply(path, parse_filepath(path, context.config));
+    Some(parsed.is_some_and(|parsed| args.only.iter().any(|only| want_list::same_title(only, parsed.name()))))
+}
+
+/// Whether the episode is in the --season and --episodes given, none when they are not given or
+/// the season and number of the episode are not known yet. Movies are never selected by them
+fn selected_episode(media: &MediaData, args: &Args) -> Option<bool> {
+    if args.season.is_empty() && args.episodes.is_none() {
+        return None;
+    }
+    match *media {
+        MediaData::TvSeries { season, episode } => Some(
+            (args.season.is_empty() || args.season.contains(&season))
+                && args.episodes.as_ref().is_none_or(|episodes| episodes.contains(&episode)),
+        ),
+        MediaData::Movie { .. } => Some(false),
+        MediaData::Daily { .. } | MediaData::Absolute { .. } => None,
+    }
+}
+
+/// Records the file as left out by --only, --only-tvdb-id, --season and --episodes
+fn not_selected(path: &Path, context: &Context) -> Error {
+    let error = Error::NotSelected {
+        path: path.to_path_buf(),
+    };
+    context.summary.lock().unwrap().record_filtered(path, &error);
+    error
+}
+
+/// Skips the file when its series or movie, or its season, is in ignored_titles, checked with the
+/// parsed name before searching and with the matched title after
+fn ignore_title(path: &Path, title: &str, media: &MediaData, context: &Context) -> error::Result<()> {
+    let season = match *media {
+        MediaData::TvSeries { season, .. } => Some(season),
+        _ => None,
+    };
+    let Some(entry) = ignored_titles::find(&context.config.ignored_titles, title, season) else {
+        return Ok(());
+    };
+    let error = Error::IgnoredTitle {
+        path: path.to_path_buf(),
+        title: title.to_string(),
+        entry: entry.to_string(),
+    };
+    context.summary.lock().unwrap().record_filtered(path, &error);
+    Err(error)
+}
+
+/// The ids of the series or movie of the file in the `.plexmatch` and `.nfo` files around it,
+/// looked for only when the file is going to be searched
+fn find_known_ids(path: &Path, context: &Context) -> KnownIds {
+    let Some(primary) = context.providers.first() else {
+        return KnownIds::default();
+    };
+
+    // ids pinned by .plexmatch or .nfo files keep reorganized folders matched to the same series
+    let tvdb_id = (primary.kind() == ProviderKind::Tvdb)
+        .then(|| local_ids::find_tvdb_id(context.fs, path))
+        .flatten()
+        .map(|(tvdb_id, source)| {
+            info!("Using TVDB id {} from {}", tvdb_id, source.display());
+            tvdb_id
+        });
+    // the NFOs of scene releases link their IMDb page
+    let imdb_id = local_ids::find_imdb_id(context.fs, path).map(|(number, source)| {
+        info!("Using IMDb id {} from {}", provider::imdb_id(number), source.display());
+        number
+    });
+    KnownIds { tvdb_id, imdb_id }
+}
+
+/// Claims the destination for the file, failing when another file of the run already has it
+/// or when it does not pass the validation or already exists, unless the user chooses to replace it or keep both files
+fn claim_destination(planned: &mut Planned, context: &Context) -> error::Result<()> {
+    planned.replace = false;
+
+    let mut in_output = planned
+        .media_file
+        .folder(context.config)
+        .join(planned.media_file.path_in_folder(context.config));
+    if let Some(shortened) = context.config.validation.fit(&planned.destination) {
+        info!("Shortening {} to {}", planned.destination.display(), shortened.display());
+        in_output.set_file_name(shortened.file_name().unwrap_or_default());
+        planned.destination = shortened;
+    }
+    if let Err(problem) = context.config.validation.check(&planned.destination, &in_output) {
+        return Err(Error::InvalidName {
+            path: planned.path.clone(),
+            destination: planned.destination.clone(),
+            problem,
+        });
+    }
+
+    let path = &planned.path;
+    let final_path = &planned.destination;
+
+    // two files of the same run mapping to the same destination, the first one in order keeps it
+    match context.claimed.lock().unwrap().entry(final_path.clone()) {
+        Entry::Occupied(entry) => {
+            return Err(Error::Conflict {
+                path: path.clone(),
+                destination: final_path.clone(),
+                claimed_by: entry.get().clone(),
+            });
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(path.clone());
+        }
+    }
+
+    if context.fs.exists(final_path) {
+        // the user is not asked about a file the library already has
+        if context.prompt.is_some() && is_duplicate(path, final_path, context) {
+            info!("{} is the same file as {}", path.display(), final_path.display());
+            return Err(Error::AlreadyExists {
+                path: planned.destination.clone(),
+            });
+        }
+        match resolve_conflict(planned, context) {
+            Resolution::Keep => {
+                return Err(Error::AlreadyExists {
+                    path: planned.destination.clone(),
+                })
+            }
+            Resolution::Replace => {
+                info!("Replacing {}", planned.destination.display());
+                planned.replace = true;
+            }
+            Resolution::KeepBoth => {
+                let free = free_destination(&planned.destination, context);
+                info!("Keeping both files, final path: {}", free.display());
+                let mut claimed = context.claimed.lock().unwrap();
+                claimed.remove(&planned.destination);
+                claimed.insert(free.clone(), planned.path.clone());
+                planned.destination = free;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether the existing destination has the contents of the file, by the hash of [hashing.dedupe]
+fn is_duplicate(path: &Path, existing: &Path, context: &Context) -> bool {
+    hashing::same_contents(context.fs, path, existing, context.config.hashing.dedupe).unwrap_or_else(|error| {
+        warn!("Could not compare {} with {}: {}", path.display(), existing.display(), error);
+        false
+    })
+}
+
+/// How to resolve the existing destination of the file: asked when running interactively,
+/// unless the user already answered for every conflict, otherwise by `on_conflict`
+fn resolve_conflict(planned: &Planned, context: &Context) -> Resolution {
+    let info = |path: &Path, name: &Path| FileInfo {
+        size: context.fs.file_size(path).ok(),
+        modified: context.fs.modified(path).ok(),
+        quality: Quality::parse(&name.to_string_lossy()),
+    };
+    let incoming = FileInfo {
+        quality: planned.media_file.parsed().quality(),
+        ..info(&planned.path, &planned.path)
+    };
+    let existing_name = context.originals.get(&planned.destination).unwrap_or(&planned.destination);
+    let existing = info(&planned.destination, existing_name);
+
+    let Some(prompt) = context.prompt else {
+        let policy = context.config.on_conflict;
+        let resolution = policy.resolve(&incoming, &existing);
+        if resolution == Resolution::Replace
+            && !context.args.force
+            && interactive::is_suspicious_replacement(&incoming, &existing, context.config.max_size_ratio)
+        {
+            warn!(
+                "{} exists and the new file {} is of a suspicious size to replace it, keeping it, replace it with --force",
+                planned.destination.display(),
+                planned.path.display()
+            );
+            return Resolution::Keep;
+        }
+        info!(
+            "{} exists ({}, the new file is {}): {:?} with {:?}",
+            planned.destination.display(),
+            existing.quality,
+            incoming.quality,
+            resolution,
+            policy
+        );
+        return resolution;
+    };
+    if let Some(resolution) = *context.conflict_resolution.lock().unwrap() {
+        return resolution;
+    }
+
+    let answer = prompt.resolve_conflict(&planned.path, &incoming, &planned.destination, &existing);
+    if answer.apply_to_all {
+        *context.conflict_resolution.lock().unwrap() = Some(answer.resolution);
+    }
+    answer.resolution
+}
+
+/// The first of `name (2).ext`, `name (3).ext`... that neither exists nor is claimed in this run
+fn free_destination(destination: &Path, context: &Context) -> PathBuf {
+    let stem = get_filestem(destination).unwrap_or_default();
+    let extension = path_utils::get_extension(destination)
+        .map(|extension| format!(".{}", extension))
+        .unwrap_or_default();
+    let claimed = context.claimed.lock().unwrap();
+    (2..)
+        .map(|number| destination.with_file_name(format!("{} ({}){}", stem, number, extension)))
+        .find(|candidate| !context.fs.exists(candidate) && !claimed.contains_key(candidate))
+        .expect("There is always a free numbered name")
+}
+
+/// Asks the user to confirm the destination of the file, when running interactively.
+/// A new name moves the file to the folder of that name, and is confirmed again
+fn confirm(planned: &mut Planned, context: &Context) -> error::Result<()> {
+    let Some(prompt) = context.prompt else {
+        return Ok(());
+    };
+
+    loop {
+        match prompt.confirm(&planned.path, &planned.destination) {
+            Decision::Accept => return Ok(()),
+            Decision::Skip => {
+                return Err(Error::Declined {
+                    path: planned.path.clone(),
+                })
+            }
+            Decision::Abort => {
+                context.cancellation.cancel();
+                return Err(Error::Declined {
+                    path: planned.path.clone(),
+                });
+            }
+            Decision::Rename(name) => {
+                context.claimed.lock().unwrap().remove(&planned.destination);
+                planned.media_file.set_name(name);
+                planned.folder = choose_folder(&planned.path, &planned.media_file, context);
+                planned.destination = planned.folder.join(planned.media_file.path_in_folder(context.config));
+                claim_destination(planned, context)?;
+            }
+        }
+    }
+}
+
+/// Chooses the folder of the series or movie, in the library of the first route it matches or
+/// else in the kids library when its content rating is one of the kids ratings
+fn choose_folder(path: &Path, media_file: &MediaFile, context: &Context) -> PathBuf {
+    let routed = routing::route(&context.config.routes, path, media_file).and_then(|index| context.routes.get(index));
+    if let Some(outputs) = routed {
+        return outputs.lock().unwrap().folder_for(context.fs, media_file, context.config);
+    }
+    let for_kids = media_file.content_rating().is_some_and(|rating| {
+        context
+            .config
+            .kids_ratings
+            .iter()
+            .any(|kids_rating| kids_rating.eq_ignore_ascii_case(rating))
+    });
+    let outputs = match &context.kids_outputs {
+        Some(kids_outputs) if for_kids => kids_outputs,
+        _ => &context.outputs,
+    };
+    outputs.lock().unwrap().folder_for(context.fs, media_file, context.config)
+}
+
+fn record_result(planned: &Planned, result: &error::Result<()>, context: &Context) {
+    context.summary.lock().unwrap().record(
+        &planned.media_file.title(),
+        planned.media_file.media_type(),
+        &planned.path,
+        result,
+    );
+}
+
+/// Moves, copies or links the file to its place in the library
+fn apply(planned: &Planned, context: &Context) -> error::Result<()> {
+    create_parent_dirs(&planned.destination, context)?;
+
+    // a staged file replaces the existing one when it is published
+    if planned.replace && !is_staging(context) {
+        remove_replaced(&planned.destination, context)?;
+    }
+
+    if context.args.plexmatch {
+        write_folder_file(&planned.folder, &planned.media_file, context, plexmatch::PLEXMATCH_FILENAME, plexmatch::write);
+    }
+    if context.args.nfo {
+        let filename = nfo::filename(&planned.media_file);
+        write_folder_file(&planned.folder, &planned.media_file, context, filename, nfo::write);
+    }
+
+    perform(&planned.path, &planned.destination, context)?;
+    if is_pending_metadata(&planned.media_file, context) {
+        info!("{} is pending metadata", planned.destination.display());
+        context.record(Operation::PendingMetadata {
+            path: planned.destination.clone(),
+        });
+    }
+    context.placed.lock().unwrap().push(Placed {
+        source: planned.path.clone(),
+        destination: planned.destination.clone(),
+        title: planned.media_file.title(),
+        media_type: planned.media_file.media_type(),
+        metadata: planned.media_file.metadata().clone(),
+        companion: false,
+        episodes: PlacedEpisodes::of(&planned.media_file),
+    });
+    Ok(())
+}
+
+/// Removes the existing file the user chose to replace. It cannot be restored by undo
+fn remove_replaced(final_path: &Path, context: &Context) -> error::Result<()> {
+    if matches!(context.args.action, Action::Test) {
+        info!("TEST: would replace {}", final_path.display());
+        return Ok(());
+    }
+    context
+        .fs
+        .remove_file(final_path)
+        .map_err(|source| Error::Filesystem {
+            path: final_path.to_path_buf(),
+            stage: Stage::Replace,
+            source,
+        })
+}
+
+/// Creates the missing directories of the destination, recording them in the journal
+fn create_parent_dirs(final_path: &Path, context: &Context) -> error::Result<()> {
+    let fs = context.fs;
+    if !matches!(context.args.action, Action::Test) {
+        if let Some(parent_final_path) = final_path.parent() {
+            // staged directories are recorded under the library they are published in
+            let missing: Vec<&Path> = parent_final_path
+                .ancestors()
+                .take_while(|dir| !dir.as_os_str().is_empty() && !fs.exists(dir))
+                .collect();
+            let parent_path = staged(parent_final_path, context);
+            fs.create_dir_all(&parent_path)
+                .map_err(|source| Error::Filesystem {
+                    path: parent_path.clone(),
+                    stage: Stage::CreateDir,
+                    source,
+                })?;
+            for dir in missing.into_iter().rev() {
+                set_ownership(&staged(dir, context), true, context);
+                context.record(Operation::CreateDir {
+                    path: dir.to_path_buf(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Does the action of the run on the file, recording it in the journal
+fn perform(path: &Path, final_path: &Path, context: &Context) -> error::Result<()> {
+    let fs = context.fs;
+    let action = context.args.action;
+    // the journal records the place in the library, where the file is once published
+    let placed_path = staged(final_path, context);
+    if is_seeding(path, context) {
+        return defer_move(path, final_path, context);
+    }
+    let (result, stage) = match action {
+        Action::Test => {
+            info!(
+                "TEST: would move from {} to {}",
+                path.display(),
+                final_path.display()
+            );
+            (Ok(()), Stage::Move)
+        }
+        Action::Move if context.config.trash_sources => (copy_file(path, &placed_path, context), Stage::Copy),
+        Action::Move => (move_file(path, &placed_path, context), Stage::Move),
+        Action::Copy => (copy_file(path, &placed_path, context), Stage::Copy),
+        // a link to a stream would break once the downloads are cleaned up, its few bytes are copied
+        Action::Symlink | Action::Hardlink if policy::is_stream(path) => (copy_file(path, &placed_path, context), Stage::Copy),
+        Action::Symlink => (fs.symlink(path, &placed_path), Stage::Symlink),
+        Action::Hardlink => (fs.hard_link(path, &placed_path), Stage::Hardlink),
+    };
+
+    if result.is_ok() && matches!(stage, Stage::Copy | Stage::Move) && !matches!(action, Action::Test) {
+        if stage == Stage::Copy && context.config.attributes.preserve {
+            if let Err(error) = fs.copy_attributes(path, &placed_path) {
+                warn!("Could not give {} the attributes of {}: {}", final_path.display(), path.display(), error);
+            }
+        }
+        set_ownership(&placed_path, false, context);
+    }
+
+    if result.is_ok() {
+        let (source, destination) = (path.to_path_buf(), final_path.to_path_buf());
+        match action {
+            Action::Test => {}
+            // undo puts the source back from the trash, and then removes the copy
+            Action::Move if context.config.trash_sources => {
+                context.record(Operation::Copy {
+                    source: source.clone(),
+                    destination,
+                });
+                match fs.trash(&source) {
+                    Ok(trashed) => {
+                        debug!("Sent {} to the trash", source.display());
+                        context.record(Operation::Trash {
+                            original: source,
+                            trashed,
+                        });
+                    }
+                    Err(error) => warn!("Could not send {} to the trash, it is left in place: {}", source.display(), error),
+                }
+            }
+            Action::Move => context.record(Operation::Move { source, destination }),
+            Action::Copy => context.record(Operation::Copy { source, destination }),
+            Action::Symlink | Action::Hardlink if stage == Stage::Copy => context.record(Operation::Copy { source, destination }),
+            Action::Symlink => context.record(Operation::Symlink { source, destination }),
+            Action::Hardlink => context.record(Operation::Hardlink { source, destination }),
+        }
+    }
+
+    result.map_err(|source| Error::Filesystem {
+        path: path.to_path_buf(),
+        stage,
+        source,
+    })
+}
+
+/// Renames the file into the library. A library on another filesystem than the downloads cannot
+/// be renamed into, the file is copied there, verified with --verify, and the source removed
+fn move_file(source: &Path, destination: &Path, context: &Context) -> Result<(), io::Error> {
+    match context.fs.rename(source, destination) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            debug!("{} is on another filesystem than the library, copying it", source.display());
+            copy_file(source, destination, context)?;
+            // like a rename, the move keeps the modification time and permissions of the file
+            if let Err(error) = context.fs.copy_attributes(source, destination) {
+                warn!("Could not give {} the attributes of {}: {}", destination.display(), source.display(), error);
+            }
+            // the file is in the library, failing to remove the source does not fail the move
+            if let Err(error) = context.fs.remove_file(source) {
+                warn!("Could not remove {} once copied, it is left in place: {}", source.display(), error);
+            }
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Gives the file or directory placed in the library the owner, group and permissions of
+/// `[attributes]`, the ones not configured are left as they are
+fn set_ownership(path: &Path, is_dir: bool, context: &Context) {
+    let attributes = &context.config.attributes;
+    if let Some(umask) = attributes.umask {
+        let mode = if is_dir { 0o777 } else { 0o666 } & !umask;
+        if let Err(error) = context.fs.set_mode(path, mode) {
+            warn!("Could not set the permissions of {}: {}", path.display(), error);
+        }
+    }
+    match attributes.ownership() {
+        Ok((None, None)) => {}
+        Ok((owner, group)) => {
+            if let Err(error) = context.fs.set_owner(path, owner, group) {
+                warn!("Could not change the owner of {}: {}", path.display(), error);
+            }
+        }
+        Err(error) => warn!("Could not find the owner of {}: {}", path.display(), error),
+    }
+}
+
+/// Whether the source of a move is still seeded by the torrent client, so that it is linked
+/// rather than moved. Streams are a few bytes and never seeded
+fn is_seeding(path: &Path, context: &Context) -> bool {
+    matches!(context.args.action, Action::Move)
+        && !context.config.trash_sources
+        && context.deferred.is_some()
+        && !policy::is_stream(path)
+        && context.seeding.as_ref().is_some_and(|seeding| seeding.is_seeding(path))
+}
+
+/// Links the seeding file into the library and defers its move to a run after it is done seeding.
+/// A hard link is tried first, a symlink when the library is on another filesystem
+fn defer_move(path: &Path, final_path: &Path, context: &Context) -> error::Result<()> {
+    let fs = context.fs;
+    let placed_path = staged(final_path, context);
+    let (result, stage) = match fs.hard_link(path, &placed_path) {
+        Ok(()) => (Ok(()), Stage::Hardlink),
+        Err(error) => {
+            debug!("Could not hard link {}, symlinking it instead: {}", path.display(), error);
+            (fs.symlink(path, &placed_path), Stage::Symlink)
+        }
+    };
+    result.map_err(|source| Error::Filesystem {
+        path: path.to_path_buf(),
+        stage,
+        source,
+    })?;
+
+    let (source, destination) = (path.to_path_buf(), final_path.to_path_buf());
+    let hardlink = stage == Stage::Hardlink;
+    if hardlink {
+        context.record(Operation::Hardlink { source: source.clone(), destination: destination.clone() });
+    } else {
+        context.record(Operation::Symlink { source: source.clone(), destination: destination.clone() });
+    }
+    info!("{} is still seeding, it is linked into the library and moved by a later run", path.display());
+    if let Some(deferred) = &context.deferred {
+        deferred.add(DeferredMove {
+            source,
+            destination,
+            hardlink,
+        });
+    }
+    Ok(())
+}
 
-    let Some(config) = read_config(&args) else {
+/// Moves the files done seeding into the library in place of their links, all of them once no
+/// torrent client is configured. Moves that fail are retried by the next run, and those of files
+/// gone from the downloads or the library are dropped
+fn complete_deferred_moves(context: &Context) {
+    let Some(deferred) = &context.deferred else {
         return;
     };
+    let fs = context.fs;
+    let seeding = |deferred_move: &DeferredMove| {
+        context.seeding.as_ref().is_some_and(|seeding| seeding.is_seeding(&deferred_move.source))
+    };
+    for deferred_move in deferred.take_done(|deferred_move| !seeding(deferred_move)) {
+        let DeferredMove { source, destination, hardlink, .. } = &deferred_move;
+        if !fs.exists(source) || !fs.exists(destination) {
+            info!("{} or its link {} is gone, its deferred move is dropped", source.display(), destination.display());
+            continue;
+        }
+        // the hard link already is the file, only the name in the downloads is left to remove. A
+        // symlink is removed first, a copy across filesystems would write through it
+        let result = if *hardlink {
+            fs.remove_file(source)
+        } else {
+            fs.remove_file(destination).and_then(|()| {
+                move_file(source, destination, context).inspect_err(|_| {
+                    if let Err(error) = fs.symlink(source, destination) {
+                        warn!("Could not link {} into the library again: {}", source.display(), error);
+                    }
+                })
+            })
+        };
+        match result {
+            Ok(()) => {
+                info!("Moved {} to {} now that it is done seeding", source.display(), destination.display());
+                context.record(Operation::Move {
+                    source: source.clone(),
+                    destination: destination.clone(),
+                });
+            }
+            Err(error) => {
+                warn!("Could not complete the move of {}, the next run retries it: {}", source.display(), error);
+                deferred.add(deferred_move);
+            }
+        }
+    }
+}
 
-    debug!("{:#?}", config);
+/// Writes a file describing the series or movie into its folder, like its .plexmatch, with the
+/// given writer. Failing to do so does not stop the file from being placed
+fn write_folder_file(
+    folder: &Path,
+    media_file: &MediaFile,
+    context: &Context,
+    filename: &str,
+    write: fn(&dyn Fs, &Path, &MediaFile) -> Result<bool, std::io::Error>,
+) {
+    if matches!(context.args.action, Action::Test) {
+        if !context.fs.exists(&folder.join(filename)) {
+            info!("TEST: would write {} into {}", filename, folder.display());
+        }
+        return;
+    }
+
+    // the file of a staged folder is published along with it, unless the library has one
+    if is_staging(context) && context.fs.exists(&folder.join(filename)) {
+        return;
+    }
+    match write(context.fs, &staged(folder, context), media_file) {
+        Ok(true) => {
+            debug!("Wrote {} into {}", filename, folder.display());
+            context.record(Operation::Write {
+                path: folder.join(filename),
+            });
+        }
+        Ok(false) => {}
+        Err(error) => warn!("Could not write {} into {}: {}", filename, folder.display(), error),
+    }
+}
 
-    info!("Connecting TVDB client");
-    let mut tvdb = TvdbClient::new(&config.tvdb_api_key);
-    if let Err(error) = tvdb.login() {
-        error!("Error in logging in to API: ({})", error);
+/// Removes the copies an interrupted run left unfinished in the output directories, unless they
+/// are continued with --resume. Test runs change nothing
+fn remove_partials(context: &Context) {
+    if context.args.resume || matches!(context.args.action, Action::Test) {
         return;
     }
-    info!("Client connected");
+    for root in output_roots(context) {
+        let partials: Vec<PathBuf> = DirWalker::new(context.fs, &root)
+            .with_max_entries(context.config.max_walk_entries)
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.is_file && transfer::is_partial(&entry.path))
+            .map(|entry| entry.path)
+            .collect();
+        for partial in partials {
+            match context.fs.remove_file(&partial) {
+                Ok(()) => info!("Removed {}, a copy left unfinished by an interrupted run", partial.display()),
+                Err(error) => warn!("Could not remove {}: {}", partial.display(), error),
+            }
+        }
+    }
+}
 
-    let input_path = PathBuf::from(&args.input);
+/// Whether the files are placed in the staging directory of the output directories rather than in them
+fn is_staging(context: &Context) -> bool {
+    context.args.staging && !matches!(context.args.action, Action::Test)
+}
 
-    if input_path.is_file() {
-        if extension_matches(&input_path, &config.extensions) {
-            process_file(&input_path, &args, &config, &tvdb);
-        } else {
-            warn!("Input filename extension is not filtered in config, ignoring");
+/// Where a destination in the library is written to, its place in the staging directory with --staging
+fn staged(destination: &Path, context: &Context) -> PathBuf {
+    if !is_staging(context) {
+        return destination.to_path_buf();
+    }
+    staging::staged_path(&output_roots(context), destination).unwrap_or_else(|| destination.to_path_buf())
+}
+
+/// The output directories of the run, with the one of --kids-output and the ones of the routes
+fn output_roots(context: &Context) -> Vec<PathBuf> {
+    let mut roots = context.outputs.lock().unwrap().roots().to_vec();
+    if let Some(kids_outputs) = &context.kids_outputs {
+        roots.extend_from_slice(kids_outputs.lock().unwrap().roots());
+    }
+    for routed in &context.routes {
+        roots.extend_from_slice(routed.lock().unwrap().roots());
+    }
+    roots
+}
+
+/// The filesystem runs act on, read-only for test runs so that they cannot change anything even
+/// where a check of the action is missed
+fn real_fs(args: &Args) -> &'static dyn Fs {
+    match args.action {
+        Action::Test => &ReadOnlyFs(&RealFs),
+        Action::Move | Action::Copy | Action::Symlink | Action::Hardlink => &RealFs,
+    }
+}
+
+/// Moves the files staged in the run into the output directories
+fn publish_staged(context: &Context) {
+    if !is_staging(context) {
+        return;
+    }
+    for root in output_roots(context) {
+        match staging::publish(context.fs, &root) {
+            Ok(0) => {}
+            Ok(published) => info!("Published {} files and folders into {}", published, root.display()),
+            Err(error) => error!(
+                "Could not publish the staged files into {}, they are left in {}: {}",
+                root.display(),
+                root.join(staging::STAGING_DIRNAME).display(),
+                error
+            ),
         }
+    }
+}
+
+fn copy_file(source: &Path, destination: &Path, context: &Context) -> Result<(), io::Error> {
+    let args = context.args;
+    let mut last_reported = 0;
+    let bar = progress::copy(source, context.fs.file_size(source).unwrap_or(0));
+    let copied = Transfer::new()
+        .cancellation(context.cancellation.clone())
+        .rate_limit(args.rate_limit.map(|rate| rate * 1024))
+        .verify(args.verify.then_some(context.config.hashing.verify))
+        .resume(args.resume)
+        .on_progress(|progress| {
+            let percent = progress.copied * 100 / progress.total.max(1);
+            if percent >= last_reported + 10 {
+                last_reported = percent;
+                debug!("Copied {}% of {}", percent, source.display());
+            }
+            bar.set_length(progress.total);
+            bar.set_position(progress.copied);
+        })
+        .copy(context.fs, source, destination);
+    bar.finish_and_clear();
+    copied?;
+    Ok(())
+}
+
+/// Logs the error of a file, skipped files are only a warning
+fn report_error(error: &Error) {
+    if error.is_skip() {
+        warn!("{}: ignoring", error);
     } else {
-        for entry in DirWalker::new(&input_path, args.max_depth, config.ignored_dirs.clone())
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path().is_file())
-            .filter(|e| extension_matches(&e.path(), &config.extensions))
-        {
-            process_file(&entry.path(), &args, &config, &tvdb);
-        }
+        error!("{}", error);
     }
+}
 
-}
\ No newline at end of file
+/// Switches the Windows console to UTF-8, so that non-ASCII titles are not garbled in the logs
+#[cfg(windows)]
+fn enable_utf8_console() {
+    const CP_UTF8: u32 = 65001;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleOutputCP(code_page: u32) -> i32;
+    }
+    // SAFETY: SetConsoleOutputCP takes a code page by value and only fails without a console
+    unsafe {
+        SetConsoleOutputCP(CP_UTF8);
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    #[cfg(windows)]
+    enable_utf8_console();
+
+    if args.pure_dry_run && !matches!(args.action, Action::Test) {
+        println!("--pure-dry-run only runs the test action, leave out --action {}", args.action);
+        return Outcome::Config.into();
+    }
+    if !args.pure_dry_run {
+        ensure_conf_dir_exists();
+    }
+
+    if !init_logger(&args) {
+        return Outcome::Error.into();
+    }
+
+    info!("Starting run {}", run_id::current());
+    debug!("{:#?}", args);
+
+    let config = match configure(&args) {
+        Ok(config) => config,
+        Err(outcome) => return outcome.into(),
+    };
+
+    debug!("{:#?}", config);
+
+    let outcome = match &args.command {
+        Some(Command::Simulate { fixture }) => {
+            simulate::simulate(Path::new(fixture), &config);
+            Outcome::Success
+        }
+        Some(Command::Undo { journal }) => undo(journal.as_deref(), &args),
+        Some(Command::Demo { keep }) => {
+            demo::demo(*keep);
+            Outcome::Success
+        }
+        // planning is a test run that keeps the destinations instead of acting on them
+        Some(Command::Plan { .. }) => run(
+            &Args {
+                action: Action::Test,
+                ..args.clone()
+            },
+            &config,
+        ),
+        Some(Command::Apply { file }) => apply_plan(Path::new(file), &args, &config),
+        Some(Command::ReapplyTemplates) => reapply_templates(&args, &config, false),
+        Some(Command::RefreshMetadata) => reapply_templates(&args, &config, true),
+        Some(Command::Trace { path }) => trace_file(Path::new(path)),
+        Some(Command::Serve { socket }) => serve(Path::new(socket), &args, config),
+        Some(Command::Config {
+            command: ConfigCommand::Validate { fixture },
+        }) => validate_config(fixture.as_deref(), &config),
+        None => run(&args, &config),
+    };
+    outcome.into()
+}
+
+/// Answers the JSON-RPC calls of the socket until interrupted: `submit` queues the import of an
+/// input with the arguments of the server, `status` tells how the imports went and `undo` reverts
+/// one, by default the last run
+fn serve(socket: &Path, args: &Args, config: Config) -> Outcome {
+    if args.output.is_empty() {
+        error!("The server imports into --output, which is required");
+        return Outcome::Error;
+    }
+    if args.interactive {
+        error!("The server cannot ask the questions of --interactive");
+        return Outcome::Error;
+    }
+
+    let job_args = Args {
+        command: None,
+        no_progress: true,
+        ..args.clone()
+    };
+    let (jobs, worker) = Jobs::start(move |input| {
+        let args = Args {
+            input: Some(input.to_string()),
+            ..job_args.clone()
+        };
+        let run_id = run_id::next();
+        info!("Starting run {}", run_id);
+        (run_id, run(&args, &config))
+    });
+    let methods = {
+        let jobs = Arc::clone(&jobs);
+        let args = args.clone();
+        move |method: &str, params: Value| call(method, params, &jobs, &args)
+    };
+    let cancellation = interrupt_cancellation();
+    let served = rpc::serve(socket, Arc::new(methods), &cancellation);
+    jobs.close();
+    if let Err(error) = served {
+        error!("Could not serve on {}: {}", socket.display(), error);
+        return Outcome::Error;
+    }
+    info!("Stopping the server, the running import stops between files");
+    let _ = worker.join();
+    Outcome::Success
+}
+
+/// Calls a method of the server with its parameters
+fn call(method: &str, params: Value, jobs: &Jobs, args: &Args) -> Result<Value, RpcError> {
+    #[derive(Deserialize)]
+    struct Submit {
+        input: String,
+    }
+    #[derive(Default, Deserialize)]
+    struct OfJob {
+        #[serde(default)]
+        job: Option<u64>,
+    }
+
+    let of_job = |params: Value| match params {
+        Value::Null => Ok(OfJob::default()),
+        params => serde_json::from_value::<OfJob>(params).map_err(RpcError::invalid_params),
+    };
+    match method {
+        "submit" => {
+            let submit: Submit = serde_json::from_value(params).map_err(RpcError::invalid_params)?;
+            Ok(json!({ "job": jobs.submit(submit.input)? }))
+        }
+        "status" => match of_job(params)?.job {
+            Some(id) => {
+                let job = jobs.job(id).ok_or_else(|| RpcError::invalid_params(format!("There is no job {}", id)))?;
+                Ok(json!(job))
+            }
+            None => Ok(json!(jobs.all())),
+        },
+        "undo" => {
+            let journal = match of_job(params)?.job {
+                Some(id) => {
+                    let job = jobs.job(id).ok_or_else(|| RpcError::invalid_params(format!("There is no job {}", id)))?;
+                    let rpc::JobState::Done { run_id, .. } = job.state else {
+                        return Err(RpcError::failed(format!("Job {} did not run yet", id)));
+                    };
+                    let history = get_filepath_in_conf_dir("history").ok_or_else(|| RpcError::failed("There is no history"))?;
+                    Some(history.join(format!("{}.json", run_id)).to_string_lossy().into_owned())
+                }
+                None => None,
+            };
+            let _running = jobs.exclusive();
+            Ok(json!({ "outcome": undo(journal.as_deref(), args) }))
+        }
+        _ => Err(RpcError::method_not_found(method)),
+    }
+}
+
+/// Prints the problems of the regexes of the configuration, tried on common release names and on
+/// the names of the fixture. The configuration was read already, so it is valid otherwise
+fn validate_config(fixture: Option<&str>, config: &Config) -> Outcome {
+    let names = match fixture.map(fs::read_to_string).transpose() {
+        Ok(contents) => contents
+            .map(|contents| simulate::read_fixture(&contents))
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|path| get_filestem(path))
+            .collect::<Vec<_>>(),
+        Err(error) => {
+            error!("Could not read fixture {}: {}", fixture.unwrap_or_default(), error);
+            return Outcome::Error;
+        }
+    };
+
+    let problems = lint::lint_regexes(config, &names);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        info!("The configuration has no problems");
+        Outcome::Success
+    } else {
+        warn!("The configuration has {} problems", problems.len());
+        Outcome::Config
+    }
+}
+
+/// Reverts the journal, by default the one of the last run. Like any run it only tells what it
+/// would revert with the test action, the default and the only one of --pure-dry-run
+fn undo(journal: Option<&str>, args: &Args) -> Outcome {
+    let path = match journal {
+        Some(journal) => PathBuf::from(journal),
+        None => {
+            let last = get_filepath_in_conf_dir("history").and_then(|history| journal::last_journal(&history));
+            let Some(last) = last else {
+                error!("There is no run to undo");
+                return Outcome::Error;
+            };
+            last
+        }
+    };
+
+    if matches!(args.action, Action::Test) {
+        return match journal::load(&path) {
+            Ok(operations) => {
+                for operation in operations.iter().rev() {
+                    info!("TEST: would revert: {}", trace::describe(operation));
+                }
+                info!("TEST: would undo {}, give --action move to undo it", path.display());
+                Outcome::Success
+            }
+            Err(error) => {
+                error!("Could not read {}: {}", path.display(), error);
+                Outcome::Error
+            }
+        };
+    }
+
+    match journal::undo_journal(&RealFs, &path) {
+        Ok(true) => {
+            info!("Undid {}", path.display());
+            Outcome::Success
+        }
+        Ok(false) => {
+            warn!(
+                "Undid {}, but some operations could not be reverted, undo it again once they can be",
+                path.display()
+            );
+            Outcome::Failed
+        }
+        Err(error) => {
+            error!("Could not undo {}: {}", path.display(), error);
+            Outcome::Error
+        }
+    }
+}
+
+/// Prints the timeline of the file over every run, the log lines mentioning it and its changes
+fn trace_file(path: &Path) -> Outcome {
+    let (Some(history), Some(log_file)) = (get_filepath_in_conf_dir("history"), get_filepath_in_conf_dir("log.txt")) else {
+        error!("There is no configuration directory to read the history from");
+        return Outcome::Error;
+    };
+    match trace::trace(path, &history, &log_file) {
+        Ok(timeline) if timeline.is_empty() => {
+            info!("No run saw {}", path.display());
+            Outcome::Success
+        }
+        Ok(timeline) => {
+            for line in timeline {
+                println!("{}", line);
+            }
+            Outcome::Success
+        }
+        Err(error) => {
+            error!("Could not read the log {}: {}", log_file.display(), error);
+            Outcome::Error
+        }
+    }
+}
+
+fn run(args: &Args, config: &Config) -> Outcome {
+    let Some(input) = &args.input else {
+        error!("Both --input and --output are required");
+        return Outcome::Error;
+    };
+    if args.output.is_empty() {
+        error!("Both --input and --output are required");
+        return Outcome::Error;
+    }
+
+    if !args.forced.is_empty() && !Path::new(input).is_file() {
+        error!("The --force flags describe a single file, give it as --input");
+        return Outcome::Error;
+    }
+
+    let outputs: Vec<PathBuf> = args.output.iter().map(PathBuf::from).collect();
+    let kids_output = args.kids_output.as_ref().map(PathBuf::from);
+    let routed: Vec<PathBuf> = config.routes.iter().map(|route| route.output.clone()).collect();
+    let checked: Vec<PathBuf> = outputs.iter().chain(&kids_output).chain(&routed).cloned().collect();
+    if let Err(error) = preflight::check_outputs(&RealFs, Path::new(input), &checked, args.action) {
+        error!("{}", error);
+        return Outcome::Error;
+    }
+
+    let providers = match connect_providers(args, config) {
+        Ok(providers) => providers,
+        Err(outcome) => return outcome,
+    };
+
+    let want_list = match &args.want_list {
+        Some(file) => match WantList::read(Path::new(file)) {
+            Ok(want_list) => {
+                info!("Importing only the {} titles of {}", want_list.count(), file);
+                Some(want_list)
+            }
+            Err(error) => {
+                error!("Could not read the want-list {}: {}", file, error);
+                return Outcome::Error;
+            }
+        },
+        None => None,
+    };
+
+    let input_path = PathBuf::from(input);
+
+    let context = Context {
+        args,
+        config,
+        outputs: Mutex::new(OutputRoots::new(&RealFs, outputs.clone(), args.placement)),
+        providers: providers.iter().map(Box::as_ref).collect(),
+        fs: real_fs(args),
+        cancellation: interrupt_cancellation(),
+        claimed: Mutex::new(HashMap::new()),
+        summary: Mutex::new(Summary::new()),
+        journal: create_journal(args),
+        batch: open_batch(args, &input_path, &checked),
+        prompt: args.interactive.then_some(&TerminalPrompt as &dyn Prompt),
+        conflict_resolution: Mutex::new(None),
+        placed: Mutex::new(Vec::new()),
+        want_list,
+        kids_outputs: kids_output.map(|kids_output| {
+            Mutex::new(OutputRoots::new(&RealFs, vec![kids_output], args.placement))
+        }),
+        routes: routed
+            .into_iter()
+            .map(|output| Mutex::new(OutputRoots::new(&RealFs, vec![output], args.placement)))
+            .collect(),
+        originals: imported_originals(config),
+        deferred: open_deferred(args, config),
+        seeding: open_seeding(args, config),
+    };
+    remove_partials(&context);
+    complete_deferred_moves(&context);
+    // the questions of --interactive would be drawn over by the bars
+    if !args.no_progress && !args.interactive {
+        progress::show();
+    }
+    process_input(&input_path, &context);
+    if let Some(batch) = &context.batch {
+        if !context.cancellation.is_cancelled() {
+            batch.finish();
+        }
+    }
+
+    if let Some(Command::Plan { file }) = &args.command {
+        let plan = Plan {
+            input: input_path,
+            // the kids and routed libraries too, so that applying the plan checks them as well
+            outputs: checked,
+            files: context.placed.lock().unwrap().iter().map(PlanEntry::from).collect(),
+        };
+        match plan.write(Path::new(file)) {
+            Ok(()) => info!("Wrote the plan of {} files to {}", plan.files.len(), file),
+            Err(error) => error!("Could not write the plan {}: {}", file, error),
+        }
+    }
+
+    record_recent(&context);
+    finish(&context)
+}
+
+/// Connects to the metadata provider of the configuration and to its fallbacks, in the order
+/// they are asked, through the recording of the arguments if there is one and otherwise
+/// through the response cache
+fn connect_providers(args: &Args, config: &Config) -> Result<Vec<Box<dyn MetadataProvider>>, Outcome> {
+    let recording = if let Some(record) = &args.record {
+        match Recording::record(Path::new(record)) {
+            Ok(recording) => Some(Arc::new(recording)),
+            Err(error) => {
+                error!("Could not open recording {}: {}", record, error);
+                return Err(Outcome::Error);
+            }
+        }
+    } else if let Some(replay) = &args.replay {
+        match Recording::replay(Path::new(replay)) {
+            Ok(recording) => {
+                info!("Replaying the responses from {}", replay);
+                Some(Arc::new(recording))
+            }
+            Err(error) => {
+                error!("Could not open recording {}: {}", replay, error);
+                return Err(Outcome::Error);
+            }
+        }
+    } else {
+        None
+    };
+    let cache = if recording.is_none() { open_cache(args, config) } else { None };
+
+    let mut kinds = vec![config.provider];
+    for kind in &config.fallback_providers {
+        if !kinds.contains(kind) {
+            kinds.push(*kind);
+        }
+    }
+
+    let mut providers = Vec::new();
+    for kind in kinds {
+        info!("Connecting {} client", kind);
+        providers.extend(connect_provider(kind, args, config, recording.clone(), cache.clone())?);
+    }
+    if providers.is_empty() {
+        warn!("No provider could be reached, naming the files offline with their parsed names");
+    } else {
+        info!("Client connected");
+    }
+    Ok(providers)
+}
+
+/// Connects the client of the provider, none when it cannot be reached and `offline_fallback` lets
+/// the run carry on without it
+fn connect_provider(
+    kind: ProviderKind,
+    args: &Args,
+    config: &Config,
+    recording: Option<Arc<Recording>>,
+    cache: Option<Arc<ResponseCache>>,
+) -> Result<Option<Box<dyn MetadataProvider>>, Outcome> {
+    let provider: Box<dyn MetadataProvider> = match kind {
+        ProviderKind::Tvdb => {
+            let mut tvdb = TvdbClient::new(&config.tvdb_api_key)
+                .with_language(config.language.clone())
+                .with_country(config.country.clone());
+            // a pure dry run logs in every time rather than storing the token
+            if let Some(token_file) = get_filepath_in_conf_dir("tvdb_token.json").filter(|_| !args.pure_dry_run) {
+                tvdb = tvdb.with_token_file(token_file);
+            }
+            if let Some(recording) = recording {
+                tvdb = tvdb.with_recording(recording);
+            }
+            if let Some(cache) = cache {
+                tvdb = tvdb.with_cache(cache);
+            }
+            match provider::block_on(tvdb.login()) {
+                Ok(()) => {}
+                Err(error) if config.offline_fallback && error.is_unreachable() => {
+                    warn!("Could not reach {} to log in ({}), carrying on without it", kind, error);
+                    return Ok(None);
+                }
+                Err(error) => {
+                    error!("Error in logging in to API: ({})", error);
+                    return Err(Outcome::ProviderAuth);
+                }
+            }
+            Box::new(tvdb)
+        }
+        ProviderKind::Tmdb => {
+            let mut tmdb = TmdbClient::new(&config.tmdb_token);
+            if let Some(recording) = recording {
+                tmdb = tmdb.with_recording(recording);
+            }
+            if let Some(cache) = cache {
+                tmdb = tmdb.with_cache(cache);
+            }
+            Box::new(tmdb)
+        }
+        ProviderKind::Tvmaze => {
+            let mut tvmaze = TvmazeClient::new();
+            if let Some(recording) = recording {
+                tvmaze = tvmaze.with_recording(recording);
+            }
+            if let Some(cache) = cache {
+                tvmaze = tvmaze.with_cache(cache);
+            }
+            Box::new(tvmaze)
+        }
+        ProviderKind::Omdb => {
+            let mut omdb = OmdbClient::new(&config.omdb_api_key);
+            if let Some(recording) = recording {
+                omdb = omdb.with_recording(recording);
+            }
+            if let Some(cache) = cache {
+                omdb = omdb.with_cache(cache);
+            }
+            Box::new(omdb)
+        }
+    };
+    Ok(Some(provider))
+}
+
+/// Whether a file is to be looked up again with `refresh-metadata`: it was named without the
+/// metadata of a provider because none could be reached or asked, as the providers failed while
+/// it was searched or were dropped by `offline_fallback`, or matched to one of several close results
+fn is_pending_metadata(media_file: &MediaFile, context: &Context) -> bool {
+    let metadata = media_file.metadata();
+    let unresolved = [metadata.tvdb_id, metadata.tmdb_id, metadata.tvmaze_id, metadata.imdb_id]
+        .iter()
+        .all(Option::is_none);
+    media_file.is_uncertain() || (unresolved && (!context.providers.is_empty() || context.config.offline_fallback))
+}
+
+/// Opens the cache of the API responses of the previous runs, unless it is disabled
+fn open_cache(args: &Args, config: &Config) -> Option<Arc<ResponseCache>> {
+    if config.cache_ttl_hours == 0 {
+        return None;
+    }
+    let path = get_filepath_in_conf_dir("cache.json")?;
+    let ttl = Duration::from_secs(config.cache_ttl_hours * 60 * 60);
+    // a pure dry run uses the cache without writing back what it adds, or starting it over
+    let opened = if args.pure_dry_run {
+        ResponseCache::open(&path, ttl).map(ResponseCache::read_only)
+    } else {
+        ResponseCache::open_or_reset(&path, ttl)
+    };
+    match opened {
+        Ok(cache) => Some(Arc::new(cache)),
+        Err(error) => {
+            warn!("Could not open the cache {}, continuing without it: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+/// Acts on the files of a plan, with the action of the arguments
+fn apply_plan(path: &Path, args: &Args, config: &Config) -> Outcome {
+    let plan = match Plan::read(path) {
+        Ok(plan) => plan,
+        Err(error) => {
+            error!("Could not read the plan {}: {}", path.display(), error);
+            return Outcome::Error;
+        }
+    };
+    if plan.outputs.is_empty() {
+        error!("The plan {} has no output directory", path.display());
+        return Outcome::Error;
+    }
+    if let Err(error) = preflight::check_outputs(&RealFs, &plan.input, &plan.outputs, args.action) {
+        error!("{}", error);
+        return Outcome::Error;
+    }
+
+    let context = Context {
+        args,
+        config,
+        outputs: Mutex::new(OutputRoots::new(&RealFs, plan.outputs.clone(), args.placement)),
+        providers: Vec::new(),
+        fs: real_fs(args),
+        cancellation: interrupt_cancellation(),
+        claimed: Mutex::new(HashMap::new()),
+        summary: Mutex::new(Summary::new()),
+        journal: create_journal(args),
+        batch: None,
+        prompt: None,
+        conflict_resolution: Mutex::new(None),
+        placed: Mutex::new(Vec::new()),
+        want_list: None,
+        kids_outputs: None,
+        routes: Vec::new(),
+        originals: imported_originals(config),
+        deferred: open_deferred(args, config),
+        seeding: open_seeding(args, config),
+    };
+    plan::apply(&plan, &context);
+
+    record_recent(&context);
+    finish(&context)
+}
+
+/// The operations of every recorded run, oldest first
+fn history_operations() -> Option<Vec<Operation>> {
+    let history = get_filepath_in_conf_dir("history")?;
+    let mut operations = Vec::new();
+    for journal in journal::journals(&history) {
+        match journal::load(&journal) {
+            Ok(loaded) => operations.extend(loaded),
+            Err(error) => warn!("Could not read the journal {}: {}", journal.display(), error),
+        }
+    }
+    Some(operations)
+}
+
+/// The names the files placed by earlier runs had before, when the upgrade policy compares the
+/// quality of existing files: the library names seldom carry the tags of the release
+fn imported_originals(config: &Config) -> HashMap<PathBuf, PathBuf> {
+    if config.on_conflict != ConflictPolicy::Upgrade {
+        return HashMap::new();
+    }
+    reapply::imported(&history_operations().unwrap_or_default())
+        .into_iter()
+        .map(|file| (file.destination, file.original))
+        .collect()
+}
+
+/// Moves the files imported by earlier runs to where the current templates and what the providers
+/// know now place them, only the ones pending metadata with `pending_only`. Like any run it only
+/// tells what it would do unless the action is move
+fn reapply_templates(args: &Args, config: &Config, pending_only: bool) -> Outcome {
+    if !matches!(args.action, Action::Test | Action::Move) {
+        error!("Imported files can only be renamed with the test or move action");
+        return Outcome::Error;
+    }
+    if args.output.is_empty() {
+        error!("--output is required to rename the imported files");
+        return Outcome::Error;
+    }
+    let Some(operations) = history_operations() else {
+        return Outcome::Error;
+    };
+    let mut imported = reapply::imported(&operations);
+    if pending_only {
+        imported.retain(|file| file.pending);
+        info!("{} imported files are pending metadata", imported.len());
+    }
+
+    let providers = match connect_providers(args, config) {
+        Ok(providers) => providers,
+        Err(outcome) => return outcome,
+    };
+    let roots: Vec<PathBuf> = args.output.iter().map(PathBuf::from).collect();
+    let context = Context {
+        args,
+        config,
+        outputs: Mutex::new(OutputRoots::new(&RealFs, roots.clone(), args.placement)),
+        providers: providers.iter().map(Box::as_ref).collect(),
+        fs: real_fs(args),
+        cancellation: interrupt_cancellation(),
+        claimed: Mutex::new(HashMap::new()),
+        summary: Mutex::new(Summary::new()),
+        journal: create_journal(args),
+        batch: None,
+        prompt: None,
+        conflict_resolution: Mutex::new(None),
+        placed: Mutex::new(Vec::new()),
+        want_list: None,
+        kids_outputs: None,
+        routes: Vec::new(),
+        originals: HashMap::new(),
+        deferred: None,
+        seeding: None,
+    };
+    reapply::reapply(&imported, &reapply::written(&operations), &roots, &context);
+
+    finish(&context)
+}
+
+/// A cancellation token cancelled by Ctrl-C, so that a run stops between files. The handler can
+/// only be installed once, the runs of a server share it
+fn interrupt_cancellation() -> CancellationToken {
+    static CANCELLATION: OnceLock<CancellationToken> = OnceLock::new();
+    CANCELLATION
+        .get_or_init(|| {
+            let cancellation = CancellationToken::new();
+            let handler_cancellation = cancellation.clone();
+            if let Err(error) = ctrlc::set_handler(move || handler_cancellation.cancel()) {
+                warn!("Could not install the interrupt handler: {}", error);
+            }
+            cancellation
+        })
+        .clone()
+}
+
+/// Creates the journal of the run in the history directory, test runs change nothing to record
+fn create_journal(args: &Args) -> Option<Journal> {
+    if matches!(args.action, Action::Test) {
+        return None;
+    }
+
+    match get_filepath_in_conf_dir("history").map(|history| Journal::create(&history)) {
+        Some(Ok(journal)) => {
+            info!("Recording this run in {}, revert it with the undo command", journal.path().display());
+            Some(journal)
+        }
+        Some(Err(error)) => {
+            warn!("Could not create the journal, this run cannot be undone: {}", error);
+            None
+        }
+        None => None,
+    }
+}
+
+/// Asks the torrent client what it seeds, for the move action only as the others leave the
+/// downloads where they are
+fn open_seeding(args: &Args, config: &Config) -> Option<Seeding> {
+    if !matches!(args.action, Action::Move) || config.trash_sources {
+        return None;
+    }
+    config.torrent_client.as_ref().map(Seeding::query)
+}
+
+/// Opens the moves deferred by earlier move runs, other actions move nothing
+fn open_deferred(args: &Args, config: &Config) -> Option<Deferred> {
+    if !matches!(args.action, Action::Move) || config.trash_sources {
+        return None;
+    }
+
+    let path = get_filepath_in_conf_dir("deferred.json")?;
+    match Deferred::open(&path) {
+        Ok(deferred) => Some(deferred),
+        Err(error) => {
+            warn!("Could not read the deferred moves {}, files are moved at once: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+/// Opens the batch of the run, resuming the one of an interrupted run of the same arguments.
+/// Test runs place nothing to resume
+fn open_batch(args: &Args, input: &Path, outputs: &[PathBuf]) -> Option<Batch> {
+    if matches!(args.action, Action::Test) {
+        return None;
+    }
+
+    let path = get_filepath_in_conf_dir("batch.json")?;
+    match Batch::open(&path, input, args.action.into(), outputs) {
+        Ok(batch) => {
+            if batch.resumed() > 0 {
+                info!("Resuming the interrupted run, {} files were already placed", batch.resumed());
+            }
+            Some(batch)
+        }
+        Err(error) => {
+            warn!("Could not open the batch {}, an interrupted run will start over: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+/// Stops the searches sent ahead of the lookups, publishes the staged files, logs the summary of
+/// the run and writes the report when asked to, returning the outcome the process exits with
+fn finish(context: &Context) -> Outcome {
+    for provider in &context.providers {
+        provider.stop_prefetch();
+    }
+    publish_staged(context);
+    report_missing_episodes(context);
+    let args = context.args;
+    let summary = context.summary.lock().unwrap();
+    summary.log(matches!(args.action, Action::Test));
+    if let Some(report) = &args.report {
+        if let Err(error) = write_report(Path::new(report), &summary) {
+            error!("Could not write the report {}: {}", report, error);
+        }
+    }
+    summary.outcome()
+}
+
+/// Adds the series and movies placed by the run to the feed of recent imports, test runs
+/// placed nothing
+fn record_recent(context: &Context) {
+    let config = context.config;
+    if matches!(context.args.action, Action::Test) || config.recent_imports == 0 {
+        return;
+    }
+
+    let imported_at = recent::now();
+    let imports: Vec<RecentImport> = context
+        .placed
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|placed| !placed.companion)
+        .map(|placed| RecentImport {
+            title: placed.title.clone(),
+            media_type: placed.media_type,
+            tvdb_id: placed.metadata.tvdb_id,
+            tmdb_id: placed.metadata.tmdb_id,
+            tvmaze_id: placed.metadata.tvmaze_id,
+            imdb_id: placed.metadata.imdb_id.map(provider::imdb_id),
+            path: placed.destination.clone(),
+            imported_at,
+        })
+        .collect();
+    if imports.is_empty() {
+        return;
+    }
+    let Some(path) = get_filepath_in_conf_dir(recent::FILENAME) else {
+        return;
+    };
+    if let Err(error) = recent::record(&path, imports, config.recent_imports) {
+        warn!("Could not update the recent imports {}: {}", path.display(), error);
+    }
+}
+
+/// Compares the episodes of every season pack placed in the run, two episodes of a season or more,
+/// with the episode list of the provider, and adds the ones missing from it to the summary
+fn report_missing_episodes(context: &Context) {
+    let mut seasons: BTreeMap<(String, u32), (Metadata, BTreeSet<u32>)> = BTreeMap::new();
+    for placed in context.placed.lock().unwrap().iter() {
+        if let Some(placed_episodes) = &placed.episodes {
+            let (_, episodes) = seasons
+                .entry((placed.title.clone(), placed_episodes.season))
+                .or_insert_with(|| (placed.metadata.clone(), BTreeSet::new()));
+            episodes.extend(&placed_episodes.episodes);
+        }
+    }
+
+    for ((title, season), (metadata, episodes)) in seasons {
+        if episodes.len() < 2 {
+            continue;
+        }
+        let Some((provider, series_id)) = context
+            .providers
+            .iter()
+            .find_map(|provider| Some((provider, provider.kind().id(&metadata)?)))
+        else {
+            continue;
+        };
+        match provider.season_episodes(series_id, season) {
+            Ok(listed) => {
+                let listed: BTreeSet<u32> = listed
+                    .iter()
+                    .filter(|episode| episode.season_number == season && episode.number > 0)
+                    .map(|episode| episode.number)
+                    .collect();
+                let missing: Vec<u32> = listed.difference(&episodes).copied().collect();
+                if !missing.is_empty() {
+                    context.summary.lock().unwrap().record_missing(&title, season, missing);
+                }
+            }
+            Err(error) => warn!("Could not list the episodes of {} season {}: {}", title, season, error),
+        }
+    }
+}
+
+/// Writes the outcome of every file of the run as JSON, each with the id of the run
+fn write_report(path: &Path, summary: &Summary) -> Result<(), io::Error> {
+    #[derive(Serialize)]
+    struct RunEntry {
+        run_id: String,
+        #[serde(flatten)]
+        entry: ReportEntry,
+    }
+
+    let run_id = run_id::current();
+    let entries: Vec<RunEntry> = summary
+        .report()
+        .into_iter()
+        .map(|entry| RunEntry {
+            run_id: run_id.clone(),
+            entry,
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&entries).map_err(io::Error::other)?;
+    fs::write(path, json)
+}
+
+/// Processes the input file, or every file with a matching extension in the input directory
+fn process_input(input_path: &Path, context: &Context) {
+    let fs = context.fs;
+    let config = context.config;
+
+    if fs.is_file(input_path) {
+        if let Err(error) = filter_file(input_path, context) {
+            warn!("{}, ignoring", error);
+            return;
+        }
+        let sidecars = single_file_sidecars(input_path, context);
+        if !check_free_space(&[(input_path.to_path_buf(), None, None)], context) {
+            skip_sidecars(input_path, sidecars, context);
+            return;
+        }
+        match process_file(input_path, context) {
+            Ok(()) => {
+                for sidecar in sidecars.iter().flatten() {
+                    if let Err(error) = place_sidecar(sidecar, input_path, context) {
+                        report_error(&error);
+                    }
+                }
+            }
+            Err(error) => {
+                report_error(&error);
+                skip_sidecars(input_path, sidecars, context);
+            }
+        }
+    } else {
+        let mut walker = DirWalker::new(fs, input_path)
+            .with_max_depth(context.args.max_depth)
+            .with_ignored_dirs(config.ignored_dirs.clone())
+            .with_follow_symlinks(!config.skip_symlinked_dirs)
+            .with_max_entries(config.max_walk_entries);
+        let mut paths = Vec::new();
+        let mut companions = Vec::new();
+        let mut deletions = Vec::new();
+        let scanning = progress::scanning(input_path);
+        for path in walker.by_ref().filter_map(|e| e.ok()).filter(|entry| entry.is_file).map(|entry| entry.path) {
+            scanning.inc(1);
+            match policy::policy_for(&path, config) {
+                ExtensionPolicy::Subtitle | ExtensionPolicy::Follow if !has_utf8_name(&path) => {
+                    let error = Error::NonUtf8 { path: path.clone() };
+                    warn!("{}", error);
+                    context.summary.lock().unwrap().record_filtered(&path, &error);
+                }
+                ExtensionPolicy::Subtitle | ExtensionPolicy::Follow => companions.push(path),
+                ExtensionPolicy::Delete => deletions.push(path),
+                ExtensionPolicy::Process | ExtensionPolicy::Ignore => {
+                    if filter_file(&path, context).is_ok() {
+                        paths.push(path);
+                    }
+                }
+            }
+        }
+        scanning.finish_and_clear();
+        for dir in walker.ignored() {
+            context
+                .summary
+                .lock()
+                .unwrap()
+                .record_filtered(dir, &Error::IgnoredDir { path: dir.clone() });
+        }
+        // the files of an unreadable directory are reported failed as a whole, the rest is imported
+        for (dir, source) in walker.take_unreadable() {
+            let error = Error::Filesystem {
+                path: dir.clone(),
+                stage: Stage::ReadDir,
+                source,
+            };
+            context.summary.lock().unwrap().record_filtered(&dir, &error);
+        }
+        // process in a stable order so that conflicts within the run are always resolved the same way
+        paths.sort();
+        companions.sort();
+        let mut groups = Groups::new(&paths, &companions, config);
+        if let Some(batch) = &context.batch {
+            paths.retain(|path| {
+                let done = batch.is_done(path);
+                if done {
+                    debug!("{} was placed by the interrupted run", path.display());
+                    groups.named.remove(path);
+                }
+                !done
+            });
+        }
+
+        let args = context.args;
+        let jobs = jobs(args);
+        // a limited run looks up only the files it imports
+        if args.max_files.is_none() && args.max_bytes.is_none() {
+            prefetch_searches(&paths, context);
+        }
+        let mut plan = (args.two_pass || jobs > 1).then(|| plan_all(&paths, context));
+        if let Some(plan) = &plan {
+            paths.retain(|path| {
+                if !plan.contains_key(path) {
+                    skip_sidecars(path, groups.named.remove(path), context);
+                }
+                plan.contains_key(path)
+            });
+        }
+
+        let throttled = args.max_files.is_some() || args.max_bytes.is_some();
+        // when limited, the oldest files have waited the longest and are imported first
+        let priority = args.priority.or(throttled.then_some(Priority::Oldest));
+        if let Some(priority) = priority {
+            sort_by_priority(&mut paths, priority, context);
+        }
+
+        // the sidecars of every file are placed or skipped along with it
+        let files: Vec<_> = paths
+            .into_iter()
+            .map(|path| {
+                let planned = plan.as_mut().and_then(|plan| plan.remove(&path));
+                let sidecars = groups.named.remove(&path);
+                (path, planned, sidecars)
+            })
+            .collect();
+        if !check_free_space(&files, context) {
+            for (path, _, sidecars) in files {
+                skip_sidecars(&path, sidecars, context);
+            }
+            return;
+        }
+        let count = files.len();
+        // the files and bytes imported, and being imported, so far
+        let imported = Mutex::new((0, 0));
+        let left = AtomicUsize::new(0);
+        let stopped = AtomicBool::new(false);
+        let bar = progress::files(count);
+        let place = |(path, planned, sidecars): Placing| {
+            if stopped.load(Ordering::SeqCst) {
+                left.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+            if context.cancellation.is_cancelled() {
+                if !stopped.swap(true, Ordering::SeqCst) {
+                    warn!("Interrupted, stopping");
+                }
+                return;
+            }
+
+            let size = fs.file_size(&path).unwrap_or(0);
+            {
+                let mut imported = imported.lock().unwrap();
+                let (imported_files, imported_bytes) = *imported;
+                if over_limit(args, imported_files, imported_bytes, size) {
+                    stopped.store(true, Ordering::SeqCst);
+                    left.fetch_add(1, Ordering::SeqCst);
+                    return;
+                }
+                *imported = (imported_files + 1, imported_bytes + size);
+            }
+
+            let result = match planned {
+                Some(mut planned) => {
+                    let result = confirm(&mut planned, context).and_then(|()| apply(&planned, context));
+                    record_result(&planned, &result, context);
+                    result
+                }
+                None => process_file(&path, context),
+            };
+            match result {
+                Ok(()) => {
+                    for sidecar in sidecars.iter().flatten() {
+                        if let Err(error) = place_sidecar(sidecar, &path, context) {
+                            report_error(&error);
+                        }
+                    }
+                    if let Some(batch) = &context.batch {
+                        batch.record(&path);
+                    }
+                }
+                Err(error) => {
+                    let mut imported = imported.lock().unwrap();
+                    *imported = (imported.0 - 1, imported.1 - size);
+                    drop(imported);
+                    report_error(&error);
+                    skip_sidecars(&path, sidecars, context);
+                }
+            }
+        };
+        workers::for_each(files, jobs, |file| {
+            bar.set_message(file.0.display().to_string());
+            place(file);
+            bar.inc(1);
+        });
+        bar.finish_and_clear();
+        let left = left.into_inner();
+        if left > 0 && !context.cancellation.is_cancelled() {
+            info!(
+                "Reached the limit of this run, {} of {} files are left for the next run",
+                left, count
+            );
+        }
+
+        for (dir, sidecars) in &groups.shared {
+            for sidecar in sidecars {
+                if let Err(error) = place_shared(sidecar, dir, context) {
+                    report_error(&error);
+                }
+            }
+        }
+        for path in &groups.orphans {
+            let error = Error::Orphan { path: path.clone() };
+            report_error(&error);
+            context.summary.lock().unwrap().record_filtered(path, &error);
+        }
+        for path in &deletions {
+            delete_file(path, context);
+        }
+        if config.clean_sources && !context.cancellation.is_cancelled() {
+            clean_source_dirs(input_path, context);
+        }
+    }
+}
+
+/// Whether the file of `size` bytes goes over the limits of the run, after the files and bytes
+/// imported so far. A single file bigger than the limit is still imported alone, otherwise it would
+/// block every run
+fn over_limit(args: &Args, imported_files: usize, imported_bytes: u64, size: u64) -> bool {
+    args.max_files.is_some_and(|max| imported_files >= max)
+        || args
+            .max_bytes
+            .is_some_and(|max| imported_files > 0 && imported_bytes + size > max)
+}
+
+/// Checks that the output directories have room for the files the run copies into them, within
+/// its limits, before any is placed. Files are counted in the output directory they are planned
+/// in, or in the only one; unplanned files of a run with several output directories are not.
+/// Returns whether the files are placed, when they are not they are all failed
+fn check_free_space(files: &[Placing], context: &Context) -> bool {
+    let roots = output_roots(context);
+    let mut needed = BTreeMap::new();
+    let (mut counted_files, mut counted_bytes) = (0, 0);
+    for (path, planned, _) in files {
+        let size = context.fs.file_size(path).unwrap_or(0);
+        if over_limit(context.args, counted_files, counted_bytes, size) {
+            break;
+        }
+        counted_files += 1;
+        counted_bytes += size;
+
+        let root = match planned {
+            Some(planned) => roots.iter().find(|root| planned.destination.starts_with(root)),
+            None => roots.first().filter(|_| roots.len() == 1),
+        };
+        if let Some(root) = root.filter(|root| takes_space(path, root, context)) {
+            *needed.entry(root.clone()).or_insert(0) += size;
+        }
+    }
+
+    let Err(error) = preflight::check_free_space(context.fs, &needed, context.config.free_space_margin) else {
+        return true;
+    };
+    if context.config.on_low_space == LowSpace::Warn {
+        warn!("{}, placing the files anyway", error);
+        return true;
+    }
+    error!("{}, no file is placed", error);
+    let mut summary = context.summary.lock().unwrap();
+    for (path, _, _) in files {
+        summary.record_filtered(path, &error);
+    }
+    false
+}
+
+/// Whether placing the file in the output directory takes space there: copies do, moves only
+/// from another filesystem and links never
+fn takes_space(path: &Path, root: &Path, context: &Context) -> bool {
+    match context.args.action {
+        Action::Copy => true,
+        Action::Move if context.config.trash_sources => true,
+        // files still seeding are linked
+        Action::Move if is_seeding(path, context) => false,
+        Action::Move => match (context.fs.device(path), context.fs.device(root)) {
+            (Ok(source), Ok(output)) => source != output,
+            _ => true,
+        },
+        Action::Symlink | Action::Hardlink | Action::Test => false,
+    }
+}
+
+/// Deletes the directories under the input whose files were moved by the run, once they hold only
+/// leftovers like .nfo files and sample folders, and then their parents left the same way. The
+/// input directory itself is kept
+fn clean_source_dirs(input_path: &Path, context: &Context) {
+    if !matches!(context.args.action, Action::Move) {
+        return;
+    }
+    let leftovers = Leftovers {
+        fs: context.fs,
+        extensions: &context.config.leftover_extensions,
+        dirs: &context.config.ignored_dirs,
+        files: &BTreeSet::new(),
+    };
+    let placed = context.placed.lock().unwrap();
+    leftovers.clean(placed.iter().map(|placed| placed.source.as_path()), input_path);
+}
+
+/// The sidecars named after a single input file, like its subtitles, found next to it. The other
+/// files of its directory are only there so that a sidecar goes with the longest name, as in a run
+/// on the whole directory; its shared sidecars are left alone
+fn single_file_sidecars(input_path: &Path, context: &Context) -> Option<Vec<PathBuf>> {
+    let dir = input_path.parent()?;
+    let mut mains = Vec::new();
+    let mut companions = Vec::new();
+    let entries: Vec<PathBuf> = DirWalker::new(context.fs, dir)
+        .with_max_depth(Some(1))
+        .with_sorting()
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?
+        .into_iter()
+        .filter(|entry| entry.is_file)
+        .map(|entry| entry.path)
+        .collect();
+    for path in entries {
+        match policy::policy_for(&path, context.config) {
+            ExtensionPolicy::Subtitle | ExtensionPolicy::Follow if has_utf8_name(&path) => companions.push(path),
+            ExtensionPolicy::Process => mains.push(path),
+            _ => {}
+        }
+    }
+    Groups::new(&mains, &companions, context.config)
+        .named
+        .remove(input_path)
+}
+
+/// Places a sidecar named after a file placed in this run next to it, renamed along with it,
+/// e.g. `Show.S01E01.en.srt` becomes `Show - s01e01.en.srt`
+fn place_sidecar(path: &Path, main: &Path, context: &Context) -> error::Result<()> {
+    let orphan = || Error::Orphan {
+        path: path.to_path_buf(),
+    };
+    let (final_path, title, media_type) = {
+        let placed = context.placed.lock().unwrap();
+        let main = placed
+            .iter()
+            .find(|placed| !placed.companion && placed.source == main)
+            .ok_or_else(orphan)?;
+        let filename = get_filename(path).ok_or_else(orphan)?;
+        let source_stem = get_filestem(&main.source).ok_or_else(orphan)?;
+        let destination_stem = get_filestem(&main.destination).ok_or_else(orphan)?;
+        let subtitle = (policy::policy_for(path, context.config) == ExtensionPolicy::Subtitle)
+            .then(|| subtitles::subtitle_name(&filename, &source_stem, &destination_stem, || read_text(path, context)))
+            .flatten();
+        let renamed = subtitle.unwrap_or_else(|| filename.replacen(&source_stem, &destination_stem, 1));
+        (main.destination.with_file_name(renamed), main.title.clone(), main.media_type)
+    };
+    place_companion(path, final_path, title, media_type, context)
+}
+
+/// The start of a text file, enough to tell the language of a subtitle, with invalid UTF-8 replaced
+fn read_text(path: &Path, context: &Context) -> Option<String> {
+    let mut bytes = Vec::new();
+    context
+        .fs
+        .open_read(path)
+        .ok()?
+        .take(subtitles::SAMPLE_BYTES)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Places a sidecar of the directory, like a poster, into the folder of the files of the
+/// directory placed in this run, as long as they all went to the same folder
+fn place_shared(path: &Path, dir: &Path, context: &Context) -> error::Result<()> {
+    let orphan = || Error::Orphan {
+        path: path.to_path_buf(),
+    };
+    let filename = get_filename(path).ok_or_else(orphan)?;
+    let (final_path, title, media_type) = {
+        let placed = context.placed.lock().unwrap();
+        let siblings: Vec<&Placed> = placed
+            .iter()
+            .filter(|placed| !placed.companion && placed.source.parent() == Some(dir))
+            .collect();
+        let main = siblings.first().ok_or_else(orphan)?;
+        let folder = main.destination.parent().ok_or_else(orphan)?;
+        if siblings
+            .iter()
+            .any(|placed| placed.destination.parent() != Some(folder))
+        {
+            return Err(orphan());
+        }
+        (folder.join(&filename), main.title.clone(), main.media_type)
+    };
+    place_companion(path, final_path, title, media_type, context)
+}
+
+fn place_companion(
+    path: &Path,
+    final_path: PathBuf,
+    title: String,
+    media_type: MediaType,
+    context: &Context,
+) -> error::Result<()> {
+    info!("Final path: {}", final_path.display());
+    let result = claim_companion(path, &final_path, context)
+        .and_then(|()| create_parent_dirs(&final_path, context))
+        .and_then(|()| perform(path, &final_path, context));
+    context
+        .summary
+        .lock()
+        .unwrap()
+        .record(&title, media_type, path, &result);
+    if result.is_ok() {
+        context.placed.lock().unwrap().push(Placed {
+            source: path.to_path_buf(),
+            destination: final_path,
+            title,
+            media_type,
+            metadata: Metadata::default(),
+            companion: true,
+            episodes: None,
+        });
+    }
+    result
+}
+
+/// Skips the sidecars of a file that was not placed, so that they are not left in the library without it
+fn skip_sidecars(main: &Path, sidecars: Option<Vec<PathBuf>>, context: &Context) {
+    for sidecar in sidecars.into_iter().flatten() {
+        let error = Error::MainSkipped {
+            path: sidecar.clone(),
+            main: main.to_path_buf(),
+        };
+        report_error(&error);
+        context.summary.lock().unwrap().record_unmatched(&sidecar, &error);
+    }
+}
+
+fn claim_companion(path: &Path, final_path: &Path, context: &Context) -> error::Result<()> {
+    match context.claimed.lock().unwrap().entry(final_path.to_path_buf()) {
+        Entry::Occupied(entry) => {
+            return Err(Error::Conflict {
+                path: path.to_path_buf(),
+                destination: final_path.to_path_buf(),
+                claimed_by: entry.get().clone(),
+            })
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(path.to_path_buf());
+        }
+    }
+    if context.fs.exists(final_path) {
+        return Err(Error::AlreadyExists {
+            path: final_path.to_path_buf(),
+        });
+    }
+    Ok(())
+}
+
+/// Deletes a file of an extension with the delete policy, only when moving so that copying
+/// and linking runs leave the downloads as they are
+fn delete_file(path: &Path, context: &Context) {
+    match context.args.action {
+        Action::Move => match context.fs.remove_file(path) {
+            Ok(()) => info!("Deleted {}", path.display()),
+            Err(error) => warn!("Could not delete {}: {}", path.display(), error),
+        },
+        Action::Test => info!("TEST: would delete {}", path.display()),
+        Action::Copy | Action::Symlink | Action::Hardlink => {
+            debug!("Keeping {}, files are only deleted when moving", path.display())
+        }
+    }
+}
+
+/// Places the file whose name could not be parsed in the quarantine directory of the first output
+/// directory with the action of the run, when the quarantine fallback is configured. The file
+/// keeps its name and stays reported as unparsed
+fn quarantine(path: &Path, error: &Error, context: &Context) {
+    let config = context.config;
+    if !matches!(error, Error::Parse { .. }) || !config.parse_fallbacks.contains(&ParseFallback::Quarantine) {
+        return;
+    }
+    let (Some(root), Some(filename)) = (output_roots(context).into_iter().next(), path.file_name()) else {
+        return;
+    };
+    let destination = root.join(&config.quarantine_dir).join(filename);
+    if context.fs.exists(&destination) {
+        warn!("{} is already in quarantine, leaving {} in place", destination.display(), path.display());
+        return;
+    }
+    info!("Quarantining {} in {}", path.display(), destination.display());
+    if let Err(error) = create_parent_dirs(&destination, context).and_then(|()| perform(path, &destination, context)) {
+        report_error(&error);
+    }
+}
+
+/// Checks that the file is one to process, recording the reason in the summary when it is not
+fn filter_file(path: &Path, context: &Context) -> error::Result<()> {
+    let config = context.config;
+    let result = if policy::policy_for(path, config) != ExtensionPolicy::Process {
+        Err(Error::ExtensionFiltered {
+            path: path.to_path_buf(),
+        })
+    } else if !has_utf8_name(path) {
+        Err(Error::NonUtf8 {
+            path: path.to_path_buf(),
+        })
+    } else if config.min_size > 0
+        // streams are a few bytes long, they are not the leftovers min_size is about
+        && !policy::is_stream(path)
+        && context.fs.file_size(path).is_ok_and(|size| size < config.min_size)
+    {
+        Err(Error::TooSmall {
+            path: path.to_path_buf(),
+            min_size: config.min_size,
+        })
+    } else if config.settle_time > 0
+        && context
+            .fs
+            .modified(path)
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age.as_secs() < config.settle_time)
+    {
+        Err(Error::InProgress {
+            path: path.to_path_buf(),
+            settle_time: config.settle_time,
+        })
+    } else {
+        Ok(())
+    };
+
+    if let Err(error) = &result {
+        if matches!(error, Error::NonUtf8 { .. }) {
+            warn!("{}", error);
+        }
+        context.summary.lock().unwrap().record_filtered(path, error);
+    }
+    result
+}
+
+/// Sends the searches of the parsed names of the files to the primary provider ahead of the
+/// lookups, which then run while the responses come in. Names pinned to an id are not searched
+fn prefetch_searches(paths: &[PathBuf], context: &Context) {
+    let Some(primary) = context.providers.first() else {
+        return;
+    };
+    let mut searches = Vec::new();
+    for path in paths {
+        let Some(mut media_file) = context.args.forced.apply(path, parse_filepath(path, context.config)) else {
+            continue;
+        };
+        if let Some(pinned) = overrides::find(&context.config.overrides, &[media_file.name()]) {
+            if pinned.tvdb_id.is_some() && primary.kind() == ProviderKind::Tvdb {
+                continue;
+            }
+            if let Some(name) = &pinned.name {
+                media_file.set_name(name.clone());
+            }
+        }
+        let search = media_file.search();
+        if !searches.contains(&search) {
+            searches.push(search);
+        }
+    }
+    debug!("Prefetching {} searches", searches.len());
+    primary.prefetch(searches);
+}
+
+/// Resolves every file and chooses every destination before anything is acted on, so that
+/// lookups, placement and conflicts depend only on the set of files and not on the processing order.
+/// Returns the files that can be placed, the others are reported
+fn plan_all(paths: &[PathBuf], context: &Context) -> HashMap<PathBuf, Planned> {
+    // the lookups run on every job, the destinations are then chosen in order
+    let resolved = workers::map(paths, jobs(context.args), |path| {
+        (!context.cancellation.is_cancelled()).then(|| resolve_file(path, context))
+    });
+    let mut plan = HashMap::new();
+    for (path, resolved) in paths.iter().zip(resolved) {
+        let Some(resolved) = resolved else {
+            warn!("Interrupted, stopping");
+            break;
+        };
+        match resolved.and_then(|media_file| place_file(path, media_file, context)) {
+            Ok(planned) => {
+                plan.insert(path.clone(), planned);
+            }
+            Err(error) => {
+                quarantine(path, &error, context);
+                report_error(&error);
+            }
+        }
+    }
+    info!(
+        "Planned {} of {} files, acting on them",
+        plan.len(),
+        paths.len()
+    );
+    plan
+}
+
+/// How many files are processed at the same time. Interactive runs ask about one file at a time
+fn jobs(args: &Args) -> usize {
+    if args.interactive {
+        return 1;
+    }
+    match args.jobs {
+        0 => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+        jobs => jobs,
+    }
+}
+
+/// Stably sorts the paths so the most wanted files come first
+fn sort_by_priority(paths: &mut [PathBuf], priority: Priority, context: &Context) {
+    let fs = context.fs;
+    match priority {
+        Priority::Oldest => paths.sort_by_key(|path| fs.modified(path).ok()),
+        Priority::Newest => paths.sort_by_key(|path| Reverse(fs.modified(path).ok())),
+        Priority::Smallest => paths.sort_by_key(|path| fs.file_size(path).ok()),
+        Priority::Largest => paths.sort_by_key(|path| Reverse(fs.file_size(path).ok())),
+        Priority::TvFirst | Priority::MoviesFirst => {
+            let first = if priority == Priority::TvFirst {
+                MediaType::Series
+            } else {
+                MediaType::Movie
+            };
+            paths.sort_by_key(|path| {
+                parse_filepath(path, context.config)
+                    .is_none_or(|media_file| media_file.media_type() != first)
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use error::SkipReason;
+    use filesystem::{MemoryFs, MemoryNode};
+    use interactive::ConflictAnswer;
+    use provider::SearchResult;
+
+    use super::*;
+
+    pub(crate) fn offline_context<'a>(args: &'a Args, config: &'a Config, fs: &'a MemoryFs) -> Context<'a> {
+        Context {
+            args,
+            config,
+            outputs: Mutex::new(OutputRoots::new(
+                fs,
+                vec![PathBuf::from("library")],
+                args.placement,
+            )),
+            providers: Vec::new(),
+            fs,
+            cancellation: CancellationToken::new(),
+            claimed: Mutex::new(HashMap::new()),
+            summary: Mutex::new(Summary::new()),
+            journal: None,
+            batch: None,
+            prompt: None,
+            conflict_resolution: Mutex::new(None),
+            placed: Mutex::new(Vec::new()),
+            want_list: None,
+            kids_outputs: None,
+            routes: Vec::new(),
+            originals: HashMap::new(),
+            deferred: None,
+            seeding: None,
+        }
+    }
+
+    /// Answers every confirmation with the next of its decisions
+    struct ScriptedPrompt(Mutex<Vec<Decision>>);
+
+    impl Prompt for ScriptedPrompt {
+        fn confirm(&self, _source: &Path, _destination: &Path) -> Decision {
+            self.0.lock().unwrap().remove(0)
+        }
+
+        fn resolve_conflict(&self, _: &Path, _: &FileInfo, _: &Path, _: &FileInfo) -> ConflictAnswer {
+            ConflictAnswer {
+                resolution: Resolution::Keep,
+                apply_to_all: false,
+            }
+        }
+
+        fn pick(&self, _searched: &str, _results: &[&SearchResult]) -> Option<usize> {
+            Some(0)
+        }
+    }
+
+    /// Accepts every destination and answers every conflict with the next of its answers
+    struct ConflictPrompt(Mutex<Vec<ConflictAnswer>>);
+
+    impl Prompt for ConflictPrompt {
+        fn confirm(&self, _source: &Path, _destination: &Path) -> Decision {
+            Decision::Accept
+        }
+
+        fn resolve_conflict(&self, _: &Path, _: &FileInfo, _: &Path, _: &FileInfo) -> ConflictAnswer {
+            self.0.lock().unwrap().remove(0)
+        }
+
+        fn pick(&self, _searched: &str, _results: &[&SearchResult]) -> Option<usize> {
+            Some(0)
+        }
+    }
+
+    #[test]
+    fn move_places_file_in_library() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S02E01.1080p.mkv", b"episode");
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert_eq!(fs.node("downloads/Severance.S02E01.1080p.mkv"), None);
+        assert_eq!(
+            fs.node("library/TV/Severance/Season 2/Severance - s02e01.mkv"),
+            Some(MemoryNode::File(b"episode".to_vec()))
+        );
+    }
+
+    #[test]
+    fn same_destination_in_one_run_is_claimed_once() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S02E01.720p-GRPB.mkv", b"b");
+        fs.add_file("downloads/Severance.S02E01.1080p-GRPA.mkv", b"a");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+
+        assert_eq!(
+            fs.node("library/TV/Severance/Season 2/Severance - s02e01.mkv"),
+            Some(MemoryNode::File(b"a".to_vec()))
+        );
+        assert!(fs.exists(Path::new("downloads/Severance.S02E01.720p-GRPB.mkv")));
+        assert!(matches!(
+            process_file(Path::new("downloads/Severance.S02E01.720p-GRPB.mkv"), &context),
+            Err(Error::Conflict { .. })
+        ));
+    }
+
+    #[test]
+    fn max_files_imports_oldest_first() {
+        let args = Args::parse_from([
+            "media-renamer", "-i", "downloads", "-o", "library", "-a", "move", "--max-files", "1",
+        ]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S02E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S02E02.mkv", b"2");
+        fs.set_modified("downloads/Severance.S02E01.mkv", SystemTime::UNIX_EPOCH + Duration::from_secs(20));
+        fs.set_modified("downloads/Severance.S02E02.mkv", SystemTime::UNIX_EPOCH + Duration::from_secs(10));
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert!(fs.exists(Path::new("downloads/Severance.S02E01.mkv")));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 2/Severance - s02e02.mkv")));
+    }
+
+    #[test]
+    fn tv_first_priority_processes_series_before_movies() {
+        let args = Args::parse_from([
+            "media-renamer", "-i", "downloads", "-o", "library", "--priority", "tv-first",
+        ]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        let mut paths = vec![
+            PathBuf::from("Conclave.2024.2160p.mkv"),
+            PathBuf::from("Severance.S02E01.mkv"),
+            PathBuf::from("Anora.2024.1080p.mkv"),
+        ];
+
+        sort_by_priority(&mut paths, Priority::TvFirst, &offline_context(&args, &config, &fs));
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("Severance.S02E01.mkv"),
+                PathBuf::from("Conclave.2024.2160p.mkv"),
+                PathBuf::from("Anora.2024.1080p.mkv"),
+            ]
+        );
+    }
+
+    #[test]
+    fn two_pass_resolves_conflicts_independently_of_priority() {
+        let args = Args::parse_from([
+            "media-renamer", "-i", "downloads", "-o", "library", "-a", "move", "--two-pass", "--priority", "largest",
+        ]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S02E01.1080p-GRPA.mkv", b"a");
+        fs.add_file("downloads/Severance.S02E01.2160p-GRPB.mkv", b"bigger");
+        fs.add_file("downloads/Severance.S02E02.mkv", b"2");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+
+        // without --two-pass the largest file would be processed first and claim the episode
+        assert_eq!(
+            fs.node("library/TV/Severance/Season 2/Severance - s02e01.mkv"),
+            Some(MemoryNode::File(b"a".to_vec()))
+        );
+        assert!(fs.exists(Path::new("downloads/Severance.S02E01.2160p-GRPB.mkv")));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 2/Severance - s02e02.mkv")));
+    }
+
+    #[test]
+    fn hardlink_keeps_source_in_place() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "hardlink"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S02E01.1080p.mkv", b"episode");
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert!(fs.exists(Path::new("downloads/Severance.S02E01.1080p.mkv")));
+        assert_eq!(
+            fs.node("library/TV/Severance/Season 2/Severance - s02e01.mkv"),
+            Some(MemoryNode::File(b"episode".to_vec()))
+        );
+    }
+
+    #[test]
+    fn interactive_decisions_are_followed() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move", "--interactive"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Shameless.S01E01.mkv", b"1");
+        fs.add_file("downloads/Shameless.S01E02.mkv", b"2");
+        fs.add_file("downloads/Shameless.S01E03.mkv", b"3");
+        let prompt = ScriptedPrompt(Mutex::new(vec![
+            Decision::Rename("Shameless (US)".to_string()),
+            Decision::Accept,
+            Decision::Skip,
+            Decision::Abort,
+        ]));
+        let mut context = offline_context(&args, &config, &fs);
+        context.prompt = Some(&prompt);
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("library/TV/Shameless (US)/Season 1/Shameless (US) - s01e01.mkv")));
+        assert!(fs.exists(Path::new("downloads/Shameless.S01E02.mkv")));
+        assert!(fs.exists(Path::new("downloads/Shameless.S01E03.mkv")));
+        assert!(context.cancellation.is_cancelled());
+    }
+
+    #[test]
+    fn conflicts_are_resolved_interactively() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move", "--interactive"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        for episode in 1..=4 {
+            fs.add_file(format!("downloads/Shameless.S01E0{}.1080p.mkv", episode), b"new");
+            fs.add_file(format!("library/TV/Shameless/Season 1/Shameless - s01e0{}.mkv", episode), b"old");
+        }
+        // the same file as the one in the library is not asked about
+        fs.add_file("downloads/Shameless.S01E05.1080p.mkv", b"same");
+        fs.add_file("library/TV/Shameless/Season 1/Shameless - s01e05.mkv", b"same");
+        let answer = |resolution, apply_to_all| ConflictAnswer {
+            resolution,
+            apply_to_all,
+        };
+        let prompt = ConflictPrompt(Mutex::new(vec![
+            answer(Resolution::Keep, false),
+            answer(Resolution::Replace, false),
+            answer(Resolution::KeepBoth, true),
+        ]));
+        let mut context = offline_context(&args, &config, &fs);
+        context.prompt = Some(&prompt);
+
+        process_input(Path::new("downloads"), &context);
+
+        let contents = |path: &str| match fs.node(path) {
+            Some(MemoryNode::File(contents)) => contents,
+            node => panic!("{} is {:?}", path, node),
+        };
+        assert_eq!(contents("library/TV/Shameless/Season 1/Shameless - s01e01.mkv"), b"old");
+        assert!(fs.exists(Path::new("downloads/Shameless.S01E01.1080p.mkv")));
+        assert_eq!(contents("library/TV/Shameless/Season 1/Shameless - s01e02.mkv"), b"new");
+        // the last answer applies to every remaining conflict
+        for episode in 3..=4 {
+            assert_eq!(contents(&format!("library/TV/Shameless/Season 1/Shameless - s01e0{}.mkv", episode)), b"old");
+            assert_eq!(contents(&format!("library/TV/Shameless/Season 1/Shameless - s01e0{} (2).mkv", episode)), b"new");
+        }
+        assert!(!fs.exists(Path::new("library/TV/Shameless/Season 1/Shameless - s01e05 (2).mkv")));
+        assert!(fs.exists(Path::new("downloads/Shameless.S01E05.1080p.mkv")));
+    }
+
+    #[test]
+    fn skipped_files_carry_reason_codes() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config {
+            min_size: 2,
+            ..Config::default()
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S02E01.mkv", b"episode");
+        fs.add_file("downloads/Severance.S02E02.mkv", b"e");
+        fs.add_file("downloads/Severance.S02E01.txt", b"info");
+        fs.add_file("downloads/Sample/Severance.S02E01.sample.mkv", b"sample");
+        fs.add_file("downloads/notes.mkv", b"notes");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+
+        let report = context.summary.lock().unwrap().report();
+        let reasons: Vec<(&str, Option<SkipReason>)> = report
+            .iter()
+            .map(|entry| (entry.path.to_str().unwrap(), entry.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("downloads/Sample", Some(SkipReason::IgnoredDir)),
+                ("downloads/Severance.S02E01.mkv", None),
+                ("downloads/Severance.S02E01.txt", Some(SkipReason::ExtensionFiltered)),
+                ("downloads/Severance.S02E02.mkv", Some(SkipReason::TooSmall)),
+                ("downloads/notes.mkv", Some(SkipReason::Unparsed)),
+            ]
+        );
+    }
+
+    #[test]
+    fn companions_follow_their_main_file() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let mut config = Config::default();
+        config
+            .extension_policies
+            .insert("txt".to_string(), ExtensionPolicy::Delete);
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Dune.2021.1080p/Dune.2021.1080p.mkv", b"movie");
+        fs.add_file("downloads/Dune.2021.1080p/Dune.2021.1080p.en.srt", b"subtitle");
+        fs.add_file("downloads/Dune.2021.1080p/Dune.2021.1080p.ITA.Forced.srt", b"sottotitolo");
+        fs.add_file("downloads/Dune.2021.1080p/poster.jpg", b"poster");
+        fs.add_file("downloads/Dune.2021.1080p/RARBG.txt", b"junk");
+        fs.add_file("downloads/Orphan.en.srt", b"orphan");
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert!(fs.exists(Path::new("library/Movies/Dune (2021)/Dune (2021).mkv")));
+        assert!(fs.exists(Path::new("library/Movies/Dune (2021)/Dune (2021).en.srt")));
+        assert!(fs.exists(Path::new("library/Movies/Dune (2021)/Dune (2021).it.forced.srt")));
+        assert!(fs.exists(Path::new("library/Movies/Dune (2021)/poster.jpg")));
+        assert!(!fs.exists(Path::new("downloads/Dune.2021.1080p/RARBG.txt")));
+        assert!(fs.exists(Path::new("downloads/Orphan.en.srt")));
+    }
+
+    #[test]
+    fn the_kodi_scheme_names_files_and_writes_nfos() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move", "--nfo"]);
+        let mut config = Config::default();
+        [config.tv_template, config.daily_template, config.movie_template] = Scheme::Kodi.templates();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E02.1080p.mkv", b"episode");
+        fs.add_file("downloads/Dune.2021.1080p.mkv", b"movie");
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 01/Severance S01E02.mkv")));
+        assert!(fs
+            .read_to_string(Path::new("library/TV/Severance/tvshow.nfo"))
+            .unwrap()
+            .contains("<title>Severance</title>"));
+        assert!(fs.exists(Path::new("library/Movies/Dune (2021)/Dune (2021).mkv")));
+        assert!(fs
+            .read_to_string(Path::new("library/Movies/Dune (2021)/movie.nfo"))
+            .unwrap()
+            .contains("<year>2021</year>"));
+    }
+
+    #[test]
+    fn sidecars_of_a_single_input_file_follow_it() {
+        let args = Args::parse_from([
+            "media-renamer",
+            "-i",
+            "downloads/Severance.S01E01.mkv",
+            "-o",
+            "library",
+            "-a",
+            "move",
+        ]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S01E01.en.srt", b"sub");
+        fs.add_file("downloads/Severance.S01E01.nfo", b"nfo");
+        fs.add_file("downloads/Severance.S01E01.Extended.mkv", b"2");
+        fs.add_file("downloads/Severance.S01E01.Extended.en.srt", b"sub");
+
+        process_input(
+            Path::new("downloads/Severance.S01E01.mkv"),
+            &offline_context(&args, &config, &fs),
+        );
+
+        let season = Path::new("library/TV/Severance/Season 1");
+        assert!(fs.exists(&season.join("Severance - s01e01.mkv")));
+        assert!(fs.exists(&season.join("Severance - s01e01.en.srt")));
+        assert!(fs.exists(&season.join("Severance - s01e01.nfo")));
+        // named after the other file, which was not given
+        assert!(fs.exists(Path::new("downloads/Severance.S01E01.Extended.en.srt")));
+    }
+
+    #[test]
+    fn test_runs_change_nothing() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S01E01.en.srt", b"sub");
+        fs.add_file("downloads/Severance.S01E01.nfo", b"nfo");
+        fs.add_file("downloads/Dune.2021.1080p/Dune.2021.1080p.mkv", b"2");
+        fs.add_file("downloads/Dune.2021.1080p/RARBG.txt", b"ad");
+        fs.add_file("downloads/home video.mkv", b"3");
+        fs.add_file("library/TV/Severance/Season 1/Severance - s01e01.mkv", b"old");
+        let config = Config {
+            parse_fallbacks: vec![ParseFallback::Heuristics, ParseFallback::Quarantine],
+            ..Config::default()
+        };
+        let snapshot = |fs: &MemoryFs| -> Vec<_> { fs.paths().into_iter().map(|path| (fs.node(&path), path)).collect() };
+        let before = snapshot(&fs);
+
+        for extra in [&["--plexmatch"][..], &["--staging", "--two-pass"], &["--jobs", "4", "--verify"]] {
+            let mut arguments = vec!["media-renamer", "-i", "downloads", "-o", "library"];
+            arguments.extend_from_slice(extra);
+            let args = Args::parse_from(arguments);
+            process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+            finish(&offline_context(&args, &config, &fs));
+            assert_eq!(snapshot(&fs), before, "{:?}", extra);
+        }
+
+        // reading through the read-only filesystem of test runs gives the same plan
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library"]);
+        let read_only = ReadOnlyFs(&fs);
+        let context = Context {
+            fs: &read_only,
+            ..offline_context(&args, &config, &fs)
+        };
+        process_input(Path::new("downloads"), &context);
+        let placed: Vec<_> = context.placed.lock().unwrap().iter().map(|placed| placed.destination.clone()).collect();
+        assert_eq!(placed, vec![PathBuf::from("library/Movies/Dune (2021)/Dune (2021).mkv")]);
+        assert_eq!(snapshot(&fs), before);
+    }
+
+    #[test]
+    fn unparsed_names_go_through_the_configured_fallbacks() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Aliens.1986.1080p.BluRay-SPARKS/sparks-aliens-1080p.mkv", b"1");
+        fs.add_file("downloads/Show 1x02.mkv", b"2");
+        let mut tagged = b"\x1a\x45\xdf\xa3\x7b\xa9".to_vec();
+        let title = b"Heat.1995.1080p.BDRip";
+        tagged.push(0x80 | title.len() as u8);
+        tagged.extend_from_slice(title);
+        fs.add_file("downloads/untitled video.mkv", &tagged);
+        fs.add_file("downloads/home video.mkv", b"4");
+
+        let config = Config::default();
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+        assert!(fs.exists(Path::new("downloads/Show 1x02.mkv")));
+        assert!(fs.exists(Path::new("downloads/home video.mkv")));
+
+        let config = Config {
+            parse_fallbacks: vec![
+                ParseFallback::ParentDir,
+                ParseFallback::EmbeddedTitle,
+                ParseFallback::Heuristics,
+                ParseFallback::Quarantine,
+            ],
+            ..Config::default()
+        };
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+        assert!(fs.exists(Path::new("library/Movies/Aliens (1986)/Aliens (1986).mkv")));
+        assert!(fs.exists(Path::new("library/TV/Show/Season 1/Show - s01e02.mkv")));
+        assert!(fs.exists(Path::new("library/Movies/Heat (1995)/Heat (1995).mkv")));
+        assert!(fs.exists(Path::new("library/Unparsed/home video.mkv")));
+    }
+
+    #[test]
+    fn sidecars_are_skipped_with_their_main_file() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move", "--interactive"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Shameless.S01E01.mkv", b"1");
+        fs.add_file("downloads/Shameless.S01E01.en.srt", b"1");
+        fs.add_file("downloads/Shameless.S01E02.mkv", b"2");
+        fs.add_file("downloads/Shameless.S01E02.en.srt", b"2");
+        let prompt = ScriptedPrompt(Mutex::new(vec![Decision::Skip, Decision::Accept]));
+        let mut context = offline_context(&args, &config, &fs);
+        context.prompt = Some(&prompt);
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("downloads/Shameless.S01E01.en.srt")));
+        assert!(fs.exists(Path::new("library/TV/Shameless/Season 1/Shameless - s01e02.en.srt")));
+        let report = context.summary.lock().unwrap().report();
+        let sidecar = report
+            .iter()
+            .find(|entry| entry.path == Path::new("downloads/Shameless.S01E01.en.srt"))
+            .unwrap();
+        assert_eq!(sidecar.reason, Some(SkipReason::MainSkipped));
+    }
+
+    #[test]
+    fn only_titles_on_the_want_list_are_imported() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        fs.add_file("downloads/Holiday.Video.2019.1080p.mkv", b"2");
+
+        let path = env::temp_dir().join(format!("media-renamer-want-list-{}.txt", std::process::id()));
+        fs::write(&path, "Severance\n").unwrap();
+        let mut context = offline_context(&args, &config, &fs);
+        context.want_list = Some(WantList::read(&path).unwrap());
+        fs::remove_file(path).unwrap();
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+        assert!(fs.exists(Path::new("downloads/Holiday.Video.2019.1080p.mkv")));
+        let report = context.summary.lock().unwrap().report();
+        let skipped = report
+            .iter()
+            .find(|entry| entry.path == Path::new("downloads/Holiday.Video.2019.1080p.mkv"))
+            .unwrap();
+        assert_eq!(skipped.reason, Some(SkipReason::NotWanted));
+    }
+
+    #[test]
+    fn ignored_titles_are_skipped_without_failing() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config {
+            ignored_titles: vec!["Severance S02".to_string()],
+            ..Config::default()
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S02E01.mkv", b"2");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+        assert!(fs.exists(Path::new("downloads/Severance.S02E01.mkv")));
+        let report = context.summary.lock().unwrap().report();
+        let skipped = report
+            .iter()
+            .find(|entry| entry.path == Path::new("downloads/Severance.S02E01.mkv"))
+            .unwrap();
+        assert_eq!(skipped.reason, Some(SkipReason::IgnoredTitle));
+    }
+
+    #[test]
+    fn only_the_selected_titles_are_imported() {
+        let args = Args::parse_from([
+            "media-renamer",
+            "-i",
+            "downloads",
+            "-o",
+            "library",
+            "-a",
+            "move",
+            "--only",
+            "the office (us)",
+        ]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/The.Office.US.S01E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S01E01.mkv", b"2");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("library/TV/The Office US/Season 1/The Office US - s01e01.mkv")));
+        assert!(fs.exists(Path::new("downloads/Severance.S01E01.mkv")));
+        let report = context.summary.lock().unwrap().report();
+        let skipped = report
+            .iter()
+            .find(|entry| entry.path == Path::new("downloads/Severance.S01E01.mkv"))
+            .unwrap();
+        assert_eq!(skipped.reason, Some(SkipReason::NotSelected));
+    }
+
+    #[test]
+    fn only_the_selected_episodes_are_imported() {
+        let args = Args::parse_from([
+            "media-renamer",
+            "-i",
+            "downloads",
+            "-o",
+            "library",
+            "-a",
+            "move",
+            "--only",
+            "severance",
+            "--season",
+            "2",
+            "--episodes",
+            "3-5",
+        ]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S02E03.mkv", b"1");
+        fs.add_file("downloads/Severance.S02E06.mkv", b"2");
+        fs.add_file("downloads/Severance.S01E04.mkv", b"3");
+        fs.add_file("downloads/Dune.2021.1080p.mkv", b"4");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 2/Severance - s02e03.mkv")));
+        assert!(fs.exists(Path::new("downloads/Severance.S02E06.mkv")));
+        assert!(fs.exists(Path::new("downloads/Severance.S01E04.mkv")));
+        assert!(fs.exists(Path::new("downloads/Dune.2021.1080p.mkv")));
+    }
+
+    #[test]
+    fn overridden_names_are_searched_instead_of_the_parsed_ones() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config {
+            overrides: toml::from_str(r#""/^sev(erance)? us$/" = { name = "Severance" }"#).unwrap(),
+            ..Config::default()
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Sev.US.S01E01.mkv", b"1");
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+    }
+
+    #[test]
+    fn forced_parts_replace_the_parsed_ones() {
+        let args = Args::parse_from([
+            "media-renamer",
+            "-i",
+            "downloads/sev_ep3_final.mkv",
+            "-o",
+            "library",
+            "-a",
+            "move",
+            "--force-name",
+            "Severance",
+            "--force-season",
+            "1",
+            "--force-episode",
+            "3",
+        ]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/sev_ep3_final.mkv", b"1");
+
+        process_input(Path::new("downloads/sev_ep3_final.mkv"), &offline_context(&args, &config, &fs));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e03.mkv")));
+
+        let forced = Forced {
+            force_year: Some(2022),
+            force_media_type: Some(MediaType::Movie),
+            ..Forced::default()
+        };
+        let media_file = forced
+            .apply(Path::new("Severance.S01E01.mkv"), parse_filepath(Path::new("Severance.S01E01.mkv"), &config))
+            .unwrap();
+        assert_eq!(media_file.title(), "Severance (2022)");
+        let forced = Forced {
+            force_season: Some(2),
+            ..Forced::default()
+        };
+        assert!(forced.apply(Path::new("Dune.2021.mkv"), parse_filepath(Path::new("Dune.2021.mkv"), &config)).is_none());
+    }
+
+    #[test]
+    fn episode_ranges_are_parsed() {
+        assert_eq!(parse_episode_range("1-10"), Ok(1..=10));
+        assert_eq!(parse_episode_range("5"), Ok(5..=5));
+        assert!(parse_episode_range("10-1").is_err());
+        assert!(parse_episode_range("one").is_err());
+    }
+
+    #[test]
+    fn files_placed_by_an_interrupted_run_are_not_placed_again() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "copy"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S01E02.mkv", b"2");
+        let mut context = offline_context(&args, &config, &fs);
+        let path = env::temp_dir().join(format!("media-renamer-resume-{}.json", std::process::id()));
+        let outputs = [PathBuf::from("library")];
+        let interrupted = Batch::open(&path, Path::new("downloads"), "copy", &outputs).unwrap();
+        interrupted.record(Path::new("downloads/Severance.S01E01.mkv"));
+        context.batch = Some(Batch::open(&path, Path::new("downloads"), "copy", &outputs).unwrap());
+
+        process_input(Path::new("downloads"), &context);
+        let batch = context.batch.take().unwrap();
+        assert!(batch.is_done(Path::new("downloads/Severance.S01E02.mkv")));
+        batch.finish();
+
+        assert!(!fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e02.mkv")));
+    }
+
+    #[test]
+    fn staged_files_reach_the_library_at_the_end_of_the_run() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move", "--staging"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S01E02.mkv", b"2");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+        assert!(!fs.exists(Path::new("library/TV")));
+        assert!(fs.exists(Path::new(
+            "library/.media-renamer-staging/TV/Severance/Season 1/Severance - s01e02.mkv"
+        )));
+
+        assert_eq!(finish(&context), Outcome::Success);
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e02.mkv")));
+        assert!(!fs.exists(Path::new("library/.media-renamer-staging")));
+    }
+
+    #[test]
+    fn parallel_runs_resolve_conflicts_in_order() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "copy", "--jobs", "4"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        for episode in 1..=8 {
+            fs.add_file(format!("downloads/Severance.S01E0{}.720p.mkv", episode), b"720p");
+        }
+        fs.add_file("downloads/Severance.S01E01.1080p.mkv", b"1080p");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+
+        for episode in 2..=8 {
+            let destination = format!("library/TV/Severance/Season 1/Severance - s01e0{}.mkv", episode);
+            assert!(fs.exists(Path::new(&destination)));
+        }
+        let first = fs.open_read(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")).unwrap();
+        assert_eq!(io::read_to_string(first).unwrap(), "1080p");
+    }
+
+    #[test]
+    fn moved_sources_can_be_sent_to_the_trash() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config {
+            trash_sources: true,
+            ..Config::default()
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        let dir = env::temp_dir().join(format!("media-renamer-trash-{}", std::process::id()));
+        let mut context = offline_context(&args, &config, &fs);
+        context.journal = Some(Journal::create(&dir.join("history")).unwrap());
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+        assert!(!fs.exists(Path::new("downloads/Severance.S01E01.mkv")));
+        assert!(fs.exists(Path::new("trash/Severance.S01E01.mkv")));
+
+        // undo puts the source back from the trash and removes the copy
+        let journal = context.journal.take().unwrap();
+        let undone = journal::undo_journal(&fs, journal.path());
+        let history = journal::history(&dir.join("history"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(undone.unwrap());
+        assert_eq!(fs.node("downloads/Severance.S01E01.mkv"), Some(MemoryNode::File(b"1".to_vec())));
+        assert!(!fs.exists(Path::new("trash/Severance.S01E01.mkv")));
+        assert!(!fs.exists(Path::new("library/TV")));
+        assert!(history.iter().all(|(_, undone)| *undone));
+    }
+
+    #[test]
+    fn report_entries_carry_the_run_id() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        let context = offline_context(&args, &config, &fs);
+        process_input(Path::new("downloads"), &context);
+
+        let path = env::temp_dir().join(format!("media-renamer-report-{}.json", std::process::id()));
+        write_report(&path, &context.summary.lock().unwrap()).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(report[0]["run_id"], run_id::current());
+        assert_eq!(report[0]["status"], "imported");
+    }
+
+    #[test]
+    fn titles_rated_for_kids_go_to_the_kids_library() {
+        use recording::request_key;
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[
+            (
+                request_key("/search", &[("q", "Bluey"), ("type", "series")]),
+                200,
+                r#"{"status": "success", "data": [{"name": "Bluey", "tvdb_id": "353546", "year": "2018"}]}"#,
+            ),
+            (
+                request_key("/search", &[("q", "Severance"), ("type", "series")]),
+                200,
+                r#"{"status": "success", "data": [{"name": "Severance", "tvdb_id": "371980", "year": "2022"}]}"#,
+            ),
+            (
+                request_key("/series/353546/extended", &[("short", "true")]),
+                200,
+                r#"{"status": "success", "data": {"contentRatings": [{"name": "G", "country": "aus"}, {"name": "TV-Y", "country": "usa"}]}}"#,
+            ),
+            (
+                request_key("/series/371980/extended", &[("short", "true")]),
+                200,
+                r#"{"status": "success", "data": {"contentRatings": [{"name": "TV-MA", "country": "usa"}]}}"#,
+            ),
+        ]));
+        provider::block_on(tvdb.login()).unwrap();
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Bluey.S01E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S01E01.mkv", b"2");
+        let mut context = offline_context(&args, &config, &fs);
+        context.providers = vec![&tvdb];
+        context.kids_outputs = Some(Mutex::new(OutputRoots::new(
+            &fs,
+            vec![PathBuf::from("kids")],
+            args.placement,
+        )));
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("kids/TV/Bluey/Season 1/Bluey - s01e01.mkv")));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+    }
+
+    #[test]
+    fn routed_titles_go_to_the_library_of_their_route() {
+        use recording::request_key;
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[
+            (
+                request_key("/search", &[("q", "Frieren"), ("type", "series")]),
+                200,
+                r#"{"status": "success", "data": [{"name": "Frieren: Beyond Journey's End", "tvdb_id": "424536", "year": "2023"}]}"#,
+            ),
+            (
+                request_key("/search", &[("q", "Severance"), ("type", "series")]),
+                200,
+                r#"{"status": "success", "data": [{"name": "Severance", "tvdb_id": "371980", "year": "2022"}]}"#,
+            ),
+            (
+                request_key("/series/424536/extended", &[("short", "true")]),
+                200,
+                r#"{"status": "success", "data": {"genres": [{"id": 27, "name": "Anime"}, {"id": 17, "name": "Fantasy"}]}}"#,
+            ),
+            (
+                request_key("/series/371980/extended", &[("short", "true")]),
+                200,
+                r#"{"status": "success", "data": {"genres": [{"id": 4, "name": "Drama"}]}}"#,
+            ),
+        ]));
+        provider::block_on(tvdb.login()).unwrap();
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config {
+            routes: vec![
+                Route {
+                    output: PathBuf::from("anime"),
+                    genres: vec!["anime".to_string()],
+                    ..Route::default()
+                },
+                Route {
+                    output: PathBuf::from("shared"),
+                    source: Some(PathBuf::from("shared")),
+                    ..Route::default()
+                },
+            ],
+            ..Config::default()
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Frieren.S01E01.mkv", b"1");
+        fs.add_file("downloads/Severance.S01E01.mkv", b"2");
+        fs.add_file("downloads/shared/Severance.S01E02.mkv", b"3");
+        let mut context = offline_context(&args, &config, &fs);
+        context.providers = vec![&tvdb];
+        context.routes = ["anime", "shared"]
+            .map(|output| Mutex::new(OutputRoots::new(&fs, vec![PathBuf::from(output)], args.placement)))
+            .into();
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new(
+            "anime/TV/Frieren - Beyond Journey's End/Season 1/Frieren - Beyond Journey's End - s01e01.mkv"
+        )));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+        assert!(fs.exists(Path::new("shared/TV/Severance/Season 1/Severance - s01e02.mkv")));
+    }
+
+    #[test]
+    fn files_named_while_the_provider_is_down_are_pending_metadata() {
+        use recording::request_key;
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[(
+            request_key("/search", &[("q", "Severance"), ("type", "series")]),
+            503,
+            "",
+        )]));
+        provider::block_on(tvdb.login()).unwrap();
+        let dir = env::temp_dir().join(format!("media-renamer-pending-{}", std::process::id()));
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"1");
+        let mut context = offline_context(&args, &config, &fs);
+        context.providers = vec![&tvdb];
+        context.journal = Some(Journal::create(&dir.join("history")).unwrap());
+
+        process_input(Path::new("downloads"), &context);
+
+        let operations = journal::load(context.journal.as_ref().unwrap().path());
+        fs::remove_dir_all(&dir).unwrap();
+        let destination = PathBuf::from("library/TV/Severance/Season 1/Severance - s01e01.mkv");
+        assert!(fs.exists(&destination));
+        assert!(operations.unwrap().contains(&Operation::PendingMetadata { path: destination }));
+    }
+
+    #[test]
+    fn titles_missing_from_the_primary_provider_are_found_by_the_fallbacks() {
+        use recording::request_key;
+
+        let responses = [
+            (
+                request_key("/search", &[("q", "Frieren"), ("type", "series")]),
+                200,
+                r#"{"status": "success", "data": []}"#,
+            ),
+            (
+                request_key("/search", &[("q", "Nowhere"), ("type", "series")]),
+                200,
+                r#"{"status": "success", "data": []}"#,
+            ),
+            (
+                request_key("/search/tv", &[("query", "Frieren")]),
+                200,
+                r#"{"page": 1, "results": [{"id": 209867, "name": "Frieren", "first_air_date": "2023-09-29"}]}"#,
+            ),
+            (request_key("/search/tv", &[("query", "Nowhere")]), 200, r#"{"page": 1, "results": []}"#),
+            (
+                request_key("/tv/209867/season/1/episode/1", &[]),
+                200,
+                r#"{"name": "The Journey's End", "season_number": 1, "episode_number": 1}"#,
+            ),
+        ];
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&responses));
+        provider::block_on(tvdb.login()).unwrap();
+        let tmdb = TmdbClient::new("").with_recording(Recording::replaying(&responses));
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Frieren.S01E01.mkv", b"1");
+        fs.add_file("downloads/Nowhere.S01E01.mkv", b"2");
+        let mut context = offline_context(&args, &config, &fs);
+        context.providers = vec![&tvdb, &tmdb];
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new(
+            "library/TV/Frieren/Season 1/Frieren - s01e01 - The Journey's End.mkv"
+        )));
+        assert!(fs.exists(Path::new("downloads/Nowhere.S01E01.mkv")));
+    }
+
+    #[test]
+    fn episodes_missing_from_season_packs_are_reported() {
+        use recording::request_key;
+
+        let episodes: Vec<String> = (1..=8)
+            .map(|number| format!(r#"{{"name": "Episode {0}", "season_number": 1, "episode_number": {0}}}"#, number))
+            .collect();
+        let season = format!(r#"{{"episodes": [{}]}}"#, episodes.join(", "));
+        let mut responses = vec![
+            (
+                request_key("/search/tv", &[("query", "Frieren")]),
+                200,
+                r#"{"page": 1, "results": [{"id": 209867, "name": "Frieren", "first_air_date": "2023-09-29"}]}"#,
+            ),
+            (request_key("/tv/209867/season/1", &[]), 200, season.as_str()),
+        ];
+        for (number, episode) in (1..).zip(&episodes) {
+            responses.push((request_key(&format!("/tv/209867/season/1/episode/{}", number), &[]), 200, episode));
+        }
+        let tmdb = TmdbClient::new("").with_recording(Recording::replaying(&responses));
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        for name in ["S01E01", "S01E02", "S01E03E04", "S01E06", "S01E08"] {
+            fs.add_file(format!("downloads/Frieren.S01/Frieren.{}.mkv", name), b"1");
+        }
+        let mut context = offline_context(&args, &config, &fs);
+        context.providers = vec![&tmdb];
+
+        process_input(Path::new("downloads"), &context);
+        finish(&context);
+
+        assert_eq!(context.summary.lock().unwrap().missing("Frieren"), [(1, vec![5, 7])]);
+    }
+
+    #[test]
+    fn releases_with_an_imdb_link_are_matched_by_remote_id() {
+        use recording::request_key;
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[(
+            request_key("/search/remoteid/tt1160419", &[]),
+            200,
+            r#"{"status": "success", "data": [{"movie": {"id": 1071, "name": "Dune", "year": "2021"}}]}"#,
+        )]));
+        provider::block_on(tvdb.login()).unwrap();
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Dune.Part.One.2021.1080p/Dune.Part.One.2021.1080p.mkv", b"1");
+        fs.add_file("downloads/Dune.Part.One.2021.1080p/group.nfo", b"https://www.imdb.com/title/tt1160419/");
+        let mut context = offline_context(&args, &config, &fs);
+        context.providers = vec![&tvdb];
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(Path::new("library/Movies/Dune (2021)/Dune (2021).mkv")));
+    }
+
+    #[test]
+    fn non_ascii_titles_round_trip() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/進撃の巨人.S01E01.mkv", b"1");
+        fs.add_file("downloads/進撃の巨人.S01E01.ja.srt", b"1");
+        fs.add_file("downloads/Бригада.S01E02.mkv", b"2");
+        fs.add_file("library/TV/БРИГАДА/Season 1/БРИГАДА - s01e01.mkv", b"0");
+
+        let path = env::temp_dir().join(format!("media-renamer-config-{}.toml", std::process::id()));
+        let config = Config {
+            tv_template: Template::parse("TV/{name}/Сезон {season}/{name} - s{season:02}e{episode:02}.{ext}").unwrap(),
+            ..Config::default()
+        };
+        let toml = format!("\u{feff}{}", toml::to_string(&config).unwrap());
+        fs::write(&path, toml).unwrap();
+        let config = load_config(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert!(fs.exists(Path::new("library/TV/進撃の巨人/Сезон 1/進撃の巨人 - s01e01.mkv")));
+        assert!(fs.exists(Path::new("library/TV/進撃の巨人/Сезон 1/進撃の巨人 - s01e01.ja.srt")));
+        // the existing folder is matched whatever the case of its Cyrillic name
+        assert!(fs.exists(Path::new("library/TV/БРИГАДА/Сезон 1/Бригада - s01e02.mkv")));
+    }
+
+    #[test]
+    fn undo_only_tells_what_it_would_revert_with_the_test_action() {
+        let dir = env::temp_dir().join(format!("media-renamer-undo-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, destination) = (dir.join("Severance.S01E01.mkv"), dir.join("Severance - s01e01.mkv"));
+        fs::write(&destination, "1").unwrap();
+        let path = dir.join("1.json");
+        let operations = [Operation::Move {
+            source: source.clone(),
+            destination: destination.clone(),
+        }];
+        fs::write(&path, serde_json::to_string(&operations).unwrap()).unwrap();
+        let journal = path.to_str().unwrap();
+
+        let tested = undo(Some(journal), &Args::parse_from(["media-renamer", "--pure-dry-run", "undo"]));
+        let untouched = destination.exists() && !source.exists() && path.exists();
+        let undone = undo(Some(journal), &Args::parse_from(["media-renamer", "-a", "move", "undo"]));
+        let moved_back = source.exists() && !destination.exists() && !path.exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tested, Outcome::Success);
+        assert!(untouched);
+        assert_eq!(undone, Outcome::Success);
+        assert!(moved_back);
+    }
+
+    #[test]
+    fn a_broken_configuration_stops_the_run_with_the_config_exit_code() {
+        let path = env::temp_dir().join(format!("media-renamer-broken-config-{}.toml", std::process::id()));
+        fs::write(&path, "tv_template = \"{name}/Season {season}\n").unwrap();
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "--config", path.to_str().unwrap()]);
+        let outcome = configure(&args).err();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(outcome, Some(Outcome::Config));
+        assert_eq!(ExitCode::from(Outcome::Config), ExitCode::from(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_skipped_with_a_reason() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        let invalid = Path::new("downloads").join(OsStr::from_bytes(b"Show.S01E01.\xff.mkv"));
+        let subtitle = Path::new("downloads").join(OsStr::from_bytes(b"Show.S01E02.\xff.srt"));
+        fs.add_file(&invalid, b"1");
+        fs.add_file(&subtitle, b"1");
+        fs.add_file("downloads/Show.S01E02.mkv", b"2");
+        let context = offline_context(&args, &config, &fs);
+
+        process_input(Path::new("downloads"), &context);
+
+        assert!(fs.exists(&invalid));
+        assert!(fs.exists(&subtitle));
+        assert!(fs.exists(Path::new("library/TV/Show/Season 1/Show - s01e02.mkv")));
+        let report = context.summary.lock().unwrap().report();
+        let reasons: Vec<_> = report
+            .iter()
+            .filter(|entry| entry.path == invalid || entry.path == subtitle)
+            .map(|entry| entry.reason)
+            .collect();
+        assert_eq!(reasons, vec![Some(SkipReason::NonUtf8); 2]);
+    }
+
+    #[test]
+    fn sizes_accept_binary_suffixes() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("500M"), Ok(500 * 1024 * 1024));
+        assert_eq!(parse_size("2g"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_size("lots").is_err());
+    }
+
+    #[test]
+    fn disc_images_and_streams_are_placed_like_videos() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "symlink"]);
+        let config = Config {
+            min_size: 1024,
+            ..Config::default()
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Dune.2021.1080p.BluRay.iso", &[0; 2048]);
+        fs.add_file("downloads/Severance.S01E01.strm", b"http://nas.local/severance/s01e01.mkv");
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert_eq!(
+            fs.node("library/Movies/Dune (2021)/Dune (2021).iso"),
+            Some(MemoryNode::Symlink(PathBuf::from("downloads/Dune.2021.1080p.BluRay.iso")))
+        );
+        // a stream keeps its address, copied rather than linked
+        assert_eq!(
+            fs.node("library/TV/Severance/Season 1/Severance - s01e01.strm"),
+            Some(MemoryNode::File(b"http://nas.local/severance/s01e01.mkv".to_vec()))
+        );
+    }
+
+    #[test]
+    fn seeding_files_are_linked_and_moved_once_done() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let client = TorrentClient {
+            seed_ratio: Some(1.0),
+            ..TorrentClient::default()
+        };
+        let torrent = |ratio: f64| torrent_client::Torrent {
+            name: "Severance.S01E01".to_string(),
+            content_path: PathBuf::from("downloads/Severance.S01E01.mkv"),
+            ratio,
+            seeding_time: 3600,
+            state: "uploading".to_string(),
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"seeding");
+        fs.add_file("downloads/Severance.S01E02.mkv", b"seeded");
+        let path = env::temp_dir().join(format!("media-renamer-seeding-{}.json", std::process::id()));
+        let mut context = Context {
+            deferred: Some(Deferred::open(&path).unwrap()),
+            seeding: Some(Seeding::new(Some(vec![torrent(0.5)]), &client)),
+            ..offline_context(&args, &config, &fs)
+        };
+
+        process_input(Path::new("downloads"), &context);
+        let season = Path::new("library/TV/Severance/Season 1");
+        assert!(fs.exists(Path::new("downloads/Severance.S01E01.mkv")));
+        assert!(fs.exists(&season.join("Severance - s01e01.mkv")));
+        assert!(!fs.exists(Path::new("downloads/Severance.S01E02.mkv")));
+        assert!(fs.exists(&season.join("Severance - s01e02.mkv")));
+
+        // still seeding on the next run
+        complete_deferred_moves(&context);
+        assert!(fs.exists(Path::new("downloads/Severance.S01E01.mkv")));
+
+        context.seeding = Some(Seeding::new(Some(vec![torrent(1.2)]), &client));
+        complete_deferred_moves(&context);
+        assert!(!fs.exists(Path::new("downloads/Severance.S01E01.mkv")));
+        assert_eq!(
+            fs.node(season.join("Severance - s01e01.mkv")),
+            Some(MemoryNode::File(b"seeding".to_vec()))
+        );
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn copies_keep_the_attributes_of_their_source_and_get_the_library_owner() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "copy"]);
+        let config = Config {
+            attributes: Attributes {
+                preserve: true,
+                owner: Some("1000".to_string()),
+                group: Some("1000".to_string()),
+                umask: Some(0o002),
+            },
+            ..Config::default()
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("library/TV/readme.txt", b"");
+        fs.add_file("downloads/Severance.S01E01.mkv", b"episode");
+        let downloaded = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        fs.set_modified("downloads/Severance.S01E01.mkv", downloaded);
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        let destination = Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv");
+        assert_eq!(fs.modified(destination).unwrap(), downloaded);
+        assert_eq!(fs.mode(destination), Some(0o664));
+        assert_eq!(fs.owner(destination), Some((Some(1000), Some(1000))));
+        // the directories created for the file too, not the ones that were there
+        assert_eq!(fs.mode("library/TV/Severance"), Some(0o775));
+        assert_eq!(fs.owner("library/TV/Severance/Season 1"), Some((Some(1000), Some(1000))));
+        assert_eq!(fs.owner("library/TV"), None);
+    }
+
+    #[test]
+    fn moves_to_another_filesystem_are_copies() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move", "--verify"]);
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"episode");
+        fs.set_device("library", 1);
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+        let destination = PathBuf::from("library/TV/Severance/Season 1/Severance - s01e01.mkv");
+        assert!(!fs.exists(Path::new("downloads/Severance.S01E01.mkv")));
+        assert_eq!(fs.node(&destination), Some(MemoryNode::File(b"episode".to_vec())));
+
+        let source = PathBuf::from("downloads/Severance.S01E01.mkv");
+        assert!(journal::undo(&fs, &[Operation::Move { source: source.clone(), destination: destination.clone() }]).is_empty());
+        assert_eq!(fs.node(&source), Some(MemoryNode::File(b"episode".to_vec())));
+        assert!(!fs.exists(&destination));
+    }
+
+    #[test]
+    fn runs_needing_more_than_the_free_space_place_nothing() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"episode 1");
+        fs.add_file("downloads/Severance.S01E02.mkv", b"episode 2");
+        fs.add_file("library/.keep", b"");
+        fs.set_available_space("library", 20);
+        let place = |action: &str, on_low_space| {
+            let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", action]);
+            let config = Config {
+                free_space_margin: 5,
+                on_low_space,
+                ..Config::default()
+            };
+            let context = offline_context(&args, &config, &fs);
+            process_input(Path::new("downloads"), &context);
+            let outcome = context.summary.lock().unwrap().outcome();
+            outcome
+        };
+        let season = Path::new("library/TV/Severance/Season 1");
+
+        assert_eq!(place("copy", LowSpace::Abort), Outcome::Failed);
+        assert!(!fs.exists(season));
+        // moves within a filesystem and links take no space
+        assert_eq!(place("hardlink", LowSpace::Abort), Outcome::Success);
+        assert!(fs.exists(&season.join("Severance - s01e02.mkv")));
+        fs.remove_file(&season.join("Severance - s01e01.mkv")).unwrap();
+        fs.remove_file(&season.join("Severance - s01e02.mkv")).unwrap();
+        assert_eq!(place("copy", LowSpace::Warn), Outcome::Success);
+        assert!(fs.exists(&season.join("Severance - s01e02.mkv")));
+    }
+
+    #[test]
+    fn unfinished_copies_are_resumed_or_removed() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01E01.mkv", b"episode 1");
+        fs.add_file("library/TV/Severance/Season 1/Severance - s01e01.mkv.media-renamer.partial", b"epi");
+        fs.add_file("library/TV/Dune/Season 1/Dune - s01e01.mkv.media-renamer.partial", b"stale");
+        let config = Config::default();
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "copy", "--resume"]);
+        let context = offline_context(&args, &config, &fs);
+        remove_partials(&context);
+        process_input(Path::new("downloads"), &context);
+        let season = Path::new("library/TV/Severance/Season 1");
+        assert_eq!(
+            fs.node(season.join("Severance - s01e01.mkv")),
+            Some(MemoryNode::File(b"episode 1".to_vec()))
+        );
+        assert!(!fs.exists(&season.join("Severance - s01e01.mkv.media-renamer.partial")));
+        assert!(fs.exists(Path::new("library/TV/Dune/Season 1/Dune - s01e01.mkv.media-renamer.partial")));
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "copy"]);
+        remove_partials(&offline_context(&args, &config, &fs));
+        assert!(!fs.exists(Path::new("library/TV/Dune/Season 1/Dune - s01e01.mkv.media-renamer.partial")));
+        assert!(fs.exists(&season.join("Severance - s01e01.mkv")));
+    }
+
+    #[test]
+    fn emptied_source_directories_are_cleaned() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config {
+            clean_sources: true,
+            ..Config::default()
+        };
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S01.1080p/Severance.S01E01.1080p/Severance.S01E01.1080p.mkv", b"episode");
+        fs.add_file("downloads/Severance.S01.1080p/Severance.S01E01.1080p/Severance.S01E01.1080p.nfo", b"nfo");
+        fs.add_file("downloads/Severance.S01.1080p/Sample/severance.sample.mkv", b"sample");
+        fs.add_file("downloads/Severance.S01.1080p/release.txt", b"release");
+        fs.add_file("downloads/Dune.2021.1080p/Dune.2021.1080p.mkv", b"movie");
+        fs.add_file("downloads/Dune.2021.1080p/Extras/interview.mkv", b"extra");
+        fs.add_file("downloads/notes.txt", b"notes");
+
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+        assert!(!fs.exists(Path::new("downloads/Severance.S01.1080p")));
+        assert!(fs.exists(Path::new("downloads/Dune.2021.1080p/Extras/interview.mkv")));
+        assert!(!fs.exists(Path::new("downloads/Dune.2021.1080p/Dune.2021.1080p.mkv")));
+        assert!(fs.exists(Path::new("downloads/notes.txt")));
+    }
+
+    #[test]
+    fn existing_destinations_follow_the_conflict_policy() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let season = Path::new("library/TV/Severance/Season 2");
+        let place = |policy, new: &[u8]| {
+            let fs = MemoryFs::new();
+            fs.add_file("downloads/Severance.S02E01.1080p.mkv", new);
+            fs.add_file(season.join("Severance - s02e01.mkv"), b"720p");
+            let config = Config {
+                on_conflict: policy,
+                ..Config::default()
+            };
+            process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+            fs.paths()
+                .into_iter()
+                .filter(|path| path.parent() == Some(season))
+                .map(|path| (path.clone(), fs.node(&path)))
+                .collect::<Vec<_>>()
+        };
+        let file = |name: &str, contents: &[u8]| (season.join(name), Some(MemoryNode::File(contents.to_vec())));
+
+        assert_eq!(place(ConflictPolicy::Skip, b"1080p"), vec![file("Severance - s02e01.mkv", b"720p")]);
+        assert_eq!(place(ConflictPolicy::Overwrite, b"480"), vec![file("Severance - s02e01.mkv", b"480")]);
+        assert_eq!(
+            place(ConflictPolicy::RenameWithSuffix, b"1080p"),
+            vec![
+                file("Severance - s02e01 (2).mkv", b"1080p"),
+                file("Severance - s02e01.mkv", b"720p"),
+            ]
+        );
+        assert_eq!(place(ConflictPolicy::KeepLarger, b"1080p"), vec![file("Severance - s02e01.mkv", b"1080p")]);
+        assert_eq!(place(ConflictPolicy::KeepLarger, b"480"), vec![file("Severance - s02e01.mkv", b"720p")]);
+    }
+
+    #[test]
+    fn replacements_of_a_suspicious_size_need_force() {
+        let config = Config {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Config::default()
+        };
+        let existing = Path::new("library/Movies/Dune (2021)/Dune (2021).mkv");
+        let place = |new: &str, force: bool| {
+            let mut args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+            args.force = force;
+            let fs = MemoryFs::new();
+            fs.add_file(Path::new("downloads").join(new), b"700 MB");
+            fs.add_file(existing, b"a 40 GB remux of the movie");
+            let context = Context {
+                originals: HashMap::from([(existing.to_path_buf(), PathBuf::from("downloads/Dune.2021.1080p.Remux.mkv"))]),
+                ..offline_context(&args, &config, &fs)
+            };
+            process_input(Path::new("downloads"), &context);
+            fs.node(existing)
+        };
+
+        assert_eq!(place("Dune.2021.1080p.mkv", false), Some(MemoryNode::File(b"a 40 GB remux of the movie".to_vec())));
+        assert_eq!(place("Dune.2021.1080p.mkv", true), Some(MemoryNode::File(b"700 MB".to_vec())));
+        // a better resolution explains the size
+        assert_eq!(place("Dune.2021.2160p.mkv", false), Some(MemoryNode::File(b"700 MB".to_vec())));
+    }
+
+    #[test]
+    fn existing_files_are_only_replaced_by_quality_upgrades() {
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config {
+            on_conflict: ConflictPolicy::Upgrade,
+            ..Config::default()
+        };
+        let existing = Path::new("library/TV/Severance/Season 2/Severance - s02e01.mkv");
+        let place = |new: &str| {
+            let fs = MemoryFs::new();
+            fs.add_file(Path::new("downloads").join(new), b"new file");
+            fs.add_file(existing, b"existing, and larger");
+            let context = Context {
+                originals: HashMap::from([(
+                    existing.to_path_buf(),
+                    PathBuf::from("old downloads/Severance.S02E01.720p.WEB-DL.mkv"),
+                )]),
+                ..offline_context(&args, &config, &fs)
+            };
+            process_input(Path::new("downloads"), &context);
+            fs.node(existing)
+        };
+
+        assert_eq!(place("Severance.S02E01.1080p.WEB-DL.mkv"), Some(MemoryNode::File(b"new file".to_vec())));
+        assert_eq!(place("Severance.S02E01.720p.BluRay.mkv"), Some(MemoryNode::File(b"new file".to_vec())));
+        assert_eq!(
+            place("Severance.S02E01.720p.HDTV.mkv"),
+            Some(MemoryNode::File(b"existing, and larger".to_vec()))
+        );
+        // without tags the larger file is kept
+        assert_eq!(
+            place("Severance.S02E01.mkv"),
+            Some(MemoryNode::File(b"existing, and larger".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_action_and_existing_destination_change_nothing() {
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Severance.S02E01.1080p.mkv", b"new");
+        fs.add_file("library/TV/Severance/Season 2/Severance - s02e01.mkv", b"old");
+        let before = fs.paths();
+
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library"]);
+        process_input(Path::new("downloads"), &offline_context(&args, &config, &fs));
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "copy"]);
+        let result = process_file(
+            Path::new("downloads/Severance.S02E01.1080p.mkv"),
+            &offline_context(&args, &config, &fs),
+        );
+
+        assert!(matches!(result, Err(Error::AlreadyExists { .. })));
+        assert_eq!(fs.paths(), before);
+        assert_eq!(
+            fs.node("library/TV/Severance/Season 2/Severance - s02e01.mkv"),
+            Some(MemoryNode::File(b"old".to_vec()))
+        );
+    }
+}
@@ -1,90 +1,533 @@
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
 
-use crate::tvdb::{TvdbClient, TvdbError};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    interactive::Prompt,
+    overrides::EpisodeOrder,
+    parsed::ParsedMedia,
+    query::{search_name, title_words},
+    provider::{imdb_id, MetadataProvider, ProviderError, Search, SearchResult},
+    Config,
+};
+
+/// How the results of a name search are chosen from
+pub struct Matching<'a> {
+    /// How many results of every search are logged
+    pub candidates: usize,
+    /// The confidence the best result needs to be matched to
+    pub min_confidence: f64,
+    /// The results within this much confidence of the best one are too close to tell apart
+    pub ambiguity_margin: f64,
+    /// Asked to choose between results too close to tell apart, the best one is taken without it
+    pub prompt: Option<&'a dyn Prompt>,
+}
+
+/// The outcome of a name search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameMatch {
+    Found,
+    NotFound,
+    /// The user chose none of the results
+    Declined,
+}
 
 #[derive(Debug)]
 pub struct MediaFile {
     name: String,
     extension: String,
     media_data: MediaData,
+    metadata: Metadata,
+    /// The title of the episode, when the provider knows it
+    episode_title: Option<String>,
+    /// The content rating of the series or movie in the rating country, when it was looked up
+    content_rating: Option<String>,
+    /// The genres of the series or movie, when they were looked up
+    genres: Vec<String>,
+    /// Whether the name was taken from search results too close to tell apart, without the user choosing
+    uncertain: bool,
+    /// Everything read from the filename, as it was before matching
+    parsed: ParsedMedia,
+}
+
+/// What the metadata provider knows about the series or movie
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub tvdb_id: Option<u64>,
+    pub tmdb_id: Option<u64>,
+    pub tvmaze_id: Option<u64>,
+    /// The number of the IMDb id, `tt0111161` is 111161
+    pub imdb_id: Option<u64>,
+    /// The year the series started or the movie was released
+    pub year: Option<u32>,
 }
 
 impl MediaFile {
+    #[cfg(test)]
     pub fn new(name: String, media_data: MediaData, extension: String) -> Self {
+        Self::from_parsed(ParsedMedia::new(name, media_data), extension)
+    }
+
+    /// The file as the parser read it, named and numbered after its filename until it is matched
+    pub fn from_parsed(parsed: ParsedMedia, extension: String) -> Self {
         Self {
-            name,
+            name: parsed.title.clone(),
             extension,
-            media_data,
+            media_data: parsed.media,
+            metadata: Metadata::default(),
+            episode_title: None,
+            content_rating: None,
+            genres: Vec::new(),
+            uncertain: false,
+            parsed,
         }
     }
 
+    /// Everything read from the filename, like the tags of the release
+    pub fn parsed(&self) -> &ParsedMedia {
+        &self.parsed
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    /// Replaces the name of the series or movie, the metadata of the old name no longer applies
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+        self.metadata = Metadata::default();
+        self.episode_title = None;
+        self.content_rating = None;
+        self.genres.clear();
+        self.uncertain = false;
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = metadata;
+    }
+
+    /// Whether the name was taken from search results too close to tell apart, without the user choosing
+    pub fn is_uncertain(&self) -> bool {
+        self.uncertain
+    }
+
     pub fn media(&self) -> &MediaData {
         &self.media_data
     }
 
+    #[allow(dead_code)]
     pub fn extension(&self) -> &str {
         &self.extension
     }
 
+    /// The title the file is grouped under: the series name, or the movie name with its year
+    pub fn title(&self) -> String {
+        match &self.media_data {
+            MediaData::TvSeries { .. } | MediaData::Daily { .. } | MediaData::Absolute { .. } => self.name.clone(),
+            MediaData::Movie { year } => format!("{} ({})", &self.name, year),
+        }
+    }
+
     pub fn media_type(&self) -> MediaType {
         match self.media_data {
-            MediaData::TvSeries { .. } => MediaType::Series,
+            MediaData::TvSeries { .. } | MediaData::Daily { .. } | MediaData::Absolute { .. } => MediaType::Series,
             MediaData::Movie { .. } => MediaType::Movie,
         }
     }
 
-    pub fn request_name(&mut self, tvdb: &TvdbClient) -> Result<bool, TvdbError> {
-        let media_type = match self.media_data {
-            MediaData::TvSeries { .. } => MediaType::Series,
-            MediaData::Movie { .. } => MediaType::Movie,
-        };
+    /// The search the name is looked up with first, with the year of a movie
+    pub fn search(&self) -> Search {
+        Search {
+            name: search_name(&self.name).to_string(),
+            media_type: self.media_type(),
+            year: match self.media_data {
+                MediaData::Movie { year } => Some(year),
+                _ => None,
+            },
+        }
+    }
 
-        let results = tvdb.search(&self.name, media_type)?;
+    /// Searches the parsed name and takes the name of the result with the highest confidence,
+    /// logging the first `candidates` results so that a mismatch can be told apart from the right
+    /// choice. When other results come close to it the prompt chooses between them
+    pub fn request_name(
+        &mut self,
+        provider: &dyn MetadataProvider,
+        matching: &Matching,
+    ) -> Result<NameMatch, ProviderError> {
+        let searched = search_name(&self.name);
+        // the year of a movie tells apart remakes sharing its name, but a year off by one in the
+        // release name would find nothing, so the name is then searched alone
+        let year = self.search().year;
+        let mut results = provider.search(searched, self.media_type(), year)?;
+        if results.is_empty() && year.is_some() {
+            results = provider.search(searched, self.media_type(), None)?;
+        }
 
-        if let Some(result) = results.first() {
-            self.name = result.name.clone();
-        } else {
-            return Ok(false);
+        debug!("{} results for {}", results.len(), searched);
+        for (rank, result) in results.iter().take(matching.candidates).enumerate() {
+            debug!(
+                "  {}. {} ({}) id {} confidence {:.2}",
+                rank + 1,
+                result.name,
+                result.year.map_or("unknown year".to_string(), |year| year.to_string()),
+                result.id.map_or("unknown".to_string(), |id| id.to_string()),
+                self.confidence(searched, result)
+            );
         }
 
+        let Some((mut result, confidence)) = self.best_result(searched, &results) else {
+            return Ok(NameMatch::NotFound);
+        };
+        if confidence < matching.min_confidence {
+            info!(
+                "The best result for {} is {} with a confidence of {:.2}, below the {:.2} of min_confidence",
+                searched, result.name, confidence, matching.min_confidence
+            );
+            return Ok(NameMatch::NotFound);
+        }
+
+        let mut close = vec![result];
+        close.extend(results.iter().filter(|other| {
+            !std::ptr::eq(*other, result) && self.confidence(searched, other) >= confidence - matching.ambiguity_margin
+        }));
+        let mut uncertain = false;
+        if close.len() > 1 {
+            match matching.prompt {
+                Some(prompt) => match prompt.pick(searched, &close) {
+                    Some(index) => result = close[index],
+                    None => return Ok(NameMatch::Declined),
+                },
+                None => {
+                    warn!(
+                        "{} could be {}, taking {}",
+                        searched,
+                        close
+                            .iter()
+                            .map(|result| result.to_string())
+                            .collect::<Vec<_>>()
+                            .join(" or "),
+                        result
+                    );
+                    uncertain = true;
+                }
+            }
+        }
+        self.name = result.name.clone();
+        self.set_metadata(provider.kind().metadata(result.id, result.year));
+        self.uncertain = uncertain;
+
+        Ok(NameMatch::Found)
+    }
+
+    /// The result the file is matched to with its confidence, the one with the highest. Ties go to
+    /// the first result, as ranked by the provider
+    fn best_result<'r>(&self, searched: &str, results: &'r [SearchResult]) -> Option<(&'r SearchResult, f64)> {
+        results
+            .iter()
+            .map(|result| (result, self.confidence(searched, result)))
+            .reduce(|best, scored| if scored.1 > best.1 { scored } else { best })
+    }
+
+    /// How likely the result is the searched series or movie, from 0 to 1. The name counts the most;
+    /// for movies the distance to the year of the release counts too, so that the right sequel or
+    /// remake is chosen. The media type is not scored, providers are only searched for the type of
+    /// the file
+    fn confidence(&self, searched: &str, result: &SearchResult) -> f64 {
+        let name = name_similarity(searched, &result.name);
+        let MediaData::Movie { year } = self.media_data else {
+            return name;
+        };
+        let year = match result.year {
+            Some(result_year) => 1.0 - (result_year.abs_diff(year).min(4) as f64 * 0.25),
+            None => 0.5,
+        };
+        0.8 * name + 0.2 * year
+    }
+
+    /// Takes the name of the series or movie with a known id instead of searching for it
+    pub fn request_by_id(&mut self, provider: &dyn MetadataProvider, id: u64) -> Result<(), ProviderError> {
+        let record = provider.record(id, self.media_type())?;
+        self.name = record.name;
+        self.set_metadata(provider.kind().metadata(Some(record.id), record.year));
+        Ok(())
+    }
+
+    /// Matches the file to the series or movie with the IMDb id, e.g. from the `.nfo` of a release.
+    /// Returns whether the provider knows the id
+    pub fn request_by_imdb_id(&mut self, provider: &dyn MetadataProvider, imdb_id: u64) -> Result<bool, ProviderError> {
+        let Some(record) = provider.find_by_imdb_id(imdb_id, self.media_type())? else {
+            return Ok(false);
+        };
+        self.name = record.name;
+        let mut metadata = provider.kind().metadata(Some(record.id), record.year);
+        metadata.imdb_id = Some(imdb_id);
+        self.set_metadata(metadata);
         Ok(true)
     }
 
-    pub fn get_path(&self) -> PathBuf {
-        let mut path = PathBuf::new();
+    pub fn content_rating(&self) -> Option<&str> {
+        self.content_rating.as_deref()
+    }
 
-        match &self.media_data {
-            MediaData::TvSeries { season, episode } => {
-                path.push("TV");
-                path.push(&self.name);
-                path.push(format!("Season {}", season));
-                path.push(format!(
-                    "{} - s{:0>2}e{:0>2}.{}",
-                    &self.name, season, episode, &self.extension
-                ));
+    /// Looks up the content rating of the series or movie in the country, e.g. `usa`, once it is
+    /// matched by the provider
+    pub fn request_content_rating(&mut self, provider: &dyn MetadataProvider, country: &str) -> Result<(), ProviderError> {
+        let Some(id) = provider.kind().id(&self.metadata) else {
+            return Ok(());
+        };
+
+        self.content_rating = provider
+            .content_ratings(id, self.media_type())?
+            .into_iter()
+            .find(|rating| rating.country.eq_ignore_ascii_case(country))
+            .map(|rating| rating.name);
+        debug!("Content rating {:?}", self.content_rating);
+        Ok(())
+    }
+
+    pub fn genres(&self) -> &[String] {
+        &self.genres
+    }
+
+    /// Looks up the genres of the series or movie once it is matched by the provider
+    pub fn request_genres(&mut self, provider: &dyn MetadataProvider) -> Result<(), ProviderError> {
+        let Some(id) = provider.kind().id(&self.metadata) else {
+            return Ok(());
+        };
+
+        self.genres = provider.genres(id, self.media_type())?;
+        debug!("Genres {:?}", self.genres);
+        Ok(())
+    }
+
+    /// Looks up the episode once the series is matched by the provider: its title and, for an
+    /// episode named by its air date or absolute number, its season and number. Releases numbered
+    /// in another order than the aired one are given the aired season and number. Movies and
+    /// series without an id are left as they are
+    pub fn request_episode(&mut self, provider: &dyn MetadataProvider, order: EpisodeOrder) -> Result<(), ProviderError> {
+        let Some(series_id) = provider.kind().id(&self.metadata) else {
+            return Ok(());
+        };
+
+        if let (EpisodeOrder::Absolute, MediaData::TvSeries { episode, .. }) = (order, &self.media_data) {
+            self.media_data = MediaData::Absolute { episode: *episode };
+        }
+
+        let found = match self.media_data {
+            MediaData::TvSeries { season, episode } if order == EpisodeOrder::Dvd => {
+                let found = provider.episode_in_dvd_order(series_id, season, episode)?;
+                match &found {
+                    Some(found) if (found.season_number, found.number) != (season, episode) => {
+                        warn!(
+                            "{} s{:0>2}e{:0>2} in DVD order is s{:0>2}e{:0>2} as aired",
+                            self.name, season, episode, found.season_number, found.number
+                        );
+                        self.media_data = MediaData::TvSeries {
+                            season: found.season_number,
+                            episode: found.number,
+                        };
+                    }
+                    Some(_) => {}
+                    None => warn!(
+                        "{} has no DVD order on {}, keeping s{:0>2}e{:0>2}",
+                        self.name,
+                        provider.kind(),
+                        season,
+                        episode
+                    ),
+                }
+                found
+            }
+            MediaData::TvSeries { season, episode } => provider
+                .episodes(series_id, season, episode)?
+                .into_iter()
+                .find(|found| found.season_number == season && found.number == episode),
+            MediaData::Daily { .. } => {
+                let air_date = self.air_date().unwrap_or_default();
+                let mut episodes = provider.episodes_aired(series_id, &air_date)?;
+                // specials aired the same day come last
+                episodes.sort_by_key(|found| found.season_number == 0);
+                let found = episodes.into_iter().next();
+                match &found {
+                    Some(found) => {
+                        debug!("Episode aired {} is s{:0>2}e{:0>2}", air_date, found.season_number, found.number);
+                        self.media_data = MediaData::TvSeries {
+                            season: found.season_number,
+                            episode: found.number,
+                        };
+                    }
+                    None => debug!("No episode aired {}", air_date),
+                }
+                found
             }
-            MediaData::Movie { year } => {
-                path.push("Movies");
-                path.push(format!("{} ({})", &self.name, year));
-                path.push(format!("{} ({}).{}", &self.name, year, &self.extension));
+            MediaData::Absolute { episode } => {
+                let found = provider.episode_by_absolute_number(series_id, episode)?;
+                match &found {
+                    Some(found) => {
+                        debug!("Absolute episode {} is s{:0>2}e{:0>2}", episode, found.season_number, found.number);
+                        self.media_data = MediaData::TvSeries {
+                            season: found.season_number,
+                            episode: found.number,
+                        };
+                    }
+                    None => debug!("No episode with absolute number {}", episode),
+                }
+                found
             }
+            MediaData::Movie { .. } => return Ok(()),
+        };
+
+        self.episode_title = found
+            .and_then(|found| found.name)
+            .filter(|name| !name.trim().is_empty());
+        debug!("Episode title {:?}", self.episode_title);
+        Ok(())
+    }
+
+    /// The air date of an episode named by it, as `YYYY-MM-DD`
+    fn air_date(&self) -> Option<String> {
+        match self.media_data {
+            MediaData::Daily { year, month, day } => Some(format!("{:04}-{:02}-{:02}", year, month, day)),
+            _ => None,
         }
+    }
 
-        path
+    pub fn get_path(&self, config: &Config) -> PathBuf {
+        let (folder, path_in_folder) = self.render(config);
+        folder.join(path_in_folder)
+    }
+
+    /// The folder of the series or movie in the library, relative to the output directory
+    pub fn folder(&self, config: &Config) -> PathBuf {
+        self.render(config).0
+    }
+
+    /// The path of the file relative to the folder of its series or movie
+    pub fn path_in_folder(&self, config: &Config) -> PathBuf {
+        self.render(config).1
+    }
+
+    fn render(&self, config: &Config) -> (PathBuf, PathBuf) {
+        let template = match self.media_data {
+            MediaData::TvSeries { .. } | MediaData::Absolute { .. } => &config.tv_template,
+            MediaData::Daily { .. } => &config.daily_template,
+            MediaData::Movie { .. } => &config.movie_template,
+        };
+        template.render(|field| self.field(field))
+    }
+
+    /// The value of a naming template field for this file
+    fn field(&self, field: &str) -> Option<String> {
+        match (field, &self.media_data) {
+            ("name", _) => Some(self.name.clone()),
+            ("year", MediaData::Movie { year }) => Some(year.to_string()),
+            ("year", MediaData::TvSeries { .. } | MediaData::Daily { .. } | MediaData::Absolute { .. }) => {
+                self.metadata.year.map(|year| year.to_string())
+            }
+            ("season", MediaData::TvSeries { season, .. }) => Some(season.to_string()),
+            // date-based shows are organized in a season per year
+            ("season", MediaData::Daily { year, .. }) => Some(year.to_string()),
+            ("episode", MediaData::TvSeries { episode, .. }) => Some(episode.to_string()),
+            // until the season is known the episode is placed in the single season of absolute ordering
+            ("season", MediaData::Absolute { .. }) => Some("1".to_string()),
+            ("episode", MediaData::Absolute { episode }) => Some(episode.to_string()),
+            ("air_date", MediaData::Daily { .. }) => self.air_date(),
+            ("episode_title", _) => self.episode_title.clone(),
+            ("ext", _) => Some(self.extension.clone()),
+            ("tvdb_id", _) => self.metadata.tvdb_id.map(|id| id.to_string()),
+            ("tmdb_id", _) => self.metadata.tmdb_id.map(|id| id.to_string()),
+            ("imdb_id", _) => self.metadata.imdb_id.map(imdb_id),
+            ("resolution", _) => self.parsed.resolution.map(|resolution| format!("{}p", resolution)),
+            ("source", _) => self.parsed.source.map(str::to_string),
+            ("codec", _) => self.parsed.codec.map(str::to_string),
+            ("group", _) => self.parsed.group.clone(),
+            ("edition", _) => self.parsed.edition.map(str::to_string),
+            ("part", _) => self.parsed.part.map(|part| part.to_string()),
+            ("language", _) => self.parsed.language.clone(),
+            _ => None,
+        }
     }
 }
 
-#[derive(Debug)]
+/// How similar a result name is to the searched name, from 0 to 1: the share of their words in
+/// common once punctuation variants and sequel numbers are normalized
+pub fn name_score(searched: &str, result: &str) -> f64 {
+    let words = |name: &str| title_words(name).into_iter().collect::<HashSet<_>>();
+    let searched = words(searched);
+    let result = words(result);
+    let all = searched.union(&result).count();
+    if all == 0 {
+        return 0.0;
+    }
+    searched.intersection(&result).count() as f64 / all as f64
+}
+
+/// How similar a result name is to the searched name, from 0 to 1: the share of their words in
+/// common, or the Jaro-Winkler similarity of the normalized names when higher, so that a typo or
+/// a missing space still matches
+pub fn name_similarity(searched: &str, result: &str) -> f64 {
+    let searched_words = title_words(searched).join(" ");
+    let result_words = title_words(result).join(" ");
+    name_score(searched, result).max(jaro_winkler(&searched_words, &result_words))
+}
+
+/// The Jaro similarity of the two strings, raised for a common prefix of up to 4 characters
+fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0;
+    for (i, c) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == *c {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+    if matches == 0 {
+        return 0.0;
+    }
+
+    let a_order = a.iter().zip(&a_matched).filter(|(_, matched)| **matched);
+    let b_order = b.iter().zip(&b_matched).filter(|(_, matched)| **matched);
+    let transpositions = a_order.zip(b_order).filter(|((a, _), (b, _))| a != b).count() / 2;
+
+    let matches = matches as f64;
+    let jaro = (matches / a.len() as f64 + matches / b.len() as f64 + (matches - transpositions as f64) / matches) / 3.0;
+    let prefix = a.iter().zip(&b).take(4).take_while(|(a, b)| a == b).count();
+    jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MediaData {
     TvSeries { season: u32, episode: u32 },
+    /// An episode of a daily show named by its air date, until its season and number are known
+    Daily { year: u32, month: u32, day: u32 },
+    /// An episode numbered from the first one of the series, like anime releases, until its
+    /// season and number are known
+    Absolute { episode: u32 },
     Movie { year: u32 },
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MediaType {
     Movie,
     Series,
@@ -98,3 +541,286 @@ impl From<MediaType> for &str {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{provider, tvdb::TvdbClient};
+
+    use super::*;
+
+    #[test]
+    fn name_score_is_the_share_of_common_words() {
+        assert_eq!(name_score("Severance", "Severance"), 1.0);
+        assert_eq!(name_score("The Office US", "The Office"), 2.0 / 3.0);
+        assert_eq!(name_score("Paradise", "Severance"), 0.0);
+        assert_eq!(name_score("Law and Order", "Law & Order"), 1.0);
+    }
+
+    #[test]
+    fn similar_names_score_high_and_others_low() {
+        assert_eq!(name_similarity("Severance", "Severance"), 1.0);
+        assert!(name_similarity("Severence", "Severance") > 0.9);
+        assert!(name_similarity("Spider Man", "Spider-Man") > 0.9);
+        assert!(name_similarity("Paradise", "Severance") < 0.6);
+        assert!(name_similarity("Dune", "The Expanse") < 0.6);
+    }
+
+    #[test]
+    fn movies_prefer_the_sequel_of_their_year() {
+        let result = |name: &str, year: u32| SearchResult {
+            name: name.to_string(),
+            id: None,
+            year: Some(year),
+        };
+        let results = vec![
+            result("The Godfather", 1972),
+            result("The Godfather Part III", 1990),
+            result("The Godfather Part II", 1974),
+        ];
+        let movie = MediaFile::new(
+            "The Godfather 2".to_string(),
+            MediaData::Movie { year: 1974 },
+            "mkv".to_string(),
+        );
+
+        assert_eq!(movie.best_result("The Godfather 2", &results).unwrap().0.name, "The Godfather Part II");
+    }
+
+    fn matching(prompt: Option<&dyn Prompt>) -> Matching<'_> {
+        Matching {
+            candidates: 5,
+            min_confidence: 0.6,
+            ambiguity_margin: 0.05,
+            prompt,
+        }
+    }
+
+    /// Picks the result of the given index, refusing to be asked anything else
+    struct PickPrompt(Option<usize>);
+
+    impl Prompt for PickPrompt {
+        fn confirm(&self, _: &std::path::Path, _: &std::path::Path) -> crate::interactive::Decision {
+            unreachable!()
+        }
+
+        fn resolve_conflict(
+            &self,
+            _: &std::path::Path,
+            _: &crate::interactive::FileInfo,
+            _: &std::path::Path,
+            _: &crate::interactive::FileInfo,
+        ) -> crate::interactive::ConflictAnswer {
+            unreachable!()
+        }
+
+        fn pick(&self, _searched: &str, results: &[&SearchResult]) -> Option<usize> {
+            assert_eq!(results.len(), 2);
+            self.0
+        }
+    }
+
+    #[test]
+    fn close_results_are_picked_by_the_user() {
+        use crate::recording::{request_key, Recording};
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[(
+            request_key("/search", &[("q", "Shameless"), ("type", "series")]),
+            200,
+            r#"{"status": "success", "data": [
+                {"name": "Shameless", "tvdb_id": "79460", "year": "2004"},
+                {"name": "Shameless (US)", "tvdb_id": "161511", "year": "2011"},
+                {"name": "Shamless Hearts", "tvdb_id": "1", "year": "2020"}
+            ]}"#,
+        )]));
+        provider::block_on(tvdb.login()).unwrap();
+        let episode = || {
+            MediaFile::new(
+                "Shameless".to_string(),
+                MediaData::TvSeries { season: 1, episode: 1 },
+                "mkv".to_string(),
+            )
+        };
+
+        let mut best = episode();
+        let found = best.request_name(&tvdb, &matching(None));
+        let mut picked = episode();
+        let picked_found = picked.request_name(&tvdb, &matching(Some(&PickPrompt(Some(1)))));
+        let mut declined = episode();
+        let declined_found = declined.request_name(&tvdb, &matching(Some(&PickPrompt(None))));
+
+        assert_eq!(found.unwrap(), NameMatch::Found);
+        assert_eq!(best.metadata().tvdb_id, Some(79460));
+        assert_eq!(picked_found.unwrap(), NameMatch::Found);
+        assert_eq!(picked.metadata().tvdb_id, Some(161511));
+        assert_eq!(declined_found.unwrap(), NameMatch::Declined);
+    }
+
+    #[test]
+    fn movies_are_searched_by_year_then_by_name_alone() {
+        use crate::recording::{request_key, Recording};
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[
+            (
+                request_key("/search", &[("q", "Nosferatu"), ("type", "movie"), ("year", "2025")]),
+                200,
+                r#"{"status": "success", "data": []}"#,
+            ),
+            (
+                request_key("/search", &[("q", "Nosferatu"), ("type", "movie")]),
+                200,
+                r#"{"status": "success", "data": [
+                    {"name": "Nosferatu", "tvdb_id": "1234", "year": "2024"}
+                ]}"#,
+            ),
+        ]));
+        provider::block_on(tvdb.login()).unwrap();
+
+        let mut movie = MediaFile::new(
+            "Nosferatu".to_string(),
+            MediaData::Movie { year: 2025 },
+            "mkv".to_string(),
+        );
+        let found = movie.request_name(&tvdb, &matching(None)).unwrap();
+
+        assert_eq!(found, NameMatch::Found);
+        assert_eq!(movie.metadata().tvdb_id, Some(1234));
+    }
+
+    #[test]
+    fn daily_episodes_are_resolved_by_air_date() {
+        use crate::recording::{request_key, Recording};
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[(
+            request_key("/series/71256/episodes/default", &[("airDate", "2024-03-12")]),
+            200,
+            r#"{"status": "success", "data": {"episodes": [
+                {"name": "Behind the Scenes", "seasonNumber": 0, "number": 12},
+                {"name": "Jon Stewart", "seasonNumber": 29, "number": 34}
+            ]}}"#,
+        )]));
+        provider::block_on(tvdb.login()).unwrap();
+
+        let mut episode = MediaFile::new(
+            "The Daily Show".to_string(),
+            MediaData::Daily { year: 2024, month: 3, day: 12 },
+            "mkv".to_string(),
+        );
+        episode.set_metadata(Metadata {
+            tvdb_id: Some(71256),
+            year: Some(1996),
+            ..Metadata::default()
+        });
+        episode.request_episode(&tvdb, EpisodeOrder::Aired).unwrap();
+
+        assert!(matches!(episode.media(), MediaData::TvSeries { season: 29, episode: 34 }));
+        assert_eq!(
+            episode.get_path(&Config::default()),
+            PathBuf::from("TV/The Daily Show/Season 29/The Daily Show - s29e34 - Jon Stewart.mkv")
+        );
+    }
+
+    #[test]
+    fn dvd_order_is_mapped_to_the_aired_one() {
+        use crate::recording::{request_key, Recording};
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[
+            (
+                request_key("/series/78874/episodes/dvd", &[("season", "1"), ("episodeNumber", "2")]),
+                200,
+                r#"{"status": "success", "data": {"episodes": [
+                    {"id": 297989, "name": "The Train Job", "seasonNumber": 1, "number": 2}
+                ]}}"#,
+            ),
+            (
+                request_key("/episodes/297989", &[]),
+                200,
+                r#"{"status": "success", "data":
+                    {"id": 297989, "name": "The Train Job", "seasonNumber": 1, "number": 1}
+                }"#,
+            ),
+        ]));
+        provider::block_on(tvdb.login()).unwrap();
+
+        let mut episode = MediaFile::new(
+            "Firefly".to_string(),
+            MediaData::TvSeries { season: 1, episode: 2 },
+            "mkv".to_string(),
+        );
+        episode.set_metadata(Metadata {
+            tvdb_id: Some(78874),
+            ..Metadata::default()
+        });
+        episode.request_episode(&tvdb, EpisodeOrder::Dvd).unwrap();
+
+        assert_eq!(
+            episode.get_path(&Config::default()),
+            PathBuf::from("TV/Firefly/Season 1/Firefly - s01e01 - The Train Job.mkv")
+        );
+    }
+
+    #[test]
+    fn absolute_numbers_are_mapped_to_seasons() {
+        use crate::recording::{request_key, Recording};
+
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&[
+            (
+                request_key("/series/424536/episodes/default", &[("page", "0")]),
+                200,
+                r#"{"status": "success", "data": {"episodes": [
+                    {"name": "Recap", "seasonNumber": 0, "number": 1, "absoluteNumber": 28},
+                    {"name": "The Journey's End", "seasonNumber": 1, "number": 1, "absoluteNumber": 1}
+                ]}, "links": {"next": "https://api4.thetvdb.com/v4/series/424536/episodes/default?page=1"}}"#,
+            ),
+            (
+                request_key("/series/424536/episodes/default", &[("page", "1")]),
+                200,
+                r#"{"status": "success", "data": {"episodes": [
+                    {"name": "It Would Be Embarrassing When We Met Again", "seasonNumber": 2, "number": 0, "absoluteNumber": 27},
+                    {"name": "The First-Class Mage Exam", "seasonNumber": 2, "number": 2, "absoluteNumber": 28}
+                ]}, "links": {"next": null}}"#,
+            ),
+        ]));
+        provider::block_on(tvdb.login()).unwrap();
+
+        let mut episode = MediaFile::new(
+            "Frieren".to_string(),
+            MediaData::Absolute { episode: 28 },
+            "mkv".to_string(),
+        );
+        episode.set_metadata(Metadata {
+            tvdb_id: Some(424536),
+            year: Some(2023),
+            ..Metadata::default()
+        });
+        episode.request_episode(&tvdb, EpisodeOrder::Aired).unwrap();
+
+        assert!(matches!(episode.media(), MediaData::TvSeries { season: 2, episode: 2 }));
+        assert_eq!(
+            episode.get_path(&Config::default()),
+            PathBuf::from("TV/Frieren/Season 2/Frieren - s02e02 - The First-Class Mage Exam.mkv")
+        );
+    }
+
+    #[test]
+    fn episode_titles_are_part_of_the_filename() {
+        let config = Config::default();
+        let mut episode = MediaFile::new(
+            "Severance".to_string(),
+            MediaData::TvSeries { season: 1, episode: 4 },
+            "mkv".to_string(),
+        );
+        assert_eq!(episode.path_in_folder(&config), PathBuf::from("Season 1/Severance - s01e04.mkv"));
+
+        episode.episode_title = Some("The You You Are".to_string());
+        assert_eq!(
+            episode.path_in_folder(&config),
+            PathBuf::from("Season 1/Severance - s01e04 - The You You Are.mkv")
+        );
+
+        episode.episode_title = Some("Before/After".to_string());
+        assert_eq!(
+            episode.path_in_folder(&config),
+            PathBuf::from("Season 1/Severance - s01e04 - Before-After.mkv")
+        );
+    }
+}
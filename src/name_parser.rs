@@ -1,107 +1,372 @@
-use std::path::Path;
+use std::{path::Path, sync::LazyLock};
 
-use log::{debug, warn};
+use log::{debug, info, warn};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    embedded,
+    filesystem::Fs,
     media::{MediaData, MediaFile},
-    path_utils::{get_extension, get_filestem},
+    parsed::ParsedMedia,
+    path_utils::{get_extension, get_filestem, normalize_whitespace},
     Config,
 };
 
+/// How many directories above a file are searched for the series and season of a pack,
+/// e.g. `Show Complete S01-S05/S03/Extras/E02.mkv`
+const MAX_PACK_DEPTH: usize = 3;
+
+/// A season range like `S01-S05`, marking a pack of several seasons rather than a single one
+static SEASON_RANGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bs(?:eason)?\s*[0-9]+\s*-\s*s?(?:eason)?\s*[0-9]+\b").unwrap());
+/// A directory holding a single season, like `S03`, `Season 3` or `Show S03`
+static SEASON_DIR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)(?:^|\s)(?:season\s*|s)([0-9]{1,3})$").unwrap());
+/// Where the series name ends in the name of a pack directory
+static PACK_MARKER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\s*(?:\bcomplete\b|\bseasons?\s*[0-9]|\bs[0-9]{1,3}\b|\(|\[).*$").unwrap()
+});
+/// Files named only after their episode inside a pack, like `S03E02`, `E02` or `Episode 2`
+static EPISODE_ONLY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(?:s(?<season>[0-9]{1,3})\s*)?(?:e|ep|episode\s*)(?<episode>[0-9]{1,4})\b").unwrap()
+});
+
+/// Patterns release names commonly use beyond the configured regexes, tried by the heuristics
+/// fallback: `Show 1x02`, `Show Season 1 Episode 2` and a movie ending with its year, as in `Movie (2020)`
+static HEURISTICS: LazyLock<[Regex; 3]> = LazyLock::new(|| {
+    [
+        Regex::new(r"(?i)^(?<name>.+?) (?<season>[0-9]{1,2})x(?<episode>[0-9]{2,3})\b").unwrap(),
+        Regex::new(r"(?i)^(?<name>.+?) season (?<season>[0-9]{1,2}) episode (?<episode>[0-9]{1,3})\b").unwrap(),
+        Regex::new(r"^(?<name>.+?) \(?(?<year>(?:19|20)[0-9]{2})\)?(?: |$)").unwrap(),
+    ]
+});
+
+/// What is tried, in the configured order, when the name of a file matches none of the regexes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseFallback {
+    /// The directories the file is in: the series and season of a pack for a file named only
+    /// after its episode, like `Show S01/E02.mkv`, or the release directory of a file with a
+    /// meaningless name, like `Movie.2020.1080p/abc123.mkv`
+    ParentDir,
+    /// The title stored in the file by the release, read from the tags of MKV and MP4 files
+    EmbeddedTitle,
+    /// Common patterns of release names the regexes miss, like `Show 1x02` or `Movie (2020)`
+    Heuristics,
+    /// Not a way to parse: the file is placed in `quarantine_dir` of the first output directory
+    /// for the user to look at, instead of being left in the input
+    Quarantine,
+}
+
+/// Which reading wins when a name is parsed both by a regex of series and by a regex of movies,
+/// like `Show 2019 S01E02`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaPreference {
+    /// The reading with the more numbers, an episode over a year, and then the one with the
+    /// shorter title; the series when they still tie
+    #[default]
+    Specific,
+    Series,
+    Movie,
+}
+
+/// Only the pack directories are looked at by default, the other fallbacks could misname files
+pub fn default_parse_fallbacks() -> Vec<ParseFallback> {
+    vec![ParseFallback::ParentDir]
+}
+
 pub fn parse_filepath(path: &Path, config: &Config) -> Option<MediaFile> {
-    let mut stem = get_filestem(path)?;
+    parse_file(path, config, None)
+}
+
+/// Parses the file like `parse_filepath`, reading it for the embedded title when that fallback is
+/// configured and the filesystem is given
+pub fn parse_file(path: &Path, config: &Config, fs: Option<&dyn Fs>) -> Option<MediaFile> {
+    Some(MediaFile::from_parsed(parse_media(path, config, fs)?, get_extension(path)?))
+}
+
+/// Reads the title, numbering and release tags of the file from its name, or else from the
+/// configured fallbacks in order
+pub fn parse_media(path: &Path, config: &Config, fs: Option<&dyn Fs>) -> Option<ParsedMedia> {
+    let raw_stem = get_filestem(path)?;
+    let stem = apply_replacements(raw_stem.clone(), config);
+    debug!("Applying regex to stem: {}", &stem);
+
+    let arbitrated = arbitrate(&stem, config).filter(|((name, _), _)| !name.trim().is_empty());
+    if let Some(((name, media_data), alternative)) = arbitrated {
+        if let MediaData::TvSeries { season, .. } = media_data {
+            validate_season(path, season, config);
+        }
+        let parsed = ParsedMedia::new(name, media_data).with_tags(&raw_stem, &stem);
+        let alternative = alternative
+            .filter(|(name, _)| !name.trim().is_empty())
+            .map(|(name, media_data)| ParsedMedia::new(name, media_data).with_tags(&raw_stem, &stem));
+        return Some(parsed.with_alternative(alternative));
+    }
+
+    for fallback in &config.parse_fallbacks {
+        let parsed = match fallback {
+            ParseFallback::ParentDir => parse_in_pack(path, &stem, config)
+                .map(|(name, media_data)| ParsedMedia::new(name, media_data).with_tags(&raw_stem, &stem))
+                .or_else(|| parse_release_dir(path, config)),
+            ParseFallback::EmbeddedTitle => fs
+                .and_then(|fs| embedded::title(fs, path))
+                .and_then(|title| parse_raw(&title, config, |stem| parse_stem(stem, config))),
+            ParseFallback::Heuristics => parse_raw(&raw_stem, config, parse_heuristics),
+            ParseFallback::Quarantine => None,
+        };
+        if let Some(parsed) = parsed {
+            info!("Parsed {} as {} with the {:?} fallback", path.display(), parsed.title, fallback);
+            return Some(parsed);
+        }
+    }
+    None
+}
+
+/// Parses a name like a filename stem with the given parser, reading its tags
+fn parse_raw(
+    raw: &str,
+    config: &Config,
+    parse: impl FnOnce(&str) -> Option<(String, MediaData)>,
+) -> Option<ParsedMedia> {
+    let stem = apply_replacements(raw.to_string(), config);
+    let (name, media_data) = parse(&stem).filter(|(name, _)| !name.trim().is_empty())?;
+    Some(ParsedMedia::new(name, media_data).with_tags(raw, &stem))
+}
+
+/// Parses the name of the directory the file is in with the regexes, for files with meaningless
+/// names in a well-named release directory. Only a single file of a movie or an episode is named
+/// after its directory, so packs of several seasons are left out
+fn parse_release_dir(path: &Path, config: &Config) -> Option<ParsedMedia> {
+    let dir = path.parent()?.file_name()?.to_str()?;
+    if SEASON_RANGE.is_match(dir) {
+        return None;
+    }
+    parse_raw(dir, config, |stem| parse_stem(stem, config))
+}
+
+/// Parses the stem with the built-in patterns of the heuristics fallback
+fn parse_heuristics(stem: &str) -> Option<(String, MediaData)> {
+    let [episode, spelled_out, movie] = &*HEURISTICS;
+    for re in [episode, spelled_out] {
+        if let Some(captures) = re.captures(stem) {
+            let season = captures["season"].parse().ok()?;
+            let episode = captures["episode"].parse().ok()?;
+            return Some((captures["name"].trim().to_string(), MediaData::TvSeries { season, episode }));
+        }
+    }
+    let captures = movie.captures(stem)?;
+    let year = captures["year"].parse().ok()?;
+    Some((captures["name"].trim().to_string(), MediaData::Movie { year }))
+}
+
+pub fn apply_replacements(mut name: String, config: &Config) -> String {
     for replacement in &config.replacements {
         debug!(
             "Applying replacement {} -> {}",
             &replacement.0, &replacement.1
         );
-        stem = stem.replace(&replacement.0, &replacement.1);
+        name = name.replace(&replacement.0, &replacement.1);
     }
-    debug!("Applying regex to stem: {}", &stem);
-
-    let (name, media_data) = parse_stem(&stem, &config)?;
+    normalize_whitespace(&name)
+}
 
-    Some(MediaFile::new(name, media_data, get_extension(path)?))
+/// The names of the directories above the file, nearest first, with the replacements applied.
+/// Names that are not valid UTF-8 are left empty rather than turned into a mangled series name
+fn pack_dirs(path: &Path, config: &Config) -> Vec<String> {
+    path.ancestors()
+        .skip(1)
+        .take(MAX_PACK_DEPTH)
+        .filter_map(|dir| dir.file_name())
+        .map(|name| apply_replacements(name.to_str().unwrap_or_default().to_string(), config))
+        .collect()
 }
 
-fn parse_stem(stem: &str, config: &Config) -> Option<(String, MediaData)> {
-    for re_string in &config.tv_regex {
-        let Ok(re) = Regex::new(re_string) else {
-            warn!(
-                "Invalid regex {} consider fixing in the config file",
-                re_string
-            );
-            continue;
-        };
+/// The season of a directory holding a single season
+fn dir_season(dir: &str) -> Option<u32> {
+    if SEASON_RANGE.is_match(dir) {
+        return None;
+    }
+    SEASON_DIR.captures(dir.trim())?[1].parse().ok()
+}
 
-        debug!("Trying TV regex {}", re_string);
+/// The series name of a pack or season directory, directories without a pack marker are not
+/// trusted to be named after the series
+fn dir_series_name(dir: &str) -> Option<String> {
+    let marker = PACK_MARKER.find(dir)?;
+    let name = dir[..marker.start()].trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
 
-        let Some(captures) = re.captures(&stem) else {
-            continue;
-        };
+/// Warns when the season of the file disagrees with the season directory it is in
+fn validate_season(path: &Path, season: u32, config: &Config) {
+    let Some(dir_season) = pack_dirs(path, config).iter().find_map(|dir| dir_season(dir)) else {
+        return;
+    };
+    if dir_season != season {
+        warn!(
+            "{} is season {} but is in a season {} directory, using the season of the filename",
+            path.display(),
+            season,
+            dir_season
+        );
+    }
+}
 
-        let Some(name) = captures.name("name").map(|n| n.as_str().to_string()) else {
-            continue;
-        };
+/// Parses a file named only after its episode, taking the series name and the season from
+/// the pack directories it is in
+fn parse_in_pack(path: &Path, stem: &str, config: &Config) -> Option<(String, MediaData)> {
+    let captures = EPISODE_ONLY.captures(stem.trim())?;
+    let episode = captures["episode"].parse().ok()?;
 
-        debug!("Found name: {}", name);
+    let dirs = pack_dirs(path, config);
+    let season = match captures.name("season") {
+        Some(season) => season.as_str().parse().ok()?,
+        None => dirs.iter().find_map(|dir| dir_season(dir))?,
+    };
+    let name = dirs.iter().find_map(|dir| dir_series_name(dir))?;
+    debug!(
+        "Found {} season {} episode {} from the pack directories",
+        name, season, episode
+    );
 
-        let Some(season) = captures.name("season").map(|s_str| s_str.as_str()) else {
-            continue;
-        };
-        let Ok(season) = season.parse::<u32>() else {
-            continue;
-        };
+    Some((name, MediaData::TvSeries { season, episode }))
+}
 
-        debug!("Found season: {}", season);
+fn parse_stem(stem: &str, config: &Config) -> Option<(String, MediaData)> {
+    arbitrate(stem, config).map(|(parsed, _)| parsed)
+}
 
-        let Some(episode) = captures.name("episode").map(|s_str| s_str.as_str()) else {
-            continue;
-        };
-        let Ok(episode) = episode.parse::<u32>() else {
-            continue;
-        };
+/// A name parsed by a regex: the title with its numbering
+type Reading = (String, MediaData);
 
-        debug!("Found episode: {}", episode);
+/// Parses the stem with the first regex of the series, daily and absolute ones that matches, and
+/// with the first of the movie ones. When both match, the reading the preference of the
+/// configuration chooses is returned first and the other one second
+fn arbitrate(stem: &str, config: &Config) -> Option<(Reading, Option<Reading>)> {
+    let series = parse_with(stem, config, &RegexKind::ALL[..3]);
+    let movie = parse_with(stem, config, &[RegexKind::Movie]);
+    let (series, movie) = match (series, movie) {
+        (Some(series), Some(movie)) => (series, movie),
+        (series, movie) => return series.or(movie).map(|parsed| (parsed, None)),
+    };
 
-        return Some((name, MediaData::TvSeries { season, episode }));
-    }
+    let numbers = |(_, media_data): &Reading| match media_data {
+        MediaData::TvSeries { .. } => 2,
+        MediaData::Daily { .. } => 3,
+        MediaData::Absolute { .. } | MediaData::Movie { .. } => 1,
+    };
+    let movie_wins = match config.media_preference {
+        MediaPreference::Series => false,
+        MediaPreference::Movie => true,
+        MediaPreference::Specific => {
+            (numbers(&movie), std::cmp::Reverse(movie.0.len())) > (numbers(&series), std::cmp::Reverse(series.0.len()))
+        }
+    };
+    info!(
+        "{} is parsed both as the series {} and as the movie {}, taking it for the {} by the {:?} preference",
+        stem,
+        series.0,
+        movie.0,
+        if movie_wins { "movie" } else { "series" },
+        config.media_preference
+    );
+    Some(if movie_wins { (movie, Some(series)) } else { (series, Some(movie)) })
+}
 
-    for re_string in &config.movie_regex {
-        let Ok(re) = Regex::new(re_string) else {
-            warn!(
-                "Invalid regex {} consider fixing in the config file",
-                re_string
-            );
-            continue;
-        };
+/// Parses the stem with the first regex of the lists that matches, in order
+fn parse_with(stem: &str, config: &Config, kinds: &[RegexKind]) -> Option<Reading> {
+    for kind in kinds {
+        for re_string in kind.regexes(config) {
+            let Ok(re) = Regex::new(re_string) else {
+                warn!(
+                    "Invalid regex {} consider fixing in the config file",
+                    re_string
+                );
+                continue;
+            };
 
-        debug!("Trying movie regex {}", re_string);
+            debug!("Trying {} {}", kind.field(), re_string);
 
-        let Some(captures) = re.captures(&stem) else {
-            continue;
-        };
+            if let Some(parsed) = kind.parse(&re, stem) {
+                return Some(parsed);
+            }
+        }
+    }
 
-        let Some(name) = captures.name("name").map(|n| n.as_str().to_string()) else {
-            continue;
-        };
+    None
+}
 
-        debug!("Found name: {}", name);
+/// The lists of regexes of the configuration, in the order they are tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegexKind {
+    Tv,
+    Daily,
+    Absolute,
+    Movie,
+}
 
-        let Some(year) = captures.name("year").map(|s_str| s_str.as_str()) else {
-            continue;
-        };
-        let Ok(year) = year.parse::<u32>() else {
-            continue;
-        };
+impl RegexKind {
+    pub const ALL: [RegexKind; 4] = [RegexKind::Tv, RegexKind::Daily, RegexKind::Absolute, RegexKind::Movie];
 
-        debug!("Found year: {}", year);
+    /// The name of the list in the configuration file
+    pub fn field(self) -> &'static str {
+        match self {
+            RegexKind::Tv => "tv_regex",
+            RegexKind::Daily => "daily_regex",
+            RegexKind::Absolute => "absolute_regex",
+            RegexKind::Movie => "movie_regex",
+        }
+    }
 
-        return Some((name, MediaData::Movie { year }));
+    pub fn regexes(self, config: &Config) -> &[String] {
+        match self {
+            RegexKind::Tv => &config.tv_regex,
+            RegexKind::Daily => &config.daily_regex,
+            RegexKind::Absolute => &config.absolute_regex,
+            RegexKind::Movie => &config.movie_regex,
+        }
     }
 
-    None
+    /// Parses the stem with a regex of this list, none when it does not match or misses a group
+    pub fn parse(self, re: &Regex, stem: &str) -> Option<(String, MediaData)> {
+        let captures = re.captures(stem)?;
+        let name = captures.name("name")?.as_str().to_string();
+        debug!("Found name: {}", name);
+        let number = |group: &str| captures.name(group)?.as_str().parse::<u32>().ok();
+
+        match self {
+            RegexKind::Tv => {
+                let season = number("season")?;
+                debug!("Found season: {}", season);
+                let episode = number("episode")?;
+                debug!("Found episode: {}", episode);
+                Some((name, MediaData::TvSeries { season, episode }))
+            }
+            RegexKind::Daily => {
+                let (Some(year), Some(month), Some(day)) = (number("year"), number("month"), number("day")) else {
+                    return None;
+                };
+                if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+                    return None;
+                }
+                debug!("Found air date: {}-{:02}-{:02}", year, month, day);
+                Some((name, MediaData::Daily { year, month, day }))
+            }
+            RegexKind::Absolute => {
+                let episode = number("episode")?;
+                debug!("Found absolute episode: {}", episode);
+                Some((name, MediaData::Absolute { episode }))
+            }
+            RegexKind::Movie => {
+                let year = number("year")?;
+                debug!("Found year: {}", year);
+                Some((name, MediaData::Movie { year }))
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -109,7 +374,7 @@ mod tests {
     use core::panic;
     use std::path::PathBuf;
 
-    use crate::media::MediaData;
+    use crate::{media::MediaData, naming::Template};
 
     use super::*;
 
@@ -121,7 +386,7 @@ mod tests {
         test_episode: u32,
     ) {
         let path = PathBuf::from(test_path);
-        let Some(media_file) = parse_filepath(&path, &config) else {
+        let Some(media_file) = parse_filepath(&path, config) else {
             panic!("parse_filepath failed for {}", test_path);
         };
         assert_eq!(media_file.name(), test_name);
@@ -136,7 +401,7 @@ mod tests {
 
     fn test_movie(config: &Config, test_path: &str, test_name: &str, test_year: u32) {
         let path = PathBuf::from(test_path);
-        let Some(media_file) = parse_filepath(&path, &config) else {
+        let Some(media_file) = parse_filepath(&path, config) else {
             panic!("parse_filepath failed for {}", test_path);
         };
         assert_eq!(media_file.name(), test_name);
@@ -194,4 +459,121 @@ mod tests {
             2017,
         );
     }
+
+    #[test]
+    fn daily_shows_are_parsed_by_air_date() {
+        let config = Config::default();
+        let media_file = parse_filepath(Path::new("The.Daily.Show.2024.03.12.Guest.720p.mkv"), &config).unwrap();
+        assert_eq!(media_file.name(), "The Daily Show");
+        assert!(matches!(
+            media_file.media(),
+            MediaData::Daily {
+                year: 2024,
+                month: 3,
+                day: 12
+            }
+        ));
+        assert_eq!(
+            media_file.get_path(&config),
+            Path::new("TV/The Daily Show/Season 2024/The Daily Show - 2024-03-12.mkv")
+        );
+
+        // not a date, so a movie of 2024
+        let media_file = parse_filepath(Path::new("Film.2024.13.40.1080p.mkv"), &config).unwrap();
+        assert!(matches!(media_file.media(), MediaData::Movie { year: 2024 }));
+    }
+
+    #[test]
+    fn anime_is_parsed_by_absolute_number() {
+        let config = Config::default();
+        let media_file = parse_filepath(Path::new("[SubsPlease] Frieren - 28 (1080p) [A1B2C3D4].mkv"), &config).unwrap();
+        assert_eq!(media_file.name(), "Frieren");
+        assert!(matches!(media_file.media(), MediaData::Absolute { episode: 28 }));
+        assert_eq!(
+            media_file.get_path(&config),
+            Path::new("TV/Frieren/Season 1/Frieren - s01e28.mkv")
+        );
+
+        let media_file = parse_filepath(Path::new("[Erai-raws] Kimetsu no Yaiba - 105v2 [1080p].mkv"), &config).unwrap();
+        assert_eq!(media_file.name(), "Kimetsu no Yaiba");
+        assert!(matches!(media_file.media(), MediaData::Absolute { episode: 105 }));
+    }
+
+    #[test]
+    fn nested_packs() {
+        let config = Config::default();
+        test_series(
+            &config,
+            "Show Complete S01-S05/S03/Show.S03E02.mkv",
+            "Show",
+            3,
+            2,
+        );
+        test_series(&config, "Show Complete S01-S05/S03/E02.mkv", "Show", 3, 2);
+        test_series(
+            &config,
+            "Show.Complete.Series.1080p/Season 4/Extras/S04E11.mkv",
+            "Show",
+            4,
+            11,
+        );
+        test_series(&config, "The Wire (2002) Season 1/Episode 7.mkv", "The Wire", 1, 7);
+        assert!(parse_filepath(&PathBuf::from("downloads/E02.mkv"), &config).is_none());
+    }
+
+    #[test]
+    fn release_tags_are_parsed_with_the_name() {
+        let config = Config {
+            movie_template: Template::parse("Movies/{name} ({year})/{name} ({year})[ {edition}][ {resolution}].{ext}").unwrap(),
+            ..Config::default()
+        };
+        let media_file = parse_filepath(Path::new("Aliens.1986.Extended.1080p.BluRay.x264-SPARKS.mkv"), &config).unwrap();
+        let parsed = media_file.parsed();
+        assert_eq!(parsed.title, "Aliens");
+        assert_eq!(parsed.source, Some("BluRay"));
+        assert_eq!(parsed.codec, Some("H.264"));
+        assert_eq!(parsed.group.as_deref(), Some("SPARKS"));
+        assert_eq!(
+            media_file.get_path(&config),
+            Path::new("Movies/Aliens (1986)/Aliens (1986) Extended 1080p.mkv")
+        );
+
+        let episode = parse_media(Path::new("Show.S02E05E06.720p.WEB-DL.mkv"), &config, None).unwrap();
+        assert_eq!(episode.episodes, vec![5, 6]);
+        assert_eq!(episode.resolution, Some(720));
+    }
+
+    #[test]
+    fn names_read_both_as_series_and_movie_are_arbitrated() {
+        let path = Path::new("Paradise.2025.S01E04.480p.x264-RUBiK.mkv");
+        let parsed = parse_media(path, &Config::default(), None).unwrap();
+        assert_eq!(parsed.title, "Paradise 2025");
+        assert!(matches!(parsed.media, MediaData::TvSeries { season: 1, .. }));
+        let alternative = parsed.alternative.unwrap();
+        assert_eq!(alternative.title, "Paradise");
+        assert!(matches!(alternative.media, MediaData::Movie { year: 2025 }));
+
+        let config = Config {
+            media_preference: MediaPreference::Movie,
+            ..Config::default()
+        };
+        let parsed = parse_media(path, &config, None).unwrap();
+        assert!(matches!(parsed.media, MediaData::Movie { year: 2025 }));
+        assert_eq!(parsed.alternative.unwrap().title, "Paradise 2025");
+
+        let parsed = parse_media(Path::new("Show.S01E04.mkv"), &Config::default(), None).unwrap();
+        assert!(parsed.alternative.is_none());
+    }
+
+    #[test]
+    fn whitespace_around_the_name_is_trimmed() {
+        let config = Config::default();
+        test_series(&config, "Show.Name.S01E02.720p\t .mkv", "Show Name", 1, 2);
+        let media_file = parse_filepath(Path::new("Show  Name\tS01E02 \t.mkv "), &config).unwrap();
+        assert_eq!(media_file.name(), "Show Name");
+        assert_eq!(
+            media_file.get_path(&config),
+            Path::new("TV/Show Name/Season 1/Show Name - s01e02.mkv")
+        );
+    }
 }
@@ -0,0 +1,403 @@
+use std::{fmt::Display, path::PathBuf};
+
+use clap::{builder::PossibleValue, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::path_utils::normalize_whitespace;
+
+/// The default layout of episodes, the one Plex expects
+pub const DEFAULT_TV_TEMPLATE: &str =
+    "TV/{name}/Season {season}/{name} - s{season:02}e{episode:02}[ - {episode_title}].{ext}";
+/// The default layout of episodes of daily shows whose season and number are unknown,
+/// the one Plex expects for date-based shows
+pub const DEFAULT_DAILY_TEMPLATE: &str = "TV/{name}/Season {season}/{name} - {air_date}[ - {episode_title}].{ext}";
+/// The default layout of movies, the one Plex expects
+pub const DEFAULT_MOVIE_TEMPLATE: &str = "Movies/{name} ({year})/{name} ({year}).{ext}";
+
+/// The layout of episodes Kodi prefers, with the year of the series and zero-padded seasons
+const KODI_TV_TEMPLATE: &str =
+    "TV/{name}[ ({year})]/Season {season:02}/{name} S{season:02}E{episode:02}[ - {episode_title}].{ext}";
+/// The layout of episodes of daily shows Kodi prefers, which it matches by their air date
+const KODI_DAILY_TEMPLATE: &str = "TV/{name}[ ({year})]/Season {season}/{name} {air_date}[ - {episode_title}].{ext}";
+
+/// The names Windows reserves for devices, which no file or directory can have, whatever its extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The fields a template can refer to
+const FIELDS: [&str; 17] = [
+    "name",
+    "year",
+    "season",
+    "episode",
+    "air_date",
+    "episode_title",
+    "ext",
+    "tvdb_id",
+    "tmdb_id",
+    "imdb_id",
+    "resolution",
+    "source",
+    "codec",
+    "group",
+    "edition",
+    "part",
+    "language",
+];
+
+/// The layout of the path of a file in the library, relative to the output directory.
+/// Fields are written `{field}`, numbers can be zero-padded with `{field:02}`, and a part in
+/// square brackets is left out when one of its fields has no value, e.g. `[ - {episode_title}]`.
+/// The folder of the series or movie is the path up to the first directory with the name in it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    source: String,
+    /// The path components of the template
+    components: Vec<Vec<Segment>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Part(Part),
+    /// Left out when one of its fields has no value
+    Optional(Vec<Part>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field { name: String, width: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("unknown field {{{0}}}, the fields are {fields}", fields = FIELDS.join(", "))]
+    UnknownField(String),
+    #[error("invalid width in {{{0}}}")]
+    InvalidWidth(String),
+    #[error("unclosed {0}")]
+    Unclosed(char),
+    #[error("unexpected {0}")]
+    Unexpected(char),
+    #[error("the template has no file name")]
+    Empty,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let components = source
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(parse_component)
+            .collect::<Result<Vec<_>, _>>()?;
+        if components.is_empty() {
+            return Err(TemplateError::Empty);
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            components,
+        })
+    }
+
+    /// Renders the template into the folder of the series or movie and the path of the file
+    /// in that folder. The values are sanitized so that they stay one component and every
+    /// component is a valid name on Windows as well, see [`sanitize_value`] and [`sanitize_component`]
+    pub fn render(&self, value: impl Fn(&str) -> Option<String>) -> (PathBuf, PathBuf) {
+        let value = |name: &str| value(name).map(|value| sanitize_value(&value));
+        let rendered = self.components.iter().map(|component| {
+            component
+                .iter()
+                .map(|segment| match segment {
+                    Segment::Part(part) => render_part(part, &value).unwrap_or_default(),
+                    Segment::Optional(parts) => parts
+                        .iter()
+                        .map(|part| render_part(part, &value))
+                        .collect::<Option<String>>()
+                        .unwrap_or_default(),
+                })
+                .collect::<String>()
+        })
+        .map(|component| sanitize_component(&component));
+
+        let folder_depth = self.folder_depth();
+        let mut folder = PathBuf::new();
+        let mut file = PathBuf::new();
+        for (index, component) in rendered.enumerate() {
+            if index < folder_depth {
+                folder.push(component);
+            } else {
+                file.push(component);
+            }
+        }
+        (folder, file)
+    }
+
+    /// How many components of the template make the folder of the series or movie
+    fn folder_depth(&self) -> usize {
+        let directories = self.components.len() - 1;
+        self.components[..directories]
+            .iter()
+            .position(|component| component.iter().any(|segment| segment.has_field("name")))
+            .map(|index| index + 1)
+            .unwrap_or(directories)
+    }
+}
+
+impl Segment {
+    fn has_field(&self, field: &str) -> bool {
+        let parts = match self {
+            Segment::Part(part) => std::slice::from_ref(part),
+            Segment::Optional(parts) => parts,
+        };
+        parts
+            .iter()
+            .any(|part| matches!(part, Part::Field { name, .. } if name == field))
+    }
+}
+
+/// Replaces the characters a value cannot have in a filename: path separators and colons become
+/// dashes, so that `Mission: Impossible` becomes `Mission - Impossible`, and the characters
+/// Windows forbids, like `?` and `*`, are left out along with control characters. Tabs and runs
+/// of spaces become a single space
+fn sanitize_value(value: &str) -> String {
+    normalize_whitespace(value)
+        .replace(": ", " - ")
+        .replace([':', '/', '\\'], "-")
+        .chars()
+        .filter(|c| !matches!(c, '?' | '*' | '"' | '<' | '>' | '|') && !c.is_control())
+        .collect()
+}
+
+/// Makes a rendered path component a valid name: without trailing dots and spaces, which Windows
+/// drops, not a reserved device name like `CON`, and not empty, `.` or `..`
+fn sanitize_component(component: &str) -> String {
+    let component = component.trim().trim_end_matches('.').trim_end();
+    let (stem, extension) = component.split_at(component.find('.').unwrap_or(component.len()));
+    if component.is_empty() {
+        "_".to_string()
+    } else if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem.trim_end())) {
+        format!("{}_{}", stem, extension)
+    } else {
+        component.to_string()
+    }
+}
+
+fn render_part(part: &Part, value: &impl Fn(&str) -> Option<String>) -> Option<String> {
+    match part {
+        Part::Literal(literal) => Some(literal.clone()),
+        Part::Field { name, width } => {
+            let value = value(name).filter(|value| !value.is_empty())?;
+            Some(format!("{:0>width$}", value, width = width))
+        }
+    }
+}
+
+fn parse_component(component: &str) -> Result<Vec<Segment>, TemplateError> {
+    let mut segments = Vec::new();
+    let mut rest = component;
+    while !rest.is_empty() {
+        if let Some(optional) = rest.strip_prefix('[') {
+            let (inside, after) = optional.split_once(']').ok_or(TemplateError::Unclosed('['))?;
+            segments.push(Segment::Optional(parse_parts(inside)?));
+            rest = after;
+        } else {
+            let end = rest.find('[').unwrap_or(rest.len());
+            segments.extend(parse_parts(&rest[..end])?.into_iter().map(Segment::Part));
+            rest = &rest[end..];
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_parts(text: &str) -> Result<Vec<Part>, TemplateError> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(field) = rest.strip_prefix('{') {
+            let (field, after) = field.split_once('}').ok_or(TemplateError::Unclosed('{'))?;
+            let (name, width) = match field.split_once(':') {
+                Some((name, width)) => (
+                    name,
+                    width
+                        .parse()
+                        .map_err(|_| TemplateError::InvalidWidth(field.to_string()))?,
+                ),
+                None => (field, 0),
+            };
+            if !FIELDS.contains(&name) {
+                return Err(TemplateError::UnknownField(name.to_string()));
+            }
+            parts.push(Part::Field {
+                name: name.to_string(),
+                width,
+            });
+            rest = after;
+        } else {
+            let end = rest.find(['{', '}', ']']).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(TemplateError::Unexpected(rest.chars().next().unwrap_or_default()));
+            }
+            parts.push(Part::Literal(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+    }
+    Ok(parts)
+}
+
+impl TryFrom<String> for Template {
+    type Error = TemplateError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Self::parse(&source)
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> Self {
+        template.source
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// The layouts of the media centers, chosen with --scheme instead of the templates of the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Plex,
+    Kodi,
+}
+
+impl Scheme {
+    /// The templates of the episodes, of the episodes of daily shows and of the movies
+    pub fn templates(self) -> [Template; 3] {
+        let [tv, daily, movie] = match self {
+            Scheme::Plex => [DEFAULT_TV_TEMPLATE, DEFAULT_DAILY_TEMPLATE, DEFAULT_MOVIE_TEMPLATE],
+            Scheme::Kodi => [KODI_TV_TEMPLATE, KODI_DAILY_TEMPLATE, DEFAULT_MOVIE_TEMPLATE],
+        };
+        [tv, daily, movie].map(|template| Template::parse(template).expect("The templates of the schemes are valid"))
+    }
+}
+
+impl ValueEnum for Scheme {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Scheme::Plex, Scheme::Kodi]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(Into::<&str>::into(*self)))
+    }
+}
+
+impl From<Scheme> for &str {
+    fn from(value: Scheme) -> Self {
+        match value {
+            Scheme::Plex => "plex",
+            Scheme::Kodi => "kodi",
+        }
+    }
+}
+
+impl Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+pub fn default_tv_template() -> Template {
+    Template::parse(DEFAULT_TV_TEMPLATE).expect("The default TV template is valid")
+}
+
+pub fn default_daily_template() -> Template {
+    Template::parse(DEFAULT_DAILY_TEMPLATE).expect("The default daily template is valid")
+}
+
+pub fn default_movie_template() -> Template {
+    Template::parse(DEFAULT_MOVIE_TEMPLATE).expect("The default movie template is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_render_fields_padding_and_optional_parts() {
+        let template = default_tv_template();
+        let values = |episode_title: Option<&'static str>| {
+            move |field: &str| match field {
+                "name" => Some("Severance".to_string()),
+                "season" => Some("1".to_string()),
+                "episode" => Some("4".to_string()),
+                "episode_title" => episode_title.map(str::to_string),
+                "ext" => Some("mkv".to_string()),
+                _ => None,
+            }
+        };
+
+        assert_eq!(
+            template.render(values(Some("The You You Are"))),
+            (
+                PathBuf::from("TV/Severance"),
+                PathBuf::from("Season 1/Severance - s01e04 - The You You Are.mkv")
+            )
+        );
+        assert_eq!(
+            template.render(values(None)).1,
+            PathBuf::from("Season 1/Severance - s01e04.mkv")
+        );
+
+        let flat = Template::parse("Shows/{name} {season}x{episode:02}.{ext}").unwrap();
+        assert_eq!(
+            flat.render(values(None)),
+            (PathBuf::from("Shows"), PathBuf::from("Severance 1x04.mkv"))
+        );
+    }
+
+    #[test]
+    fn rendered_names_are_valid_on_every_system() {
+        let template = default_movie_template();
+        let render = |name: &'static str| {
+            template.render(move |field: &str| match field {
+                "name" => Some(name.to_string()),
+                "year" => Some("1996".to_string()),
+                "ext" => Some("mkv".to_string()),
+                _ => None,
+            })
+        };
+
+        assert_eq!(
+            render("Mission: Impossible"),
+            (
+                PathBuf::from("Movies/Mission - Impossible (1996)"),
+                PathBuf::from("Mission - Impossible (1996).mkv")
+            )
+        );
+        assert_eq!(render("What?/Why*").1, PathBuf::from("What-Why (1996).mkv"));
+        assert_eq!(render("Con\t Air \t").1, PathBuf::from("Con Air (1996).mkv"));
+        assert_eq!(sanitize_component("Mr. Robot Jr. "), "Mr. Robot Jr");
+        assert_eq!(sanitize_component("nul.mkv"), "nul_.mkv");
+        assert_eq!(sanitize_component("Con Air"), "Con Air");
+        assert_eq!(sanitize_component(".."), "_");
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert_eq!(
+            Template::parse("TV/{title}.{ext}"),
+            Err(TemplateError::UnknownField("title".to_string()))
+        );
+        assert_eq!(Template::parse("TV/{name"), Err(TemplateError::Unclosed('{')));
+        assert_eq!(Template::parse("TV/[ - {name}"), Err(TemplateError::Unclosed('[')));
+        assert_eq!(
+            Template::parse("TV/{season:two}"),
+            Err(TemplateError::InvalidWidth("season:two".to_string()))
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    filesystem::Fs,
+    media::{MediaData, MediaFile},
+    provider::imdb_id,
+};
+
+/// The name of the file Kodi reads the ids of a series from, in the series folder
+pub const TVSHOW_NFO_FILENAME: &str = "tvshow.nfo";
+/// The name of the file Kodi reads the ids of a movie from, in the movie folder
+pub const MOVIE_NFO_FILENAME: &str = "movie.nfo";
+
+/// The name of the `.nfo` of the folder of the media file
+pub fn filename(media_file: &MediaFile) -> &'static str {
+    match media_file.media() {
+        MediaData::Movie { .. } => MOVIE_NFO_FILENAME,
+        MediaData::TvSeries { .. } | MediaData::Daily { .. } | MediaData::Absolute { .. } => TVSHOW_NFO_FILENAME,
+    }
+}
+
+/// The contents of the `.nfo` of the folder of the media file: the title, the year and the ids,
+/// the first of them being the one Kodi scrapes with
+pub fn contents(media_file: &MediaFile) -> String {
+    let metadata = media_file.metadata();
+    let (root, year) = match media_file.media() {
+        MediaData::Movie { year } => ("movie", Some(*year)),
+        MediaData::TvSeries { .. } | MediaData::Daily { .. } | MediaData::Absolute { .. } => ("tvshow", metadata.year),
+    };
+
+    let mut contents = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n");
+    contents.push_str(&format!("<{}>\n", root));
+    contents.push_str(&format!("  <title>{}</title>\n", escape(media_file.name())));
+    if let Some(year) = year {
+        contents.push_str(&format!("  <year>{}</year>\n", year));
+    }
+    let ids = [
+        ("tvdb", metadata.tvdb_id.map(|id| id.to_string())),
+        ("tmdb", metadata.tmdb_id.map(|id| id.to_string())),
+        ("imdb", metadata.imdb_id.map(imdb_id)),
+    ];
+    for (index, (kind, id)) in ids.into_iter().filter_map(|(kind, id)| Some((kind, id?))).enumerate() {
+        let default = if index == 0 { " default=\"true\"" } else { "" };
+        contents.push_str(&format!("  <uniqueid type=\"{}\"{}>{}</uniqueid>\n", kind, default, id));
+    }
+    contents.push_str(&format!("</{}>\n", root));
+    contents
+}
+
+/// Writes the `.nfo` into the folder unless it already has one.
+/// Returns whether the file was written
+pub fn write(fs: &dyn Fs, folder: &Path, media_file: &MediaFile) -> Result<bool, io::Error> {
+    let path = folder.join(filename(media_file));
+    if fs.exists(&path) {
+        return Ok(false);
+    }
+
+    let mut file = fs.open_write(&path, false)?;
+    file.write_all(contents(media_file).as_bytes())?;
+    file.flush()?;
+    Ok(true)
+}
+
+/// Escapes the characters XML gives a meaning to
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{filesystem::MemoryFs, local_ids, media::Metadata};
+
+    use super::*;
+
+    #[test]
+    fn writes_title_year_and_ids_kodi_and_the_next_runs_read() {
+        let fs = MemoryFs::new();
+        fs.create_dir_all(Path::new("TV/Law & Order (1990)")).unwrap();
+        let mut media_file = MediaFile::new(
+            "Law & Order".to_string(),
+            MediaData::TvSeries { season: 1, episode: 1 },
+            "mkv".to_string(),
+        );
+        media_file.set_metadata(Metadata {
+            tvdb_id: Some(75692),
+            imdb_id: Some(98844),
+            year: Some(1990),
+            ..Metadata::default()
+        });
+
+        assert!(write(&fs, Path::new("TV/Law & Order (1990)"), &media_file).unwrap());
+        assert!(!write(&fs, Path::new("TV/Law & Order (1990)"), &media_file).unwrap());
+        assert_eq!(
+            fs.read_to_string(Path::new("TV/Law & Order (1990)/tvshow.nfo")).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n\
+             <tvshow>\n  <title>Law &amp; Order</title>\n  <year>1990</year>\n  \
+             <uniqueid type=\"tvdb\" default=\"true\">75692</uniqueid>\n  \
+             <uniqueid type=\"imdb\">tt0098844</uniqueid>\n</tvshow>\n"
+        );
+        let episode = Path::new("TV/Law & Order (1990)/Season 01/Law & Order S01E01.mkv");
+        fs.add_file(episode, b"");
+        assert_eq!(local_ids::find_tvdb_id(&fs, episode).map(|(id, _)| id), Some(75692));
+    }
+}
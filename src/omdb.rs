@@ -0,0 +1,357 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::{
+    cache::ResponseCache,
+    media::MediaType,
+    provider::{
+        self, imdb_id, parse_imdb_id, ApiClient, ContentRating, Episode, MetadataProvider, ProviderError, ProviderKind,
+        Record, SearchResult,
+    },
+    recording::Recording,
+};
+
+const API_BASE_URL: &str = "https://www.omdbapi.com";
+
+/// The country the ratings of OMDb are from, as the country code of `rating_country`
+const RATING_COUNTRY: &str = "usa";
+
+/// How many seasons are looked through for an air date or an absolute number
+const MAX_SEASONS: u32 = 50;
+
+/// Client for the OMDb API, whose series and movies are identified by their IMDb id
+#[derive(Clone)]
+pub struct OmdbClient {
+    api: ApiClient,
+}
+
+impl OmdbClient {
+    pub fn new<S>(api_key: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            api: ApiClient::new(API_BASE_URL).with_key_param("apikey", api_key.into()),
+        }
+    }
+
+    /// Records the API responses to, or replays them from, the given recording
+    pub fn with_recording(mut self, recording: impl Into<Arc<Recording>>) -> Self {
+        self.api = self.api.with_recording(recording);
+        self
+    }
+
+    /// Keeps the API responses in the given cache and serves them from it on later runs
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.api = self.api.with_cache(cache);
+        self
+    }
+
+    /// The episodes of a season of the series, none when the series has no such season.
+    /// OMDb answers anything it does not know with a reply saying it failed
+    fn season(&self, series_id: u64, season: u32) -> Result<Option<Vec<SeasonEpisode>>, ProviderError> {
+        let text = self
+            .api
+            .get_blocking("/", &[("i", &imdb_id(series_id)), ("Season", &season.to_string())])?;
+        let reply: SeasonReply = serde_json::from_str(&text)?;
+        Ok(reply.found().then_some(reply.episodes))
+    }
+}
+
+impl MetadataProvider for OmdbClient {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Omdb
+    }
+
+    fn search(&self, name: &str, media_type: MediaType, year: Option<u32>) -> Result<Vec<SearchResult>, ProviderError> {
+        let year = year.map(|year| year.to_string());
+        let mut query = vec![("s", name), ("type", omdb_type(media_type))];
+        if let Some(year) = &year {
+            query.push(("y", year));
+        }
+        let text = self.api.get_blocking("/", &query)?;
+        let reply: SearchReply = serde_json::from_str(&text)?;
+
+        Ok(reply
+            .search
+            .into_iter()
+            .map(|result| SearchResult {
+                id: parse_imdb_id(&result.imdb_id),
+                year: year_of(&result.year),
+                name: result.title,
+            })
+            .collect())
+    }
+
+    fn record(&self, id: u64, media_type: MediaType) -> Result<Record, ProviderError> {
+        let text = self
+            .api
+            .get_blocking("/", &[("i", &imdb_id(id)), ("type", omdb_type(media_type))])?;
+        let title: Title = serde_json::from_str(&text)?;
+
+        Ok(Record {
+            id,
+            year: year_of(&title.year),
+            name: title.title,
+        })
+    }
+
+    /// The IMDb id is the id of OMDb, so the title is only looked up to know it exists
+    fn find_by_imdb_id(&self, imdb_id: u64, media_type: MediaType) -> Result<Option<Record>, ProviderError> {
+        let text = self
+            .api
+            .get_blocking("/", &[("i", &provider::imdb_id(imdb_id)), ("type", omdb_type(media_type))])?;
+        let reply: FoundReply = serde_json::from_str(&text)?;
+        if reply.response != "True" {
+            return Ok(None);
+        }
+        self.record(imdb_id, media_type).map(Some)
+    }
+
+    /// OMDb only has the rating of the United States
+    fn content_ratings(&self, id: u64, media_type: MediaType) -> Result<Vec<ContentRating>, ProviderError> {
+        let text = self
+            .api
+            .get_blocking("/", &[("i", &imdb_id(id)), ("type", omdb_type(media_type))])?;
+        let title: Title = serde_json::from_str(&text)?;
+
+        Ok(title
+            .rated
+            .filter(|rated| rated != "N/A")
+            .map(|name| ContentRating {
+                name,
+                country: RATING_COUNTRY.to_string(),
+            })
+            .into_iter()
+            .collect())
+    }
+
+    /// OMDb lists the genres in one string, like `Animation, Comedy`
+    fn genres(&self, id: u64, media_type: MediaType) -> Result<Vec<String>, ProviderError> {
+        let text = self
+            .api
+            .get_blocking("/", &[("i", &imdb_id(id)), ("type", omdb_type(media_type))])?;
+        let title: Title = serde_json::from_str(&text)?;
+
+        Ok(title
+            .genre
+            .iter()
+            .flat_map(|genre| genre.split(','))
+            .map(str::trim)
+            .filter(|genre| !genre.is_empty() && *genre != "N/A")
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn episodes(&self, series_id: u64, season: u32, episode: u32) -> Result<Vec<Episode>, ProviderError> {
+        let Some(episodes) = self.season(series_id, season)? else {
+            return Ok(Vec::new());
+        };
+        Ok(episodes
+            .into_iter()
+            .filter_map(|found| found.episode(season))
+            .filter(|found| found.number == episode)
+            .collect())
+    }
+
+    fn season_episodes(&self, series_id: u64, season: u32) -> Result<Vec<Episode>, ProviderError> {
+        let Some(episodes) = self.season(series_id, season)? else {
+            return Ok(Vec::new());
+        };
+        Ok(episodes.into_iter().filter_map(|found| found.episode(season)).collect())
+    }
+
+    /// Looks through the seasons, OMDb cannot search by date
+    fn episodes_aired(&self, series_id: u64, air_date: &str) -> Result<Vec<Episode>, ProviderError> {
+        for season in 1..=MAX_SEASONS {
+            let Some(episodes) = self.season(series_id, season)? else {
+                break;
+            };
+            let aired: Vec<Episode> = episodes
+                .into_iter()
+                .filter(|found| found.released == air_date)
+                .filter_map(|found| found.episode(season))
+                .collect();
+            if !aired.is_empty() {
+                return Ok(aired);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// Counts the episodes of the seasons in order, OMDb has no absolute numbers. Absolute numbers
+    /// start at 1, there is no episode 0
+    fn episode_by_absolute_number(&self, series_id: u64, absolute_number: u32) -> Result<Option<Episode>, ProviderError> {
+        let Some(index) = absolute_number.checked_sub(1) else {
+            return Ok(None);
+        };
+        let mut before = 0;
+        for season in 1..=MAX_SEASONS {
+            let Some(episodes) = self.season(series_id, season)? else {
+                break;
+            };
+            let count = episodes.len() as u32;
+            if index < before + count {
+                return Ok(episodes
+                    .into_iter()
+                    .nth((index - before) as usize)
+                    .and_then(|found| found.episode(season))
+                    .map(|found| Episode {
+                        absolute_number: Some(absolute_number),
+                        ..found
+                    }));
+            }
+            before += count;
+        }
+        Ok(None)
+    }
+
+    /// OMDb has no DVD order
+    fn episode_in_dvd_order(&self, _series_id: u64, _season: u32, _episode: u32) -> Result<Option<Episode>, ProviderError> {
+        Ok(None)
+    }
+}
+
+fn omdb_type(media_type: MediaType) -> &'static str {
+    match media_type {
+        MediaType::Series => "series",
+        MediaType::Movie => "movie",
+    }
+}
+
+/// The first year of an OMDb year, which is a range like `2011–2019` for series
+fn year_of(year: &str) -> Option<u32> {
+    year.get(..4).and_then(|year| year.parse().ok())
+}
+
+#[derive(Deserialize)]
+struct SearchReply {
+    /// Missing when nothing was found
+    #[serde(default, rename = "Search")]
+    search: Vec<SearchItem>,
+}
+
+#[derive(Deserialize)]
+struct SearchItem {
+    #[serde(rename = "Title")]
+    title: String,
+    #[serde(rename = "Year")]
+    year: String,
+    #[serde(rename = "imdbID")]
+    imdb_id: String,
+}
+
+#[derive(Deserialize)]
+struct Title {
+    #[serde(rename = "Title")]
+    title: String,
+    #[serde(default, rename = "Year")]
+    year: String,
+    #[serde(default, rename = "Rated")]
+    rated: Option<String>,
+    #[serde(default, rename = "Genre")]
+    genre: Option<String>,
+}
+
+/// Whether OMDb found what was asked for
+#[derive(Deserialize)]
+struct FoundReply {
+    #[serde(rename = "Response")]
+    response: String,
+}
+
+#[derive(Deserialize)]
+struct SeasonReply {
+    #[serde(rename = "Response")]
+    response: String,
+    #[serde(default, rename = "Episodes")]
+    episodes: Vec<SeasonEpisode>,
+}
+
+impl SeasonReply {
+    fn found(&self) -> bool {
+        self.response == "True"
+    }
+}
+
+#[derive(Deserialize)]
+struct SeasonEpisode {
+    #[serde(rename = "Title")]
+    title: String,
+    #[serde(default, rename = "Released")]
+    released: String,
+    #[serde(rename = "Episode")]
+    episode: String,
+}
+
+impl SeasonEpisode {
+    /// The episode, none when OMDb does not give its number
+    fn episode(self, season: u32) -> Option<Episode> {
+        Some(Episode {
+            number: self.episode.parse().ok()?,
+            name: Some(self.title).filter(|title| title != "N/A"),
+            season_number: season,
+            absolute_number: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::request_key;
+
+    /// Severance, tt11280740, with two episodes in its first season and one in its second
+    fn severance() -> OmdbClient {
+        let season = |number: &str| request_key("/", &[("i", "tt11280740"), ("Season", number)]);
+        OmdbClient::new("").with_recording(Recording::replaying(&[
+            (
+                season("1"),
+                200,
+                r#"{"Response": "True", "Episodes": [
+                    {"Title": "Good News About Hell", "Released": "2022-02-18", "Episode": "1"},
+                    {"Title": "Half Loop", "Released": "2022-02-18", "Episode": "2"}
+                ]}"#,
+            ),
+            (
+                season("2"),
+                200,
+                r#"{"Response": "True", "Episodes": [
+                    {"Title": "Hello, Ms. Cobel", "Released": "2025-01-17", "Episode": "1"}
+                ]}"#,
+            ),
+            (season("3"), 200, r#"{"Response": "False", "Error": "Series or season not found!"}"#),
+        ]))
+    }
+
+    #[test]
+    fn episodes_are_found_in_their_season() {
+        let omdb = severance();
+        let episodes = omdb.episodes(11280740, 1, 2).unwrap();
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(episodes[0].name.as_deref(), Some("Half Loop"));
+        assert_eq!(omdb.season_episodes(11280740, 1).unwrap().len(), 2);
+        assert!(omdb.episodes(11280740, 3, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn episodes_are_found_by_air_date_through_the_seasons() {
+        let omdb = severance();
+        let aired = omdb.episodes_aired(11280740, "2025-01-17").unwrap();
+        assert_eq!(aired.len(), 1);
+        assert_eq!((aired[0].season_number, aired[0].number), (2, 1));
+        assert!(omdb.episodes_aired(11280740, "2030-01-01").unwrap().is_empty());
+    }
+
+    #[test]
+    fn absolute_numbers_count_the_episodes_of_the_seasons() {
+        let omdb = severance();
+        let third = omdb.episode_by_absolute_number(11280740, 3).unwrap().unwrap();
+        assert_eq!((third.season_number, third.number, third.absolute_number), (2, 1, Some(3)));
+        let first = omdb.episode_by_absolute_number(11280740, 1).unwrap().unwrap();
+        assert_eq!((first.season_number, first.number), (1, 1));
+        assert_eq!(omdb.episode_by_absolute_number(11280740, 0).unwrap(), None);
+        assert_eq!(omdb.episode_by_absolute_number(11280740, 4).unwrap(), None);
+    }
+}
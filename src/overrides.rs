@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::want_list::same_title;
+
+/// The order the episode numbers of the releases of a series follow
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EpisodeOrder {
+    /// The order the episodes aired in, the one of the library
+    #[default]
+    Aired,
+    /// The order of the DVD or Blu-ray release, mapped to the aired one
+    Dvd,
+    /// Counted from the first episode of the series, whatever the season in the name
+    Absolute,
+}
+
+/// Settings of a single series or movie, given in `[overrides]` under its name, or under a regex
+/// between slashes like `"/^shameless.*us$/"` matching the parsed names of its files
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Override {
+    #[serde(default)]
+    pub episode_order: EpisodeOrder,
+    /// The TVDB id the files are matched to without searching, when the provider is TVDB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvdb_id: Option<u64>,
+    /// The name searched instead of the parsed one, e.g. `Shameless (US)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// The override of the series or movie with one of the names, the parsed one or the matched one,
+/// compared ignoring case, punctuation and spacing or matched by the regex of the override
+pub fn find<'o>(overrides: &'o BTreeMap<String, Override>, names: &[&str]) -> Option<&'o Override> {
+    overrides
+        .iter()
+        .find(|(key, _)| names.iter().any(|name| key_matches(key, name)))
+        .map(|(_, found)| found)
+}
+
+/// Checks that the regexes of the overrides are valid, so that a typo is not silently never matched
+pub fn check(overrides: &BTreeMap<String, Override>) -> Result<(), String> {
+    for key in overrides.keys() {
+        if let Some(pattern) = key_regex(key) {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|error| format!("invalid regex in [overrides] {}: {}", key, error))?;
+        }
+    }
+    Ok(())
+}
+
+fn key_matches(key: &str, name: &str) -> bool {
+    match key_regex(key) {
+        Some(pattern) => RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .is_ok_and(|regex| regex.is_match(name)),
+        None => same_title(key, name),
+    }
+}
+
+/// The regex of a key between slashes
+fn key_regex(key: &str) -> Option<&str> {
+    key.strip_prefix('/')?.strip_suffix('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_are_found_by_name_or_regex() {
+        let overrides: BTreeMap<String, Override> = toml::from_str(
+            r#"
+            "shameless us" = { tvdb_id = 161511 }
+            "/^the office.*(us|2005)/" = { name = "The Office (US)" }
+            "#,
+        )
+        .unwrap();
+        assert!(check(&overrides).is_ok());
+
+        assert_eq!(find(&overrides, &["Shameless US"]).unwrap().tvdb_id, Some(161511));
+        assert_eq!(find(&overrides, &["The Office 2005"]).unwrap().name.as_deref(), Some("The Office (US)"));
+        assert!(find(&overrides, &["Shameless"]).is_none());
+        assert!(find(&overrides, &["The Office"]).is_none());
+
+        let invalid: BTreeMap<String, Override> = toml::from_str(r#""/(unclosed/" = {}"#).unwrap();
+        assert!(check(&invalid).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::{media::MediaData, quality::Quality, subtitles};
+
+/// The episode numbers following the season of a multi-episode release, like `S01E01E02` or `S01E01-E03`
+static EPISODES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bs[0-9]+((?:\s*-?\s*e[0-9]+)+)").unwrap());
+/// An episode number of a multi-episode release
+static EPISODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)e([0-9]+)").unwrap());
+/// The release group at the end of the name, like `-SURCODE`
+static GROUP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"-([A-Za-z0-9]+)\s*$").unwrap());
+/// The release group of anime releases at the start of the name, like `[SubsPlease]`
+static LEADING_GROUP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*\[([^\]]+)\]").unwrap());
+/// The part of a movie split across several files, like `Part 2` or `CD1`
+static PART: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:part|pt|cd|disc|disk)\s*([0-9]{1,2})\b").unwrap());
+
+/// The video codec tags of releases, by the name they are shown with
+const CODECS: [(&str, &[&str]); 5] = [
+    ("H.265", &["x265", "h265", "hevc"]),
+    ("H.264", &["x264", "h264", "avc"]),
+    ("AV1", &["av1"]),
+    ("VP9", &["vp9"]),
+    ("XviD", &["xvid", "divx"]),
+];
+
+/// The edition tags of releases, by the name they are shown with, matched on the words of the name
+const EDITIONS: [(&str, &[&str]); 8] = [
+    ("Director's Cut", &["directors cut", "director's cut", "dc"]),
+    ("Extended", &["extended", "extended cut", "extended edition"]),
+    ("Unrated", &["unrated"]),
+    ("Theatrical", &["theatrical", "theatrical cut"]),
+    ("Remastered", &["remastered"]),
+    ("IMAX", &["imax"]),
+    ("Final Cut", &["final cut"]),
+    ("Criterion", &["criterion"]),
+];
+
+/// Everything the parser reads from a filename: the title and numbering matched and searched
+/// with, and the tags of the release after them. Parsed once, it is shared by every feature
+/// working on release tags, like the naming template fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedMedia {
+    /// The title as it is in the filename, once the replacements are applied
+    pub title: String,
+    /// The season and episode, air date, absolute number or year of the file
+    pub media: MediaData,
+    /// Every episode of a multi-episode file like `S01E01E02`, the episode alone otherwise, none for movies
+    pub episodes: Vec<u32>,
+    /// The vertical resolution, e.g. 1080
+    pub resolution: Option<u32>,
+    /// The source of the release, e.g. `BluRay`
+    pub source: Option<&'static str>,
+    /// The video codec, e.g. `H.265`
+    pub codec: Option<&'static str>,
+    /// The release group, e.g. `FLUX`
+    pub group: Option<String>,
+    /// The language of the audio as a code like `de`, or `multi` for several
+    pub language: Option<String>,
+    /// The edition of a movie, e.g. `Extended`
+    pub edition: Option<&'static str>,
+    /// The part of a movie split across files, e.g. 2 for `CD2`
+    pub part: Option<u32>,
+    /// The other reading of a name parsed both as a series and as a movie, searched when this one
+    /// is not found with `confirm_media_type`
+    pub alternative: Option<Box<ParsedMedia>>,
+}
+
+impl ParsedMedia {
+    /// A file known only by its title and numbering, without tags
+    pub fn new(title: String, media: MediaData) -> Self {
+        let episodes = match media {
+            MediaData::TvSeries { episode, .. } | MediaData::Absolute { episode } => vec![episode],
+            MediaData::Daily { .. } | MediaData::Movie { .. } => Vec::new(),
+        };
+        Self {
+            title,
+            media,
+            episodes,
+            resolution: None,
+            source: None,
+            codec: None,
+            group: None,
+            language: None,
+            edition: None,
+            part: None,
+            alternative: None,
+        }
+    }
+
+    pub fn with_alternative(mut self, alternative: Option<ParsedMedia>) -> Self {
+        self.alternative = alternative.map(Box::new);
+        self
+    }
+
+    /// Reads the tags of the filename stem, as it was before the replacements so that the group
+    /// keeps its dash. Only the part after the title is read, so that a title like
+    /// `Deathly Hallows Part 1` or `It` is not taken for tags
+    pub fn with_tags(mut self, raw_stem: &str, stem: &str) -> Self {
+        let after_title = match stem.find(self.title.as_str()) {
+            Some(start) if !self.title.is_empty() => &stem[start + self.title.len()..],
+            _ => stem,
+        };
+
+        if let MediaData::TvSeries { episode, .. } = self.media {
+            self.episodes = episodes(after_title).unwrap_or_else(|| vec![episode]);
+        }
+        let quality = Quality::parse(after_title);
+        self.resolution = quality.resolution;
+        self.source = quality.source;
+
+        let words: Vec<String> = after_title
+            .split(|c: char| !(c.is_alphanumeric() || c == '\''))
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        let has = |tag: &str| {
+            let tag_words: Vec<&str> = tag.split(' ').collect();
+            words.windows(tag_words.len()).any(|window| window == tag_words.as_slice())
+        };
+        self.codec = CODECS
+            .iter()
+            .find(|(_, tags)| tags.iter().any(|tag| has(tag)))
+            .map(|(codec, _)| *codec);
+        self.edition = EDITIONS
+            .iter()
+            .find(|(_, tags)| tags.iter().any(|tag| has(tag)))
+            .map(|(edition, _)| *edition);
+        self.language = if has("multi") {
+            Some("multi".to_string())
+        } else {
+            words.iter().find_map(|word| subtitles::language_of_name(word)).map(str::to_string)
+        };
+        self.part = PART.captures(after_title).and_then(|captures| captures[1].parse().ok());
+        self.group = LEADING_GROUP
+            .captures(raw_stem)
+            .or_else(|| GROUP.captures(raw_stem))
+            .map(|captures| captures[1].trim().to_string());
+        self
+    }
+
+    /// The resolution and source of the release
+    pub fn quality(&self) -> Quality {
+        Quality {
+            resolution: self.resolution,
+            source: self.source,
+        }
+    }
+}
+
+/// The episodes of a multi-episode name, a range when written like `S01E01-E03`
+fn episodes(name: &str) -> Option<Vec<u32>> {
+    let numbers = EPISODES.captures(name)?;
+    let numbers = &numbers[1];
+    let episodes: Vec<u32> = EPISODE
+        .captures_iter(numbers)
+        .filter_map(|captures| captures[1].parse().ok())
+        .collect();
+    match episodes.as_slice() {
+        [first, last] if numbers.contains('-') && first < last => Some((*first..=*last).collect()),
+        [] => None,
+        _ => Some(episodes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(title: &str, media: MediaData, raw_stem: &str) -> ParsedMedia {
+        ParsedMedia::new(title.to_string(), media).with_tags(raw_stem, &raw_stem.replace('.', " "))
+    }
+
+    #[test]
+    fn tags_are_read_after_the_title() {
+        let movie = parse(
+            "Blade Runner 2049",
+            MediaData::Movie { year: 2017 },
+            "Blade.Runner.2049.2017.Directors.Cut.2160p.MA.WEB-DL.TrueHD.Atmos.7.1.DV.HDR.H.265-FLUX",
+        );
+        assert_eq!(movie.resolution, Some(2160));
+        assert_eq!(movie.source, Some("WEB-DL"));
+        assert_eq!(movie.codec, None);
+        assert_eq!(movie.group.as_deref(), Some("FLUX"));
+        assert_eq!(movie.edition, Some("Director's Cut"));
+        assert_eq!(movie.part, None);
+
+        let movie = parse(
+            "Harry Potter and the Deathly Hallows Part 1",
+            MediaData::Movie { year: 2010 },
+            "Harry.Potter.and.the.Deathly.Hallows.Part.1.2010.German.x264.CD2-GRP",
+        );
+        assert_eq!(movie.part, Some(2));
+        assert_eq!(movie.language.as_deref(), Some("de"));
+        assert_eq!(movie.codec, Some("H.264"));
+
+        let anime = parse(
+            "Frieren",
+            MediaData::Absolute { episode: 28 },
+            "[SubsPlease] Frieren - 28 (1080p) [A1B2C3D4]",
+        );
+        assert_eq!(anime.group.as_deref(), Some("SubsPlease"));
+        assert_eq!(anime.episodes, vec![28]);
+    }
+
+    #[test]
+    fn multi_episode_files_list_every_episode() {
+        let episodes = |raw_stem: &str| parse("Show", MediaData::TvSeries { season: 1, episode: 1 }, raw_stem).episodes;
+        assert_eq!(episodes("Show.S01E01E02.720p"), vec![1, 2]);
+        assert_eq!(episodes("Show.S01E01-E03.720p"), vec![1, 2, 3]);
+        assert_eq!(episodes("Show.S01E01.720p"), vec![1]);
+    }
+}
@@ -1,13 +1,26 @@
 use std::path::Path;
 
+/// Whether the filename is valid UTF-8. The helpers below return None for the others, as their
+/// names cannot be parsed or rendered into a library name without losing bytes
+pub fn has_utf8_name(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.to_str().is_some())
+}
+
 pub fn get_filestem(path: &Path) -> Option<String> {
     Some(path.file_stem()?.to_str()?.to_string())
 }
 
+/// The extension without the spaces or tabs around it, so that `Show.S01E01.mkv ` is a `mkv`
 pub fn get_extension(path: &Path) -> Option<String> {
-    Some(path.extension()?.to_str()?.to_string())
+    Some(path.extension()?.to_str()?.trim().to_string())
 }
 
 pub fn get_filename(path: &Path) -> Option<String> {
     Some(path.file_name()?.to_str()?.to_string())
+}
+
+/// Turns every run of whitespace, like the tabs some releases end their names with, into a single
+/// space and removes it at both ends
+pub fn normalize_whitespace(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
 }
\ No newline at end of file
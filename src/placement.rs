@@ -0,0 +1,319 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use clap::{builder::PossibleValue, ValueEnum};
+use log::debug;
+
+use crate::{filesystem::Fs, media::MediaFile, query::normalize_title, Config};
+
+/// How new series and movies are spread across several output directories
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    MostFreeSpace,
+    RoundRobin,
+    SeriesAffinity,
+}
+
+impl ValueEnum for Placement {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Placement::MostFreeSpace,
+            Placement::RoundRobin,
+            Placement::SeriesAffinity,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(Into::<&str>::into(*self)))
+    }
+}
+
+impl From<Placement> for &str {
+    fn from(value: Placement) -> Self {
+        match value {
+            Placement::MostFreeSpace => "most-free-space",
+            Placement::RoundRobin => "round-robin",
+            Placement::SeriesAffinity => "existing-series-affinity",
+        }
+    }
+}
+
+impl Display for Placement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+/// The series and movie folders already in the output directories, indexed at startup
+#[derive(Debug, Default)]
+pub struct LibraryIndex {
+    /// The folders by the key they are matched with, with the index of their root
+    folders: HashMap<String, Vec<(usize, PathBuf)>>,
+}
+
+impl LibraryIndex {
+    /// Indexes the series and movie folders of every root
+    pub fn build(fs: &dyn Fs, roots: &[PathBuf]) -> Self {
+        let mut index = Self::default();
+        for (root_index, root) in roots.iter().enumerate() {
+            for section in LIBRARY_SECTIONS {
+                let Ok(entries) = fs.read_dir(&root.join(section)) else {
+                    continue;
+                };
+                for entry in entries.into_iter().filter(|entry| fs.is_dir(entry)) {
+                    let Ok(relative) = entry.strip_prefix(root) else {
+                        continue;
+                    };
+                    index
+                        .folders
+                        .entry(Self::key(relative))
+                        .or_default()
+                        .push((root_index, relative.to_path_buf()));
+                }
+            }
+        }
+        debug!("Indexed {} existing library folders", index.folders.len());
+        index
+    }
+
+    /// The key folders are matched by: case, punctuation and, for series, a trailing year
+    /// are ignored so that `TV/Show (2020)` matches `TV/show`, while `Movies/Dune (1984)`
+    /// still differs from `Movies/Dune (2021)`
+    fn key(folder: &Path) -> String {
+        let section = folder
+            .parent()
+            .map(|section| section.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let name = folder
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let name = if section == "tv" {
+            strip_year(&name)
+        } else {
+            &name
+        };
+
+        format!("{}/{}", section, normalize_title(name))
+    }
+
+    /// The existing folders matching the folder, as their root index and exact spelling.
+    /// Folders differing only by case come before the ones differing by punctuation or year
+    pub fn find(&self, folder: &Path) -> Vec<(usize, PathBuf)> {
+        let mut found = self
+            .folders
+            .get(&Self::key(folder))
+            .cloned()
+            .unwrap_or_default();
+        let lowercase = folder.to_string_lossy().to_lowercase();
+        found.sort_by_key(|(_, existing)| existing.to_string_lossy().to_lowercase() != lowercase);
+        found
+    }
+}
+
+/// Removes a trailing year in parentheses, e.g. `show (2020)` becomes `show`
+fn strip_year(name: &str) -> &str {
+    let Some(without_paren) = name.trim_end().strip_suffix(')') else {
+        return name;
+    };
+    match without_paren.rsplit_once('(') {
+        Some((before, year)) if year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) => {
+            before.trim_end()
+        }
+        _ => name,
+    }
+}
+
+/// The directories directly under an output directory that hold series and movie folders
+const LIBRARY_SECTIONS: [&str; 2] = ["TV", "Movies"];
+
+/// The output directories of a run and the placement policy choosing between them
+pub struct OutputRoots {
+    roots: Vec<PathBuf>,
+    placement: Placement,
+    index: LibraryIndex,
+    next: usize,
+    /// The folder chosen for each series or movie in this run, so that its files stay together
+    assigned: HashMap<String, PathBuf>,
+}
+
+impl OutputRoots {
+    pub fn new(fs: &dyn Fs, roots: Vec<PathBuf>, placement: Placement) -> Self {
+        assert!(!roots.is_empty(), "At least one output directory is needed");
+        Self {
+            index: LibraryIndex::build(fs, &roots),
+            roots,
+            placement,
+            next: 0,
+            assigned: HashMap::new(),
+        }
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Chooses the folder the series or movie of the media file should be placed in
+    pub fn folder_for(&mut self, fs: &dyn Fs, media_file: &MediaFile, config: &Config) -> PathBuf {
+        let title = media_file.title();
+        if let Some(folder) = self.assigned.get(&title) {
+            return folder.clone();
+        }
+
+        let folder = media_file.folder(config);
+        let existing = self.index.find(&folder);
+        let index = self.choose_root(fs, &existing);
+        // reuse the spelling of the folder already in the library on that root
+        let folder = existing
+            .into_iter()
+            .find(|(root_index, _)| *root_index == index)
+            .map(|(_, existing_folder)| existing_folder)
+            .unwrap_or(folder);
+        let folder = self.roots[index].join(folder);
+
+        debug!("Placing {} in {}", title, folder.display());
+        self.assigned.insert(title, folder.clone());
+        folder
+    }
+
+    fn choose_root(&mut self, fs: &dyn Fs, existing: &[(usize, PathBuf)]) -> usize {
+        if self.roots.len() == 1 {
+            return 0;
+        }
+
+        match self.placement {
+            Placement::MostFreeSpace => self.most_free_space(fs),
+            Placement::RoundRobin => {
+                let index = self.next;
+                self.next = (self.next + 1) % self.roots.len();
+                index
+            }
+            Placement::SeriesAffinity => existing
+                .first()
+                .map(|(root_index, _)| *root_index)
+                .unwrap_or_else(|| self.most_free_space(fs)),
+        }
+    }
+
+    fn most_free_space(&self, fs: &dyn Fs) -> usize {
+        // the first root wins ties so that the choice is deterministic
+        let mut best = 0;
+        let mut best_space = 0;
+        for (index, root) in self.roots.iter().enumerate() {
+            let space = fs.available_space(root).unwrap_or(0);
+            if space > best_space {
+                best = index;
+                best_space = space;
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{filesystem::MemoryFs, media::MediaData};
+
+    use super::*;
+
+    fn episode(name: &str, season: u32, episode: u32) -> MediaFile {
+        MediaFile::new(
+            name.to_string(),
+            MediaData::TvSeries { season, episode },
+            "mkv".to_string(),
+        )
+    }
+
+    fn roots() -> Vec<PathBuf> {
+        vec![PathBuf::from("/mnt/disk1"), PathBuf::from("/mnt/disk2")]
+    }
+
+    #[test]
+    fn most_free_space_keeps_series_together() {
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.set_available_space("/mnt/disk1", 100);
+        fs.set_available_space("/mnt/disk2", 200);
+        let mut outputs = OutputRoots::new(&fs, roots(), Placement::MostFreeSpace);
+
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Severance", 1, 1), &config),
+            Path::new("/mnt/disk2/TV/Severance")
+        );
+        fs.set_available_space("/mnt/disk2", 50);
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Severance", 1, 2), &config),
+            Path::new("/mnt/disk2/TV/Severance")
+        );
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Paradise", 1, 1), &config),
+            Path::new("/mnt/disk1/TV/Paradise")
+        );
+    }
+
+    #[test]
+    fn round_robin_alternates_between_titles() {
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        let mut outputs = OutputRoots::new(&fs, roots(), Placement::RoundRobin);
+
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Severance", 1, 1), &config),
+            Path::new("/mnt/disk1/TV/Severance")
+        );
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Paradise", 1, 1), &config),
+            Path::new("/mnt/disk2/TV/Paradise")
+        );
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Severance", 1, 2), &config),
+            Path::new("/mnt/disk1/TV/Severance")
+        );
+    }
+
+    #[test]
+    fn existing_folder_spelling_is_reused() {
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("/mnt/disk1/TV/Shameless (US)/Season 1/Shameless - s01e01.mkv", b"");
+        fs.add_file("/mnt/disk1/TV/Mr. Robot (2015)/Season 1/Mr Robot - s01e01.mkv", b"");
+        fs.add_file("/mnt/disk1/Movies/Dune (1984)/Dune (1984).mkv", b"");
+        let mut outputs = OutputRoots::new(&fs, vec![PathBuf::from("/mnt/disk1")], Placement::SeriesAffinity);
+
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Mr Robot", 1, 2), &config),
+            Path::new("/mnt/disk1/TV/Mr. Robot (2015)")
+        );
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Shameless", 1, 2), &config),
+            Path::new("/mnt/disk1/TV/Shameless")
+        );
+        let dune = MediaFile::new("Dune".to_string(), MediaData::Movie { year: 2021 }, "mkv".to_string());
+        assert_eq!(outputs.folder_for(&fs, &dune, &config), Path::new("/mnt/disk1/Movies/Dune (2021)"));
+    }
+
+    #[test]
+    fn affinity_reuses_root_and_spelling_of_existing_series() {
+        let config = Config::default();
+        let fs = MemoryFs::new();
+        fs.add_file("/mnt/disk2/TV/SEVERANCE/Season 1/Severance - s01e01.mkv", b"");
+        fs.set_available_space("/mnt/disk1", 200);
+        fs.set_available_space("/mnt/disk2", 100);
+        let mut outputs = OutputRoots::new(&fs, roots(), Placement::SeriesAffinity);
+
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Severance", 2, 1), &config),
+            Path::new("/mnt/disk2/TV/SEVERANCE")
+        );
+        assert_eq!(
+            outputs.folder_for(&fs, &episode("Paradise", 1, 1), &config),
+            Path::new("/mnt/disk1/TV/Paradise")
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    claim_companion, create_parent_dirs,
+    error::{self, Error, Stage},
+    media::MediaType,
+    perform, report_error, Context, Placed,
+};
+
+/// The destinations chosen by a run, written by the plan command to be reviewed, edited and
+/// then executed by the apply command
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Plan {
+    pub input: PathBuf,
+    pub outputs: Vec<PathBuf>,
+    pub files: Vec<PlanEntry>,
+}
+
+/// A file of a plan with the destination it is placed at
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PlanEntry {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// The series or movie the file belongs to, as grouped in the summary
+    pub title: String,
+    pub media_type: MediaType,
+}
+
+impl From<&Placed> for PlanEntry {
+    fn from(placed: &Placed) -> Self {
+        Self {
+            source: placed.source.clone(),
+            destination: placed.destination.clone(),
+            title: placed.title.clone(),
+            media_type: placed.media_type,
+        }
+    }
+}
+
+impl Plan {
+    pub fn write(&self, path: &Path) -> Result<(), io::Error> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, io::Error> {
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+/// Acts on every file of the plan, in order. The destinations are taken as they are, files
+/// whose source is gone or whose destination is already taken are left out
+pub fn apply(plan: &Plan, context: &Context) {
+    info!("Applying a plan of {} files", plan.files.len());
+    for entry in &plan.files {
+        if context.cancellation.is_cancelled() {
+            warn!("Interrupted, stopping");
+            break;
+        }
+
+        let result = apply_entry(entry, context);
+        if let Err(error) = &result {
+            report_error(error);
+        }
+        context
+            .summary
+            .lock()
+            .unwrap()
+            .record(&entry.title, entry.media_type, &entry.source, &result);
+    }
+}
+
+fn apply_entry(entry: &PlanEntry, context: &Context) -> error::Result<()> {
+    if !context.fs.is_file(&entry.source) {
+        return Err(Error::Filesystem {
+            path: entry.source.clone(),
+            stage: Stage::Move,
+            source: io::Error::new(io::ErrorKind::NotFound, "the file of the plan is gone"),
+        });
+    }
+
+    info!("Final path: {}", entry.destination.display());
+    claim_companion(&entry.source, &entry.destination, context)?;
+    create_parent_dirs(&entry.destination, context)?;
+    perform(&entry.source, &entry.destination, context)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{
+        filesystem::{Fs, MemoryFs},
+        tests::offline_context,
+        Args, Config,
+    };
+
+    use super::*;
+
+    #[test]
+    fn applies_edited_destinations_and_skips_taken_ones() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/Show.S01E01.mkv", b"one");
+        fs.add_file("downloads/Show.S01E02.mkv", b"two");
+        fs.add_file("library/Taken.mkv", b"");
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config::default();
+        let context = offline_context(&args, &config, &fs);
+
+        let plan = Plan {
+            input: PathBuf::from("downloads"),
+            outputs: vec![PathBuf::from("library")],
+            files: vec![
+                PlanEntry {
+                    source: PathBuf::from("downloads/Show.S01E01.mkv"),
+                    destination: PathBuf::from("library/Renamed/Renamed - s01e01.mkv"),
+                    title: "Renamed".to_string(),
+                    media_type: MediaType::Series,
+                },
+                PlanEntry {
+                    source: PathBuf::from("downloads/Show.S01E02.mkv"),
+                    destination: PathBuf::from("library/Taken.mkv"),
+                    title: "Renamed".to_string(),
+                    media_type: MediaType::Series,
+                },
+                PlanEntry {
+                    source: PathBuf::from("downloads/Gone.mkv"),
+                    destination: PathBuf::from("library/Gone.mkv"),
+                    title: "Gone".to_string(),
+                    media_type: MediaType::Movie,
+                },
+            ],
+        };
+        apply(&plan, &context);
+
+        assert!(fs.is_file(Path::new("library/Renamed/Renamed - s01e01.mkv")));
+        assert!(fs.is_file(Path::new("downloads/Show.S01E02.mkv")));
+        assert!(!fs.exists(Path::new("library/Gone.mkv")));
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    filesystem::Fs,
+    media::{MediaData, MediaFile},
+    provider::imdb_id,
+};
+
+/// The name of the hint file Plex reads to pin the match of a folder
+pub const PLEXMATCH_FILENAME: &str = ".plexmatch";
+
+/// The contents of the `.plexmatch` of the folder of the media file
+pub fn contents(media_file: &MediaFile) -> String {
+    let metadata = media_file.metadata();
+    let year = match media_file.media() {
+        MediaData::Movie { year } => Some(*year),
+        MediaData::TvSeries { .. } | MediaData::Daily { .. } | MediaData::Absolute { .. } => metadata.year,
+    };
+
+    let mut contents = format!("title: {}\n", media_file.name());
+    if let Some(year) = year {
+        contents.push_str(&format!("year: {}\n", year));
+    }
+    if let Some(tvdb_id) = metadata.tvdb_id {
+        contents.push_str(&format!("tvdbid: {}\n", tvdb_id));
+    }
+    if let Some(tmdb_id) = metadata.tmdb_id {
+        contents.push_str(&format!("tmdbid: {}\n", tmdb_id));
+    }
+    if let Some(number) = metadata.imdb_id {
+        contents.push_str(&format!("imdbid: {}\n", imdb_id(number)));
+    }
+    contents
+}
+
+/// Writes the `.plexmatch` into the folder unless it already has one.
+/// Returns whether the file was written
+pub fn write(fs: &dyn Fs, folder: &Path, media_file: &MediaFile) -> Result<bool, io::Error> {
+    let path = folder.join(PLEXMATCH_FILENAME);
+    if fs.exists(&path) {
+        return Ok(false);
+    }
+
+    let mut file = fs.open_write(&path, false)?;
+    file.write_all(contents(media_file).as_bytes())?;
+    file.flush()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{filesystem::MemoryFs, media::Metadata};
+
+    use super::*;
+
+    #[test]
+    fn writes_title_year_and_id_once() {
+        let fs = MemoryFs::new();
+        fs.create_dir_all(Path::new("TV/Severance")).unwrap();
+        let mut media_file = MediaFile::new(
+            "Severance".to_string(),
+            MediaData::TvSeries { season: 2, episode: 1 },
+            "mkv".to_string(),
+        );
+        media_file.set_metadata(Metadata {
+            tvdb_id: Some(371980),
+            year: Some(2022),
+            ..Metadata::default()
+        });
+
+        assert!(write(&fs, Path::new("TV/Severance"), &media_file).unwrap());
+        assert!(!write(&fs, Path::new("TV/Severance"), &media_file).unwrap());
+        assert_eq!(
+            fs.node("TV/Severance/.plexmatch"),
+            Some(crate::filesystem::MemoryNode::File(
+                b"title: Severance\nyear: 2022\ntvdbid: 371980\n".to_vec()
+            ))
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{path_utils::get_extension, Config};
+
+/// What is done with the files of an extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtensionPolicy {
+    /// Parsed, matched and placed in the library
+    Process,
+    /// Placed next to the processed file it belongs to, named after it
+    Subtitle,
+    /// Placed in the folder of the processed file of its directory, like posters and NFOs
+    Follow,
+    Ignore,
+    /// Deleted when the action is move, so that nothing is left behind in the downloads
+    Delete,
+}
+
+/// The extension of the files holding the address of a stream, which media servers play like
+/// the video. Their contents are all there is to them
+const STREAM_EXTENSION: &str = "strm";
+
+/// The extensions processed by default: videos, disc images that Plex plays as versions of the
+/// movie, and streams
+pub fn default_extensions() -> Vec<String> {
+    ["mkv", "srr", "iso", "img", STREAM_EXTENSION].map(str::to_string).to_vec()
+}
+
+/// Whether the file holds the address of a stream rather than a video
+pub fn is_stream(path: &Path) -> bool {
+    get_extension(path).is_some_and(|extension| extension.eq_ignore_ascii_case(STREAM_EXTENSION))
+}
+
+/// The policies of the extensions that are not processed by default
+pub fn default_extension_policies() -> BTreeMap<String, ExtensionPolicy> {
+    let subtitles = ["srt", "ass", "ssa", "sub", "idx", "vtt"];
+    let follow = ["nfo", "jpg", "jpeg", "png"];
+    subtitles
+        .iter()
+        .map(|extension| (extension.to_string(), ExtensionPolicy::Subtitle))
+        .chain(
+            follow
+                .iter()
+                .map(|extension| (extension.to_string(), ExtensionPolicy::Follow)),
+        )
+        .collect()
+}
+
+/// The policy of the file: the one configured for its extension, processing for the extensions
+/// in `extensions` and ignoring for any other. Extensions are compared case-insensitively
+pub fn policy_for(path: &Path, config: &Config) -> ExtensionPolicy {
+    let Some(extension) = get_extension(path) else {
+        return ExtensionPolicy::Ignore;
+    };
+    let extension = extension.to_lowercase();
+
+    if let Some((_, policy)) = config
+        .extension_policies
+        .iter()
+        .find(|(configured, _)| configured.to_lowercase() == extension)
+    {
+        return *policy;
+    }
+    if config
+        .extensions
+        .iter()
+        .any(|configured| configured.to_lowercase() == extension)
+    {
+        return ExtensionPolicy::Process;
+    }
+    ExtensionPolicy::Ignore
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_fall_back_to_extensions() {
+        let mut config = Config::default();
+        config
+            .extension_policies
+            .insert("txt".to_string(), ExtensionPolicy::Delete);
+
+        assert_eq!(policy_for(Path::new("Show.S01E01.MKV"), &config), ExtensionPolicy::Process);
+        assert_eq!(policy_for(Path::new("Show.S01E01.en.srt"), &config), ExtensionPolicy::Subtitle);
+        assert_eq!(policy_for(Path::new("poster.jpg"), &config), ExtensionPolicy::Follow);
+        assert_eq!(policy_for(Path::new("RARBG.txt"), &config), ExtensionPolicy::Delete);
+        assert_eq!(policy_for(Path::new("setup.exe"), &config), ExtensionPolicy::Ignore);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use indicatif::HumanBytes;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, filesystem::Fs, Action};
+
+/// The name of the files written into the output directories to probe them
+const PROBE_FILENAME: &str = ".media-renamer-probe";
+
+/// Checks that every output directory can take the files of the run before any of them is
+/// processed, so that a bad output fails the run at once instead of failing every file
+pub fn check_outputs(fs: &dyn Fs, input: &Path, outputs: &[PathBuf], action: Action) -> Result<(), Error> {
+    let input = fs.canonicalize(input).unwrap_or_else(|_| input.to_path_buf());
+    for output in outputs {
+        check_output(fs, &input, output, action)?;
+    }
+    Ok(())
+}
+
+fn check_output(fs: &dyn Fs, input: &Path, output: &Path, action: Action) -> Result<(), Error> {
+    let failed = |message: &str| Error::Preflight {
+        path: output.to_path_buf(),
+        message: message.to_string(),
+    };
+
+    if !fs.is_dir(output) {
+        return Err(failed("does not exist or is not a directory, create it or fix the --output path"));
+    }
+
+    let canonical = fs
+        .canonicalize(output)
+        .map_err(|error| failed(&format!("cannot be resolved: {}", error)))?;
+    if fs.is_dir(input) && canonical.starts_with(input) {
+        return Err(failed(&format!(
+            "is inside the input directory {}, the imported files would be found again by the next run",
+            input.display()
+        )));
+    }
+
+    // the test action writes nothing, there is nothing more to check
+    if matches!(action, Action::Test) {
+        return Ok(());
+    }
+
+    let probe = output.join(PROBE_FILENAME);
+    fs.open_write(&probe, false)
+        .and_then(|mut file| file.write_all(b"probe"))
+        .map_err(|error| failed(&format!("is not writable: {}", error)))?;
+
+    let result = match action {
+        Action::Symlink => check_symlinks(fs, &probe),
+        Action::Hardlink => check_hard_links(fs, input, &probe),
+        _ => Ok(()),
+    };
+
+    if let Err(error) = fs.remove_file(&probe) {
+        debug!("Could not remove {}: {}", probe.display(), error);
+    }
+    result.map_err(|message| failed(&message))
+}
+
+/// What is done when an output directory has too little free space for the files of the run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LowSpace {
+    /// No file is placed, they are all failed
+    #[default]
+    Abort,
+    /// The files are placed anyway, the ones that do not fit fail when they are copied
+    Warn,
+}
+
+/// Checks that the output directories have the space the run needs in them, bytes by output
+/// directory, with `margin` bytes to spare
+pub fn check_free_space(fs: &dyn Fs, needed: &BTreeMap<PathBuf, u64>, margin: u64) -> Result<(), Error> {
+    for (output, &bytes) in needed {
+        let available = match fs.available_space(output) {
+            Ok(available) => available,
+            Err(error) => {
+                debug!("Could not tell the free space of {}: {}", output.display(), error);
+                continue;
+            }
+        };
+        if bytes.saturating_add(margin) > available {
+            return Err(Error::Preflight {
+                path: output.to_path_buf(),
+                message: format!(
+                    "has {} free, the files of the run need {} and {} are kept free",
+                    HumanBytes(available),
+                    HumanBytes(bytes),
+                    HumanBytes(margin)
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Creates a symlink next to the probe file, some filesystems and Windows users without
+/// the right privilege cannot
+fn check_symlinks(fs: &dyn Fs, probe: &Path) -> Result<(), String> {
+    let link = probe.with_extension("link");
+    fs.symlink(probe, &link).map_err(|error| {
+        format!(
+            "does not support symlinks ({}), use --action copy or move instead",
+            error
+        )
+    })?;
+    if let Err(error) = fs.remove_file(&link) {
+        debug!("Could not remove {}: {}", link.display(), error);
+    }
+    Ok(())
+}
+
+/// Hard links only work within a filesystem, so the input has to be on the same one as the output
+fn check_hard_links(fs: &dyn Fs, input: &Path, probe: &Path) -> Result<(), String> {
+    let source = if fs.is_dir(input) {
+        input.join(PROBE_FILENAME)
+    } else {
+        input.to_path_buf()
+    };
+    let created = source != input && !fs.exists(&source);
+    if created {
+        fs.open_write(&source, false)
+            .and_then(|mut file| file.write_all(b"probe"))
+            .map_err(|error| format!("cannot be checked for hard links, the input is not writable: {}", error))?;
+    }
+
+    let link = probe.with_extension("link");
+    let result = fs.hard_link(&source, &link).map_err(|error| {
+        format!(
+            "cannot hold hard links to the input ({}), it must be on the same filesystem, otherwise use --action copy",
+            error
+        )
+    });
+    for path in [Some(link.as_path()), created.then_some(source.as_path())].into_iter().flatten() {
+        if fs.exists(path) {
+            if let Err(error) = fs.remove_file(path) {
+                debug!("Could not remove {}: {}", path.display(), error);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filesystem::MemoryFs;
+
+    use super::*;
+
+    #[test]
+    fn rejects_missing_and_nested_outputs() {
+        let fs = MemoryFs::new();
+        fs.add_file("downloads/library/TV/.keep", b"");
+        fs.add_file("library/.keep", b"");
+        let check = |output: &str| {
+            check_outputs(&fs, Path::new("downloads"), &[PathBuf::from(output)], Action::Symlink)
+        };
+
+        assert!(check("library").is_ok());
+        assert!(matches!(check("missing"), Err(Error::Preflight { .. })));
+        assert!(matches!(check("downloads/library"), Err(Error::Preflight { .. })));
+        // the probes are cleaned up
+        assert_eq!(fs.paths().iter().filter(|path| path.starts_with("library")).count(), 2);
+    }
+}
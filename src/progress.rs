@@ -0,0 +1,84 @@
+use std::{path::Path, sync::LazyLock, time::Duration};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{LevelFilter, Log, Metadata, Record};
+use simplelog::{Config, SharedLogger};
+
+/// The progress bars of the run, hidden until shown by `show` so that tests and the subcommands
+/// draw nothing
+static BARS: LazyLock<MultiProgress> = LazyLock::new(|| MultiProgress::with_draw_target(ProgressDrawTarget::hidden()));
+
+/// Draws the progress bars on the terminal, they are still hidden when it is redirected
+pub fn show() {
+    BARS.set_draw_target(ProgressDrawTarget::stderr());
+}
+
+/// A spinner counting the files found while the input directory is scanned
+pub fn scanning(input: &Path) -> ProgressBar {
+    let bar = BARS.add(ProgressBar::new_spinner());
+    bar.set_style(ProgressStyle::with_template("{spinner} Scanning {msg}: {pos} files").unwrap());
+    bar.set_message(input.display().to_string());
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
+
+/// A bar of the files of the run, showing the one being processed
+pub fn files(count: usize) -> ProgressBar {
+    let bar = BARS.add(ProgressBar::new(count as u64));
+    bar.set_style(
+        ProgressStyle::with_template("[{elapsed_precise}] {bar:30} {pos}/{len} files, {eta} left {wide_msg}").unwrap(),
+    );
+    bar
+}
+
+/// A bar of the bytes of a file being copied
+pub fn copy(source: &Path, total: u64) -> ProgressBar {
+    let bar = BARS.add(ProgressBar::new(total));
+    bar.set_style(
+        ProgressStyle::with_template("  {bar:30} {bytes}/{total_bytes} {bytes_per_sec} {wide_msg}").unwrap(),
+    );
+    bar.set_message(source.file_name().unwrap_or_default().to_string_lossy().into_owned());
+    bar
+}
+
+/// Hides the progress bars while another logger writes to the terminal, so that its lines are
+/// not drawn over by them
+pub struct ProgressLogger {
+    inner: Box<dyn SharedLogger>,
+}
+
+impl ProgressLogger {
+    pub fn new(inner: Box<dyn SharedLogger>) -> Box<Self> {
+        Box::new(Self { inner })
+    }
+}
+
+impl Log for ProgressLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.enabled(record.metadata()) {
+            BARS.suspend(|| self.inner.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+impl SharedLogger for ProgressLogger {
+    fn level(&self) -> LevelFilter {
+        self.inner.level()
+    }
+
+    fn config(&self) -> Option<&Config> {
+        self.inner.config()
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        self
+    }
+}
@@ -0,0 +1,644 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    Client, StatusCode,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::{
+    cache::ResponseCache,
+    media::{MediaType, Metadata},
+    recording::{request_key, RecordedResponse, Recording, RecordingMode},
+};
+
+/// How many times a request failing in a way that may not last is sent again
+const MAX_RETRIES: u32 = 4;
+
+/// The wait before the first retry, doubled for every next one
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// How many times a rate limited request is sent again. The client slows down each time, so
+/// this is more than the retries of other failures
+const MAX_RATE_LIMITED_RETRIES: u32 = 10;
+
+/// The time between two requests once the API first rate limits, doubled each time it does again
+const THROTTLE_BASE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The most time between two requests, however many times the API rate limits
+const THROTTLE_MAX_INTERVAL: Duration = Duration::from_secs(8);
+
+/// Where series and movies are matched and their episodes looked up
+pub trait MetadataProvider: Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    /// Searches series or movies by name, the best match first, restricted to the ones of the
+    /// year when it is given
+    fn search(&self, name: &str, media_type: MediaType, year: Option<u32>) -> Result<Vec<SearchResult>, ProviderError>;
+
+    /// Gets the series or movie with the given id
+    fn record(&self, id: u64, media_type: MediaType) -> Result<Record, ProviderError>;
+
+    /// Finds the series or movie with the given IMDb id, the number of `tt0111161`
+    fn find_by_imdb_id(&self, imdb_id: u64, media_type: MediaType) -> Result<Option<Record>, ProviderError>;
+
+    /// Gets the episodes of the series with the given season and episode number
+    fn episodes(&self, series_id: u64, season: u32, episode: u32) -> Result<Vec<Episode>, ProviderError>;
+
+    /// Gets every episode of the season of the series
+    fn season_episodes(&self, series_id: u64, season: u32) -> Result<Vec<Episode>, ProviderError>;
+
+    /// Gets the episodes of the series aired on the date, given as `YYYY-MM-DD`
+    fn episodes_aired(&self, series_id: u64, air_date: &str) -> Result<Vec<Episode>, ProviderError>;
+
+    /// Finds the episode of the series with the given absolute number, counting from the first
+    /// episode of the first season
+    fn episode_by_absolute_number(&self, series_id: u64, absolute_number: u32) -> Result<Option<Episode>, ProviderError>;
+
+    /// Finds the episode of the series with the given season and number in the order of the DVD
+    /// release, with its season and number as aired. None when the provider has no DVD order of it
+    fn episode_in_dvd_order(&self, series_id: u64, season: u32, episode: u32) -> Result<Option<Episode>, ProviderError>;
+
+    /// Gets the content ratings of the series or movie with the given id, in every country
+    fn content_ratings(&self, id: u64, media_type: MediaType) -> Result<Vec<ContentRating>, ProviderError>;
+
+    /// Gets the genres of the series or movie with the given id, like `Animation` or `Documentary`
+    fn genres(&self, id: u64, media_type: MediaType) -> Result<Vec<String>, ProviderError>;
+
+    /// Starts the searches the lookups of a run begin with, without waiting for them, so that the
+    /// lookups find their responses received or on their way. Providers that send one request at
+    /// a time leave them to the lookups
+    fn prefetch(&self, _searches: Vec<Search>) {}
+
+    /// Stops the searches started by [`MetadataProvider::prefetch`] that are still running, at the
+    /// end of the run
+    fn stop_prefetch(&self) {}
+}
+
+/// A search of a series or movie by name, restricted to the ones of the year when it is given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Search {
+    pub name: String,
+    pub media_type: MediaType,
+    pub year: Option<u32>,
+}
+
+/// The metadata providers, chosen with `provider` in the configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    Tvdb,
+    Tmdb,
+    Tvmaze,
+    Omdb,
+}
+
+impl ProviderKind {
+    /// The id of the series or movie at this provider
+    pub fn id(&self, metadata: &Metadata) -> Option<u64> {
+        match self {
+            ProviderKind::Tvdb => metadata.tvdb_id,
+            ProviderKind::Tmdb => metadata.tmdb_id,
+            ProviderKind::Tvmaze => metadata.tvmaze_id,
+            ProviderKind::Omdb => metadata.imdb_id,
+        }
+    }
+
+    /// The metadata of a series or movie found at this provider
+    pub fn metadata(&self, id: Option<u64>, year: Option<u32>) -> Metadata {
+        let mut metadata = Metadata {
+            year,
+            ..Metadata::default()
+        };
+        match self {
+            ProviderKind::Tvdb => metadata.tvdb_id = id,
+            ProviderKind::Tmdb => metadata.tmdb_id = id,
+            ProviderKind::Tvmaze => metadata.tvmaze_id = id,
+            ProviderKind::Omdb => metadata.imdb_id = id,
+        }
+        metadata
+    }
+}
+
+impl Display for ProviderKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderKind::Tvdb => write!(f, "TVDB"),
+            ProviderKind::Tmdb => write!(f, "TMDB"),
+            ProviderKind::Tvmaze => write!(f, "TVmaze"),
+            ProviderKind::Omdb => write!(f, "OMDb"),
+        }
+    }
+}
+
+/// A series or movie found by a search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub name: String,
+    pub id: Option<u64>,
+    pub year: Option<u32>,
+}
+
+impl Display for SearchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(year) = self.year {
+            write!(f, " ({})", year)?;
+        }
+        if let Some(id) = self.id {
+            write!(f, " id {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// A series or movie as returned by its id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub id: u64,
+    pub name: String,
+    pub year: Option<u32>,
+}
+
+/// An episode of a series
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Episode {
+    pub name: Option<String>,
+    pub season_number: u32,
+    pub number: u32,
+    /// The number of the episode counting from the first one of the series, used by anime releases
+    pub absolute_number: Option<u32>,
+}
+
+/// The rating of a series or movie in a country, e.g. `PG` in `usa`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentRating {
+    pub name: String,
+    pub country: String,
+}
+
+/// Writes the number of an IMDb id as the id, 111161 is `tt0111161`
+pub fn imdb_id(number: u64) -> String {
+    format!("tt{:07}", number)
+}
+
+/// Reads the number of an IMDb id like `tt0111161`
+pub fn parse_imdb_id(id: &str) -> Option<u64> {
+    id.strip_prefix("tt")?.parse().ok()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    #[error("Request error: {0}")]
+    RequestError(#[from] reqwest::Error),
+    #[error("Parse error: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("HTTP error: {0}")]
+    HttpError(StatusCode),
+    #[error("No recorded response for {0}")]
+    NotRecorded(String),
+}
+
+impl ProviderError {
+    /// Whether the provider could not be reached at all, because of the network or of an outage
+    /// on its side, rather than rejecting the request
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            ProviderError::RequestError(error) => error.is_connect() || error.is_timeout(),
+            ProviderError::HttpError(status) => status.is_server_error(),
+            ProviderError::ParseError(_) | ProviderError::NotRecorded(_) => false,
+        }
+    }
+}
+
+/// The runtime the requests of every provider are sent on, started by the first one
+pub fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("the runtime of the providers could not be started")
+    })
+}
+
+/// Waits for the future on the runtime of the providers, from a thread that is not one of its own
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    runtime().block_on(future)
+}
+
+/// The HTTP side of a provider. Clones share the token, the connection pool and the responses,
+/// so a single client can be handed to every worker of a run. Requests are sent without blocking,
+/// many at once from the tasks of the runtime, while [`ApiClient::get_blocking`] waits for one
+/// from the thread of a worker
+#[derive(Clone)]
+pub struct ApiClient {
+    base_url: &'static str,
+    client: Client,
+    token: Arc<RwLock<Option<String>>>,
+    recording: Option<Arc<Recording>>,
+    /// The responses of the previous runs, shared by the providers
+    cache: Option<Arc<ResponseCache>>,
+    /// A query parameter carrying the API key, sent with every request but left out of the
+    /// recorded requests
+    key_param: Option<(&'static str, String)>,
+    /// The responses of this run by request, so each series or movie is looked up once.
+    /// Workers asking for the same request wait on the slot of the first one instead of
+    /// sending the request again
+    responses: Arc<Mutex<HashMap<String, ResponseSlot>>>,
+    /// Gets a new token when the API answers that the current one expired
+    refresh: Option<Refresh>,
+    /// Held while the token is refreshed, so that workers rejected together log in once
+    refreshing: Arc<tokio::sync::Mutex<()>>,
+    /// The pace of the requests of every worker
+    throttle: Arc<Mutex<Throttle>>,
+}
+
+/// The body of a response, empty until the request succeeded
+type ResponseSlot = Arc<tokio::sync::Mutex<Option<String>>>;
+
+/// A request sent by a task of the runtime
+pub type Pending<T> = Pin<Box<dyn Future<Output = Result<T, ProviderError>> + Send>>;
+
+/// Logs in again through the client and returns the new token
+type Refresh = Arc<dyn Fn(ApiClient) -> Pending<String> + Send + Sync>;
+
+impl ApiClient {
+    pub fn new(base_url: &'static str) -> Self {
+        Self {
+            base_url,
+            client: Client::new(),
+            token: Arc::new(RwLock::new(None)),
+            recording: None,
+            cache: None,
+            key_param: None,
+            responses: Arc::new(Mutex::new(HashMap::new())),
+            refresh: None,
+            refreshing: Arc::new(tokio::sync::Mutex::new(())),
+            throttle: Arc::new(Mutex::new(Throttle::default())),
+        }
+    }
+
+    /// Records the API responses to, or replays them from, the given recording, which can be
+    /// shared by several providers
+    pub fn with_recording(mut self, recording: impl Into<Arc<Recording>>) -> Self {
+        self.recording = Some(recording.into());
+        self
+    }
+
+    /// Keeps the responses in the given cache and serves them from it on later runs
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Sends the API key as the given query parameter of every request
+    pub fn with_key_param(mut self, name: &'static str, key: String) -> Self {
+        self.key_param = Some((name, key));
+        self
+    }
+
+    /// Whether the responses are served from a recording, which needs no authentication
+    pub fn is_replaying(&self) -> bool {
+        self.recording
+            .as_ref()
+            .is_some_and(|recording| recording.mode() == RecordingMode::Replay)
+    }
+
+    /// Logs in again with the given function and retries when a request is rejected as unauthorized
+    pub fn with_refresh<F>(mut self, refresh: F) -> Self
+    where
+        F: Fn(ApiClient) -> Pending<String> + Send + Sync + 'static,
+    {
+        self.refresh = Some(Arc::new(refresh));
+        self
+    }
+
+    /// Sets the bearer token sent with every request
+    pub fn set_token(&self, token: String) {
+        *self.token.write().unwrap() = Some(token);
+    }
+
+    /// Sends a JSON body to the endpoint and returns the body of the reply. It is neither
+    /// recorded nor kept, as it is meant for logging in
+    pub async fn post(&self, endpoint: &str, body: String) -> Result<String, ProviderError> {
+        let res = self
+            .client
+            .post(format!("{}{}", self.base_url, endpoint))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?;
+
+        if res.status() != StatusCode::OK {
+            return Err(ProviderError::HttpError(res.status()));
+        }
+        Ok(res.text().await?)
+    }
+
+    /// Like [`ApiClient::get`], waiting for the reply on the calling thread
+    pub fn get_blocking(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<String, ProviderError> {
+        block_on(self.get(endpoint, query))
+    }
+
+    /// Sends a GET request to the endpoint and returns the body of the reply, or the body
+    /// already received for the same request in this run. Failed requests are not kept
+    pub async fn get(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<String, ProviderError> {
+        let key = request_key(endpoint, query);
+        let slot = self
+            .responses
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        // held for the whole request so that concurrent identical requests collapse into one
+        let mut slot = slot.lock().await;
+        if let Some(body) = slot.as_ref() {
+            return Ok(body.clone());
+        }
+
+        let body = self.send(&key, endpoint, query).await?;
+        *slot = Some(body.clone());
+        Ok(body)
+    }
+
+    /// Sends the request, again after a growing wait when it fails in a way that may not last,
+    /// like a server error or a lost connection. When it is rate limited it is sent again after
+    /// the wait the API asks for in `Retry-After`, and the requests are slowed down for the rest of the run
+    async fn fetch(
+        &self,
+        endpoint: &str,
+        query: &[(&str, &str)],
+        token: Option<&str>,
+    ) -> Result<(StatusCode, String), ProviderError> {
+        let mut attempt = 0;
+        let mut rate_limited = 0;
+        loop {
+            let result = self.fetch_once(endpoint, query, token).await;
+            match &result {
+                Ok((StatusCode::TOO_MANY_REQUESTS, retry_after, _)) if rate_limited < MAX_RATE_LIMITED_RETRIES => {
+                    let delay = retry_after.unwrap_or_else(|| backoff(rate_limited));
+                    let interval = self.throttle.lock().unwrap().slow_down(delay);
+                    warn!(
+                        "{} answered {}, retrying in {:.1}s and sending at most one request every {:.2}s from now on",
+                        endpoint,
+                        StatusCode::TOO_MANY_REQUESTS,
+                        delay.as_secs_f32(),
+                        interval.as_secs_f32()
+                    );
+                    rate_limited += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let transient = match &result {
+                Ok((status, _, _)) => status.is_server_error(),
+                Err(ProviderError::RequestError(error)) => error.is_timeout() || error.is_connect(),
+                Err(_) => false,
+            };
+            if !transient || attempt == MAX_RETRIES {
+                return result.map(|(status, _, text)| (status, text));
+            }
+
+            let delay = backoff(attempt);
+            match &result {
+                Ok((status, _, _)) => warn!("{} answered {}, retrying in {:.1}s", endpoint, status, delay.as_secs_f32()),
+                Err(error) => warn!("{} failed ({}), retrying in {:.1}s", endpoint, error, delay.as_secs_f32()),
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends the request once, when the throttle allows it. Returns the status, the wait asked
+    /// for by `Retry-After` and the body
+    async fn fetch_once(
+        &self,
+        endpoint: &str,
+        query: &[(&str, &str)],
+        token: Option<&str>,
+    ) -> Result<(StatusCode, Option<Duration>, String), ProviderError> {
+        let wait = self.throttle.lock().unwrap().reserve(Instant::now());
+        tokio::time::sleep(wait).await;
+
+        let mut request = self.client.get(format!("{}{}", self.base_url, endpoint)).query(query);
+        if let Some((name, key)) = &self.key_param {
+            request = request.query(&[(name, key)]);
+        }
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let res = request.send().await?;
+
+        let status = res.status();
+        let retry_after = res
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now()));
+        Ok((status, retry_after, res.text().await?))
+    }
+
+    /// Replaces the token a request was rejected with, unless another worker already did.
+    /// Returns the token to retry with, none when the client cannot log in again
+    async fn refresh_token(&self, rejected: Option<String>) -> Result<Option<String>, ProviderError> {
+        let Some(refresh) = &self.refresh else {
+            return Ok(None);
+        };
+
+        let _refreshing = self.refreshing.lock().await;
+        let current = self.token.read().unwrap().clone();
+        if current != rejected {
+            return Ok(current);
+        }
+
+        info!("The token was rejected, logging in again");
+        let token = refresh(self.clone()).await?;
+        self.set_token(token.clone());
+        Ok(Some(token))
+    }
+
+    /// Sends a GET request to the endpoint and returns the body of the reply, going through the
+    /// recording if there is one and otherwise through the cache
+    async fn send(&self, key: &str, endpoint: &str, query: &[(&str, &str)]) -> Result<String, ProviderError> {
+        // the providers share the cache, so its keys tell them apart by their base URL
+        let cache_key = format!("{}{}", self.base_url, key);
+        let cache = self.cache.as_ref().filter(|_| self.recording.is_none());
+        if let Some(body) = cache.and_then(|cache| cache.get(&cache_key)) {
+            return Ok(body);
+        }
+
+        if let Some(recording) = &self.recording {
+            if recording.mode() == RecordingMode::Replay {
+                let response = recording
+                    .get(key)
+                    .ok_or_else(|| ProviderError::NotRecorded(key.to_string()))?;
+                let status = StatusCode::from_u16(response.status)
+                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                if status != StatusCode::OK {
+                    return Err(ProviderError::HttpError(status));
+                }
+                return Ok(response.body);
+            }
+        }
+
+        let token = self.token.read().unwrap().clone();
+        let (mut status, mut text) = self.fetch(endpoint, query, token.as_deref()).await?;
+        if status == StatusCode::UNAUTHORIZED {
+            if let Some(token) = self.refresh_token(token).await? {
+                (status, text) = self.fetch(endpoint, query, Some(&token)).await?;
+            }
+        }
+
+        if let Some(recording) = &self.recording {
+            recording.insert(
+                key.to_string(),
+                RecordedResponse {
+                    status: status.as_u16(),
+                    body: text.clone(),
+                },
+            );
+        }
+
+        if status != StatusCode::OK {
+            return Err(ProviderError::HttpError(status));
+        }
+
+        if let Some(cache) = cache {
+            cache.insert(cache_key, text.clone());
+        }
+        Ok(text)
+    }
+}
+
+/// The pace of the requests of a client: unlimited until the API rate limits, then at most one
+/// request every interval, the interval doubling each time the API rate limits again
+#[derive(Debug, Default)]
+struct Throttle {
+    interval: Duration,
+    /// When the next request can be sent, none when it can be sent right away
+    next: Option<Instant>,
+}
+
+impl Throttle {
+    /// Takes the next slot to send a request in and returns how long to wait for it
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let start = self.next.map_or(now, |next| next.max(now));
+        if !self.interval.is_zero() {
+            self.next = Some(start + self.interval);
+        }
+        start - now
+    }
+
+    /// Holds every request back for the delay and slows the requests down for the rest of the
+    /// run. Returns the new interval between requests
+    fn slow_down(&mut self, delay: Duration) -> Duration {
+        self.interval = (self.interval * 2).clamp(THROTTLE_BASE_INTERVAL, THROTTLE_MAX_INTERVAL);
+        let resume = Instant::now() + delay;
+        self.next = Some(self.next.map_or(resume, |next| next.max(resume)));
+        self.interval
+    }
+}
+
+/// The wait asked for by a `Retry-After` header, given either in seconds or as the date to retry
+/// at, which is no wait when it is already past
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// The wait before the retry after the given number of failed ones: doubling from the base
+/// delay, plus up to half of it again so that workers rejected together do not retry together
+fn backoff(attempt: u32) -> Duration {
+    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or(0);
+    delay + delay.mul_f64(f64::from(nanos % 1000) / 2000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn clones_share_responses_across_threads() {
+        let api = ApiClient::new("").with_recording(Recording::replaying(&[(
+            request_key("/search", &[("q", "Severance")]),
+            200,
+            "Severance",
+        )]));
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let api = api.clone();
+                thread::spawn(move || api.get_blocking("/search", &[("q", "Severance")]).unwrap())
+            })
+            .collect();
+
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), "Severance");
+        }
+        assert_eq!(api.responses.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn retries_wait_longer_each_time() {
+        for attempt in 0..MAX_RETRIES {
+            let delay = backoff(attempt);
+            let base = RETRY_BASE_DELAY * 2u32.pow(attempt);
+            assert!(delay >= base && delay < base * 3 / 2);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_read_in_seconds_or_as_a_date() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_445_412_480);
+        assert_eq!(parse_retry_after(" 120 ", now), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:30:00 GMT", now),
+            Some(Duration::from_secs(1_445_412_600 - 1_445_412_480))
+        );
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn rate_limits_slow_down_the_following_requests() {
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        assert_eq!(throttle.reserve(now), Duration::ZERO);
+        assert_eq!(throttle.reserve(now), Duration::ZERO);
+
+        assert_eq!(throttle.slow_down(Duration::ZERO), THROTTLE_BASE_INTERVAL);
+        assert_eq!(throttle.slow_down(Duration::ZERO), THROTTLE_BASE_INTERVAL * 2);
+        let now = Instant::now() + Duration::from_secs(1);
+        assert_eq!(throttle.reserve(now), Duration::ZERO);
+        assert_eq!(throttle.reserve(now), THROTTLE_BASE_INTERVAL * 2);
+        assert_eq!(throttle.reserve(now), THROTTLE_BASE_INTERVAL * 4);
+
+        for _ in 0..10 {
+            throttle.slow_down(Duration::ZERO);
+        }
+        assert_eq!(throttle.interval, THROTTLE_MAX_INTERVAL);
+    }
+}
@@ -0,0 +1,107 @@
+use std::{cmp::Ordering, fmt::Display, sync::LazyLock};
+
+use regex::Regex;
+
+/// A resolution tag like `1080p` or `4K`
+static RESOLUTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:(2160|1080|720|576|480)[pi]|(4k|uhd))\b").unwrap());
+
+/// The source tags of releases, by the name they are shown with, best first
+const SOURCES: [(&str, &[&str]); 6] = [
+    ("Remux", &["remux"]),
+    ("BluRay", &["bluray", "blu-ray", "bdrip", "brrip", "uhdbd"]),
+    ("WEB-DL", &["web-dl", "webdl"]),
+    ("WEBRip", &["webrip"]),
+    ("HDTV", &["hdtv"]),
+    ("DVD", &["dvdrip", "dvd"]),
+];
+
+/// The quality of a release as told by the tags of its filename
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quality {
+    /// The vertical resolution, e.g. 1080
+    pub resolution: Option<u32>,
+    pub source: Option<&'static str>,
+}
+
+impl Quality {
+    /// Reads the resolution and source tags of the filename
+    pub fn parse(name: &str) -> Self {
+        let resolution = RESOLUTION.captures(name).and_then(|captures| match captures.get(1) {
+            Some(lines) => lines.as_str().parse().ok(),
+            None => Some(2160),
+        });
+
+        let words: Vec<String> = name
+            .split(|c: char| !(c.is_alphanumeric() || c == '-'))
+            .map(|word| word.to_lowercase())
+            .collect();
+        let source = SOURCES
+            .iter()
+            .find(|(_, tags)| words.iter().any(|word| tags.contains(&word.as_str())))
+            .map(|(source, _)| *source);
+
+        Self { resolution, source }
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.resolution.is_some() || self.source.is_some()
+    }
+
+    /// How the quality compares with the other one: by resolution, then by source when the
+    /// resolutions are the same. None when the tags of the two do not tell
+    pub fn compare(&self, other: &Quality) -> Option<Ordering> {
+        let rank = |source: &str| SOURCES.iter().position(|(name, _)| *name == source);
+        // the best source comes first in the table
+        let sources = match (self.source.and_then(rank), other.source.and_then(rank)) {
+            (Some(own), Some(other)) => Some(other.cmp(&own)),
+            _ => None,
+        };
+        match (self.resolution, other.resolution) {
+            (Some(own), Some(other)) if own != other => Some(own.cmp(&other)),
+            (Some(_), Some(_)) => Some(sources.unwrap_or(Ordering::Equal)),
+            _ => sources,
+        }
+    }
+}
+
+impl Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.resolution, self.source) {
+            (Some(resolution), Some(source)) => write!(f, "{}p {}", resolution, source),
+            (Some(resolution), None) => write!(f, "{}p", resolution),
+            (None, Some(source)) => write!(f, "{}", source),
+            (None, None) => write!(f, "unknown quality"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_read_from_the_filename() {
+        assert_eq!(
+            Quality::parse("Conclave.2024.2160p.UHD.BluRay.x265-SURCODE.mkv").to_string(),
+            "2160p BluRay"
+        );
+        assert_eq!(
+            Quality::parse("Anora.2024.2160p.iT.WEB-DL.DDP5.1.DV.HDR.H.265-DRX.mkv").to_string(),
+            "2160p WEB-DL"
+        );
+        assert_eq!(Quality::parse("Show.S01E01.720p.HDTV.x264.mkv").to_string(), "720p HDTV");
+        assert_eq!(Quality::parse("Show.S01E01.4K.mkv").resolution, Some(2160));
+        assert!(!Quality::parse("Show - s01e01.mkv").is_known());
+    }
+
+    #[test]
+    fn resolution_counts_before_source() {
+        let compare = |new: &str, existing: &str| Quality::parse(new).compare(&Quality::parse(existing));
+        assert_eq!(compare("Show.S01E01.1080p.HDTV", "Show.S01E01.720p.BluRay"), Some(Ordering::Greater));
+        assert_eq!(compare("Show.S01E01.1080p.WEB-DL", "Show.S01E01.1080p.BluRay"), Some(Ordering::Less));
+        assert_eq!(compare("Show.S01E01.1080p", "Show.S01E01.1080p.BluRay"), Some(Ordering::Equal));
+        assert_eq!(compare("Show.S01E01.BluRay", "Show.S01E01.720p.HDTV"), Some(Ordering::Greater));
+        assert_eq!(compare("Show.S01E01.1080p", "Show - s01e01"), None);
+    }
+}
@@ -0,0 +1,89 @@
+/// Language tags releases append to the title, compared case-insensitively
+const LANGUAGE_TAGS: [&str; 28] = [
+    "french", "german", "italian", "spanish", "dutch", "swedish", "danish", "norwegian", "finnish",
+    "polish", "russian", "japanese", "korean", "portuguese", "truefrench", "vff", "vostfr", "ita",
+    "eng", "ger", "fre", "spa", "de", "fr", "es", "nl", "pl", "multi",
+];
+
+/// The name to search for: the parsed name without the language tags at its end,
+/// e.g. `Show DE` is searched as `Show`. A name made only of tags is kept as it is
+pub fn search_name(name: &str) -> &str {
+    let mut searched = name.trim_end();
+    while let Some((rest, last)) = searched.rsplit_once(char::is_whitespace) {
+        if rest.trim().is_empty()
+            || !LANGUAGE_TAGS
+                .iter()
+                .any(|tag| tag.eq_ignore_ascii_case(last))
+        {
+            break;
+        }
+        searched = rest.trim_end();
+    }
+    searched
+}
+
+/// The title in the form titles are compared in: lowercase, `&` spelled `and`, apostrophes
+/// of any kind dropped and any other punctuation, like hyphens and colons, taken as a space
+pub fn normalize_title(title: &str) -> String {
+    let title = title
+        .to_lowercase()
+        .replace('&', " and ")
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '`' | '\u{00b4}'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>();
+    title.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Roman numerals and number words sequels are numbered with, by their number
+const SEQUEL_NUMBERS: [(&str, &str); 18] = [
+    ("ii", "2"), ("iii", "3"), ("iv", "4"), ("v", "5"), ("vi", "6"), ("vii", "7"), ("viii", "8"),
+    ("ix", "9"), ("x", "10"), ("two", "2"), ("three", "3"), ("four", "4"), ("five", "5"),
+    ("six", "6"), ("seven", "7"), ("eight", "8"), ("nine", "9"), ("ten", "10"),
+];
+
+/// The words of the normalized title with sequel numbers written as digits and the word `part`
+/// left out, so that `Part II`, `Part 2` and `2` are the same
+pub fn title_words(title: &str) -> Vec<String> {
+    normalize_title(title)
+        .split(' ')
+        .filter(|word| !word.is_empty() && *word != "part")
+        .map(|word| {
+            SEQUEL_NUMBERS
+                .iter()
+                .find(|(sequel, _)| *sequel == word)
+                .map(|(_, number)| number.to_string())
+                .unwrap_or_else(|| word.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_tags_are_stripped_from_the_end() {
+        assert_eq!(search_name("Show DE"), "Show");
+        assert_eq!(search_name("Film FRENCH"), "Film");
+        assert_eq!(search_name("Film German DL MULTI"), "Film German DL");
+        assert_eq!(search_name("Le Film ITA ENG"), "Le Film");
+        assert_eq!(search_name("French Kiss"), "French Kiss");
+        assert_eq!(search_name("German"), "German");
+    }
+
+    #[test]
+    fn punctuation_variants_normalize_the_same() {
+        assert_eq!(normalize_title("Law & Order"), normalize_title("Law and Order"));
+        assert_eq!(normalize_title("Grey\u{2019}s Anatomy"), normalize_title("Greys Anatomy"));
+        assert_eq!(normalize_title("Star Wars: Andor"), normalize_title("Star Wars - Andor"));
+        assert_eq!(normalize_title("Mr. Robot"), "mr robot");
+    }
+
+    #[test]
+    fn sequel_numbers_are_digits() {
+        assert_eq!(title_words("Dune: Part Two"), vec!["dune", "2"]);
+        assert_eq!(title_words("The Godfather Part II"), title_words("The Godfather 2"));
+        assert_eq!(title_words("Rocky IV"), vec!["rocky", "4"]);
+    }
+}
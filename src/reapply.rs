@@ -0,0 +1,359 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info, warn};
+
+use crate::{
+    apply, claim_companion, claim_destination,
+    cleanup::Leftovers,
+    find_known_ids,
+    error::{self, Error},
+    journal::Operation,
+    path_utils::{get_filename, get_filestem},
+    perform,
+    policy::{policy_for, ExtensionPolicy},
+    is_pending_metadata, report_error, resolve_media, Action, Context, Forced, Planned,
+};
+
+/// A file placed in the library by an earlier run, as recorded in the history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Imported {
+    /// The file as it was named before the first run placed it, which is parsed again
+    pub original: PathBuf,
+    /// Where the file is now
+    pub destination: PathBuf,
+    /// Whether the file was named without metadata or with an uncertain match, and not since
+    pub pending: bool,
+}
+
+/// The files placed by the operations of the journals, given oldest first. A file moved again
+/// by a later run, like an earlier reapplication of the templates, keeps its original name. A
+/// file stays pending metadata until it is moved or refreshed, a run moving a file still pending
+/// marks it again
+pub fn imported(operations: &[Operation]) -> Vec<Imported> {
+    let mut originals: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+    let mut pending: BTreeSet<PathBuf> = BTreeSet::new();
+    for operation in operations {
+        match operation {
+            Operation::Move { source, destination } => {
+                let original = originals.remove(source).unwrap_or_else(|| source.clone());
+                originals.insert(destination.clone(), original);
+                pending.remove(source);
+            }
+            Operation::Copy { source, destination }
+            | Operation::Symlink { source, destination }
+            | Operation::Hardlink { source, destination } => {
+                let original = originals.get(source).cloned().unwrap_or_else(|| source.clone());
+                originals.insert(destination.clone(), original);
+            }
+            Operation::PendingMetadata { path } => {
+                pending.insert(path.clone());
+            }
+            Operation::Refreshed { path } => {
+                pending.remove(path);
+            }
+            Operation::CreateDir { .. } | Operation::Write { .. } | Operation::Trash { .. } => {}
+        }
+    }
+
+    originals
+        .into_iter()
+        .map(|(destination, original)| Imported {
+            pending: pending.contains(&destination),
+            original,
+            destination,
+        })
+        .collect()
+}
+
+/// The files written by the operations of the journals, like the `.plexmatch` of a show folder
+pub fn written(operations: &[Operation]) -> BTreeSet<PathBuf> {
+    operations
+        .iter()
+        .filter_map(|operation| match operation {
+            Operation::Write { path } => Some(path.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Moves the imported files in the output directories that are not where the current templates
+/// place them, along with the files named after them like subtitles. The folders they leave with
+/// nothing but the `written` files of earlier runs, like a misspelled show folder and the
+/// `tvshow.nfo` written into it, are deleted. Anything else, artwork included, keeps its folder
+pub fn reapply(imported: &[Imported], written: &BTreeSet<PathBuf>, roots: &[PathBuf], context: &Context) {
+    info!(
+        "Checking {} files of earlier runs against the templates",
+        imported.len()
+    );
+    let mut moved: BTreeMap<&Path, Vec<PathBuf>> = BTreeMap::new();
+    for file in imported {
+        if context.cancellation.is_cancelled() {
+            warn!("Interrupted, stopping");
+            break;
+        }
+        if !context.fs.is_file(&file.destination)
+            || policy_for(&file.destination, context.config) != ExtensionPolicy::Process
+        {
+            continue;
+        }
+        let Some(root) = roots.iter().find(|root| file.destination.starts_with(root)) else {
+            debug!("{} is not in the output directories", file.destination.display());
+            continue;
+        };
+
+        let planned = match plan_file(file, root, context) {
+            Ok(Some(planned)) => planned,
+            Ok(None) => continue,
+            Err(error) => {
+                report_error(&error);
+                context
+                    .summary
+                    .lock()
+                    .unwrap()
+                    .record_unmatched(&file.destination, &error);
+                continue;
+            }
+        };
+
+        let result = apply(&planned, context).and_then(|()| move_companions(&planned, context));
+        match &result {
+            Ok(()) => moved.entry(root).or_default().push(planned.path.clone()),
+            Err(error) => report_error(error),
+        }
+        context.summary.lock().unwrap().record(
+            &planned.media_file.title(),
+            planned.media_file.media_type(),
+            &planned.path,
+            &result,
+        );
+    }
+
+    if !matches!(context.args.action, Action::Move) {
+        return;
+    }
+    let leftovers = Leftovers {
+        fs: context.fs,
+        extensions: &[],
+        dirs: &[],
+        files: written,
+    };
+    for (root, files) in moved {
+        leftovers.clean(files.iter().map(PathBuf::as_path), root);
+    }
+}
+
+/// Resolves the original name of the file again and chooses its destination with the current
+/// templates, none when the file is already there
+fn plan_file(file: &Imported, root: &Path, context: &Context) -> error::Result<Option<Planned>> {
+    let known = find_known_ids(&file.destination, context);
+    let media_file = resolve_media(
+        &file.original,
+        context.config,
+        Some(context.fs),
+        &context.providers,
+        known,
+        context.prompt,
+        &Forced::default(),
+    )?;
+
+    let folder = root.join(media_file.folder(context.config));
+    let destination = folder.join(media_file.path_in_folder(context.config));
+    if destination == file.destination {
+        debug!("{} already follows the templates", file.destination.display());
+        if file.pending && !is_pending_metadata(&media_file, context) {
+            info!("Found the metadata of {}", file.destination.display());
+            context.record(Operation::Refreshed {
+                path: file.destination.clone(),
+            });
+        }
+        return Ok(None);
+    }
+
+    info!("Renaming {} to {}", file.destination.display(), destination.display());
+    let mut planned = Planned {
+        path: file.destination.clone(),
+        media_file,
+        folder,
+        destination,
+        replace: false,
+    };
+    claim_destination(&mut planned, context)?;
+    Ok(Some(planned))
+}
+
+/// Moves the files next to the renamed one that are named after it, like `Show - s01e01.en.srt`
+fn move_companions(planned: &Planned, context: &Context) -> error::Result<()> {
+    let (Some(old_stem), Some(new_stem), Some(old_folder), Some(new_folder)) = (
+        get_filestem(&planned.path),
+        get_filestem(&planned.destination),
+        planned.path.parent(),
+        planned.destination.parent(),
+    ) else {
+        return Ok(());
+    };
+
+    let siblings = context.fs.read_dir(old_folder).map_err(|source| Error::Filesystem {
+        path: old_folder.to_path_buf(),
+        stage: error::Stage::Move,
+        source,
+    })?;
+    for sibling in siblings {
+        let Some(suffix) =
+            get_filename(&sibling).and_then(|name| name.strip_prefix(&format!("{}.", old_stem)).map(str::to_string))
+        else {
+            continue;
+        };
+        if sibling == planned.path || !context.fs.is_file(&sibling) {
+            continue;
+        }
+        let destination = new_folder.join(format!("{}.{}", new_stem, suffix));
+        claim_companion(&sibling, &destination, context)?;
+        perform(&sibling, &destination, context)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{
+        filesystem::{Fs, MemoryFs},
+        naming::Template,
+        tests::offline_context,
+        Args, Config,
+    };
+
+    use super::*;
+
+    #[test]
+    fn files_keep_their_original_name_across_runs() {
+        let operations = vec![
+            Operation::CreateDir {
+                path: PathBuf::from("library/TV"),
+            },
+            Operation::Move {
+                source: PathBuf::from("downloads/Show.S01E01.mkv"),
+                destination: PathBuf::from("library/TV/Show/Season 1/Show - s01e01.mkv"),
+            },
+            Operation::Move {
+                source: PathBuf::from("library/TV/Show/Season 1/Show - s01e01.mkv"),
+                destination: PathBuf::from("library/TV/Show/S01/Show 1x01.mkv"),
+            },
+        ];
+        assert_eq!(
+            imported(&operations),
+            vec![Imported {
+                original: PathBuf::from("downloads/Show.S01E01.mkv"),
+                destination: PathBuf::from("library/TV/Show/S01/Show 1x01.mkv"),
+                pending: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn files_stay_pending_until_moved_or_refreshed() {
+        let placed = |name: &str| Operation::Move {
+            source: PathBuf::from(format!("downloads/{}.S01E01.mkv", name)),
+            destination: PathBuf::from(format!("library/{}.mkv", name)),
+        };
+        let path = |name: &str| PathBuf::from(format!("library/{}.mkv", name));
+        let operations = vec![
+            placed("Offline"),
+            Operation::PendingMetadata { path: path("Offline") },
+            placed("Refreshed"),
+            Operation::PendingMetadata { path: path("Refreshed") },
+            Operation::Refreshed { path: path("Refreshed") },
+            placed("Renamed"),
+            Operation::PendingMetadata { path: path("Renamed") },
+            Operation::Move {
+                source: path("Renamed"),
+                destination: path("Renamed (2020)"),
+            },
+        ];
+        let pending: Vec<PathBuf> = imported(&operations)
+            .into_iter()
+            .filter(|file| file.pending)
+            .map(|file| file.destination)
+            .collect();
+        assert_eq!(pending, vec![path("Offline")]);
+    }
+
+    #[test]
+    fn files_and_subtitles_are_moved_to_the_new_template() {
+        let fs = MemoryFs::new();
+        fs.add_file("library/TV/Show/Season 1/Show - s01e01.mkv", b"one");
+        fs.add_file("library/TV/Show/Season 1/Show - s01e01.en.srt", b"sub");
+        fs.add_file("library/TV/Show/Season 1/Show - s01e02.mkv", b"two");
+        fs.add_file("library/TV/Sevrance/Season 1/Sevrance - s01e01.mkv", b"one");
+        fs.add_file("library/TV/Sevrance/tvshow.nfo", b"<tvshow/>");
+        fs.add_file("library/TV/Sevrance/.plexmatch", b"title: Sevrance");
+        fs.add_file("library/TV/Sevrance (2019)/Season 1/Sevrance (2019) - s01e01.mkv", b"one");
+        fs.add_file("library/TV/Sevrance (2019)/tvshow.nfo", b"<tvshow/>");
+        fs.add_file("library/TV/Sevrance (2019)/poster.jpg", b"art");
+        fs.add_file("library/TV/Sevrance (2019)/Sample/sample.mkv", b"sample");
+        let args = Args::parse_from(["media-renamer", "-i", "downloads", "-o", "library", "-a", "move"]);
+        let config = Config {
+            tv_template: Template::parse("TV/{name}/S{season:02}/{name} {season}x{episode:02}.{ext}").unwrap(),
+            ..Config::default()
+        };
+        let context = offline_context(&args, &config, &fs);
+
+        let imported = vec![
+            Imported {
+                original: PathBuf::from("downloads/Show.S01E01.mkv"),
+                destination: PathBuf::from("library/TV/Show/Season 1/Show - s01e01.mkv"),
+                pending: false,
+            },
+            Imported {
+                original: PathBuf::from("downloads/Show.S01E01.en.srt"),
+                destination: PathBuf::from("library/TV/Show/Season 1/Show - s01e01.en.srt"),
+                pending: false,
+            },
+            Imported {
+                original: PathBuf::from("downloads/Gone.S01E03.mkv"),
+                destination: PathBuf::from("library/TV/Gone/Season 1/Gone - s01e03.mkv"),
+                pending: false,
+            },
+            Imported {
+                original: PathBuf::from("downloads/Severance.S01E01.mkv"),
+                destination: PathBuf::from("library/TV/Sevrance/Season 1/Sevrance - s01e01.mkv"),
+                pending: false,
+            },
+            Imported {
+                original: PathBuf::from("downloads/Severance.2019.S01E01.mkv"),
+                destination: PathBuf::from("library/TV/Sevrance (2019)/Season 1/Sevrance (2019) - s01e01.mkv"),
+                pending: false,
+            },
+        ];
+        let written = written(&[
+            Operation::Write {
+                path: PathBuf::from("library/TV/Sevrance/tvshow.nfo"),
+            },
+            Operation::Write {
+                path: PathBuf::from("library/TV/Sevrance/.plexmatch"),
+            },
+            Operation::Write {
+                path: PathBuf::from("library/TV/Sevrance (2019)/tvshow.nfo"),
+            },
+        ]);
+        reapply(&imported, &written, &[PathBuf::from("library")], &context);
+
+        assert!(fs.is_file(Path::new("library/TV/Show/S01/Show 1x01.mkv")));
+        assert!(fs.is_file(Path::new("library/TV/Show/S01/Show 1x01.en.srt")));
+        assert!(!fs.exists(Path::new("library/TV/Show/Season 1/Show - s01e01.mkv")));
+        // not imported by a recorded run, so left alone
+        assert!(fs.is_file(Path::new("library/TV/Show/Season 1/Show - s01e02.mkv")));
+        // the misspelled show folder is left with only the files written into it, so it goes
+        assert!(fs.is_file(Path::new("library/TV/Severance/S01/Severance 1x01.mkv")));
+        assert!(!fs.exists(Path::new("library/TV/Sevrance")));
+        assert!(!fs.exists(Path::new("library/TV/Sevrance (2019)/Season 1")));
+        // artwork and samples were not written by a run, so their folder stays with them
+        assert!(fs.is_file(Path::new("library/TV/Sevrance (2019)/poster.jpg")));
+        assert!(fs.is_file(Path::new("library/TV/Sevrance (2019)/Sample/sample.mkv")));
+        assert!(fs.is_file(Path::new("library/TV/Sevrance (2019)/tvshow.nfo")));
+    }
+}
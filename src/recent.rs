@@ -0,0 +1,84 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{deferred, media::MediaType};
+
+/// The file of the recent imports in the config directory
+pub const FILENAME: &str = "recent.json";
+
+/// A series episode or movie in the feed of recent imports, that dashboards read to show what
+/// was added without asking the media server
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecentImport {
+    /// The series name, or the movie name with its year
+    pub title: String,
+    pub media_type: MediaType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvdb_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tmdb_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvmaze_id: Option<u64>,
+    /// Like `tt0111161`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imdb_id: Option<String>,
+    /// Where the file is in the library
+    pub path: PathBuf,
+    /// When the file was imported, in seconds since the epoch
+    pub imported_at: u64,
+}
+
+/// The current time in seconds since the epoch
+pub fn now() -> u64 {
+    deferred::seconds(SystemTime::now())
+}
+
+/// Adds the imports to the feed at `path`, the newest first, keeping the latest `keep` of them.
+/// The feed is replaced at once, so that a dashboard never reads half of it
+pub fn record(path: &Path, imports: Vec<RecentImport>, keep: usize) -> Result<(), io::Error> {
+    let previous: Vec<RecentImport> = match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(error) => return Err(error),
+    };
+    let mut recent: Vec<RecentImport> = imports.into_iter().rev().chain(previous).collect();
+    recent.truncate(keep);
+
+    let json = serde_json::to_string_pretty(&recent).map_err(io::Error::other)?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, json)?;
+    fs::rename(&partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_latest_imports_are_kept_newest_first() {
+        let path = std::env::temp_dir().join(format!("media-renamer-recent-{}.json", std::process::id()));
+        let import = |episode: u32| RecentImport {
+            title: "Severance".to_string(),
+            media_type: MediaType::Series,
+            tvdb_id: Some(371980),
+            tmdb_id: None,
+            tvmaze_id: None,
+            imdb_id: None,
+            path: PathBuf::from(format!("library/TV/Severance/Season 1/Severance - s01e0{}.mkv", episode)),
+            imported_at: u64::from(episode),
+        };
+
+        record(&path, vec![import(1), import(2)], 3).unwrap();
+        record(&path, vec![import(3), import(4)], 3).unwrap();
+        let recent: Vec<RecentImport> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recent, vec![import(4), import(3), import(2)]);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+/// Whether the API responses are being recorded or served back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingMode {
+    Record,
+    Replay,
+}
+
+/// A raw API response as it was received
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// A file of API responses keyed by request, used to rerun offline with the exact provider data
+pub struct Recording {
+    path: PathBuf,
+    mode: RecordingMode,
+    responses: Mutex<BTreeMap<String, RecordedResponse>>,
+}
+
+impl Recording {
+    /// Starts a new recording that will be written to `path`, extending it if it already exists
+    pub fn record(path: &Path) -> Result<Self, io::Error> {
+        let responses = if path.exists() {
+            Self::load(path)?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            mode: RecordingMode::Record,
+            responses: Mutex::new(responses),
+        })
+    }
+
+    /// Opens an existing recording to serve its responses back
+    pub fn replay(path: &Path) -> Result<Self, io::Error> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            mode: RecordingMode::Replay,
+            responses: Mutex::new(Self::load(path)?),
+        })
+    }
+
+    fn load(path: &Path) -> Result<BTreeMap<String, RecordedResponse>, io::Error> {
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    pub fn mode(&self) -> RecordingMode {
+        self.mode
+    }
+
+    pub fn get(&self, key: &str) -> Option<RecordedResponse> {
+        self.responses.lock().unwrap().get(key).cloned()
+    }
+
+    /// Stores the response and writes the whole recording to disk, so that it survives an interrupted run
+    pub fn insert(&self, key: String, response: RecordedResponse) {
+        let mut responses = self.responses.lock().unwrap();
+        responses.insert(key, response);
+
+        let json = serde_json::to_string_pretty(&*responses)
+            .expect("Could not serialize the recorded responses");
+        if let Err(error) = fs::write(&self.path, json) {
+            error!(
+                "Could not write recorded responses to {}: {}",
+                self.path.display(),
+                error
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+impl Recording {
+    /// A recording replaying the given `(request key, status, body)` responses. It is only kept
+    /// in memory, so that a test leaves no file behind whatever happens
+    pub fn replaying(responses: &[(String, u16, &str)]) -> Self {
+        let responses = responses
+            .iter()
+            .map(|(key, status, body)| {
+                let response = RecordedResponse {
+                    status: *status,
+                    body: body.to_string(),
+                };
+                (key.clone(), response)
+            })
+            .collect();
+        Self {
+            path: PathBuf::new(),
+            mode: RecordingMode::Replay,
+            responses: Mutex::new(responses),
+        }
+    }
+}
+
+/// Builds the key identifying a request in a recording
+pub fn request_key(endpoint: &str, query: &[(&str, &str)]) -> String {
+    if query.is_empty() {
+        return endpoint.to_string();
+    }
+
+    let query = query
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{}", endpoint, query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_responses_are_replayed() {
+        let path = std::env::temp_dir().join(format!("media-renamer-recording-{}.json", std::process::id()));
+        let key = request_key("/search", &[("q", "Severance"), ("type", "series")]);
+        assert_eq!(key, "/search?q=Severance&type=series");
+
+        let recording = Recording::record(&path).unwrap();
+        let response = RecordedResponse {
+            status: 200,
+            body: "{\"status\": \"success\", \"data\": []}".to_string(),
+        };
+        recording.insert(key.clone(), response.clone());
+
+        let replay = Recording::replay(&path).unwrap();
+        assert_eq!(replay.mode(), RecordingMode::Replay);
+        assert_eq!(replay.get(&key), Some(response));
+        assert_eq!(replay.get("/search?q=Other&type=series"), None);
+
+        fs::remove_file(path).unwrap();
+    }
+}
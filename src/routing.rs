@@ -0,0 +1,132 @@
+use std::path::{Component, Path, PathBuf};
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::media::{MediaFile, MediaType};
+
+/// A rule sending the series and movies it matches to a library of their own instead of the
+/// output directory, given as `[[routes]]`, like anime to `/mnt/Anime`. Every criterion given has
+/// to match
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Route {
+    /// The library the matched series and movies are placed in
+    pub output: PathBuf,
+    /// The genres at the provider, the title has to have one of them. Compared ignoring case
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<String>,
+    /// A regex the name of the series or movie matches, ignoring case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The directory the files come from, like `downloads/anime`, or the name of one of the
+    /// directories they are in, like `anime`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    /// Only the series or only the movies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<MediaType>,
+}
+
+impl Route {
+    fn matches(&self, path: &Path, media_file: &MediaFile) -> bool {
+        let genre = self.genres.is_empty()
+            || media_file
+                .genres()
+                .iter()
+                .any(|genre| self.genres.iter().any(|routed| routed.eq_ignore_ascii_case(genre)));
+        let name = self
+            .name
+            .as_deref()
+            .is_none_or(|name| name_regex(name).is_ok_and(|regex| regex.is_match(media_file.name())));
+        let source = self.source.as_deref().is_none_or(|source| comes_from(path, source));
+        let media_type = self.media_type.is_none_or(|media_type| media_type == media_file.media_type());
+        genre && name && source && media_type
+    }
+
+    fn has_criteria(&self) -> bool {
+        !self.genres.is_empty() || self.name.is_some() || self.source.is_some() || self.media_type.is_some()
+    }
+}
+
+/// The index of the first route the file matches
+pub fn route(routes: &[Route], path: &Path, media_file: &MediaFile) -> Option<usize> {
+    routes.iter().position(|route| route.matches(path, media_file))
+}
+
+/// Whether a route needs the genres of the titles, which are looked up only then
+pub fn needs_genres(routes: &[Route]) -> bool {
+    routes.iter().any(|route| !route.genres.is_empty())
+}
+
+/// Checks that the regexes of the routes are valid and that every route has a criterion, so that
+/// a typo neither silently never matches nor sends everything to one library
+pub fn check(routes: &[Route]) -> Result<(), String> {
+    for route in routes {
+        if !route.has_criteria() {
+            return Err(format!(
+                "the route to {} has no genres, name, source or media_type to match",
+                route.output.display()
+            ));
+        }
+        if let Some(name) = &route.name {
+            name_regex(name).map_err(|error| format!("invalid regex in the route to {}: {}", route.output.display(), error))?;
+        }
+    }
+    Ok(())
+}
+
+fn name_regex(name: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(name).case_insensitive(true).build()
+}
+
+/// Whether the file is under the source directory or, for a source of a single name, in a
+/// directory of that name
+fn comes_from(path: &Path, source: &Path) -> bool {
+    let mut components = source.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(name)), None) => path
+            .parent()
+            .is_some_and(|parent| parent.components().any(|component| component == Component::Normal(name))),
+        _ => path.starts_with(source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::MediaData;
+
+    #[test]
+    fn the_first_matching_route_is_taken() {
+        let routes: Vec<Route> = toml::from_str::<toml::Table>(
+            r#"
+            [[routes]]
+            output = "/mnt/Documentaries"
+            genres = ["documentary"]
+            media_type = "movie"
+
+            [[routes]]
+            output = "/mnt/Anime"
+            source = "anime"
+
+            [[routes]]
+            output = "/mnt/Kids"
+            name = "^bluey$"
+            "#,
+        )
+        .unwrap()["routes"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(check(&routes), Ok(()));
+
+        let episode = MediaFile::new("Bluey".to_string(), MediaData::TvSeries { season: 1, episode: 1 }, "mkv".to_string());
+        assert_eq!(route(&routes, Path::new("downloads/Bluey.S01E01.mkv"), &episode), Some(2));
+        assert_eq!(route(&routes, Path::new("downloads/anime/Bluey.S01E01.mkv"), &episode), Some(1));
+        let movie = MediaFile::new("Bluey".to_string(), MediaData::Movie { year: 2024 }, "mkv".to_string());
+        // the genres were not looked up
+        assert_eq!(route(&routes, Path::new("downloads/anime.mkv"), &movie), Some(2));
+
+        assert!(check(&[Route::default()]).is_err());
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    fmt::Display,
+    io,
+    path::Path,
+    sync::{mpsc, Arc, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{exit_code::Outcome, transfer::CancellationToken};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// The methods a client calls, by name and with their parameters
+pub type Methods = dyn Fn(&str, Value) -> Result<Value, RpcError> + Send + Sync;
+
+/// A call that failed, with its JSON-RPC 2.0 error code
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn method_not_found(method: &str) -> Self {
+        Self {
+            code: METHOD_NOT_FOUND,
+            message: format!("There is no method {}", method),
+        }
+    }
+
+    pub fn invalid_params(error: impl Display) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: error.to_string(),
+        }
+    }
+
+    /// The method was called as it should, but could not do what was asked
+    pub fn failed(message: impl Display) -> Self {
+        Self {
+            code: SERVER_ERROR,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// None for a notification, which is not answered
+    #[serde(default)]
+    id: Option<Value>,
+}
+
+/// Answers a line of JSON-RPC 2.0, a call or a batch of them. None when there is nothing to
+/// answer, as for notifications
+pub fn handle(line: &str, methods: &Methods) -> Option<String> {
+    let response = match serde_json::from_str::<Value>(line) {
+        Ok(Value::Array(calls)) if !calls.is_empty() => {
+            let responses: Vec<Value> = calls
+                .into_iter()
+                .filter_map(|call| handle_call(call, methods))
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        Ok(call) => handle_call(call, methods),
+        Err(error) => Some(response(
+            Value::Null,
+            Err(RpcError {
+                code: PARSE_ERROR,
+                message: error.to_string(),
+            }),
+        )),
+    };
+    response.map(|response| response.to_string())
+}
+
+fn handle_call(call: Value, methods: &Methods) -> Option<Value> {
+    let request = match serde_json::from_value::<Request>(call) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        _ => {
+            let error = RpcError {
+                code: INVALID_REQUEST,
+                message: "Not a JSON-RPC 2.0 request".to_string(),
+            };
+            return Some(response(Value::Null, Err(error)));
+        }
+    };
+    debug!("Called {} with {}", request.method, request.params);
+    let result = methods(&request.method, request.params);
+    request.id.map(|id| response(id, result))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(error) => json!({ "jsonrpc": "2.0", "error": error, "id": id }),
+    }
+}
+
+/// An import submitted to the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Job {
+    pub id: u64,
+    /// The file or folder imported
+    pub input: String,
+    #[serde(flatten)]
+    pub state: JobState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum JobState {
+    Queued,
+    Running,
+    /// The run id names the journal that undoes the import
+    Done { run_id: String, outcome: Outcome },
+}
+
+/// The imports submitted to the server, run one at a time in the order they came, each as a run
+/// of its own with its journal
+pub struct Jobs {
+    jobs: Mutex<Vec<Job>>,
+    queue: Mutex<Option<mpsc::Sender<u64>>>,
+    /// Held while a job runs, so that nothing else changes the library meanwhile
+    running: Mutex<()>,
+}
+
+impl Jobs {
+    /// Starts the thread running the jobs with `run`, which imports the input it is given as a new
+    /// run and returns the id of the run with how it ended
+    pub fn start(run: impl Fn(&str) -> (String, Outcome) + Send + 'static) -> (Arc<Self>, JoinHandle<()>) {
+        let (sender, receiver) = mpsc::channel();
+        let jobs = Arc::new(Self {
+            jobs: Mutex::new(Vec::new()),
+            queue: Mutex::new(Some(sender)),
+            running: Mutex::new(()),
+        });
+        let worker = Arc::clone(&jobs);
+        let handle = thread::spawn(move || {
+            for id in receiver {
+                let running = worker.exclusive();
+                let input = worker.update(id, JobState::Running);
+                info!("Running job {}, the import of {}", id, input);
+                let (run_id, outcome) = run(&input);
+                worker.update(id, JobState::Done { run_id, outcome });
+                drop(running);
+            }
+        });
+        (jobs, handle)
+    }
+
+    /// Queues the import of the input, returns the id of its job
+    pub fn submit(&self, input: String) -> Result<u64, RpcError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let id = jobs.len() as u64 + 1;
+        let queue = self.queue.lock().unwrap();
+        if queue.as_ref().is_none_or(|sender| sender.send(id).is_err()) {
+            return Err(RpcError::failed("The server is stopping"));
+        }
+        // the worker waits for the lock on the jobs to find this one
+        jobs.push(Job {
+            id,
+            input,
+            state: JobState::Queued,
+        });
+        Ok(id)
+    }
+
+    pub fn job(&self, id: u64) -> Option<Job> {
+        self.jobs.lock().unwrap().iter().find(|job| job.id == id).cloned()
+    }
+
+    /// Every job submitted since the server started, the oldest first
+    pub fn all(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().clone()
+    }
+
+    /// Waits for the running job to end and keeps the next one from starting until the guard is dropped
+    pub fn exclusive(&self) -> MutexGuard<'_, ()> {
+        self.running.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes no more jobs, the queued ones still run
+    pub fn close(&self) {
+        self.queue.lock().unwrap().take();
+    }
+
+    /// Changes the state of the job, returns its input
+    fn update(&self, id: u64, state: JobState) -> String {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .iter_mut()
+            .find(|job| job.id == id)
+            .expect("Jobs are queued once submitted");
+        job.state = state;
+        job.input.clone()
+    }
+}
+
+/// Answers the JSON-RPC 2.0 calls sent to the Unix socket, one per line, until the cancellation.
+/// Only the user running the server can connect to the socket
+#[cfg(unix)]
+pub fn serve(socket: &Path, methods: Arc<Methods>, cancellation: &CancellationToken) -> Result<(), io::Error> {
+    use std::{
+        fs,
+        os::unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+        time::Duration,
+    };
+
+    // the socket of a server that stopped without removing it would keep the new one from binding
+    if UnixStream::connect(socket).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "another server answers on the socket",
+        ));
+    }
+    match fs::remove_file(socket) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+        _ => {}
+    }
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+    info!("Answering JSON-RPC calls on {}", socket.display());
+
+    while !cancellation.is_cancelled() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                let methods = Arc::clone(&methods);
+                thread::spawn(move || {
+                    if let Err(error) = answer(&stream, methods.as_ref()) {
+                        warn!("The connection to a client failed: {}", error);
+                    }
+                });
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(100)),
+            Err(error) => warn!("Could not accept a connection: {}", error),
+        }
+    }
+    fs::remove_file(socket)
+}
+
+#[cfg(not(unix))]
+pub fn serve(_socket: &Path, _methods: Arc<Methods>, _cancellation: &CancellationToken) -> Result<(), io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix sockets only exist on unix",
+    ))
+}
+
+/// Answers the calls of a client until it disconnects
+#[cfg(unix)]
+fn answer<S>(stream: S, methods: &Methods) -> Result<(), io::Error>
+where
+    S: io::Read + io::Write + Copy,
+{
+    use std::io::{BufRead, BufReader};
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(mut response) = handle(&line, methods) {
+            response.push('\n');
+            let mut writer = stream;
+            writer.write_all(response.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn methods(method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "echo" => Ok(params),
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    #[test]
+    fn calls_are_answered_as_json_rpc() {
+        let answer =
+            |line: &str| handle(line, &methods).map(|response| serde_json::from_str::<Value>(&response).unwrap());
+
+        assert_eq!(
+            answer(r#"{"jsonrpc": "2.0", "method": "echo", "params": {"input": "downloads"}, "id": 1}"#),
+            Some(json!({ "jsonrpc": "2.0", "result": {"input": "downloads"}, "id": 1 }))
+        );
+        assert_eq!(
+            answer(r#"{"jsonrpc": "2.0", "method": "nothing", "id": "a"}"#).unwrap()["error"]["code"],
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(answer(r#"{"jsonrpc": "2.0", "method": "echo"}"#), None);
+        assert_eq!(answer("{").unwrap()["error"]["code"], PARSE_ERROR);
+        assert_eq!(
+            answer(r#"{"method": "echo", "id": 1}"#).unwrap()["error"]["code"],
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            answer(
+                r#"[{"jsonrpc": "2.0", "method": "echo", "params": [1], "id": 1}, {"jsonrpc": "2.0", "method": "echo"}]"#
+            ),
+            Some(json!([{ "jsonrpc": "2.0", "result": [1], "id": 1 }]))
+        );
+    }
+
+    #[test]
+    fn jobs_run_in_the_order_they_came() {
+        let (jobs, worker) = Jobs::start(|input| match input {
+            "downloads/Severance.S01E01.mkv" => ("1".to_string(), Outcome::Success),
+            _ => ("2".to_string(), Outcome::Unmatched),
+        });
+        assert_eq!(jobs.submit("downloads/Severance.S01E01.mkv".to_string()), Ok(1));
+        assert_eq!(jobs.submit("downloads/notes.mkv".to_string()), Ok(2));
+        jobs.close();
+        assert!(jobs.submit("downloads".to_string()).is_err());
+        worker.join().unwrap();
+
+        let done = |run_id: &str, outcome| JobState::Done {
+            run_id: run_id.to_string(),
+            outcome,
+        };
+        assert_eq!(jobs.job(1).unwrap().state, done("1", Outcome::Success));
+        assert_eq!(
+            json!(jobs.job(2).unwrap()),
+            json!({ "id": 2, "input": "downloads/notes.mkv", "state": "done", "run_id": "2", "outcome": "unmatched" })
+        );
+        assert_eq!(jobs.all().len(), 2);
+    }
+}
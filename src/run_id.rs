@@ -0,0 +1,78 @@
+use std::{
+    sync::{LazyLock, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use simplelog::{Config, SharedLogger};
+
+/// The id of the current run, the milliseconds since the epoch it started at. The journal of the
+/// run is named after it, and it is written in the log file and the report so that the runs
+/// sharing them can be told apart. A server starts a new run for every import it is given
+static RUN_ID: LazyLock<RwLock<u128>> = LazyLock::new(|| RwLock::new(now()));
+
+fn now() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0)
+}
+
+pub fn current() -> String {
+    RUN_ID.read().unwrap().to_string()
+}
+
+/// Starts a new run and returns its id, later than the previous one even when started within the
+/// same millisecond
+pub fn next() -> String {
+    let mut run_id = RUN_ID.write().unwrap();
+    *run_id = now().max(*run_id + 1);
+    run_id.to_string()
+}
+
+/// Prefixes the lines of another logger with the run id
+pub struct RunLogger {
+    inner: Box<dyn SharedLogger>,
+}
+
+impl RunLogger {
+    pub fn new(inner: Box<dyn SharedLogger>) -> Box<Self> {
+        Box::new(Self { inner })
+    }
+}
+
+impl Log for RunLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.log(
+            &Record::builder()
+                .args(format_args!("[run {}] {}", current(), record.args()))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+impl SharedLogger for RunLogger {
+    fn level(&self) -> LevelFilter {
+        self.inner.level()
+    }
+
+    fn config(&self) -> Option<&Config> {
+        self.inner.config()
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        self
+    }
+}
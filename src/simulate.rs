@@ -0,0 +1,68 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{error, info};
+
+use crate::{
+    error::Error,
+    local_ids::KnownIds,
+    policy::{policy_for, ExtensionPolicy},
+    resolve_media, Config, Forced,
+};
+
+/// Reads the hypothetical filenames from the contents of a fixture file,
+/// one per line. Empty lines and lines starting with # are skipped
+pub fn read_fixture(contents: &str) -> Vec<PathBuf> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Runs every filename in the fixture through the parse and naming pipeline in offline mode
+/// and prints where each file would end up. Nothing is touched on disk and TVDB is not queried
+pub fn simulate(fixture: &Path, config: &Config) {
+    let contents = match fs::read_to_string(fixture) {
+        Ok(contents) => contents,
+        Err(error) => {
+            error!("Could not read fixture {}: {}", fixture.display(), error);
+            return;
+        }
+    };
+
+    let paths = read_fixture(&contents);
+    info!("Simulating {} filenames from {}", paths.len(), fixture.display());
+
+    for path in paths {
+        if policy_for(&path, config) != ExtensionPolicy::Process {
+            println!("{} -> IGNORED (extension)", path.display());
+            continue;
+        }
+
+        match resolve_media(&path, config, None, &[], KnownIds::default(), None, &Forced::default()) {
+            Ok(media_file) => println!("{} -> {}", path.display(), media_file.get_path(config).display()),
+            Err(Error::Junk { reason, .. }) => println!("{} -> JUNK ({})", path.display(), reason),
+            Err(_) => println!("{} -> UNPARSED", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixture_skips_comments_and_blank_lines() {
+        let contents = "# old releases\nShow.S01E01.mkv\n\n   \nMovie.2020.1080p.mkv\n";
+        assert_eq!(
+            read_fixture(contents),
+            vec![
+                PathBuf::from("Show.S01E01.mkv"),
+                PathBuf::from("Movie.2020.1080p.mkv")
+            ]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::filesystem::Fs;
+
+/// The hidden directory in each output directory the files are placed in with --staging. Being in
+/// the output directory it is on the same filesystem, so that publishing is only renaming
+pub const STAGING_DIRNAME: &str = ".media-renamer-staging";
+
+/// Where a destination in one of the output directories is placed before it is published, none
+/// when it is in none of them
+pub fn staged_path(roots: &[PathBuf], destination: &Path) -> Option<PathBuf> {
+    roots.iter().find_map(|root| {
+        let relative = destination.strip_prefix(root).ok()?;
+        Some(root.join(STAGING_DIRNAME).join(relative))
+    })
+}
+
+/// Moves everything staged in the output directory into it. A folder new to the library is
+/// renamed into it at once, with all of its files; the files of an existing one are renamed into
+/// it one by one. Returns how many files and folders were published
+pub fn publish(fs: &dyn Fs, root: &Path) -> Result<usize, io::Error> {
+    let staging = root.join(STAGING_DIRNAME);
+    if !fs.is_dir(&staging) {
+        return Ok(0);
+    }
+    let published = publish_dir(fs, &staging, root)?;
+    fs.remove_dir(&staging)?;
+    Ok(published)
+}
+
+fn publish_dir(fs: &dyn Fs, staged_dir: &Path, library_dir: &Path) -> Result<usize, io::Error> {
+    let mut entries = fs.read_dir(staged_dir)?;
+    entries.sort();
+    let mut published = 0;
+    for staged in entries {
+        let target = library_dir.join(staged.file_name().expect("Directory entries have a name"));
+        if fs.is_dir(&staged) && fs.is_dir(&target) {
+            published += publish_dir(fs, &staged, &target)?;
+            fs.remove_dir(&staged)?;
+        } else {
+            // a replaced file is replaced by the rename
+            fs.rename(&staged, &target)?;
+            published += 1;
+        }
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFs;
+
+    #[test]
+    fn new_folders_are_published_whole_and_existing_ones_file_by_file() {
+        let fs = MemoryFs::new();
+        let root = Path::new("library");
+        fs.add_file("library/TV/Severance/Season 1/Severance - s01e01.mkv", b"1");
+        let staged = |path: &str| staged_path(&[root.to_path_buf()], &root.join(path)).unwrap();
+        for path in [
+            "TV/Severance/Season 1/Severance - s01e02.mkv",
+            "TV/Severance/Season 2/Severance - s02e01.mkv",
+            "TV/Severance/Season 2/Severance - s02e02.mkv",
+        ] {
+            fs.add_file(staged(path), b"2");
+        }
+        assert_eq!(
+            staged("TV/Severance/Season 2/Severance - s02e01.mkv"),
+            Path::new("library/.media-renamer-staging/TV/Severance/Season 2/Severance - s02e01.mkv")
+        );
+
+        assert_eq!(publish(&fs, root).unwrap(), 2);
+
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e01.mkv")));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 1/Severance - s01e02.mkv")));
+        assert!(fs.exists(Path::new("library/TV/Severance/Season 2/Severance - s02e02.mkv")));
+        assert!(!fs.exists(&root.join(STAGING_DIRNAME)));
+    }
+}
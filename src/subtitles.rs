@@ -0,0 +1,167 @@
+/// The languages of subtitles as Plex reads them, by the words release names use for them
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("en", &["en", "eng", "english"]),
+    ("it", &["it", "ita", "italian", "italiano"]),
+    ("es", &["es", "spa", "esp", "spanish", "espanol", "español", "castellano"]),
+    ("fr", &["fr", "fre", "fra", "french", "francais", "français"]),
+    ("de", &["de", "ger", "deu", "german", "deutsch"]),
+    ("pt", &["pt", "por", "portuguese", "portugues", "português"]),
+    ("nl", &["nl", "dut", "nld", "dutch", "nederlands"]),
+    ("sv", &["sv", "swe", "swedish", "svenska"]),
+    ("pl", &["pl", "pol", "polish", "polski"]),
+    ("ru", &["ru", "rus", "russian"]),
+    ("ja", &["ja", "jpn", "japanese"]),
+    ("ko", &["ko", "kor", "korean"]),
+    ("zh", &["zh", "chi", "zho", "chinese"]),
+    ("ar", &["ar", "ara", "arabic"]),
+];
+
+/// Words common in the dialogue of a language and rare in the others, counted to tell the
+/// language of a subtitle whose name does not give it
+const COMMON_WORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "you", "and", "what", "this", "that", "is", "are", "have", "don't"]),
+    ("it", &["che", "non", "sono", "questo", "perché", "della", "cosa", "hai", "io", "sei"]),
+    ("es", &["que", "el", "los", "qué", "está", "pero", "por", "muy", "tengo", "eres"]),
+    ("fr", &["le", "les", "est", "pas", "vous", "je", "c'est", "une", "qui", "mais"]),
+    ("de", &["und", "ich", "nicht", "das", "ist", "du", "sie", "ein", "mit", "was"]),
+    ("pt", &["não", "você", "uma", "está", "isso", "muito", "tem", "aqui", "então", "ele"]),
+    ("nl", &["het", "een", "niet", "ik", "je", "wat", "dat", "zijn", "maar", "hij"]),
+];
+
+/// How many more common words the language of a subtitle needs than the next most common one
+const CONTENT_MARGIN: usize = 2;
+
+/// The least common words a subtitle needs for its language to be told from its contents
+const CONTENT_MIN_WORDS: usize = 10;
+
+/// How much of a subtitle is read to tell its language
+pub const SAMPLE_BYTES: u64 = 256 * 1024;
+
+/// The extensions of the subtitles in plain text, whose language can be told from their contents
+const TEXT_EXTENSIONS: &[&str] = &["srt", "ass", "ssa", "vtt"];
+
+/// The name of a subtitle named after a main file, for the main file renamed to `new_stem`: the
+/// language and whether it is forced or for the hearing impaired, as Plex reads them, so that
+/// `Show.S01E01.English.Forced.srt` becomes `Show - s01e01.en.forced.srt`. The language is taken
+/// from the words between the name of the main file and the extension, like `english`, `ita` or
+/// `pt-BR`, or else from the text of the subtitle. None when the language is not found, the
+/// subtitle is then renamed as any other sidecar
+pub fn subtitle_name(
+    filename: &str,
+    main_stem: &str,
+    new_stem: &str,
+    text: impl FnOnce() -> Option<String>,
+) -> Option<String> {
+    let rest = filename.strip_prefix(main_stem)?;
+    let (words, extension) = rest.rsplit_once('.')?;
+
+    let mut language = None;
+    let mut hearing_impaired = false;
+    let mut forced = false;
+    for word in words.split(['.', ' ', '[', ']', '(', ')']).filter(|word| !word.is_empty()) {
+        match word.to_lowercase().as_str() {
+            "forced" | "foreign" => forced = true,
+            "sdh" | "hi" | "cc" => hearing_impaired = true,
+            word => language = language.or_else(|| language_of_word(word)),
+        }
+    }
+    let language = match language {
+        Some(language) => language,
+        None if TEXT_EXTENSIONS.contains(&extension.to_lowercase().as_str()) => language_of_text(&text()?)?.to_string(),
+        None => return None,
+    };
+
+    let mut name = format!("{}.{}", new_stem, language);
+    if hearing_impaired {
+        name.push_str(".sdh");
+    }
+    if forced {
+        name.push_str(".forced");
+    }
+    Some(format!("{}.{}", name, extension))
+}
+
+/// The language code of a word of a subtitle name, with its region when it has one, like `pt-BR`
+fn language_of_word(word: &str) -> Option<String> {
+    if word == "brazilian" {
+        return Some("pt-BR".to_string());
+    }
+    let (language, region) = match word.split_once(['-', '_']) {
+        Some((language, region)) if region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()) => {
+            (language, Some(region))
+        }
+        Some(_) => return None,
+        None => (word, None),
+    };
+    let (code, _) = LANGUAGES.iter().find(|(_, words)| words.contains(&language))?;
+    Some(match region {
+        Some(region) => format!("{}-{}", code, region.to_uppercase()),
+        None => code.to_string(),
+    })
+}
+
+/// The language code of the full name of a language in a release name, like `german`. Short
+/// codes like `de` or `it` are not taken, as they are common words of titles
+pub fn language_of_name(word: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(_, words)| words.iter().any(|name| name.len() > 3 && *name == word))
+        .map(|(code, _)| *code)
+}
+
+/// The language whose common words are the most frequent in the text, when they are frequent
+/// enough and clearly more than those of any other language
+fn language_of_text(text: &str) -> Option<&'static str> {
+    let mut counts: Vec<(&str, usize)> = COMMON_WORDS.iter().map(|(code, _)| (*code, 0)).collect();
+    for word in text
+        .split(|c: char| !c.is_alphabetic() && c != '\'')
+        .filter(|word| !word.is_empty())
+    {
+        let word = word.to_lowercase();
+        for ((_, words), (_, count)) in COMMON_WORDS.iter().zip(counts.iter_mut()) {
+            if words.contains(&word.as_str()) {
+                *count += 1;
+            }
+        }
+    }
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let (code, most) = counts[0];
+    let second = counts.get(1).map_or(0, |(_, count)| *count);
+    (most >= CONTENT_MIN_WORDS && most >= second * CONTENT_MARGIN).then_some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(filename: &str, text: Option<&str>) -> Option<String> {
+        subtitle_name(filename, "Show.S01E01", "Show - s01e01", || text.map(str::to_string))
+    }
+
+    #[test]
+    fn languages_and_flags_are_read_from_the_name() {
+        assert_eq!(name("Show.S01E01.english.srt", None).as_deref(), Some("Show - s01e01.en.srt"));
+        assert_eq!(name("Show.S01E01.ita.srt", None).as_deref(), Some("Show - s01e01.it.srt"));
+        assert_eq!(name("Show.S01E01.pt-BR.srt", None).as_deref(), Some("Show - s01e01.pt-BR.srt"));
+        assert_eq!(
+            name("Show.S01E01.English.Forced.srt", None).as_deref(),
+            Some("Show - s01e01.en.forced.srt")
+        );
+        assert_eq!(name("Show.S01E01.eng.SDH.srt", None).as_deref(), Some("Show - s01e01.en.sdh.srt"));
+        assert_eq!(name("Show.S01E01.en.idx", None).as_deref(), Some("Show - s01e01.en.idx"));
+        assert_eq!(name("Show.S01E01.sub", None), None);
+        assert_eq!(name("Show.S01E01.2.srt", None), None);
+    }
+
+    #[test]
+    fn languages_are_told_from_the_text_when_the_name_lacks_them() {
+        let english = "1\n00:00:01,000 --> 00:00:02,000\nWhat is this? You have the keys and the car, don't you?\n\
+                       2\n00:00:03,000 --> 00:00:04,000\nThat is what they are. You and the others have this.";
+        let italian = "1\n00:00:01,000 --> 00:00:02,000\nChe cosa hai fatto? Non sono io, questo non è mio.\n\
+                       2\n00:00:03,000 --> 00:00:04,000\nPerché sei qui? Io non so che cosa della casa.";
+        assert_eq!(name("Show.S01E01.srt", Some(english)).as_deref(), Some("Show - s01e01.en.srt"));
+        assert_eq!(name("Show.S01E01.srt", Some(italian)).as_deref(), Some("Show - s01e01.it.srt"));
+        assert_eq!(name("Show.S01E01.forced.srt", Some(english)).as_deref(), Some("Show - s01e01.en.forced.srt"));
+        assert_eq!(name("Show.S01E01.srt", Some("Ok.")), None);
+    }
+}
@@ -0,0 +1,285 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info, warn};
+use serde::Serialize;
+
+use crate::{
+    error::{Error, SkipReason},
+    exit_code::Outcome,
+    media::MediaType,
+};
+
+/// The outcome of the files grouped under one series or movie
+#[derive(Debug, Default)]
+struct Group {
+    media_type: Option<MediaType>,
+    imported: Vec<PathBuf>,
+    skipped: Vec<(PathBuf, String, SkipReason)>,
+    failed: Vec<(PathBuf, String)>,
+    /// The episodes missing from the season packs, by season
+    missing: Vec<(u32, Vec<u32>)>,
+}
+
+/// The summary of a run, grouped by resolved series or movie
+#[derive(Debug, Default)]
+pub struct Summary {
+    groups: BTreeMap<String, Group>,
+    unmatched: Group,
+    /// The files and directories left out before being processed, like the ones with other extensions
+    filtered: Group,
+}
+
+/// The outcome of a file in the JSON report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Imported,
+    Skipped,
+    Failed,
+}
+
+/// A file in the JSON report
+#[derive(Debug, Serialize)]
+pub struct ReportEntry {
+    pub path: PathBuf,
+    pub status: Status,
+    /// The series or movie the file was resolved to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<SkipReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the outcome of a file that was resolved to `title`
+    pub fn record(&mut self, title: &str, media_type: MediaType, path: &Path, result: &Result<(), Error>) {
+        let group = self.groups.entry(title.to_string()).or_default();
+        group.media_type = Some(media_type);
+        Self::record_in(group, path, result.as_ref().map(|_| ()));
+    }
+
+    /// Records the episodes the provider lists in a season that the season pack imported for `title` misses
+    pub fn record_missing(&mut self, title: &str, season: u32, episodes: Vec<u32>) {
+        self.groups.entry(title.to_string()).or_default().missing.push((season, episodes));
+    }
+
+    /// The episodes missing from the season packs of `title`, by season
+    #[cfg(test)]
+    pub fn missing(&self, title: &str) -> &[(u32, Vec<u32>)] {
+        self.groups.get(title).map_or(&[], |group| &group.missing)
+    }
+
+    /// Records a file that could not be resolved to a series or movie
+    pub fn record_unmatched(&mut self, path: &Path, error: &Error) {
+        Self::record_in(&mut self.unmatched, path, Err(error));
+    }
+
+    /// Records a file or directory that was left out before being processed
+    pub fn record_filtered(&mut self, path: &Path, error: &Error) {
+        Self::record_in(&mut self.filtered, path, Err(error));
+    }
+
+    fn record_in(group: &mut Group, path: &Path, result: Result<(), &Error>) {
+        match result {
+            Ok(()) => group.imported.push(path.to_path_buf()),
+            Err(error) => match error.skip_reason() {
+                Some(reason) => group.skipped.push((path.to_path_buf(), error.to_string(), reason)),
+                None => group.failed.push((path.to_path_buf(), error.to_string())),
+            },
+        }
+    }
+
+    /// Every file of the run with its outcome and, for skipped files, the reason code
+    pub fn report(&self) -> Vec<ReportEntry> {
+        let titled = self
+            .groups
+            .iter()
+            .map(|(title, group)| (Some(title), group));
+        let untitled = [&self.unmatched, &self.filtered].into_iter().map(|group| (None, group));
+
+        let mut entries = Vec::new();
+        for (title, group) in titled.chain(untitled) {
+            let entry = |path: &PathBuf, status, reason, message: Option<&String>| ReportEntry {
+                path: path.clone(),
+                status,
+                title: title.cloned(),
+                reason,
+                message: message.cloned(),
+            };
+            entries.extend(group.imported.iter().map(|path| entry(path, Status::Imported, None, None)));
+            entries.extend(
+                group
+                    .skipped
+                    .iter()
+                    .map(|(path, message, reason)| entry(path, Status::Skipped, Some(*reason), Some(message))),
+            );
+            entries.extend(
+                group
+                    .failed
+                    .iter()
+                    .map(|(path, message)| entry(path, Status::Failed, None, Some(message))),
+            );
+        }
+        entries.sort_by(|first, second| first.path.cmp(&second.path));
+        entries
+    }
+
+    /// Logs the summary, one line per series or movie followed by the files that did not make it
+    pub fn log(&self, test: bool) {
+        let unmatched = self.unmatched.skipped.len() + self.unmatched.failed.len();
+        if self.groups.is_empty() && unmatched == 0 {
+            info!("Summary: no files were processed");
+            return;
+        }
+
+        info!(
+            "Summary{}:",
+            if test { " (test, nothing was changed)" } else { "" }
+        );
+        for (title, group) in &self.groups {
+            info!("{}: {}", title, group.counts());
+            for (season, episodes) in &group.missing {
+                let episodes: Vec<String> = episodes.iter().map(|episode| format!("E{:02}", episode)).collect();
+                warn!("  S{:02}: missing {}", season, episodes.join(", "));
+            }
+            for path in &group.imported {
+                debug!("  imported {}", path.display());
+            }
+            for (path, reason) in group.problems() {
+                warn!("  {}: {}", path.display(), reason);
+            }
+        }
+
+        if unmatched > 0 {
+            info!("Unmatched: {} skipped", files(unmatched));
+            for (path, reason) in self.unmatched.problems() {
+                warn!("  {}: {}", path.display(), reason);
+            }
+        }
+
+        if !self.filtered.skipped.is_empty() {
+            info!("Filtered out: {}", files(self.filtered.skipped.len()));
+            for (path, reason) in self.filtered.problems() {
+                debug!("  {}: {}", path.display(), reason);
+            }
+        }
+
+        info!("Total: {}", self.totals());
+    }
+
+    /// How the run ended: failed when a file failed, else unmatched when a file was not found or
+    /// parsed. Files skipped on purpose, like existing or filtered ones, do not count against it
+    pub fn outcome(&self) -> Outcome {
+        let titled = self.groups.values().chain([&self.filtered]);
+        if titled.clone().any(|group| !group.failed.is_empty()) {
+            Outcome::Failed
+        } else if !self.unmatched.skipped.is_empty() || !self.unmatched.failed.is_empty() {
+            Outcome::Unmatched
+        } else {
+            Outcome::Success
+        }
+    }
+
+    /// The counts of the whole run, the unmatched files apart from the other skipped and failed ones
+    fn totals(&self) -> String {
+        let titled = self.groups.values().chain([&self.filtered]);
+        let imported: usize = self.groups.values().map(|group| group.imported.len()).sum();
+        let skipped: usize = titled.clone().map(|group| group.skipped.len()).sum();
+        let failed: usize = titled.map(|group| group.failed.len()).sum();
+        let unmatched = self.unmatched.skipped.len() + self.unmatched.failed.len();
+        format!(
+            "{} processed, {} imported, {} skipped, {} unmatched, {} failed",
+            files(imported + skipped + failed + unmatched),
+            imported,
+            skipped,
+            unmatched,
+            failed
+        )
+    }
+}
+
+impl Group {
+    /// The skipped and failed files with what happened to them
+    fn problems(&self) -> impl Iterator<Item = (&PathBuf, &String)> {
+        self.skipped
+            .iter()
+            .map(|(path, reason, _)| (path, reason))
+            .chain(self.failed.iter().map(|(path, reason)| (path, reason)))
+    }
+
+    fn counts(&self) -> String {
+        let unit = match self.media_type {
+            Some(MediaType::Series) => "episode",
+            Some(MediaType::Movie) => "movie",
+            None => "file",
+        };
+        let mut counts = vec![format!(
+            "{} {}{} imported",
+            self.imported.len(),
+            unit,
+            if self.imported.len() == 1 { "" } else { "s" }
+        )];
+        if !self.skipped.is_empty() {
+            counts.push(format!("{} skipped", self.skipped.len()));
+        }
+        if !self.failed.is_empty() {
+            counts.push(format!("{} failed", self.failed.len()));
+        }
+        counts.join(", ")
+    }
+}
+
+fn files(count: usize) -> String {
+    format!("{} file{}", count, if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_outcomes_by_title() {
+        let mut summary = Summary::new();
+        for episode in 1..=9 {
+            summary.record(
+                "Severance",
+                MediaType::Series,
+                &PathBuf::from(format!("Severance.S02E0{}.mkv", episode)),
+                &Ok(()),
+            );
+        }
+        summary.record(
+            "Severance",
+            MediaType::Series,
+            Path::new("Severance.S02E01.720p.mkv"),
+            &Err(Error::AlreadyExists {
+                path: PathBuf::from("Severance - s02e01.mkv"),
+            }),
+        );
+        summary.record("Dune (2021)", MediaType::Movie, Path::new("Dune.2021.mkv"), &Ok(()));
+
+        assert_eq!(summary.groups["Severance"].counts(), "9 episodes imported, 1 skipped");
+        assert_eq!(summary.groups["Dune (2021)"].counts(), "1 movie imported");
+
+        summary.record_unmatched(
+            Path::new("Unknown.Show.S01E01.mkv"),
+            &Error::Parse {
+                path: PathBuf::from("Unknown.Show.S01E01.mkv"),
+            },
+        );
+        assert_eq!(
+            summary.totals(),
+            "12 files processed, 10 imported, 1 skipped, 1 unmatched, 0 failed"
+        );
+        assert_eq!(summary.outcome(), Outcome::Unmatched);
+    }
+}
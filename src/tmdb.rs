@@ -0,0 +1,407 @@
+use std::sync::Arc;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::{
+    cache::ResponseCache,
+    media::MediaType,
+    provider::{
+        self, ApiClient, ContentRating, Episode, MetadataProvider, ProviderError, ProviderKind, Record, SearchResult,
+    },
+    recording::Recording,
+};
+
+const API_BASE_URL: &str = "https://api.themoviedb.org/3";
+
+/// Client for the TMDB API, which often knows movies TVDB does not. Authenticates with the
+/// API read access token of the account, no login is needed
+#[derive(Clone)]
+pub struct TmdbClient {
+    api: ApiClient,
+}
+
+impl TmdbClient {
+    pub fn new<S>(token: S) -> Self
+    where
+        S: Into<String>,
+    {
+        let api = ApiClient::new(API_BASE_URL);
+        api.set_token(token.into());
+        Self { api }
+    }
+
+    /// Records the API responses to, or replays them from, the given recording
+    pub fn with_recording(mut self, recording: impl Into<Arc<Recording>>) -> Self {
+        self.api = self.api.with_recording(recording);
+        self
+    }
+
+    /// Keeps the API responses in the given cache and serves them from it on later runs
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.api = self.api.with_cache(cache);
+        self
+    }
+
+    /// The seasons of the series in order, without the specials
+    fn seasons(&self, series_id: u64) -> Result<Vec<TmdbSeason>, ProviderError> {
+        let text = self.api.get_blocking(&format!("/tv/{}", series_id), &[])?;
+        let series: TmdbSeries = serde_json::from_str(&text)?;
+
+        let mut seasons: Vec<TmdbSeason> = series
+            .seasons
+            .into_iter()
+            .filter(|season| season.season_number != 0)
+            .collect();
+        seasons.sort_by_key(|season| season.season_number);
+        Ok(seasons)
+    }
+}
+
+impl MetadataProvider for TmdbClient {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Tmdb
+    }
+
+    fn search(&self, name: &str, media_type: MediaType, year: Option<u32>) -> Result<Vec<SearchResult>, ProviderError> {
+        let (endpoint, year_param) = match media_type {
+            MediaType::Series => ("/search/tv", "first_air_date_year"),
+            MediaType::Movie => ("/search/movie", "primary_release_year"),
+        };
+        let year = year.map(|year| year.to_string());
+        let mut query = vec![("query", name)];
+        if let Some(year) = &year {
+            query.push((year_param, year));
+        }
+        let text = self.api.get_blocking(endpoint, &query)?;
+        let json: SearchReply = serde_json::from_str(&text)?;
+
+        Ok(json
+            .results
+            .into_iter()
+            .map(|result| SearchResult {
+                year: year_of(&result.date),
+                name: result.name,
+                id: Some(result.id),
+            })
+            .collect())
+    }
+
+    fn record(&self, id: u64, media_type: MediaType) -> Result<Record, ProviderError> {
+        let endpoint = match media_type {
+            MediaType::Series => format!("/tv/{}", id),
+            MediaType::Movie => format!("/movie/{}", id),
+        };
+        let text = self.api.get_blocking(&endpoint, &[])?;
+        let record: TmdbRecord = serde_json::from_str(&text)?;
+
+        Ok(Record {
+            year: year_of(&record.date),
+            id: record.id,
+            name: record.name,
+        })
+    }
+
+    fn find_by_imdb_id(&self, imdb_id: u64, media_type: MediaType) -> Result<Option<Record>, ProviderError> {
+        let endpoint = format!("/find/{}", provider::imdb_id(imdb_id));
+        let text = self.api.get_blocking(&endpoint, &[("external_source", "imdb_id")])?;
+        let json: FindReply = serde_json::from_str(&text)?;
+
+        let results = match media_type {
+            MediaType::Series => json.tv_results,
+            MediaType::Movie => json.movie_results,
+        };
+        Ok(results.into_iter().next().map(|record| Record {
+            year: year_of(&record.date),
+            id: record.id,
+            name: record.name,
+        }))
+    }
+
+    fn content_ratings(&self, id: u64, media_type: MediaType) -> Result<Vec<ContentRating>, ProviderError> {
+        match media_type {
+            MediaType::Series => {
+                let text = self.api.get_blocking(&format!("/tv/{}/content_ratings", id), &[])?;
+                let json: ContentRatingsReply = serde_json::from_str(&text)?;
+                Ok(json
+                    .results
+                    .into_iter()
+                    .map(|rating| ContentRating {
+                        name: rating.rating,
+                        country: rating.iso_3166_1,
+                    })
+                    .collect())
+            }
+            MediaType::Movie => {
+                let text = self.api.get_blocking(&format!("/movie/{}/release_dates", id), &[])?;
+                let json: ReleaseDatesReply = serde_json::from_str(&text)?;
+                // a country has a date per kind of release, the first certified one is taken
+                Ok(json
+                    .results
+                    .into_iter()
+                    .filter_map(|country| {
+                        let name = country
+                            .release_dates
+                            .into_iter()
+                            .map(|release| release.certification)
+                            .find(|certification| !certification.is_empty())?;
+                        Some(ContentRating {
+                            name,
+                            country: country.iso_3166_1,
+                        })
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    fn genres(&self, id: u64, media_type: MediaType) -> Result<Vec<String>, ProviderError> {
+        let endpoint = match media_type {
+            MediaType::Series => format!("/tv/{}", id),
+            MediaType::Movie => format!("/movie/{}", id),
+        };
+        let text = self.api.get_blocking(&endpoint, &[])?;
+        let json: GenresReply = serde_json::from_str(&text)?;
+        Ok(json.genres.into_iter().map(|genre| genre.name).collect())
+    }
+
+    fn episodes(&self, series_id: u64, season: u32, episode: u32) -> Result<Vec<Episode>, ProviderError> {
+        let endpoint = format!("/tv/{}/season/{}/episode/{}", series_id, season, episode);
+        let text = match self.api.get_blocking(&endpoint, &[]) {
+            Ok(text) => text,
+            // TMDB answers episodes it does not know with not found rather than an empty list
+            Err(ProviderError::HttpError(StatusCode::NOT_FOUND)) => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let episode: TmdbEpisode = serde_json::from_str(&text)?;
+        Ok(vec![episode.into()])
+    }
+
+    fn season_episodes(&self, series_id: u64, season: u32) -> Result<Vec<Episode>, ProviderError> {
+        let text = match self.api.get_blocking(&format!("/tv/{}/season/{}", series_id, season), &[]) {
+            Ok(text) => text,
+            Err(ProviderError::HttpError(StatusCode::NOT_FOUND)) => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let json: SeasonReply = serde_json::from_str(&text)?;
+        Ok(json.episodes.into_iter().map(Episode::from).collect())
+    }
+
+    /// Looks in the last season that started by the air date, TMDB cannot search by date
+    fn episodes_aired(&self, series_id: u64, air_date: &str) -> Result<Vec<Episode>, ProviderError> {
+        let Some(season) = self
+            .seasons(series_id)?
+            .into_iter()
+            .filter(|season| season.air_date.as_deref().is_some_and(|started| started <= air_date))
+            .max_by(|a, b| a.air_date.cmp(&b.air_date))
+        else {
+            return Ok(Vec::new());
+        };
+
+        let endpoint = format!("/tv/{}/season/{}", series_id, season.season_number);
+        let text = self.api.get_blocking(&endpoint, &[])?;
+        let json: SeasonReply = serde_json::from_str(&text)?;
+        Ok(json
+            .episodes
+            .into_iter()
+            .filter(|episode| episode.air_date.as_deref() == Some(air_date))
+            .map(Episode::from)
+            .collect())
+    }
+
+    /// Counts the episodes of the seasons in order, TMDB has no absolute numbers. Absolute numbers
+    /// start at 1, there is no episode 0
+    fn episode_by_absolute_number(&self, series_id: u64, absolute_number: u32) -> Result<Option<Episode>, ProviderError> {
+        let Some(index) = absolute_number.checked_sub(1) else {
+            return Ok(None);
+        };
+        let mut before = 0;
+        for season in self.seasons(series_id)? {
+            if index < before + season.episode_count {
+                let number = index - before + 1;
+                let name = self
+                    .episodes(series_id, season.season_number, number)?
+                    .into_iter()
+                    .next()
+                    .and_then(|episode| episode.name);
+                return Ok(Some(Episode {
+                    name,
+                    season_number: season.season_number,
+                    number,
+                    absolute_number: Some(absolute_number),
+                }));
+            }
+            before += season.episode_count;
+        }
+        Ok(None)
+    }
+
+    /// TMDB only has the DVD order of some series as an episode group, which is not looked up
+    fn episode_in_dvd_order(&self, _series_id: u64, _season: u32, _episode: u32) -> Result<Option<Episode>, ProviderError> {
+        Ok(None)
+    }
+}
+
+/// The year of a TMDB date, which is empty when unknown
+fn year_of(date: &Option<String>) -> Option<u32> {
+    date.as_deref().and_then(|date| date.get(..4)).and_then(|year| year.parse().ok())
+}
+
+#[derive(Deserialize)]
+struct SearchReply {
+    results: Vec<TmdbRecord>,
+}
+
+/// A series or a movie, which TMDB gives a title and a release date instead of a name and a
+/// first air date
+#[derive(Deserialize)]
+struct TmdbRecord {
+    id: u64,
+    #[serde(alias = "title")]
+    name: String,
+    #[serde(default, rename = "first_air_date", alias = "release_date")]
+    date: Option<String>,
+}
+
+/// The series and movies with an id of another site
+#[derive(Deserialize)]
+struct FindReply {
+    #[serde(default)]
+    movie_results: Vec<TmdbRecord>,
+    #[serde(default)]
+    tv_results: Vec<TmdbRecord>,
+}
+
+#[derive(Deserialize)]
+struct TmdbSeries {
+    #[serde(default)]
+    seasons: Vec<TmdbSeason>,
+}
+
+#[derive(Deserialize)]
+struct TmdbSeason {
+    season_number: u32,
+    #[serde(default)]
+    episode_count: u32,
+    #[serde(default)]
+    air_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SeasonReply {
+    episodes: Vec<TmdbEpisode>,
+}
+
+#[derive(Deserialize)]
+struct TmdbEpisode {
+    #[serde(default)]
+    name: Option<String>,
+    season_number: u32,
+    episode_number: u32,
+    #[serde(default)]
+    air_date: Option<String>,
+}
+
+impl From<TmdbEpisode> for Episode {
+    fn from(episode: TmdbEpisode) -> Self {
+        Self {
+            name: episode.name,
+            season_number: episode.season_number,
+            number: episode.episode_number,
+            absolute_number: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ContentRatingsReply {
+    results: Vec<TmdbContentRating>,
+}
+
+#[derive(Deserialize)]
+struct TmdbContentRating {
+    iso_3166_1: String,
+    rating: String,
+}
+
+/// The genres in the details of a series or movie
+#[derive(Deserialize)]
+struct GenresReply {
+    #[serde(default)]
+    genres: Vec<TmdbGenre>,
+}
+
+#[derive(Deserialize)]
+struct TmdbGenre {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ReleaseDatesReply {
+    results: Vec<CountryReleases>,
+}
+
+#[derive(Deserialize)]
+struct CountryReleases {
+    iso_3166_1: String,
+    release_dates: Vec<TmdbRelease>,
+}
+
+#[derive(Deserialize)]
+struct TmdbRelease {
+    #[serde(default)]
+    certification: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recording::request_key;
+
+    use super::*;
+
+    #[test]
+    fn movies_and_absolute_episodes_are_read_from_tmdb() {
+        let tmdb = TmdbClient::new("").with_recording(Recording::replaying(&[
+            (
+                request_key("/search/movie", &[("query", "Dune")]),
+                200,
+                r#"{"page": 1, "results": [{"id": 438631, "title": "Dune", "release_date": "2021-09-15"}]}"#,
+            ),
+            (
+                request_key("/tv/209867", &[]),
+                200,
+                r#"{"id": 209867, "name": "Frieren", "first_air_date": "2023-09-29", "seasons": [
+                    {"season_number": 0, "episode_count": 3, "air_date": "2024-01-01"},
+                    {"season_number": 1, "episode_count": 28, "air_date": "2023-09-29"},
+                    {"season_number": 2, "episode_count": 10, "air_date": "2026-01-16"}
+                ]}"#,
+            ),
+            (
+                request_key("/tv/209867/season/2/episode/2", &[]),
+                200,
+                r#"{"name": "The First-Class Mage Exam", "season_number": 2, "episode_number": 2}"#,
+            ),
+        ]));
+
+        assert_eq!(
+            tmdb.search("Dune", MediaType::Movie, None).unwrap(),
+            vec![SearchResult {
+                name: "Dune".to_string(),
+                id: Some(438631),
+                year: Some(2021),
+            }]
+        );
+        assert_eq!(
+            tmdb.episode_by_absolute_number(209867, 30).unwrap(),
+            Some(Episode {
+                name: Some("The First-Class Mage Exam".to_string()),
+                season_number: 2,
+                number: 2,
+                absolute_number: Some(30),
+            })
+        );
+        assert_eq!(tmdb.episode_by_absolute_number(209867, 39).unwrap(), None);
+        // absolute numbers start at 1, 0 is not the episode 0 of the first season
+        assert_eq!(tmdb.episode_by_absolute_number(209867, 0).unwrap(), None);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{info, warn};
+use reqwest::{header::SET_COOKIE, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::provider::{self, ProviderError};
+
+/// The qBittorrent client seeding the downloads, given as `[torrent_client]`. With the move
+/// action, files of torrents still seeding are linked into the library and moved there by a later
+/// run, once the torrent reaches its ratio or seeding time
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct TorrentClient {
+    /// The address of the Web UI, like `http://localhost:8080`
+    pub url: String,
+
+    /// The user of the Web UI, none when it does not ask to log in from this machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// The ratio a torrent is done seeding at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed_ratio: Option<f64>,
+
+    /// The hours of seeding a torrent is done at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed_time_hours: Option<u64>,
+}
+
+/// A torrent of the client, as listed by `/api/v2/torrents/info`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Torrent {
+    pub name: String,
+    /// The file of a single file torrent, the directory of the others
+    pub content_path: PathBuf,
+    pub ratio: f64,
+    /// How long the torrent has been seeding, in seconds
+    #[serde(default)]
+    pub seeding_time: u64,
+    /// Like `uploading`, `stalledUP` or `pausedUP`
+    pub state: String,
+}
+
+/// The states of a torrent the client stopped seeding, like when it reached the share limits of
+/// the client. `stoppedUP` is the name of `pausedUP` since qBittorrent 5
+const DONE_STATES: [&str; 2] = ["pausedUP", "stoppedUP"];
+
+/// What the torrent client seeds, asked once at the start of a run
+pub struct Seeding {
+    /// None when the client could not be asked, every file is then taken as seeding so that
+    /// none is moved from under it
+    torrents: Option<Vec<Torrent>>,
+    seed_ratio: Option<f64>,
+    seed_time: Option<Duration>,
+}
+
+impl Seeding {
+    /// Asks the client for its torrents
+    pub fn query(client: &TorrentClient) -> Self {
+        let torrents = match provider::block_on(torrents(client)) {
+            Ok(torrents) => {
+                info!("{} torrents are in the torrent client", torrents.len());
+                Some(torrents)
+            }
+            Err(error) => {
+                warn!(
+                    "Could not ask the torrent client at {} what it seeds, the files are linked rather than moved: {}",
+                    client.url, error
+                );
+                None
+            }
+        };
+        Self::new(torrents, client)
+    }
+
+    /// The torrents of the client with the targets of the configuration
+    pub fn new(torrents: Option<Vec<Torrent>>, client: &TorrentClient) -> Self {
+        Self {
+            torrents,
+            seed_ratio: client.seed_ratio,
+            seed_time: client.seed_time_hours.map(|hours| Duration::from_secs(hours * 60 * 60)),
+        }
+    }
+
+    /// Whether the file belongs to a torrent of the client that did not reach its targets yet.
+    /// Files of no torrent are not seeded
+    pub fn is_seeding(&self, path: &Path) -> bool {
+        let Some(torrents) = &self.torrents else {
+            return true;
+        };
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        torrents
+            .iter()
+            .filter(|torrent| !torrent.content_path.as_os_str().is_empty())
+            .find(|torrent| path.starts_with(&torrent.content_path) || absolute.starts_with(&torrent.content_path))
+            .is_some_and(|torrent| !self.is_done(torrent))
+    }
+
+    fn is_done(&self, torrent: &Torrent) -> bool {
+        DONE_STATES.contains(&torrent.state.as_str())
+            || self.seed_ratio.is_some_and(|ratio| torrent.ratio >= ratio)
+            || self
+                .seed_time
+                .is_some_and(|time| Duration::from_secs(torrent.seeding_time) >= time)
+    }
+}
+
+/// Lists the torrents of the client through its Web API, logging in first when a user is given
+async fn torrents(client: &TorrentClient) -> Result<Vec<Torrent>, ProviderError> {
+    let url = client.url.trim_end_matches('/');
+    let http = Client::new();
+    let mut request = http.get(format!("{}/api/v2/torrents/info", url));
+    if let Some(username) = &client.username {
+        let login = http
+            .post(format!("{}/api/v2/auth/login", url))
+            .form(&[("username", username.as_str()), ("password", client.password.as_deref().unwrap_or(""))])
+            .send()
+            .await?;
+        // a refused login is answered with a success and `Fails.`, and no session cookie
+        let session = login
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|cookie| cookie.to_str().ok())
+            .find_map(|cookie| cookie.split(';').next().filter(|pair| pair.starts_with("SID=")))
+            .map(str::to_string)
+            .ok_or(ProviderError::HttpError(StatusCode::FORBIDDEN))?;
+        request = request.header(reqwest::header::COOKIE, session);
+    }
+
+    let response = request.send().await?;
+    if response.status() != StatusCode::OK {
+        return Err(ProviderError::HttpError(response.status()));
+    }
+    Ok(serde_json::from_str(&response.text().await?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_seeding_until_their_torrent_reaches_a_target() {
+        let torrents: Vec<Torrent> = serde_json::from_str(
+            r#"[
+                {"name": "Severance.S01E01", "content_path": "downloads/Severance.S01E01.mkv", "ratio": 0.4,
+                 "seeding_time": 3600, "state": "uploading", "hash": "a"},
+                {"name": "Severance.S01", "content_path": "downloads/Severance.S01", "ratio": 2.5,
+                 "seeding_time": 3600, "state": "stalledUP", "hash": "b"},
+                {"name": "Frieren.S01", "content_path": "downloads/Frieren.S01", "ratio": 0.1,
+                 "seeding_time": 7200, "state": "pausedUP", "hash": "c"}
+            ]"#,
+        )
+        .unwrap();
+        let client = TorrentClient {
+            seed_ratio: Some(2.0),
+            seed_time_hours: Some(48),
+            ..TorrentClient::default()
+        };
+        let seeding = Seeding::new(Some(torrents), &client);
+
+        assert!(seeding.is_seeding(Path::new("downloads/Severance.S01E01.mkv")));
+        // the ratio is reached
+        assert!(!seeding.is_seeding(Path::new("downloads/Severance.S01/Severance.S01E02.mkv")));
+        // the client stopped seeding it
+        assert!(!seeding.is_seeding(Path::new("downloads/Frieren.S01/Frieren.S01E01.mkv")));
+        // not a torrent of the client
+        assert!(!seeding.is_seeding(Path::new("downloads/Severance.S01E03.mkv")));
+        // the client could not be asked
+        assert!(Seeding::new(None, &client).is_seeding(Path::new("downloads/Severance.S01E03.mkv")));
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::journal::{self, Operation};
+
+/// What the runs did with a file, as lines grouped by run, oldest run first: the lines of the log
+/// mentioning the file and the changes of the journals moving, copying or linking it. The file
+/// can be a source or a destination, it is followed through the runs that moved it again
+pub fn trace(path: &Path, history_dir: &Path, log_file: &Path) -> Result<Vec<String>, io::Error> {
+    let journals: Vec<(PathBuf, bool, Vec<Operation>)> = journal::history(history_dir)
+        .into_iter()
+        .filter_map(|(journal, undone)| {
+            let operations = journal::load(&journal).ok()?;
+            Some((journal, undone, operations))
+        })
+        .collect();
+
+    let related = related_paths(path, journals.iter().flat_map(|(_, _, operations)| operations));
+
+    // the runs by id, each with its log lines and changes
+    let mut runs: BTreeMap<u128, Vec<String>> = BTreeMap::new();
+    let mut searched: Vec<String> = related.iter().map(|path| path.display().to_string()).collect();
+    for (journal, undone, operations) in &journals {
+        let changes: Vec<String> = operations
+            .iter()
+            .filter(|operation| involves(operation, &related))
+            .map(describe)
+            .collect();
+        if changes.is_empty() {
+            continue;
+        }
+        // the run undoing this one logs the journal it undid, by its name before it was marked undone
+        let logged_name = if *undone { journal.with_extension("") } else { journal.clone() };
+        searched.push(logged_name.display().to_string());
+        let lines = runs.entry(run_number(journal::journal_run_id(journal))).or_default();
+        lines.extend(changes.into_iter().map(|change| format!("  changed: {}", change)));
+        if *undone {
+            lines.push(format!("  undone since, see {}", journal.display()));
+        }
+    }
+
+    let log = match fs::read_to_string(log_file) {
+        Ok(log) => log,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+    let mut logged: BTreeMap<u128, Vec<String>> = BTreeMap::new();
+    for line in log.lines().filter(|line| searched.iter().any(|searched| line.contains(searched.as_str()))) {
+        logged
+            .entry(run_number(log_run_id(line)))
+            .or_default()
+            .push(format!("  {}", line));
+    }
+
+    let mut timeline = Vec::new();
+    for run in logged.keys().chain(runs.keys()).copied().collect::<BTreeSet<_>>() {
+        timeline.push(match run {
+            0 => "Lines of no run id:".to_string(),
+            run => format!("Run {}:", run),
+        });
+        timeline.extend(logged.remove(&run).into_iter().flatten());
+        timeline.extend(runs.remove(&run).into_iter().flatten());
+    }
+    Ok(timeline)
+}
+
+/// The file and every path it was moved, copied or linked from or to, through any number of runs
+fn related_paths<'o>(path: &Path, operations: impl Iterator<Item = &'o Operation> + Clone) -> BTreeSet<PathBuf> {
+    let mut related = BTreeSet::from([path.to_path_buf()]);
+    if let Ok(absolute) = std::path::absolute(path) {
+        related.insert(absolute);
+    }
+    loop {
+        let found: Vec<PathBuf> = operations
+            .clone()
+            .filter(|operation| involves(operation, &related))
+            .flat_map(|operation| match operation {
+                Operation::Move { source, destination }
+                | Operation::Copy { source, destination }
+                | Operation::Symlink { source, destination }
+                | Operation::Hardlink { source, destination } => vec![source.clone(), destination.clone()],
+                Operation::Trash { original, trashed } => {
+                    std::iter::once(original.clone()).chain(trashed.clone()).collect()
+                }
+                Operation::CreateDir { .. }
+                | Operation::Write { .. }
+                | Operation::PendingMetadata { .. }
+                | Operation::Refreshed { .. } => Vec::new(),
+            })
+            .filter(|path| !related.contains(path))
+            .collect();
+        if found.is_empty() {
+            return related;
+        }
+        related.extend(found);
+    }
+}
+
+fn involves(operation: &Operation, related: &BTreeSet<PathBuf>) -> bool {
+    match operation {
+        Operation::Move { source, destination }
+        | Operation::Copy { source, destination }
+        | Operation::Symlink { source, destination }
+        | Operation::Hardlink { source, destination } => related.contains(source) || related.contains(destination),
+        Operation::Write { path } | Operation::PendingMetadata { path } | Operation::Refreshed { path } => {
+            related.contains(path)
+        }
+        Operation::Trash { original, .. } => related.contains(original),
+        Operation::CreateDir { .. } => false,
+    }
+}
+
+/// The operation as a sentence, like `moved <source> to <destination>`
+pub fn describe(operation: &Operation) -> String {
+    let (verb, source, destination) = match operation {
+        Operation::Move { source, destination } => ("moved", source, destination),
+        Operation::Copy { source, destination } => ("copied", source, destination),
+        Operation::Symlink { source, destination } => ("symlinked", source, destination),
+        Operation::Hardlink { source, destination } => ("hard linked", source, destination),
+        Operation::Write { path } => return format!("wrote {}", path.display()),
+        Operation::Trash { original, .. } => return format!("sent {} to the trash", original.display()),
+        Operation::CreateDir { path } => return format!("created {}", path.display()),
+        Operation::PendingMetadata { path } => return format!("placed {} pending metadata", path.display()),
+        Operation::Refreshed { path } => return format!("found the metadata of {}", path.display()),
+    };
+    format!("{} {} to {}", verb, source.display(), destination.display())
+}
+
+/// The run id the log line starts with, like `12:00:00 [INFO] [run 1700000000000] ...`
+fn log_run_id(line: &str) -> Option<&str> {
+    let start = line.find("[run ")? + "[run ".len();
+    let length = line[start..].find(']')?;
+    Some(&line[start..start + length])
+}
+
+/// Runs sort by id, the time they started at; lines of no known run come first
+fn run_number(run_id: Option<&str>) -> u128 {
+    run_id.and_then(|run_id| run_id.parse().ok()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_file_is_followed_through_the_runs_that_moved_it() {
+        let dir = std::env::temp_dir().join(format!("media-renamer-trace-{}", std::process::id()));
+        let history = dir.join("history");
+        fs::create_dir_all(&history).unwrap();
+        let write_journal = |name: &str, operations: &[Operation]| {
+            fs::write(history.join(name), serde_json::to_string(operations).unwrap()).unwrap();
+        };
+        write_journal(
+            "1000.json",
+            &[Operation::Move {
+                source: PathBuf::from("/downloads/Show.S01E01.mkv"),
+                destination: PathBuf::from("/library/TV/Show/Season 1/Show - s01e01.mkv"),
+            }],
+        );
+        write_journal(
+            "2000.json.undone",
+            &[Operation::Move {
+                source: PathBuf::from("/library/TV/Show/Season 1/Show - s01e01.mkv"),
+                destination: PathBuf::from("/library/TV/Show (2020)/Season 1/Show - s01e01.mkv"),
+            }],
+        );
+        write_journal(
+            "2500.json",
+            &[Operation::Move {
+                source: PathBuf::from("/downloads/Other.S01E01.mkv"),
+                destination: PathBuf::from("/library/TV/Other/Season 1/Other - s01e01.mkv"),
+            }],
+        );
+        let log = dir.join("log.txt");
+        fs::write(
+            &log,
+            [
+                "10:00:00 [INFO] [run 1000] Processing file /downloads/Show.S01E01.mkv",
+                "10:00:01 [INFO] [run 1000] Processing file /downloads/Other.S01E01.mkv",
+                format!("12:00:00 [INFO] [run 3000] Undid {}", history.join("2000.json").display()).as_str(),
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let timeline = trace(
+            Path::new("/library/TV/Show (2020)/Season 1/Show - s01e01.mkv"),
+            &history,
+            &log,
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            timeline,
+            [
+                "Run 1000:".to_string(),
+                "  10:00:00 [INFO] [run 1000] Processing file /downloads/Show.S01E01.mkv".to_string(),
+                "  changed: moved /downloads/Show.S01E01.mkv to /library/TV/Show/Season 1/Show - s01e01.mkv".to_string(),
+                "Run 2000:".to_string(),
+                "  changed: moved /library/TV/Show/Season 1/Show - s01e01.mkv to /library/TV/Show (2020)/Season 1/Show - s01e01.mkv"
+                    .to_string(),
+                format!("  undone since, see {}", history.join("2000.json.undone").display()),
+                "Run 3000:".to_string(),
+                format!("  12:00:00 [INFO] [run 3000] Undid {}", history.join("2000.json").display()),
+            ]
+        );
+    }
+}
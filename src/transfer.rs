@@ -0,0 +1,327 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+use crate::{
+    filesystem::Fs,
+    hashing::{self, HashSettings},
+};
+
+const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Appended to the name of a destination while it is copied, media servers do not scan these files
+pub const PARTIAL_SUFFIX: &str = ".media-renamer.partial";
+
+/// Where the destination is written until it is complete, next to it
+pub fn partial_path(destination: &Path) -> PathBuf {
+    let mut partial = destination.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial)
+}
+
+/// Whether the file is a copy left unfinished by a run
+pub fn is_partial(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(PARTIAL_SUFFIX))
+}
+
+/// A token that can be shared across threads to cancel running transfers
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// The progress of a transfer, reported after every chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub copied: u64,
+    pub total: u64,
+}
+
+/// A chunked file copy supporting cancellation, progress reporting, rate limiting,
+/// verification and resuming of partially copied files. The copy is written next to the
+/// destination under a partial name and renamed to it once complete, so that the destination is
+/// never seen half-written. Within a copy-on-write filesystem the copy is a reflink, instant and
+/// taking no space until either file is written to
+pub struct Transfer<'a> {
+    chunk_size: usize,
+    cancellation: Option<CancellationToken>,
+    progress: Option<Box<dyn FnMut(Progress) + 'a>>,
+    rate_limit: Option<u64>,
+    verify: Option<HashSettings>,
+    resume: bool,
+}
+
+impl Default for Transfer<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Transfer<'a> {
+    pub fn new() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            cancellation: None,
+            progress: None,
+            rate_limit: None,
+            verify: None,
+            resume: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(Progress) + 'a,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Limits the transfer speed to the given bytes per second
+    pub fn rate_limit(mut self, bytes_per_second: Option<u64>) -> Self {
+        self.rate_limit = bytes_per_second.filter(|rate| *rate > 0);
+        self
+    }
+
+    /// Compares the hash of the destination with the one of the source after copying
+    pub fn verify(mut self, verify: Option<HashSettings>) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Continues from the end of the partial copy left by an interrupted transfer instead of starting over
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Copies `source` to `destination` returning the number of bytes in the destination.
+    /// A cancelled transfer fails with `ErrorKind::Interrupted` and leaves the partial file in place
+    /// so it can be resumed, a failed verification fails with `ErrorKind::InvalidData` and removes it
+    pub fn copy(&mut self, fs: &dyn Fs, source: &Path, destination: &Path) -> Result<u64, io::Error> {
+        let mut source_file = fs.open_read(source)?;
+        let total = fs.file_size(source)?;
+        let partial = partial_path(destination);
+
+        let existing = if self.resume {
+            fs.file_size(&partial).unwrap_or(0)
+        } else {
+            0
+        };
+        // a partial copy bigger than the source is not one of it, start over
+        let resumed_at = if existing <= total { existing } else { 0 };
+
+        // a reflink shares the data of the source, there is nothing to verify
+        if resumed_at == 0 {
+            match fs.reflink(source, &partial).and_then(|()| fs.rename(&partial, destination)) {
+                Ok(()) => {
+                    debug!("Reflinked {} to {}", source.display(), destination.display());
+                    if let Some(progress) = &mut self.progress {
+                        progress(Progress { copied: total, total });
+                    }
+                    return Ok(total);
+                }
+                Err(error) => debug!("Could not reflink {}, copying it: {}", source.display(), error),
+            }
+        }
+
+        let mut destination_file = if resumed_at > 0 {
+            debug!(
+                "Resuming transfer of {} at {} of {} bytes",
+                source.display(),
+                resumed_at,
+                total
+            );
+            source_file.seek(SeekFrom::Start(resumed_at))?;
+            fs.open_write(&partial, true)?
+        } else {
+            fs.open_write(&partial, false)?
+        };
+
+        let mut copied = resumed_at;
+        let mut buffer = vec![0; self.chunk_size];
+        let started = Instant::now();
+
+        loop {
+            if self.cancellation.as_ref().is_some_and(|token| token.is_cancelled()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "transfer cancelled",
+                ));
+            }
+
+            let read = source_file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            destination_file.write_all(&buffer[..read])?;
+            copied += read as u64;
+
+            if let Some(progress) = &mut self.progress {
+                progress(Progress { copied, total });
+            }
+
+            if let Some(rate) = self.rate_limit {
+                let expected = Duration::from_secs_f64((copied - resumed_at) as f64 / rate as f64);
+                let elapsed = started.elapsed();
+                if expected > elapsed {
+                    thread::sleep(expected - elapsed);
+                }
+            }
+        }
+
+        destination_file.flush()?;
+        drop(destination_file);
+
+        let verified = match self.verify {
+            Some(settings) => hashing::same_contents(fs, source, &partial, settings)?,
+            None => true,
+        };
+        if !verified {
+            if let Err(error) = fs.remove_file(&partial) {
+                debug!("Could not remove {}: {}", partial.display(), error);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "verification failed: {} differs from {}",
+                    destination.display(),
+                    source.display()
+                ),
+            ));
+        }
+
+        fs.rename(&partial, destination)?;
+        Ok(copied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filesystem::{MemoryFs, MemoryNode};
+
+    use super::*;
+
+    #[test]
+    fn copies_in_chunks_reporting_progress() {
+        let fs = MemoryFs::new();
+        fs.add_file("source.mkv", b"0123456789");
+
+        let mut reports = Vec::new();
+        let copied = Transfer::new()
+            .chunk_size(4)
+            .verify(Some(HashSettings::default()))
+            .on_progress(|progress| reports.push(progress.copied))
+            .copy(&fs, Path::new("source.mkv"), Path::new("destination.mkv"))
+            .unwrap();
+
+        assert_eq!(copied, 10);
+        assert_eq!(reports, vec![4, 8, 10]);
+        assert_eq!(
+            fs.node("destination.mkv"),
+            Some(MemoryNode::File(b"0123456789".to_vec()))
+        );
+    }
+
+    #[test]
+    fn copy_on_write_filesystems_reflink() {
+        let fs = MemoryFs::new();
+        fs.set_reflinks("btrfs");
+        fs.add_file("btrfs/downloads/source.mkv", b"0123456789");
+        fs.add_file("ext4/source.mkv", b"0123456789");
+
+        let copy = |source: &str, destination: &str| {
+            let mut reports = Vec::new();
+            Transfer::new()
+                .chunk_size(4)
+                .on_progress(|progress| reports.push(progress.copied))
+                .copy(&fs, Path::new(source), Path::new(destination))
+                .unwrap();
+            assert_eq!(fs.node(destination), Some(MemoryNode::File(b"0123456789".to_vec())));
+            reports
+        };
+
+        assert_eq!(copy("btrfs/downloads/source.mkv", "btrfs/destination.mkv"), vec![10]);
+        assert_eq!(copy("ext4/source.mkv", "btrfs/copied.mkv"), vec![4, 8, 10]);
+    }
+
+    #[test]
+    fn resumes_partial_destination() {
+        let fs = MemoryFs::new();
+        fs.add_file("source.mkv", b"0123456789");
+        fs.add_file("destination.mkv.media-renamer.partial", b"0123");
+
+        let mut reports = Vec::new();
+        Transfer::new()
+            .chunk_size(4)
+            .resume(true)
+            .on_progress(|progress| reports.push(progress.copied))
+            .copy(&fs, Path::new("source.mkv"), Path::new("destination.mkv"))
+            .unwrap();
+
+        assert_eq!(reports, vec![8, 10]);
+        assert_eq!(
+            fs.node("destination.mkv"),
+            Some(MemoryNode::File(b"0123456789".to_vec()))
+        );
+        assert!(!fs.exists(Path::new("destination.mkv.media-renamer.partial")));
+    }
+
+    #[test]
+    fn cancelled_transfer_is_interrupted() {
+        let fs = MemoryFs::new();
+        fs.add_file("source.mkv", b"0123456789");
+
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let error = Transfer::new()
+            .chunk_size(4)
+            .cancellation(token)
+            .on_progress(move |_| cancel.cancel())
+            .copy(&fs, Path::new("source.mkv"), Path::new("destination.mkv"))
+            .unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(
+            fs.node("destination.mkv.media-renamer.partial"),
+            Some(MemoryNode::File(b"0123".to_vec()))
+        );
+        assert!(!fs.exists(Path::new("destination.mkv")));
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Sends the file to the recycle bin through the shell, which asks nothing with these flags
+#[cfg(windows)]
+pub fn trash(path: &Path) -> Result<Option<PathBuf>, io::Error> {
+    // the path is passed in the environment so that no quoting of it is needed
+    let script = "Add-Type -AssemblyName Microsoft.VisualBasic; \
+        [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteFile($env:MEDIA_RENAMER_TRASH, 'OnlyErrorDialogs', 'SendToRecycleBin')";
+    let status = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .env("MEDIA_RENAMER_TRASH", std::path::absolute(path)?)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("the recycle bin refused the file ({})", status)));
+    }
+    // the recycle bin does not tell where the file went
+    Ok(None)
+}
+
+/// Sends the file to the Trash through the Finder, so that it can be put back from there
+#[cfg(target_os = "macos")]
+pub fn trash(path: &Path) -> Result<Option<PathBuf>, io::Error> {
+    let status = std::process::Command::new("osascript")
+        .args([
+            "-e",
+            "on run argv",
+            "-e",
+            "tell application \"Finder\" to delete POSIX file (item 1 of argv)",
+            "-e",
+            "end run",
+        ])
+        .arg(std::path::absolute(path)?)
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("the Finder refused the file ({})", status)));
+    }
+    // the Finder does not tell where the file went
+    Ok(None)
+}
+
+/// Moves the file into the trash of the freedesktop.org specification, the one of the home
+/// directory or, for a file on another filesystem, the `.Trash/<uid>` or `.Trash-<uid>` at the top
+/// of that one
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn trash(path: &Path) -> Result<Option<PathBuf>, io::Error> {
+    use std::{
+        fs::{self, OpenOptions},
+        io::Write,
+    };
+
+    let path = std::path::absolute(path)?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the path has no file name"))?;
+    let trash_dir = freedesktop::trash_dir(&path)?;
+    let (files, info) = (trash_dir.join("files"), trash_dir.join("info"));
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    // the info file reserves the name in the trash, a file of the same name trashed earlier keeps its own
+    for number in 1u32.. {
+        let mut trashed = name.to_owned();
+        if number > 1 {
+            trashed.push(format!(".{}", number));
+        }
+        let mut info_name = trashed.clone();
+        info_name.push(".trashinfo");
+        let info_path = info.join(info_name);
+        let mut info_file = match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        };
+        write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            freedesktop::encode_path(&path),
+            freedesktop::deletion_date(std::time::SystemTime::now())
+        )?;
+        let trashed = files.join(&trashed);
+        if let Err(error) = fs::rename(&path, &trashed) {
+            let _ = fs::remove_file(&info_path);
+            return Err(error);
+        }
+        return Ok(Some(trashed));
+    }
+    unreachable!("a free name is always found")
+}
+
+/// The `.trashinfo` describing a file in a freedesktop.org trash, which goes once the file is put
+/// back. None for a file of another trash
+pub fn info_path(trashed: &Path) -> Option<PathBuf> {
+    let files = trashed.parent()?;
+    if files.file_name()? != "files" {
+        return None;
+    }
+    let mut name = trashed.file_name()?.to_owned();
+    name.push(".trashinfo");
+    Some(files.parent()?.join("info").join(name))
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod freedesktop {
+    use std::{
+        env, fs, io,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::{MetadataExt, PermissionsExt},
+        },
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    /// The trash the file is moved to without leaving its filesystem
+    pub fn trash_dir(path: &Path) -> Result<PathBuf, io::Error> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|home| home.join(".local/share")));
+        let device = fs::metadata(path)?.dev();
+        if let Some(data_home) = data_home {
+            let data_home_device = data_home
+                .ancestors()
+                .find_map(|dir| fs::metadata(dir).ok())
+                .map(|metadata| metadata.dev());
+            if data_home_device == Some(device) {
+                return Ok(data_home.join("Trash"));
+            }
+        }
+
+        let mut top = path;
+        while let Some(parent) = top.parent() {
+            if fs::metadata(parent)?.dev() != device {
+                break;
+            }
+            top = parent;
+        }
+        // the real user, not the owner of a home directory shared or kept through sudo
+        // SAFETY: getuid takes nothing and cannot fail
+        let uid = unsafe { libc::getuid() };
+        Ok(top_trash_dir(top, uid))
+    }
+
+    /// The trash of the user at the top of a filesystem: the directory of the user in the `.Trash`
+    /// an administrator set up there, when it is a real directory with the sticky bit, otherwise
+    /// the `.Trash-<uid>` of the user
+    fn top_trash_dir(top: &Path, uid: u32) -> PathBuf {
+        let shared = top.join(".Trash");
+        let usable = fs::symlink_metadata(&shared)
+            .is_ok_and(|metadata| metadata.is_dir() && metadata.permissions().mode() & 0o1000 != 0);
+        if usable {
+            shared.join(uid.to_string())
+        } else {
+            top.join(format!(".Trash-{}", uid))
+        }
+    }
+
+    /// The path percent-encoded as in a URL, as the specification asks
+    pub fn encode_path(path: &Path) -> String {
+        path.as_os_str()
+            .as_bytes()
+            .iter()
+            .map(|&byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (byte as char).to_string(),
+                _ => format!("%{:02X}", byte),
+            })
+            .collect()
+    }
+
+    /// The time as `YYYY-MM-DDThh:mm:ss`, in UTC as there is no time zone database to convert it with
+    pub fn deletion_date(time: SystemTime) -> String {
+        let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+        // days to a civil date, from https://howardhinnant.github.io/date_algorithms.html
+        let days = days as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use super::*;
+
+        #[test]
+        fn trash_info_is_formatted_as_specified() {
+            assert_eq!(
+                encode_path(Path::new("/downloads/Show S01E01 [1080p].mkv")),
+                "/downloads/Show%20S01E01%20%5B1080p%5D.mkv"
+            );
+            let time = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+            assert_eq!(deletion_date(time), "2024-02-29T23:59:59");
+        }
+
+        #[test]
+        fn the_shared_trash_is_used_only_with_the_sticky_bit() {
+            let top = env::temp_dir().join(format!("media-renamer-trash-{}", std::process::id()));
+            fs::create_dir_all(&top).unwrap();
+            assert_eq!(top_trash_dir(&top, 1000), top.join(".Trash-1000"));
+
+            let shared = top.join(".Trash");
+            fs::create_dir(&shared).unwrap();
+            let without_sticky = top_trash_dir(&top, 1000);
+            fs::set_permissions(&shared, fs::Permissions::from_mode(0o1777)).unwrap();
+            let with_sticky = top_trash_dir(&top, 1000);
+            fs::remove_dir_all(&top).unwrap();
+
+            assert_eq!(without_sticky, top.join(".Trash-1000"));
+            assert_eq!(with_sticky, shared.join("1000"));
+        }
+    }
+}
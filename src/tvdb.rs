@@ -1,21 +1,53 @@
-use std::{error, fmt::Display};
-
-use const_format::concatcp;
-use reqwest::{
-    blocking::Client,
-    header::CONTENT_TYPE, StatusCode,
+use std::{
+    fs, io, panic,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
 };
-use serde::Deserialize;
 
-use crate::media::MediaType;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+
+use crate::{
+    cache::ResponseCache,
+    media::MediaType,
+    provider::{
+        self, ApiClient, ContentRating, Episode, MetadataProvider, ProviderError, ProviderKind, Record, Search,
+        SearchResult,
+    },
+    recording::Recording,
+};
 
 const API_BASE_URL: &str = "https://api4.thetvdb.com/v4";
 
-/// Client for the TVDB API, implements only the needed functionality for this software
+/// How long a stored token is used for. TVDB tokens are valid for a month
+const TOKEN_LIFETIME_SECS: u64 = 28 * 24 * 60 * 60;
+
+/// Client for the TVDB API, implements only the needed functionality for this software.
+/// Clones share the token, the connection pool and the responses, so a single client can be
+/// handed to every worker of a run
+#[derive(Clone)]
 pub struct TvdbClient {
     api_key: String,
-    client: Client,
-    token: Option<String>,
+    api: ApiClient,
+    /// The TVDB language and country codes searches are restricted to, e.g. `fra` and `fra`
+    language: Option<String>,
+    country: Option<String>,
+    /// Where the token is kept between runs
+    token_file: Option<PathBuf>,
+    /// The tasks of [`MetadataProvider::prefetch`], stopped at the end of the run
+    prefetching: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+/// The token of the last login, written to the token file
+#[derive(Deserialize, Serialize)]
+struct StoredToken {
+    /// The API key the token was obtained with, so that changing the key logs in again
+    api_key: String,
+    token: String,
+    /// Seconds since the epoch
+    obtained_at: u64,
 }
 
 impl TvdbClient {
@@ -25,103 +57,409 @@ impl TvdbClient {
     {
         Self {
             api_key: api_key.into(),
-            client: Client::new(),
-            token: None,
+            api: ApiClient::new(API_BASE_URL),
+            language: None,
+            country: None,
+            token_file: None,
+            prefetching: Arc::default(),
         }
+        .refreshing()
+    }
+
+    /// Keeps the token in the given file, so that the next runs do not log in again
+    pub fn with_token_file(mut self, path: PathBuf) -> Self {
+        self.token_file = Some(path);
+        self.refreshing()
     }
 
-    pub fn login(&mut self) -> Result<(), TvdbError> {
-        let res = self
-            .client
-            .post(concatcp!(API_BASE_URL, "/login"))
-            .header(CONTENT_TYPE, "application/json")
-            .body(format!("{{\"apikey\": \"{}\"}}", self.api_key))
-            .send()?;
+    /// Logs in again when a request is rejected because the token expired
+    fn refreshing(mut self) -> Self {
+        let api_key = self.api_key.clone();
+        let token_file = self.token_file.clone();
+        self.api = self.api.with_refresh(move |api| {
+            let (api_key, token_file) = (api_key.clone(), token_file.clone());
+            Box::pin(async move { request_token(&api, &api_key, token_file.as_deref()).await })
+        });
+        self
+    }
 
-        if res.status() != StatusCode::OK {
-            return Err(TvdbError::HttpError(res.status()));
-        }
+    /// Records the API responses to, or replays them from, the given recording
+    pub fn with_recording(mut self, recording: impl Into<Arc<Recording>>) -> Self {
+        self.api = self.api.with_recording(recording);
+        self
+    }
 
-        let text = res.text()?;
-        let json: ApiReply<LoginReply> =
-            serde_json::from_str(&text)?;
+    /// Keeps the API responses in the given cache and serves them from it on later runs
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.api = self.api.with_cache(cache);
+        self
+    }
 
-        self.token = Some(json.data.token);
+    /// Restricts searches to the given TVDB language code
+    pub fn with_language(mut self, language: Option<String>) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Restricts searches to the given TVDB country code
+    pub fn with_country(mut self, country: Option<String>) -> Self {
+        self.country = country;
+        self
+    }
+
+    pub async fn login(&self) -> Result<(), ProviderError> {
+        if self.api.is_replaying() {
+            // replayed responses need no authentication, and the token is never recorded
+            self.api.set_token(String::new());
+            return Ok(());
+        }
+
+        let stored = self
+            .token_file
+            .as_deref()
+            .and_then(|path| stored_token(path, &self.api_key));
+        let token = match stored {
+            Some(token) => {
+                debug!("Using the stored TVDB token");
+                token
+            }
+            None => request_token(&self.api, &self.api_key, self.token_file.as_deref()).await?,
+        };
+        self.api.set_token(token);
 
         Ok(())
     }
 
-    pub fn search(&self, name: &str, media_type: MediaType) -> Result<SearchReply, TvdbError> {
-        let res = self
-            .client
-            .get(concatcp!(API_BASE_URL, "/search"))
-            .query(&[("q", name), ("type", media_type.into())])
-            .bearer_auth(self.token()?)
-            .send()?;
+    /// Sends the searches together, at most [`MAX_CONCURRENT_SEARCHES`] at a time, and returns
+    /// their results in the order of the searches
+    pub async fn search_all(&self, searches: Vec<Search>) -> Vec<Result<Vec<SearchResult>, ProviderError>> {
+        let mut results = Vec::new();
+        results.resize_with(searches.len(), || None);
+        let mut tasks = JoinSet::new();
+        for (index, search) in searches.into_iter().enumerate() {
+            if tasks.len() == MAX_CONCURRENT_SEARCHES {
+                if let Some((index, result)) = tasks.join_next().await.map(finished) {
+                    results[index] = Some(result);
+                }
+            }
+            let client = self.clone();
+            tasks.spawn(async move { (index, client.search(&search.name, search.media_type, search.year).await) });
+        }
+        while let Some((index, result)) = tasks.join_next().await.map(finished) {
+            results[index] = Some(result);
+        }
+        results
+            .into_iter()
+            .map(|result| result.expect("every search is joined"))
+            .collect()
+    }
 
-        if res.status() != StatusCode::OK {
-            return Err(TvdbError::HttpError(res.status()));
+    pub async fn search(
+        &self,
+        name: &str,
+        media_type: MediaType,
+        year: Option<u32>,
+    ) -> Result<Vec<SearchResult>, ProviderError> {
+        let year = year.map(|year| year.to_string());
+        let mut query = vec![("q", name), ("type", media_type.into())];
+        if let Some(year) = &year {
+            query.push(("year", year));
         }
+        if let Some(language) = &self.language {
+            query.push(("language", language));
+        }
+        if let Some(country) = &self.country {
+            query.push(("country", country));
+        }
+        let text = self.api.get("/search", &query).await?;
+        let json: ApiReply<Vec<TvdbSearchResult>> = serde_json::from_str(&text)?;
+
+        Ok(json
+            .data
+            .into_iter()
+            .map(|result| SearchResult {
+                name: result.name,
+                id: result.tvdb_id.and_then(|id| id.parse().ok()),
+                year: result.year.and_then(|year| year.parse().ok()),
+            })
+            .collect())
+    }
 
-        let text = res.text()?;
-        let json: ApiReply<SearchReply> = serde_json::from_str(&text)?;
+    pub async fn record(&self, id: u64, media_type: MediaType) -> Result<Record, ProviderError> {
+        let endpoint = match media_type {
+            MediaType::Series => format!("/series/{}", id),
+            MediaType::Movie => format!("/movies/{}", id),
+        };
+        let text = self.api.get(&endpoint, &[]).await?;
+        let json: ApiReply<TvdbRecord> = serde_json::from_str(&text)?;
 
-        Ok(json.data)
+        Ok(json.data.into())
     }
 
-    fn token(&self) -> Result<&str, TvdbError> {
-        self.token
-            .as_ref()
-            .map(|s| s.as_str())
-            .ok_or(TvdbError::Unauthenticated)
+    pub async fn find_by_imdb_id(&self, imdb_id: u64, media_type: MediaType) -> Result<Option<Record>, ProviderError> {
+        let endpoint = format!("/search/remoteid/{}", provider::imdb_id(imdb_id));
+        let text = self.api.get(&endpoint, &[]).await?;
+        let json: ApiReply<Vec<RemoteIdResult>> = serde_json::from_str(&text)?;
+
+        Ok(json
+            .data
+            .into_iter()
+            .find_map(|result| match media_type {
+                MediaType::Series => result.series,
+                MediaType::Movie => result.movie,
+            })
+            .map(Record::from))
     }
-}
 
-#[derive(Debug)]
-pub enum TvdbError {
-    Unauthenticated,
-    RequestError(reqwest::Error),
-    ParseError(serde_json::Error),
-    HttpError(StatusCode),
-}
+    pub async fn content_ratings(&self, id: u64, media_type: MediaType) -> Result<Vec<ContentRating>, ProviderError> {
+        let endpoint = match media_type {
+            MediaType::Series => format!("/series/{}/extended", id),
+            MediaType::Movie => format!("/movies/{}/extended", id),
+        };
+        let text = self.api.get(&endpoint, &[("short", "true")]).await?;
+        let json: ApiReply<ExtendedRecord> = serde_json::from_str(&text)?;
 
-impl Display for TvdbError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            TvdbError::Unauthenticated => write!(f, "Unauthenticated"),
-            TvdbError::RequestError(error) => write!(f, "Request error: {}", error),
-            TvdbError::ParseError(error) => write!(f, "Parse error: {}", error),
-            TvdbError::HttpError(status_code) => write!(f, "HTTP error: {}", status_code),
-        }
+        Ok(json
+            .data
+            .content_ratings
+            .into_iter()
+            .map(|rating| ContentRating {
+                name: rating.name,
+                country: rating.country,
+            })
+            .collect())
+    }
+
+    pub async fn genres(&self, id: u64, media_type: MediaType) -> Result<Vec<String>, ProviderError> {
+        let endpoint = match media_type {
+            MediaType::Series => format!("/series/{}/extended", id),
+            MediaType::Movie => format!("/movies/{}/extended", id),
+        };
+        let text = self.api.get(&endpoint, &[("short", "true")]).await?;
+        let json: ApiReply<ExtendedRecord> = serde_json::from_str(&text)?;
+
+        Ok(json.data.genres.into_iter().map(|genre| genre.name).collect())
     }
-}
 
-impl error::Error for TvdbError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        match self {
-            TvdbError::RequestError(error) => Some(error),
-            TvdbError::ParseError(error) => Some(error),
-            _ => None,
+    pub async fn episodes(&self, series_id: u64, season: u32, episode: u32) -> Result<Vec<Episode>, ProviderError> {
+        let (season, episode) = (season.to_string(), episode.to_string());
+        self.series_episodes(series_id, &[("season", &season), ("episodeNumber", &episode)]).await
+    }
+
+    pub async fn season_episodes(&self, series_id: u64, season: u32) -> Result<Vec<Episode>, ProviderError> {
+        self.series_episodes(series_id, &[("season", &season.to_string())]).await
+    }
+
+    pub async fn episodes_aired(&self, series_id: u64, air_date: &str) -> Result<Vec<Episode>, ProviderError> {
+        self.series_episodes(series_id, &[("airDate", air_date)]).await
+    }
+
+    /// Goes through the pages of the episode list of the series until the episode is found
+    pub async fn episode_by_absolute_number(
+        &self,
+        series_id: u64,
+        absolute_number: u32,
+    ) -> Result<Option<Episode>, ProviderError> {
+        let endpoint = format!("/series/{}/episodes/default", series_id);
+        for page in 0..MAX_EPISODE_PAGES {
+            let text = self.api.get(&endpoint, &[("page", &page.to_string())]).await?;
+            let json: ApiReply<EpisodesReply> = serde_json::from_str(&text)?;
+            let has_next = json.links.is_some_and(|links| links.next.is_some());
+
+            // specials are numbered outside of the absolute order
+            let found = json
+                .data
+                .episodes
+                .into_iter()
+                .find(|episode| episode.absolute_number == Some(absolute_number) && episode.season_number != 0);
+            if found.is_some() || !has_next {
+                return Ok(found.map(Episode::from));
+            }
         }
+        Ok(None)
     }
-}
 
-impl From<reqwest::Error> for TvdbError {
-    fn from(value: reqwest::Error) -> Self {
-        Self::RequestError(value)
+    /// Finds the episode in the DVD order, then gets it by its id for its numbers in the default one
+    pub async fn episode_in_dvd_order(
+        &self,
+        series_id: u64,
+        season: u32,
+        episode: u32,
+    ) -> Result<Option<Episode>, ProviderError> {
+        let (season_query, episode_query) = (season.to_string(), episode.to_string());
+        let found = self
+            .ordered_episodes(series_id, "dvd", &[("season", &season_query), ("episodeNumber", &episode_query)])
+            .await?
+            .into_iter()
+            .find(|found| found.season_number == season && found.number == episode);
+        let Some(id) = found.and_then(|found| found.id) else {
+            return Ok(None);
+        };
+
+        let text = self.api.get(&format!("/episodes/{}", id), &[]).await?;
+        let json: ApiReply<TvdbEpisode> = serde_json::from_str(&text)?;
+        Ok(Some(json.data.into()))
+    }
+
+    async fn series_episodes(&self, series_id: u64, query: &[(&str, &str)]) -> Result<Vec<Episode>, ProviderError> {
+        Ok(self
+            .ordered_episodes(series_id, "default", query)
+            .await?
+            .into_iter()
+            .map(Episode::from)
+            .collect())
+    }
+
+    /// The episodes of the series numbered in an order of TVDB, `default`, `dvd`, `absolute`...
+    async fn ordered_episodes(
+        &self,
+        series_id: u64,
+        season_type: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<TvdbEpisode>, ProviderError> {
+        let endpoint = format!("/series/{}/episodes/{}", series_id, season_type);
+        let text = self.api.get(&endpoint, query).await?;
+        let json: ApiReply<EpisodesReply> = serde_json::from_str(&text)?;
+
+        Ok(json.data.episodes)
     }
 }
 
-impl From<serde_json::Error> for TvdbError {
-    fn from(value: serde_json::Error) -> Self {
-        Self::ParseError(value)
+/// The lookups of the trait wait for the async ones on the thread of the worker
+impl MetadataProvider for TvdbClient {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Tvdb
+    }
+
+    fn search(&self, name: &str, media_type: MediaType, year: Option<u32>) -> Result<Vec<SearchResult>, ProviderError> {
+        provider::block_on(TvdbClient::search(self, name, media_type, year))
+    }
+
+    fn record(&self, id: u64, media_type: MediaType) -> Result<Record, ProviderError> {
+        provider::block_on(TvdbClient::record(self, id, media_type))
+    }
+
+    fn find_by_imdb_id(&self, imdb_id: u64, media_type: MediaType) -> Result<Option<Record>, ProviderError> {
+        provider::block_on(TvdbClient::find_by_imdb_id(self, imdb_id, media_type))
+    }
+
+    fn content_ratings(&self, id: u64, media_type: MediaType) -> Result<Vec<ContentRating>, ProviderError> {
+        provider::block_on(TvdbClient::content_ratings(self, id, media_type))
+    }
+
+    fn genres(&self, id: u64, media_type: MediaType) -> Result<Vec<String>, ProviderError> {
+        provider::block_on(TvdbClient::genres(self, id, media_type))
+    }
+
+    fn episodes(&self, series_id: u64, season: u32, episode: u32) -> Result<Vec<Episode>, ProviderError> {
+        provider::block_on(TvdbClient::episodes(self, series_id, season, episode))
+    }
+
+    fn season_episodes(&self, series_id: u64, season: u32) -> Result<Vec<Episode>, ProviderError> {
+        provider::block_on(TvdbClient::season_episodes(self, series_id, season))
+    }
+
+    fn episodes_aired(&self, series_id: u64, air_date: &str) -> Result<Vec<Episode>, ProviderError> {
+        provider::block_on(TvdbClient::episodes_aired(self, series_id, air_date))
+    }
+
+    fn episode_by_absolute_number(&self, series_id: u64, absolute_number: u32) -> Result<Option<Episode>, ProviderError> {
+        provider::block_on(TvdbClient::episode_by_absolute_number(self, series_id, absolute_number))
+    }
+
+    fn episode_in_dvd_order(&self, series_id: u64, season: u32, episode: u32) -> Result<Option<Episode>, ProviderError> {
+        provider::block_on(TvdbClient::episode_in_dvd_order(self, series_id, season, episode))
+    }
+
+    /// Sends the searches from a task of the runtime, their responses are kept for the lookups
+    fn prefetch(&self, searches: Vec<Search>) {
+        let client = self.clone();
+        let task = provider::runtime().spawn(async move {
+            let count = searches.len();
+            let failed = client.search_all(searches).await.iter().filter(|result| result.is_err()).count();
+            debug!("Prefetched {} searches, {} failed", count, failed);
+        });
+        self.prefetching.lock().unwrap().push(task);
+    }
+
+    /// Aborts the searches still being sent and waits for their tasks to end, so that they no
+    /// longer hold the responses when the cache is saved
+    fn stop_prefetch(&self) {
+        let tasks = std::mem::take(&mut *self.prefetching.lock().unwrap());
+        provider::block_on(async {
+            for task in tasks {
+                task.abort();
+                // an aborted task ends with a cancellation, a panic was logged when it happened
+                let _ = task.await;
+            }
+        });
     }
 }
 
+/// How many searches of [`TvdbClient::search_all`] are sent at the same time
+const MAX_CONCURRENT_SEARCHES: usize = 8;
+
+/// The output of a finished task, panicking again when the task did
+fn finished<T>(joined: Result<T, JoinError>) -> T {
+    joined.unwrap_or_else(|error| panic::resume_unwind(error.into_panic()))
+}
+
+/// How many pages of 500 episodes are searched for an absolute number, more than any series has
+const MAX_EPISODE_PAGES: u32 = 20;
+
 #[derive(Deserialize)]
 struct ApiReply<T> {
+    #[allow(dead_code)]
     status: String,
     data: T,
+    #[serde(default)]
+    links: Option<Links>,
+}
+
+/// The pagination of a reply
+#[derive(Deserialize)]
+struct Links {
+    #[serde(default)]
+    next: Option<String>,
+}
+
+/// Logs in and returns the new token, written to the token file if there is one
+async fn request_token(api: &ApiClient, api_key: &str, token_file: Option<&Path>) -> Result<String, ProviderError> {
+    let text = api.post("/login", format!("{{\"apikey\": \"{}\"}}", api_key)).await?;
+    let json: ApiReply<LoginReply> = serde_json::from_str(&text)?;
+    let token = json.data.token;
+
+    if let Some(path) = token_file {
+        let stored = StoredToken {
+            api_key: api_key.to_string(),
+            token: token.clone(),
+            obtained_at: now(),
+        };
+        if let Err(error) = store_token(path, &stored) {
+            warn!("Could not store the TVDB token in {}: {}", path.display(), error);
+        }
+    }
+    Ok(token)
+}
+
+/// The token in the token file, unless it was obtained with another API key or is too old
+fn stored_token(path: &Path, api_key: &str) -> Option<String> {
+    let contents = fs::read_to_string(path).ok()?;
+    let stored: StoredToken = serde_json::from_str(&contents).ok()?;
+    let fresh = now().saturating_sub(stored.obtained_at) < TOKEN_LIFETIME_SECS;
+    (stored.api_key == api_key && fresh).then_some(stored.token)
+}
+
+fn store_token(path: &Path, stored: &StoredToken) -> Result<(), io::Error> {
+    let json = serde_json::to_string(stored).map_err(io::Error::other)?;
+    fs::write(path, json)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
 }
 
 #[derive(Deserialize)]
@@ -129,9 +467,176 @@ struct LoginReply {
     token: String,
 }
 
-pub type SearchReply = Vec<SearchResult>;
+#[derive(Deserialize)]
+struct TvdbSearchResult {
+    name: String,
+    #[serde(default)]
+    tvdb_id: Option<String>,
+    #[serde(default)]
+    year: Option<String>,
+}
+
+/// A series or movie as returned by its TVDB id
+#[derive(Deserialize)]
+struct TvdbRecord {
+    id: u64,
+    name: String,
+    #[serde(default)]
+    year: Option<String>,
+}
+
+impl From<TvdbRecord> for Record {
+    fn from(record: TvdbRecord) -> Self {
+        Self {
+            id: record.id,
+            name: record.name,
+            year: record.year.and_then(|year| year.parse().ok()),
+        }
+    }
+}
+
+/// What an id of another site is the id of, a series or a movie
+#[derive(Deserialize)]
+struct RemoteIdResult {
+    #[serde(default)]
+    series: Option<TvdbRecord>,
+    #[serde(default)]
+    movie: Option<TvdbRecord>,
+}
+
+/// The part of the extended record of a series or movie that is used
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExtendedRecord {
+    #[serde(default)]
+    content_ratings: Vec<TvdbContentRating>,
+    #[serde(default)]
+    genres: Vec<TvdbGenre>,
+}
 
 #[derive(Deserialize)]
-pub struct SearchResult {
-    pub name: String,
+struct TvdbGenre {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct TvdbContentRating {
+    name: String,
+    country: String,
+}
+
+#[derive(Deserialize)]
+struct EpisodesReply {
+    episodes: Vec<TvdbEpisode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TvdbEpisode {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    name: Option<String>,
+    season_number: u32,
+    number: u32,
+    #[serde(default)]
+    absolute_number: Option<u32>,
+}
+
+impl From<TvdbEpisode> for Episode {
+    fn from(episode: TvdbEpisode) -> Self {
+        Self {
+            name: episode.name,
+            season_number: episode.season_number,
+            number: episode.number,
+            absolute_number: episode.absolute_number,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::request_key;
+
+    #[test]
+    fn searches_are_sent_together_and_answered_in_order() {
+        let names: Vec<_> = (1..=12).map(|number| format!("Show {}", number)).collect();
+        let bodies: Vec<_> = (1..=12)
+            .map(|number| {
+                format!(r#"{{"status": "success", "data": [{{"name": "Show {0}", "tvdb_id": "{0}"}}]}}"#, number)
+            })
+            .collect();
+        // the seventh search is not recorded
+        let responses: Vec<_> = names
+            .iter()
+            .zip(&bodies)
+            .filter(|(name, _)| name.as_str() != "Show 7")
+            .map(|(name, body)| (request_key("/search", &[("q", name), ("type", "series")]), 200, body.as_str()))
+            .collect();
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&responses));
+        provider::block_on(tvdb.login()).unwrap();
+
+        let searches = names
+            .iter()
+            .map(|name| Search {
+                name: name.clone(),
+                media_type: MediaType::Series,
+                year: None,
+            })
+            .collect();
+        let results = provider::block_on(tvdb.search_all(searches));
+
+        assert_eq!(results.len(), 12);
+        for (number, result) in (1..=12).zip(&results) {
+            match result {
+                Ok(found) if number != 7 => assert_eq!(found[0].id, Some(number)),
+                Err(ProviderError::NotRecorded(_)) if number == 7 => {}
+                other => panic!("unexpected result of Show {}: {:?}", number, other),
+            }
+        }
+        // the lookups of the files then find the responses received
+        assert_eq!(
+            MetadataProvider::search(&tvdb, "Show 12", MediaType::Series, None).unwrap()[0].name,
+            "Show 12"
+        );
+    }
+
+    #[test]
+    fn stopped_prefetches_release_the_client() {
+        let body = r#"{"status": "success", "data": [{"name": "Severance", "tvdb_id": "371980"}]}"#;
+        let responses = [(request_key("/search", &[("q", "Severance"), ("type", "series")]), 200, body)];
+        let tvdb = TvdbClient::new("").with_recording(Recording::replaying(&responses));
+        provider::block_on(tvdb.login()).unwrap();
+
+        tvdb.prefetch(vec![Search {
+            name: "Severance".to_string(),
+            media_type: MediaType::Series,
+            year: None,
+        }]);
+        tvdb.stop_prefetch();
+
+        // no task holds a clone of the client, and with it the responses, any longer
+        assert_eq!(Arc::strong_count(&tvdb.prefetching), 1);
+        assert!(tvdb.prefetching.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn stored_tokens_are_used_for_their_key_until_they_are_too_old() {
+        let path = std::env::temp_dir().join(format!("media-renamer-token-{}.json", std::process::id()));
+        let stored = |obtained_at| StoredToken {
+            api_key: "key".to_string(),
+            token: "token".to_string(),
+            obtained_at,
+        };
+
+        store_token(&path, &stored(now())).unwrap();
+        assert_eq!(stored_token(&path, "key"), Some("token".to_string()));
+        assert_eq!(stored_token(&path, "other key"), None);
+
+        store_token(&path, &stored(now() - TOKEN_LIFETIME_SECS)).unwrap();
+        assert_eq!(stored_token(&path, "key"), None);
+
+        fs::remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,288 @@
+use std::sync::Arc;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::{
+    cache::ResponseCache,
+    media::MediaType,
+    provider::{
+        self, ApiClient, ContentRating, Episode, MetadataProvider, ProviderError, ProviderKind, Record, SearchResult,
+    },
+    recording::Recording,
+};
+
+const API_BASE_URL: &str = "https://api.tvmaze.com";
+
+/// Client for the TVmaze API, which needs no key. It only knows series
+#[derive(Clone)]
+pub struct TvmazeClient {
+    api: ApiClient,
+}
+
+impl TvmazeClient {
+    pub fn new() -> Self {
+        Self {
+            api: ApiClient::new(API_BASE_URL),
+        }
+    }
+
+    /// Records the API responses to, or replays them from, the given recording
+    pub fn with_recording(mut self, recording: impl Into<Arc<Recording>>) -> Self {
+        self.api = self.api.with_recording(recording);
+        self
+    }
+
+    /// Keeps the API responses in the given cache and serves them from it on later runs
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.api = self.api.with_cache(cache);
+        self
+    }
+
+    /// Gets the body of the reply, none when TVmaze does not know what was asked for
+    fn get_found(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<Option<String>, ProviderError> {
+        match self.api.get_blocking(endpoint, query) {
+            Ok(text) => Ok(Some(text)),
+            Err(ProviderError::HttpError(StatusCode::NOT_FOUND)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl Default for TvmazeClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetadataProvider for TvmazeClient {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Tvmaze
+    }
+
+    /// TVmaze cannot search by year, the results are left for the caller to choose from
+    fn search(&self, name: &str, media_type: MediaType, _year: Option<u32>) -> Result<Vec<SearchResult>, ProviderError> {
+        if media_type == MediaType::Movie {
+            return Ok(Vec::new());
+        }
+
+        let text = self.api.get_blocking("/search/shows", &[("q", name)])?;
+        let results: Vec<ScoredShow> = serde_json::from_str(&text)?;
+        Ok(results.into_iter().map(|result| result.show.into()).collect())
+    }
+
+    fn record(&self, id: u64, media_type: MediaType) -> Result<Record, ProviderError> {
+        if media_type == MediaType::Movie {
+            return Err(ProviderError::HttpError(StatusCode::NOT_FOUND));
+        }
+
+        let text = self.api.get_blocking(&format!("/shows/{}", id), &[])?;
+        let show: Show = serde_json::from_str(&text)?;
+        Ok(Record {
+            year: year_of(&show.premiered),
+            id: show.id,
+            name: show.name,
+        })
+    }
+
+    fn find_by_imdb_id(&self, imdb_id: u64, media_type: MediaType) -> Result<Option<Record>, ProviderError> {
+        if media_type == MediaType::Movie {
+            return Ok(None);
+        }
+
+        let Some(text) = self.get_found("/lookup/shows", &[("imdb", &provider::imdb_id(imdb_id))])? else {
+            return Ok(None);
+        };
+        let show: Show = serde_json::from_str(&text)?;
+        Ok(Some(Record {
+            year: year_of(&show.premiered),
+            id: show.id,
+            name: show.name,
+        }))
+    }
+
+    /// TVmaze has no content ratings
+    fn content_ratings(&self, _id: u64, _media_type: MediaType) -> Result<Vec<ContentRating>, ProviderError> {
+        Ok(Vec::new())
+    }
+
+    fn genres(&self, id: u64, media_type: MediaType) -> Result<Vec<String>, ProviderError> {
+        if media_type == MediaType::Movie {
+            return Ok(Vec::new());
+        }
+
+        let text = self.api.get_blocking(&format!("/shows/{}", id), &[])?;
+        let show: Show = serde_json::from_str(&text)?;
+        Ok(show.genres)
+    }
+
+    fn episodes(&self, series_id: u64, season: u32, episode: u32) -> Result<Vec<Episode>, ProviderError> {
+        let (season, episode) = (season.to_string(), episode.to_string());
+        let endpoint = format!("/shows/{}/episodebynumber", series_id);
+        let Some(text) = self.get_found(&endpoint, &[("season", &season), ("number", &episode)])? else {
+            return Ok(Vec::new());
+        };
+        let episode: TvmazeEpisode = serde_json::from_str(&text)?;
+        Ok(vec![episode.into()])
+    }
+
+    /// Filters the episode list of the series, TVmaze lists the episodes of a season by its id only
+    fn season_episodes(&self, series_id: u64, season: u32) -> Result<Vec<Episode>, ProviderError> {
+        let Some(text) = self.get_found(&format!("/shows/{}/episodes", series_id), &[])? else {
+            return Ok(Vec::new());
+        };
+        let episodes: Vec<TvmazeEpisode> = serde_json::from_str(&text)?;
+        Ok(episodes
+            .into_iter()
+            .map(Episode::from)
+            .filter(|episode| episode.season_number == season)
+            .collect())
+    }
+
+    fn episodes_aired(&self, series_id: u64, air_date: &str) -> Result<Vec<Episode>, ProviderError> {
+        let endpoint = format!("/shows/{}/episodesbydate", series_id);
+        let Some(text) = self.get_found(&endpoint, &[("date", air_date)])? else {
+            return Ok(Vec::new());
+        };
+        let episodes: Vec<TvmazeEpisode> = serde_json::from_str(&text)?;
+        Ok(episodes.into_iter().map(Episode::from).collect())
+    }
+
+    /// Counts through the episode list of the series, which leaves the specials out
+    fn episode_by_absolute_number(&self, series_id: u64, absolute_number: u32) -> Result<Option<Episode>, ProviderError> {
+        let text = self.api.get_blocking(&format!("/shows/{}/episodes", series_id), &[])?;
+        let episodes: Vec<TvmazeEpisode> = serde_json::from_str(&text)?;
+        let index = (absolute_number as usize).checked_sub(1);
+        Ok(index
+            .and_then(|index| episodes.into_iter().nth(index))
+            .map(|episode| Episode {
+                absolute_number: Some(absolute_number),
+                ..episode.into()
+            }))
+    }
+
+    /// TVmaze has no DVD order
+    fn episode_in_dvd_order(&self, _series_id: u64, _season: u32, _episode: u32) -> Result<Option<Episode>, ProviderError> {
+        Ok(None)
+    }
+}
+
+/// The year of a TVmaze date like `2022-02-18`
+fn year_of(date: &Option<String>) -> Option<u32> {
+    date.as_deref().and_then(|date| date.get(..4)).and_then(|year| year.parse().ok())
+}
+
+#[derive(Deserialize)]
+struct ScoredShow {
+    show: Show,
+}
+
+#[derive(Deserialize)]
+struct Show {
+    id: u64,
+    name: String,
+    #[serde(default)]
+    premiered: Option<String>,
+    #[serde(default)]
+    genres: Vec<String>,
+}
+
+impl From<Show> for SearchResult {
+    fn from(show: Show) -> Self {
+        Self {
+            year: year_of(&show.premiered),
+            name: show.name,
+            id: Some(show.id),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TvmazeEpisode {
+    #[serde(default)]
+    name: Option<String>,
+    season: u32,
+    /// Specials have no number
+    #[serde(default)]
+    number: Option<u32>,
+}
+
+impl From<TvmazeEpisode> for Episode {
+    fn from(episode: TvmazeEpisode) -> Self {
+        Self {
+            name: episode.name,
+            season_number: if episode.number.is_some() { episode.season } else { 0 },
+            number: episode.number.unwrap_or_default(),
+            absolute_number: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::request_key;
+
+    /// Severance, 44933 on TVmaze, with two episodes in its first season and one in its second
+    fn severance() -> TvmazeClient {
+        TvmazeClient::new().with_recording(Recording::replaying(&[
+            (
+                request_key("/shows/44933/episodes", &[]),
+                200,
+                r#"[
+                    {"name": "Good News About Hell", "season": 1, "number": 1},
+                    {"name": "Half Loop", "season": 1, "number": 2},
+                    {"name": "Hello, Ms. Cobel", "season": 2, "number": 1}
+                ]"#,
+            ),
+            (
+                request_key("/shows/44933/episodebynumber", &[("season", "1"), ("number", "2")]),
+                200,
+                r#"{"name": "Half Loop", "season": 1, "number": 2}"#,
+            ),
+            (
+                request_key("/shows/44933/episodebynumber", &[("season", "3"), ("number", "1")]),
+                404,
+                r#"{"name": "Not Found", "status": 404}"#,
+            ),
+            (
+                request_key("/shows/44933/episodesbydate", &[("date", "2025-01-17")]),
+                200,
+                r#"[{"name": "Hello, Ms. Cobel", "season": 2, "number": 1}]"#,
+            ),
+            (
+                request_key("/shows/44933/episodesbydate", &[("date", "2030-01-01")]),
+                404,
+                r#"{"name": "Not Found", "status": 404}"#,
+            ),
+        ]))
+    }
+
+    #[test]
+    fn episodes_are_found_in_their_season() {
+        let tvmaze = severance();
+        let episodes = tvmaze.episodes(44933, 1, 2).unwrap();
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(episodes[0].name.as_deref(), Some("Half Loop"));
+        assert_eq!(tvmaze.season_episodes(44933, 1).unwrap().len(), 2);
+        assert!(tvmaze.episodes(44933, 3, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn episodes_are_found_by_air_date() {
+        let tvmaze = severance();
+        let aired = tvmaze.episodes_aired(44933, "2025-01-17").unwrap();
+        assert_eq!(aired.len(), 1);
+        assert_eq!((aired[0].season_number, aired[0].number), (2, 1));
+        assert!(tvmaze.episodes_aired(44933, "2030-01-01").unwrap().is_empty());
+    }
+
+    #[test]
+    fn absolute_numbers_count_through_the_episode_list() {
+        let tvmaze = severance();
+        let third = tvmaze.episode_by_absolute_number(44933, 3).unwrap().unwrap();
+        assert_eq!((third.season_number, third.number, third.absolute_number), (2, 1, Some(3)));
+        assert_eq!(tvmaze.episode_by_absolute_number(44933, 0).unwrap(), None);
+        assert_eq!(tvmaze.episode_by_absolute_number(44933, 4).unwrap(), None);
+    }
+}
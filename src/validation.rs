@@ -0,0 +1,189 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// The checks run against the destination of every file before it is acted on, so that a
+/// custom template cannot quietly produce broken library names. A file failing one is not placed
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Validation {
+    /// The longest the whole destination can be, in characters, 0 for no limit
+    #[serde(default)]
+    pub max_path_length: usize,
+
+    /// The longest a file or folder name of the destination can be, in bytes, 0 for no limit
+    #[serde(default = "default_max_name_length")]
+    pub max_name_length: usize,
+
+    /// Characters the path in the output directory cannot contain
+    #[serde(default)]
+    pub forbidden_chars: String,
+
+    /// Regular expressions the file name has to match, e.g. `s[0-9]{2}e[0-9]{2}|\(\d{4}\)`
+    #[serde(default)]
+    pub required: Vec<String>,
+
+    /// Text left over from a template that the path in the output directory cannot contain
+    #[serde(default = "default_placeholders")]
+    pub placeholders: Vec<String>,
+
+    /// Shorten the file name to max_name_length and max_path_length instead of failing the file
+    #[serde(default)]
+    pub truncate: bool,
+}
+
+/// The limit of most filesystems
+fn default_max_name_length() -> usize {
+    255
+}
+
+fn default_placeholders() -> Vec<String> {
+    ["{", "}", "TODO"].map(str::to_string).to_vec()
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self {
+            max_path_length: 0,
+            max_name_length: default_max_name_length(),
+            forbidden_chars: String::new(),
+            required: Vec::new(),
+            placeholders: default_placeholders(),
+            truncate: false,
+        }
+    }
+}
+
+impl Validation {
+    /// The destination with its file name shortened to the length limits, keeping the extension,
+    /// when `truncate` is set and it is too long. The end of a file name is usually an episode
+    /// title, which matters the least. None when the file name is fine or cannot be shortened enough
+    pub fn fit(&self, destination: &Path) -> Option<PathBuf> {
+        let file_name = destination.file_name()?.to_str()?;
+        let parent_length = destination.parent()?.join("").to_string_lossy().chars().count();
+        let fits = |name: &str| {
+            (self.max_name_length == 0 || name.len() <= self.max_name_length)
+                && (self.max_path_length == 0 || parent_length + name.chars().count() <= self.max_path_length)
+        };
+        if !self.truncate || fits(file_name) {
+            return None;
+        }
+
+        let (stem, extension) = match file_name.rsplit_once('.') {
+            Some((stem, extension)) => (stem, format!(".{}", extension)),
+            None => (file_name, String::new()),
+        };
+        let mut end = stem.len();
+        loop {
+            end = stem[..end].char_indices().next_back()?.0;
+            let shortened = stem[..end].trim_end_matches([' ', '.', '-']);
+            if shortened.is_empty() {
+                return None;
+            }
+            let name = format!("{}{}", shortened, extension);
+            if fits(&name) {
+                return Some(destination.with_file_name(name));
+            }
+        }
+    }
+
+    /// Checks the destination of a file and the part of it rendered from the template.
+    /// Returns what is wrong with it, if anything
+    pub fn check(&self, destination: &Path, in_output: &Path) -> Result<(), String> {
+        let length = destination.to_string_lossy().chars().count();
+        if self.max_path_length > 0 && length > self.max_path_length {
+            return Err(format!(
+                "is {} characters long, more than the {} of max_path_length",
+                length, self.max_path_length
+            ));
+        }
+
+        for component in in_output.iter() {
+            let name = component.to_string_lossy();
+            if self.max_name_length > 0 && name.len() > self.max_name_length {
+                return Err(format!(
+                    "has a name of {} bytes, more than the {} of max_name_length: {}",
+                    name.len(),
+                    self.max_name_length,
+                    name
+                ));
+            }
+        }
+
+        let in_output = in_output.to_string_lossy();
+        if let Some(forbidden) = in_output.chars().find(|c| self.forbidden_chars.contains(*c)) {
+            return Err(format!("contains the forbidden character {:?}", forbidden));
+        }
+        if let Some(placeholder) = self
+            .placeholders
+            .iter()
+            .find(|placeholder| !placeholder.is_empty() && in_output.contains(placeholder.as_str()))
+        {
+            return Err(format!("contains the placeholder {:?}", placeholder));
+        }
+
+        let file_name = destination.file_name().unwrap_or_default().to_string_lossy();
+        for required in &self.required {
+            let regex =
+                Regex::new(required).map_err(|error| format!("cannot be checked against {}: {}", required, error))?;
+            if !regex.is_match(&file_name) {
+                return Err(format!("has a file name not matching {}", required));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_names_are_reported() {
+        let validation = Validation {
+            max_path_length: 60,
+            forbidden_chars: ":".to_string(),
+            required: vec![r"s[0-9]{2}e[0-9]{2}".to_string()],
+            ..Validation::default()
+        };
+        let check = |in_output: &str| validation.check(&Path::new("library").join(in_output), Path::new(in_output));
+
+        assert_eq!(check("TV/Severance/Season 1/Severance - s01e01.mkv"), Ok(()));
+        assert!(check("TV/Severance/Season 1/Severance - s01e01 - Good News About Hell.mkv").is_err());
+        assert!(check("TV/Frieren: Beyond/Season 1/Frieren - s01e01.mkv").is_err());
+        assert!(check("TV/Severance/Season 1/Severance - {episode}.mkv").is_err());
+        assert!(check("TV/Severance/Season 1/Severance - 1x01.mkv").is_err());
+        assert!(check(&format!("TV/{}/Severance - s01e01.mkv", "a".repeat(256))).is_err());
+    }
+
+    #[test]
+    fn long_file_names_are_shortened_when_asked() {
+        let validation = Validation {
+            max_path_length: 52,
+            max_name_length: 30,
+            truncate: true,
+            ..Validation::default()
+        };
+        let fit = |destination: &str| validation.fit(Path::new(destination));
+
+        assert_eq!(fit("library/TV/Severance/Season 1/Severance - s01e01.mkv"), None);
+        assert_eq!(
+            fit("library/Severance - s01e04 - The You You Are.mkv"),
+            Some(PathBuf::from("library/Severance - s01e04 - The Y.mkv"))
+        );
+        assert_eq!(
+            fit("library/TV/Severance/Season 1/Severance - s01e04 - The You You Are.mkv"),
+            Some(PathBuf::from("library/TV/Severance/Season 1/Severance - s01e04.mkv"))
+        );
+        assert_eq!(fit(&format!("library/{}/Severance - s01e01.mkv", "a".repeat(60))), None);
+        assert_eq!(
+            Validation {
+                truncate: false,
+                ..validation.clone()
+            }
+            .fit(Path::new("library/Severance - s01e04 - The You You Are.mkv")),
+            None
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    fs, io,
+    path::Path,
+    sync::LazyLock,
+};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::media::{MediaData, MediaFile};
+
+/// A year at the end of a title of a text want-list, like `Dune (2021)`
+static TRAILING_YEAR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(.*?)\s*\(([0-9]{4})\)$").unwrap());
+
+/// The series and movies the user wants, the other files of the input are left alone
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WantList {
+    titles: Vec<Wanted>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Wanted {
+    /// The title in the form it is compared in
+    title: String,
+    /// The year the title has to have, when the list tells it
+    year: Option<u32>,
+}
+
+/// An item of a JSON want-list: the series and movies of the Sonarr and Radarr APIs have their
+/// title at the top, the items of a Trakt list have it in their show or movie
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonItem {
+    Trakt {
+        #[serde(alias = "movie")]
+        show: JsonTitle,
+    },
+    Plain(JsonTitle),
+}
+
+#[derive(Deserialize)]
+struct JsonTitle {
+    title: String,
+    #[serde(default)]
+    year: Option<u32>,
+}
+
+impl WantList {
+    /// Reads a want-list, a JSON array when the file ends with `.json` and otherwise a text file
+    /// with one title per line, optionally followed by its year in parentheses
+    pub fn read(path: &Path) -> Result<Self, io::Error> {
+        let contents = fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        if is_json {
+            Self::parse_json(&contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+        } else {
+            Ok(Self::parse_text(&contents))
+        }
+    }
+
+    /// Parses a text want-list, empty lines and lines starting with # are skipped
+    fn parse_text(contents: &str) -> Self {
+        let titles = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match TRAILING_YEAR.captures(line) {
+                Some(captures) => Wanted::new(&captures[1], captures[2].parse().ok()),
+                None => Wanted::new(line, None),
+            })
+            .collect();
+        Self { titles }
+    }
+
+    fn parse_json(contents: &str) -> Result<Self, serde_json::Error> {
+        let items: Vec<JsonItem> = serde_json::from_str(contents)?;
+        let titles = items
+            .into_iter()
+            .map(|item| match item {
+                JsonItem::Trakt { show: title } | JsonItem::Plain(title) => Wanted::new(&title.title, title.year),
+            })
+            .collect();
+        Ok(Self { titles })
+    }
+
+    /// How many series and movies are on the list
+    pub fn count(&self) -> usize {
+        self.titles.len()
+    }
+
+    /// Whether the series or movie of the file is on the list. Titles are compared ignoring case
+    /// and punctuation, and years only when both the list and the file have one
+    pub fn wants(&self, media_file: &MediaFile) -> bool {
+        let title = normalize(media_file.name());
+        let year = match media_file.media() {
+            MediaData::Movie { year } => Some(*year),
+            _ => media_file.metadata().year,
+        };
+        self.titles.iter().any(|wanted| {
+            wanted.title == title && (wanted.year.is_none() || year.is_none() || wanted.year == year)
+        })
+    }
+}
+
+impl Wanted {
+    fn new(title: &str, year: Option<u32>) -> Self {
+        Self {
+            title: normalize(title),
+            year,
+        }
+    }
+}
+
+/// Whether two titles are the same ignoring case, punctuation and spacing, so that
+/// `Agents of S.H.I.E.L.D.` and the parsed `Agents of S H I E L D` are
+pub fn same_title(a: &str, b: &str) -> bool {
+    compact_title(a) == compact_title(b)
+}
+
+/// The title in lower case without punctuation and spacing, the form titles are compared in
+pub fn compact_title(title: &str) -> String {
+    normalize(title).replace(' ', "")
+}
+
+/// The lower case words of a title, so that `Marvel's Agents of S.H.I.E.L.D.` and
+/// `marvels agents of shield` compare equal
+fn normalize(title: &str) -> String {
+    title
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::media::Metadata;
+
+    use super::*;
+
+    fn movie(name: &str, year: u32) -> MediaFile {
+        MediaFile::new(name.to_string(), MediaData::Movie { year }, "mkv".to_string())
+    }
+
+    #[test]
+    fn text_lists_match_titles_and_years() {
+        let list = WantList::parse_text("# wanted\nMarvel's Agents of S.H.I.E.L.D.\n\nDune (2021)\n");
+        assert_eq!(list.count(), 2);
+
+        let mut series = MediaFile::new(
+            "Marvels Agents of SHIELD".to_string(),
+            MediaData::TvSeries { season: 1, episode: 1 },
+            "mkv".to_string(),
+        );
+        series.set_metadata(Metadata {
+            tvdb_id: Some(263365),
+            year: Some(2013),
+            ..Metadata::default()
+        });
+        assert!(list.wants(&series));
+        assert!(list.wants(&movie("Dune", 2021)));
+        assert!(!list.wants(&movie("Dune", 1984)));
+        assert!(!list.wants(&movie("Arrival", 2016)));
+    }
+
+    #[test]
+    fn titles_are_compared_without_spacing() {
+        assert!(same_title("Agents of S.H.I.E.L.D.", "Agents of S H I E L D"));
+        assert!(same_title("the office", "The Office"));
+        assert!(!same_title("The Office", "Office"));
+    }
+
+    #[test]
+    fn json_lists_of_trakt_sonarr_and_radarr_are_read() {
+        let list = WantList::parse_json(
+            r#"[
+                {"type": "movie", "movie": {"title": "Arrival", "year": 2016, "ids": {"trakt": 1}}},
+                {"type": "show", "show": {"title": "Severance", "year": 2022}},
+                {"title": "Dune", "year": 2021, "monitored": true}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(list.count(), 3);
+        assert!(list.wants(&movie("Arrival", 2016)));
+        assert!(list.wants(&movie("Dune", 2021)));
+        assert!(!list.wants(&movie("Dune", 1984)));
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread, vec,
+};
+
+/// Runs `work` on every item with up to `jobs` threads, each taking the next item in order, and
+/// returns the results in the order of the items. A single job runs on the calling thread
+pub fn map<T: Sync, R: Send>(items: &[T], jobs: usize, work: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    if jobs <= 1 {
+        return items.iter().map(work).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = work(item);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("Every item is worked on"))
+        .collect()
+}
+
+/// Runs `work` on every item with up to `jobs` threads, each taking the next item in order.
+/// A single job runs on the calling thread
+pub fn for_each<T: Send>(items: Vec<T>, jobs: usize, work: impl Fn(T) + Sync) {
+    if jobs <= 1 {
+        items.into_iter().for_each(work);
+        return;
+    }
+
+    let count = items.len();
+    let items: Mutex<vec::IntoIter<T>> = Mutex::new(items.into_iter());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(count) {
+            scope.spawn(|| loop {
+                let Some(item) = items.lock().unwrap().next() else {
+                    break;
+                };
+                work(item);
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_keep_the_order_of_the_items() {
+        let items: Vec<u64> = (0..50).collect();
+        let squares = map(&items, 4, |item| {
+            thread::sleep(std::time::Duration::from_millis(50 - item));
+            item * item
+        });
+        assert_eq!(squares, items.iter().map(|item| item * item).collect::<Vec<_>>());
+
+        let sum = AtomicUsize::new(0);
+        for_each(items, 4, |item| {
+            sum.fetch_add(item as usize, Ordering::Relaxed);
+        });
+        assert_eq!(sum.into_inner(), (0..50).sum::<usize>());
+    }
+}